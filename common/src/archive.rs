@@ -0,0 +1,301 @@
+//! Support for roms stored inside of compressed archives. Zip, gzip, and 7z archives are supported.
+//! A rom inside of an archive is named by the path of the archive and the name of the member, like `roms/archive.zip#member.nes`
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// The character that separates the path of an archive from the name of a member inside of it
+pub const ARCHIVE_SEPARATOR: char = '#';
+
+/// The largest member of an archive that is read. This is much larger than any real rom,
+/// and keeps a large file or a zip bomb in the rom folder from being decompressed into memory.
+const MAX_MEMBER_SIZE: u64 = 64 * 1024 * 1024;
+
+/// The kinds of archives that roms can be loaded from
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum ArchiveType {
+    /// A zip archive
+    Zip,
+    /// A gzip compressed file, containing a single member
+    Gzip,
+    /// A 7z archive
+    SevenZip,
+}
+
+impl ArchiveType {
+    /// Determine the type of archive from the extension of the path
+    fn from_path(p: &Path) -> Option<Self> {
+        let ext = p.extension()?.to_str()?.to_lowercase();
+        match ext.as_str() {
+            "zip" => Some(Self::Zip),
+            "gz" => Some(Self::Gzip),
+            "7z" => Some(Self::SevenZip),
+            _ => None,
+        }
+    }
+}
+
+/// Convert an io error into the error for a failed load
+fn to_io_error<E: std::fmt::Display>(e: E) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string())
+}
+
+/// The error for a member of an archive that is larger than the largest allowed member
+fn too_large() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        "The archive member is too large to be a rom",
+    )
+}
+
+/// Read a member of an archive, failing when it is larger than the largest allowed member
+fn read_limited(r: &mut dyn Read) -> std::io::Result<Vec<u8>> {
+    let mut contents = Vec::new();
+    r.take(MAX_MEMBER_SIZE + 1).read_to_end(&mut contents)?;
+    if contents.len() as u64 > MAX_MEMBER_SIZE {
+        return Err(too_large());
+    }
+    Ok(contents)
+}
+
+/// Split a rom name into the path of the archive and the name of the member, if the name refers to an archive member.
+/// The name is split at the first separator that follows the extension of an archive, so the name of a member can contain the separator.
+pub fn split_name(name: &str) -> Option<(PathBuf, String)> {
+    if Path::new(name).is_file() {
+        return None;
+    }
+    name.match_indices(ARCHIVE_SEPARATOR).find_map(|(i, _)| {
+        let archive = PathBuf::from(&name[..i]);
+        ArchiveType::from_path(&archive)?;
+        Some((
+            archive,
+            name[i + ARCHIVE_SEPARATOR.len_utf8()..].to_string(),
+        ))
+    })
+}
+
+/// The name of a member of an archive, like `roms/archive.zip#member.nes`
+pub fn member_name(archive: &Path, member: &str) -> String {
+    format!("{}{}{}", archive.display(), ARCHIVE_SEPARATOR, member)
+}
+
+/// Returns the file that holds the rom, the archive for archive members or the rom itself otherwise.
+pub fn source_path(name: &Path) -> PathBuf {
+    match name.to_str().and_then(split_name) {
+        Some((archive, _)) => archive,
+        None => name.to_path_buf(),
+    }
+}
+
+/// Returns a file name for the rom, suitable for naming save files. Archive members include the name of the archive.
+pub fn rom_file_name(name: &str) -> String {
+    let file_name = |p: &Path| {
+        p.file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default()
+    };
+    match split_name(name) {
+        Some((archive, member)) => format!(
+            "{}{}{}",
+            file_name(&archive),
+            ARCHIVE_SEPARATOR,
+            file_name(Path::new(&member))
+        ),
+        None => file_name(Path::new(name)),
+    }
+}
+
+/// Read every member of an archive in order, calling the function with the name and contents of each member.
+/// Members that are too large to be a rom are skipped. Returns None when the path is not a supported archive.
+fn for_each_member<F>(p: &Path, f: &mut F) -> Option<std::io::Result<()>>
+where
+    F: FnMut(String, std::io::Result<Vec<u8>>),
+{
+    let kind = ArchiveType::from_path(p)?;
+    let result = match kind {
+        ArchiveType::Zip => std::fs::File::open(p).and_then(|file| {
+            let mut zip = zip::ZipArchive::new(file).map_err(to_io_error)?;
+            for i in 0..zip.len() {
+                if let Ok(mut file) = zip.by_index(i) {
+                    if !file.is_dir() && file.size() <= MAX_MEMBER_SIZE {
+                        let name = member_name(p, file.name());
+                        f(name, read_limited(&mut file));
+                    }
+                }
+            }
+            Ok(())
+        }),
+        ArchiveType::Gzip => {
+            //the only member is the file without the gz extension
+            let member = p.file_stem()?.to_string_lossy().to_string();
+            std::fs::File::open(p).map(|file| {
+                f(
+                    member_name(p, &member),
+                    read_limited(&mut flate2::read::GzDecoder::new(file)),
+                );
+            })
+        }
+        ArchiveType::SevenZip => sevenz_rust::SevenZReader::open(p, sevenz_rust::Password::empty())
+            .and_then(|mut sz| {
+                sz.for_each_entries(|entry, reader| {
+                    if !entry.is_directory() && entry.size() <= MAX_MEMBER_SIZE {
+                        f(member_name(p, entry.name()), read_limited(reader));
+                    } else {
+                        //members of a solid archive are decompressed in order, so skipped members still have to be read
+                        std::io::copy(reader, &mut std::io::sink())?;
+                    }
+                    Ok(true)
+                })
+            })
+            .map_err(to_io_error),
+    };
+    Some(result)
+}
+
+/// Read every rom held by a file, calling the function with the name and contents of each rom.
+/// Each member of an archive is a rom, and the archive is only read and decompressed once.
+/// Any other file is a single rom.
+pub fn for_each_rom<F>(p: &Path, mut f: F)
+where
+    F: FnMut(String, std::io::Result<Vec<u8>>),
+{
+    match for_each_member(p, &mut f) {
+        Some(Ok(())) => {}
+        Some(Err(e)) => f(p.display().to_string(), Err(e)),
+        None => f(p.display().to_string(), std::fs::read(p)),
+    }
+}
+
+/// Read a single member of an archive
+fn read_member(archive: &Path, member: &str) -> std::io::Result<Vec<u8>> {
+    let kind = ArchiveType::from_path(archive)
+        .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::Unsupported))?;
+    match kind {
+        ArchiveType::Zip => {
+            let f = std::fs::File::open(archive)?;
+            let mut zip = zip::ZipArchive::new(f).map_err(to_io_error)?;
+            let mut file = zip.by_name(member).map_err(to_io_error)?;
+            if file.size() > MAX_MEMBER_SIZE {
+                return Err(too_large());
+            }
+            read_limited(&mut file)
+        }
+        ArchiveType::Gzip => {
+            let f = std::fs::File::open(archive)?;
+            read_limited(&mut flate2::read::GzDecoder::new(f))
+        }
+        ArchiveType::SevenZip => {
+            let mut sz = sevenz_rust::SevenZReader::open(archive, sevenz_rust::Password::empty())
+                .map_err(to_io_error)?;
+            let mut contents = None;
+            sz.for_each_entries(|entry, reader| {
+                if entry.name() == member {
+                    contents = Some(if entry.size() > MAX_MEMBER_SIZE {
+                        Err(too_large())
+                    } else {
+                        read_limited(reader)
+                    });
+                    return Ok(false);
+                }
+                //members of a solid archive are decompressed in order, so skipped members still have to be read
+                std::io::copy(reader, &mut std::io::sink())?;
+                Ok(true)
+            })
+            .map_err(to_io_error)?;
+            contents.unwrap_or_else(|| Err(std::io::ErrorKind::NotFound.into()))
+        }
+    }
+}
+
+/// Read the contents of a rom, which may be a regular file or a member of an archive.
+pub fn read_rom(name: &str) -> std::io::Result<Vec<u8>> {
+    match split_name(name) {
+        Some((archive, member)) => read_member(&archive, &member),
+        None => std::fs::read(name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    /// Write a zip archive with the given members into the temporary folder
+    fn write_zip(name: &str, members: &[(&str, &[u8])]) -> PathBuf {
+        let path = std::env::temp_dir().join(name);
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(&path).unwrap());
+        for (member, contents) in members {
+            zip.start_file(*member, zip::write::FileOptions::default())
+                .unwrap();
+            zip.write_all(contents).unwrap();
+        }
+        zip.finish().unwrap();
+        path
+    }
+
+    #[test]
+    fn split_member_with_separator() {
+        let (archive, member) = split_name("roms/games#1.zip#disk#2.fds").unwrap();
+        assert_eq!(archive, PathBuf::from("roms/games#1.zip"));
+        assert_eq!(member, "disk#2.fds");
+        assert!(split_name("roms/not#an#archive.nes").is_none());
+    }
+
+    #[test]
+    fn zip_members() {
+        let path = write_zip(
+            "archive_members_test.zip",
+            &[("a.nes", &[1, 2, 3]), ("b#c.nes", &[4, 5])],
+        );
+        let mut roms = Vec::new();
+        for_each_rom(&path, |name, contents| roms.push((name, contents.unwrap())));
+        let a = member_name(&path, "a.nes");
+        let bc = member_name(&path, "b#c.nes");
+        assert_eq!(
+            roms,
+            vec![(a.clone(), vec![1, 2, 3]), (bc.clone(), vec![4, 5])]
+        );
+        assert_eq!(read_rom(&a).unwrap(), vec![1, 2, 3]);
+        assert_eq!(read_rom(&bc).unwrap(), vec![4, 5]);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn large_members_are_skipped() {
+        let big = vec![0; MAX_MEMBER_SIZE as usize + 1];
+        let path = write_zip(
+            "archive_large_test.zip",
+            &[("big.nes", &big), ("small.nes", &[1, 2])],
+        );
+        let mut roms = Vec::new();
+        for_each_rom(&path, |name, contents| roms.push((name, contents.unwrap())));
+        let small = member_name(&path, "small.nes");
+        assert_eq!(roms, vec![(small, vec![1, 2])]);
+        assert!(read_rom(&member_name(&path, "big.nes")).is_err());
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn patches_for_archive_members() {
+        let path = write_zip("archive_patch_test.zip", &[("patched.nes", &[0; 16])]);
+        let patch = std::env::temp_dir().join("patched.ips");
+        std::fs::write(&patch, b"PATCHEOF").unwrap();
+        let name = member_name(&path, "patched.nes");
+        assert_eq!(
+            crate::patch::find_patches(Path::new(&name)),
+            vec![patch.clone()]
+        );
+        let _ = std::fs::remove_file(path);
+        let _ = std::fs::remove_file(patch);
+    }
+
+    #[test]
+    fn plain_file_is_one_rom() {
+        let path = std::env::temp_dir().join("archive_plain_test.nes");
+        std::fs::write(&path, [7, 8]).unwrap();
+        let mut roms = Vec::new();
+        for_each_rom(&path, |name, contents| roms.push((name, contents.unwrap())));
+        assert_eq!(roms, vec![(path.display().to_string(), vec![7, 8])]);
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -1,12 +1,15 @@
 use serde::{Deserialize, Serialize};
 
+pub mod archive;
 pub mod audio;
 pub mod event;
 pub mod input;
 pub mod network;
+pub mod patch;
 pub mod recording;
 pub mod rom_status;
 pub mod romlist;
+pub mod screenshot;
 pub mod storage;
 pub mod streaming;
 pub mod video;
@@ -22,6 +25,8 @@ pub enum CartridgeError {
     IncompatibleRom,
     /// The rom might use a mapper that is not yet implemented
     IncompatibleMapper(u32),
+    /// The rom might use a board, named by a unif rom, that is not known
+    UnknownBoard(String),
     /// The rom might be too short, indicating some bytes got cut off of the end, or that it has been corrupted/modified
     RomTooShort,
     /// The rom has bytes that were not parsed
@@ -30,6 +35,14 @@ pub enum CartridgeError {
     BadLength,
     /// The rom header was not found
     HeaderNotFound,
+    /// A patch for the rom is not a valid patch
+    InvalidPatch,
+    /// The checksum of a patch for the rom is wrong, the patch is corrupted
+    PatchChecksumMismatch,
+    /// A patch was made for a different rom
+    PatchSourceMismatch,
+    /// The result of applying a patch does not match the checksum in the patch
+    PatchTargetMismatch,
 }
 
 /// A constant that defines when the code was compiled
@@ -0,0 +1,551 @@
+//! Code for applying and creating patches for rom images. Ips, ups, and bps patches are supported.
+
+use std::path::{Path, PathBuf};
+
+use crate::CartridgeError;
+
+/// The errors that can occur when applying a patch
+#[derive(Debug, Clone, PartialEq)]
+pub enum PatchError {
+    /// The patch does not have the correct header
+    InvalidHeader,
+    /// The patch ends in the middle of a record
+    Truncated,
+    /// The checksum of the patch itself does not match
+    PatchChecksum,
+    /// The data being patched is not what the patch was made for
+    SourceMismatch,
+    /// The result of the patch does not match the expected checksum
+    TargetMismatch,
+    /// The patch creates data larger than any rom
+    TooLarge,
+}
+
+impl From<PatchError> for CartridgeError {
+    fn from(value: PatchError) -> Self {
+        match value {
+            PatchError::InvalidHeader | PatchError::Truncated | PatchError::TooLarge => {
+                CartridgeError::InvalidPatch
+            }
+            PatchError::PatchChecksum => CartridgeError::PatchChecksumMismatch,
+            PatchError::SourceMismatch => CartridgeError::PatchSourceMismatch,
+            PatchError::TargetMismatch => CartridgeError::PatchTargetMismatch,
+        }
+    }
+}
+
+/// The extensions of patch files, in the order they are applied
+const PATCH_EXTENSIONS: [&str; 3] = ["ips", "ups", "bps"];
+
+/// Find the patches that sit next to a rom, with the same name as the rom but with a patch extension.
+/// The patches for a member of an archive sit next to the archive, with the same name as the member.
+pub fn find_patches(rom: &Path) -> Vec<PathBuf> {
+    let rom = match rom.to_str().and_then(crate::archive::split_name) {
+        Some((archive, member)) => match Path::new(&member).file_name() {
+            Some(name) => archive.with_file_name(name),
+            None => return Vec::new(),
+        },
+        None => rom.to_path_buf(),
+    };
+    PATCH_EXTENSIONS
+        .iter()
+        .map(|e| rom.with_extension(e))
+        .filter(|p| *p != rom && p.is_file())
+        .collect()
+}
+
+/// Apply a patch of any supported format, detected by the header of the patch
+pub fn apply_patch(data: &mut Vec<u8>, patch: &[u8]) -> Result<(), PatchError> {
+    if patch.starts_with(IPS_HEADER) {
+        apply_ips(data, patch)
+    } else if patch.starts_with(UPS_HEADER) {
+        apply_ups(data, patch)
+    } else if patch.starts_with(BPS_HEADER) {
+        apply_bps(data, patch)
+    } else {
+        Err(PatchError::InvalidHeader)
+    }
+}
+
+/// The lookup table for calculating crc32
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if (c & 1) != 0 {
+                0xEDB88320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+};
+
+/// Calculate the crc32 of some data, as used by ups and bps patches
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, b| {
+        CRC32_TABLE[((crc ^ *b as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+/// The header of an ips patch
+const IPS_HEADER: &[u8] = b"PATCH";
+/// The footer of an ips patch
+const IPS_FOOTER: &[u8] = b"EOF";
+/// The largest offset that an ips patch can address
+const IPS_MAX_OFFSET: usize = 0xFFFFFF;
+/// The largest amount of data in a single ips record
+const IPS_MAX_RECORD: usize = 0xFFFF;
+
+/// Read a big endian number of the given number of bytes from a patch
+fn read_be(patch: &[u8], pos: &mut usize, len: usize) -> Result<usize, PatchError> {
+    if *pos + len > patch.len() {
+        return Err(PatchError::Truncated);
+    }
+    let v = patch[*pos..*pos + len]
+        .iter()
+        .fold(0, |acc, b| (acc << 8) | *b as usize);
+    *pos += len;
+    Ok(v)
+}
+
+/// Apply an ips patch to the given data, growing the data when the patch writes past the end of it.
+pub fn apply_ips(data: &mut Vec<u8>, patch: &[u8]) -> Result<(), PatchError> {
+    if !patch.starts_with(IPS_HEADER) {
+        return Err(PatchError::InvalidHeader);
+    }
+    let mut pos = IPS_HEADER.len();
+    loop {
+        if patch[pos..].starts_with(IPS_FOOTER) {
+            pos += IPS_FOOTER.len();
+            //an optional truncation length can follow the footer
+            if patch.len() - pos >= 3 {
+                let len = read_be(patch, &mut pos, 3)?;
+                data.truncate(len);
+            }
+            return Ok(());
+        }
+        let offset = read_be(patch, &mut pos, 3)?;
+        let size = read_be(patch, &mut pos, 2)?;
+        if size == 0 {
+            let size = read_be(patch, &mut pos, 2)?;
+            let value = read_be(patch, &mut pos, 1)? as u8;
+            if data.len() < offset + size {
+                data.resize(offset + size, 0);
+            }
+            data[offset..offset + size].fill(value);
+        } else {
+            if pos + size > patch.len() {
+                return Err(PatchError::Truncated);
+            }
+            if data.len() < offset + size {
+                data.resize(offset + size, 0);
+            }
+            data[offset..offset + size].copy_from_slice(&patch[pos..pos + size]);
+            pos += size;
+        }
+    }
+}
+
+/// Create an ips patch that converts original into modified. Data beyond the addressable range of ips is ignored.
+pub fn create_ips(original: &[u8], modified: &[u8]) -> Vec<u8> {
+    let mut patch = IPS_HEADER.to_vec();
+    let end = modified.len().min(IPS_MAX_OFFSET);
+    let differs = |i: usize| original.get(i) != Some(&modified[i]);
+    let mut i = 0;
+    while i < end {
+        if !differs(i) {
+            i += 1;
+            continue;
+        }
+        //the offset that spells EOF would be mistaken for the footer
+        let start = if i == 0x454f46 { i - 1 } else { i };
+        let mut stop = i + 1;
+        //small runs of identical bytes are included to avoid the overhead of a new record
+        while stop < end && stop - start < IPS_MAX_RECORD {
+            if (stop..(stop + 6).min(end)).any(differs) {
+                stop += 1;
+            } else {
+                break;
+            }
+        }
+        patch.extend_from_slice(&(start as u32).to_be_bytes()[1..]);
+        patch.extend_from_slice(&((stop - start) as u16).to_be_bytes());
+        patch.extend_from_slice(&modified[start..stop]);
+        i = stop;
+    }
+    patch.extend_from_slice(IPS_FOOTER);
+    if modified.len() < original.len() {
+        patch.extend_from_slice(&(modified.len() as u32).to_be_bytes()[1..]);
+    }
+    patch
+}
+
+/// The largest size of the result of a ups or bps patch. This is much larger than any real rom,
+/// and keeps a damaged or malicious patch from allocating an unreasonable amount of memory.
+const MAX_TARGET_SIZE: usize = 64 * 1024 * 1024;
+
+/// The header of a ups patch
+const UPS_HEADER: &[u8] = b"UPS1";
+/// The header of a bps patch
+const BPS_HEADER: &[u8] = b"BPS1";
+
+/// Read a variable length number, as used by ups and bps patches
+fn read_varint(patch: &[u8], pos: &mut usize) -> Result<usize, PatchError> {
+    let mut value: usize = 0;
+    let mut shift: usize = 1;
+    loop {
+        let x = *patch.get(*pos).ok_or(PatchError::Truncated)?;
+        *pos += 1;
+        value = value
+            .checked_add((x & 0x7f) as usize * shift)
+            .ok_or(PatchError::Truncated)?;
+        if (x & 0x80) != 0 {
+            return Ok(value);
+        }
+        shift = shift.checked_shl(7).ok_or(PatchError::Truncated)?;
+        value = value.checked_add(shift).ok_or(PatchError::Truncated)?;
+    }
+}
+
+/// Check the footer of a ups or bps patch, returning the source and target checksums.
+fn check_footer(patch: &[u8], header: &[u8]) -> Result<(u32, u32), PatchError> {
+    if !patch.starts_with(header) {
+        return Err(PatchError::InvalidHeader);
+    }
+    if patch.len() < header.len() + 12 {
+        return Err(PatchError::Truncated);
+    }
+    let footer = patch.len() - 12;
+    let read = |i: usize| u32::from_le_bytes(patch[i..i + 4].try_into().unwrap());
+    if crc32(&patch[..footer + 8]) != read(footer + 8) {
+        return Err(PatchError::PatchChecksum);
+    }
+    Ok((read(footer), read(footer + 4)))
+}
+
+/// Apply a ups patch to the given data, the checksums of the data before and after patching are verified.
+pub fn apply_ups(data: &mut Vec<u8>, patch: &[u8]) -> Result<(), PatchError> {
+    let (source_crc, target_crc) = check_footer(patch, UPS_HEADER)?;
+    if crc32(data) != source_crc {
+        return Err(PatchError::SourceMismatch);
+    }
+    let end = patch.len() - 12;
+    let mut pos = UPS_HEADER.len();
+    let _source_size = read_varint(patch, &mut pos)?;
+    let target_size = read_varint(patch, &mut pos)?;
+    if target_size > MAX_TARGET_SIZE {
+        return Err(PatchError::TooLarge);
+    }
+    let mut out = data.clone();
+    out.resize(target_size, 0);
+    let mut offset = 0;
+    while pos < end {
+        offset += read_varint(patch, &mut pos)?;
+        loop {
+            if pos >= end {
+                return Err(PatchError::Truncated);
+            }
+            let x = patch[pos];
+            pos += 1;
+            if let Some(b) = out.get_mut(offset) {
+                *b ^= x;
+            }
+            offset += 1;
+            if x == 0 {
+                break;
+            }
+        }
+    }
+    if crc32(&out) != target_crc {
+        return Err(PatchError::TargetMismatch);
+    }
+    *data = out;
+    Ok(())
+}
+
+/// Apply a bps patch to the given data, the checksums of the data before and after patching are verified.
+pub fn apply_bps(data: &mut Vec<u8>, patch: &[u8]) -> Result<(), PatchError> {
+    let (source_crc, target_crc) = check_footer(patch, BPS_HEADER)?;
+    if crc32(data) != source_crc {
+        return Err(PatchError::SourceMismatch);
+    }
+    let end = patch.len() - 12;
+    let mut pos = BPS_HEADER.len();
+    let _source_size = read_varint(patch, &mut pos)?;
+    let target_size = read_varint(patch, &mut pos)?;
+    if target_size > MAX_TARGET_SIZE {
+        return Err(PatchError::TooLarge);
+    }
+    let metadata_size = read_varint(patch, &mut pos)?;
+    pos = pos
+        .checked_add(metadata_size)
+        .ok_or(PatchError::Truncated)?;
+    let mut out = Vec::with_capacity(target_size);
+    let mut source_relative: isize = 0;
+    let mut target_relative: isize = 0;
+    while pos < end {
+        let command = read_varint(patch, &mut pos)?;
+        let length = (command >> 2) + 1;
+        if out.len() + length > target_size {
+            return Err(PatchError::TargetMismatch);
+        }
+        match command & 3 {
+            0 => {
+                //source read
+                let start = out.len();
+                if start + length > data.len() {
+                    return Err(PatchError::SourceMismatch);
+                }
+                out.extend_from_slice(&data[start..start + length]);
+            }
+            1 => {
+                //target read
+                if pos + length > end {
+                    return Err(PatchError::Truncated);
+                }
+                out.extend_from_slice(&patch[pos..pos + length]);
+                pos += length;
+            }
+            c => {
+                let d = read_varint(patch, &mut pos)?;
+                let offset = if (d & 1) != 0 {
+                    -((d >> 1) as isize)
+                } else {
+                    (d >> 1) as isize
+                };
+                if c == 2 {
+                    //source copy
+                    source_relative += offset;
+                    if source_relative < 0 || source_relative as usize + length > data.len() {
+                        return Err(PatchError::SourceMismatch);
+                    }
+                    let start = source_relative as usize;
+                    out.extend_from_slice(&data[start..start + length]);
+                    source_relative += length as isize;
+                } else {
+                    //target copy, the source and destination can overlap
+                    target_relative += offset;
+                    if target_relative < 0 || target_relative as usize >= out.len() {
+                        return Err(PatchError::Truncated);
+                    }
+                    for _ in 0..length {
+                        let b = out[target_relative as usize];
+                        out.push(b);
+                        target_relative += 1;
+                    }
+                }
+            }
+        }
+    }
+    if out.len() != target_size || crc32(&out) != target_crc {
+        return Err(PatchError::TargetMismatch);
+    }
+    *data = out;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Write a variable length number, the opposite of read_varint
+    fn write_varint(out: &mut Vec<u8>, mut v: usize) {
+        loop {
+            let x = (v & 0x7f) as u8;
+            v >>= 7;
+            if v == 0 {
+                out.push(0x80 | x);
+                break;
+            }
+            out.push(x);
+            v -= 1;
+        }
+    }
+
+    /// Add the checksums to the end of a ups or bps patch
+    fn finish(mut patch: Vec<u8>, source: &[u8], target: &[u8]) -> Vec<u8> {
+        patch.extend_from_slice(&crc32(source).to_le_bytes());
+        patch.extend_from_slice(&crc32(target).to_le_bytes());
+        let crc = crc32(&patch);
+        patch.extend_from_slice(&crc.to_le_bytes());
+        patch
+    }
+
+    /// A ups patch converting [1, 2, 3, 4] into [1, 9, 3, 4, 5]
+    fn ups_patch() -> Vec<u8> {
+        let mut patch = UPS_HEADER.to_vec();
+        write_varint(&mut patch, 4);
+        write_varint(&mut patch, 5);
+        write_varint(&mut patch, 1);
+        patch.extend_from_slice(&[2 ^ 9, 0]);
+        write_varint(&mut patch, 1);
+        patch.extend_from_slice(&[5, 0]);
+        finish(patch, &[1, 2, 3, 4], &[1, 9, 3, 4, 5])
+    }
+
+    /// A bps patch converting [1, 2, 3, 4] into [1, 2, 7, 7, 7, 4], using every command
+    fn bps_patch() -> Vec<u8> {
+        let mut patch = BPS_HEADER.to_vec();
+        write_varint(&mut patch, 4);
+        write_varint(&mut patch, 6);
+        write_varint(&mut patch, 0);
+        //source read of 2 bytes
+        write_varint(&mut patch, 1 << 2);
+        //target read of 1 byte
+        write_varint(&mut patch, 1);
+        patch.push(7);
+        //target copy of 2 bytes, starting at offset 2
+        write_varint(&mut patch, (1 << 2) | 3);
+        write_varint(&mut patch, 2 << 1);
+        //source copy of 1 byte, starting at offset 3
+        write_varint(&mut patch, 2);
+        write_varint(&mut patch, 3 << 1);
+        finish(patch, &[1, 2, 3, 4], &[1, 2, 7, 7, 7, 4])
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+        assert_eq!(crc32(&[]), 0);
+    }
+
+    #[test]
+    fn ips_round_trip() {
+        let original: Vec<u8> = (0..1000).map(|i| (i % 251) as u8).collect();
+        let mut modified = original.clone();
+        modified[10] = 0xff;
+        modified[11] = 0xfe;
+        modified[500..520].fill(0x42);
+        modified.extend_from_slice(&[1, 2, 3]);
+        let patch = create_ips(&original, &modified);
+        let mut data = original.clone();
+        apply_patch(&mut data, &patch).unwrap();
+        assert_eq!(data, modified);
+
+        //a shorter result uses the truncation length after the footer
+        let shorter = original[..600].to_vec();
+        let patch = create_ips(&original, &shorter);
+        let mut data = original.clone();
+        apply_ips(&mut data, &patch).unwrap();
+        assert_eq!(data, shorter);
+    }
+
+    #[test]
+    fn ips_rle_record() {
+        let mut patch = IPS_HEADER.to_vec();
+        patch.extend_from_slice(&[0, 0, 2, 0, 0, 0, 3, 0xaa]);
+        patch.extend_from_slice(IPS_FOOTER);
+        let mut data = vec![0; 4];
+        apply_ips(&mut data, &patch).unwrap();
+        assert_eq!(data, [0, 0, 0xaa, 0xaa, 0xaa]);
+    }
+
+    #[test]
+    fn ips_bad_input() {
+        let mut data = vec![0; 4];
+        assert_eq!(
+            apply_ips(&mut data, b"PATCX"),
+            Err(PatchError::InvalidHeader)
+        );
+        assert_eq!(
+            apply_ips(&mut data, b"PATCH\x00\x00\x01\x00\x05\x01"),
+            Err(PatchError::Truncated)
+        );
+        assert_eq!(
+            apply_ips(&mut data, b"PATCH\x00"),
+            Err(PatchError::Truncated)
+        );
+        assert_eq!(data, [0; 4]);
+    }
+
+    #[test]
+    fn ups_apply() {
+        let mut data = vec![1, 2, 3, 4];
+        apply_patch(&mut data, &ups_patch()).unwrap();
+        assert_eq!(data, [1, 9, 3, 4, 5]);
+    }
+
+    #[test]
+    fn ups_bad_input() {
+        let mut data = vec![1, 2, 3, 5];
+        assert_eq!(
+            apply_ups(&mut data, &ups_patch()),
+            Err(PatchError::SourceMismatch)
+        );
+        let mut patch = ups_patch();
+        patch[6] ^= 1;
+        let mut data = vec![1, 2, 3, 4];
+        assert_eq!(apply_ups(&mut data, &patch), Err(PatchError::PatchChecksum));
+        assert_eq!(apply_ups(&mut data, b"UPS1"), Err(PatchError::Truncated));
+        assert_eq!(data, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn ups_target_size_limit() {
+        let mut patch = UPS_HEADER.to_vec();
+        write_varint(&mut patch, 4);
+        write_varint(&mut patch, MAX_TARGET_SIZE + 1);
+        let patch = finish(patch, &[1, 2, 3, 4], &[]);
+        let mut data = vec![1, 2, 3, 4];
+        assert_eq!(apply_ups(&mut data, &patch), Err(PatchError::TooLarge));
+    }
+
+    #[test]
+    fn bps_apply() {
+        let mut data = vec![1, 2, 3, 4];
+        apply_patch(&mut data, &bps_patch()).unwrap();
+        assert_eq!(data, [1, 2, 7, 7, 7, 4]);
+    }
+
+    #[test]
+    fn bps_bad_input() {
+        let mut data = vec![4, 3, 2, 1];
+        assert_eq!(
+            apply_bps(&mut data, &bps_patch()),
+            Err(PatchError::SourceMismatch)
+        );
+        let mut patch = bps_patch();
+        let last = patch.len() - 1;
+        patch[last] ^= 1;
+        let mut data = vec![1, 2, 3, 4];
+        assert_eq!(apply_bps(&mut data, &patch), Err(PatchError::PatchChecksum));
+        assert_eq!(
+            apply_patch(&mut data, b"XYZ1"),
+            Err(PatchError::InvalidHeader)
+        );
+        assert_eq!(data, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn bps_target_size_limit() {
+        let mut patch = BPS_HEADER.to_vec();
+        write_varint(&mut patch, 4);
+        write_varint(&mut patch, MAX_TARGET_SIZE + 1);
+        write_varint(&mut patch, 0);
+        let patch = finish(patch, &[1, 2, 3, 4], &[]);
+        let mut data = vec![1, 2, 3, 4];
+        assert_eq!(apply_bps(&mut data, &patch), Err(PatchError::TooLarge));
+
+        //a command that writes past the target size is rejected before copying anything
+        let mut patch = BPS_HEADER.to_vec();
+        write_varint(&mut patch, 4);
+        write_varint(&mut patch, 4);
+        write_varint(&mut patch, 0);
+        write_varint(&mut patch, 1 << 2);
+        write_varint(&mut patch, (1_000_000 << 2) | 3);
+        write_varint(&mut patch, 0);
+        let patch = finish(patch, &[1, 2, 3, 4], &[1, 2, 3, 4]);
+        assert_eq!(
+            apply_bps(&mut data, &patch),
+            Err(PatchError::TargetMismatch)
+        );
+    }
+}
@@ -13,6 +13,10 @@ use std::{
 pub trait GetMapperNumber {
     /// Retrieve the mapper number of the cartridge
     fn mappernum(&self) -> u32;
+    /// Retrieve a description of any corrections made to the header of the rom when it was loaded
+    fn header_correction(&self) -> Option<String> {
+        None
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize, PartialEq, strum::EnumIter)]
@@ -103,6 +107,9 @@ pub struct RomListResult {
     pub mapper: u32,
     /// The ranking for the rom, higher is better. Negative is worse.
     pub ranking: RomRanking,
+    /// The corrections made to the header of the rom when it was loaded
+    #[serde(default)]
+    pub correction: Option<String>,
 }
 
 /// A single entry for a potentially valid rom for the emulator
@@ -152,6 +159,7 @@ impl RomList {
                     Ok(_rom) => {}
                     Err(romerr) => match romerr {
                         CartridgeError::IncompatibleMapper(_m) => {}
+                        CartridgeError::UnknownBoard(_b) => {}
                         CartridgeError::FsError(_e) => {}
                         _ => {
                             quant += 1;
@@ -172,6 +180,7 @@ impl RomList {
                     Ok(_rom) => {}
                     Err(romerr) => match romerr {
                         CartridgeError::IncompatibleMapper(_m) => {}
+                        CartridgeError::UnknownBoard(_b) => {}
                         CartridgeError::FsError(_e) => {}
                         CartridgeError::InvalidRom => {}
                         _ => {
@@ -279,9 +288,11 @@ impl RomListParser {
     }
 
     /// Performs a recursive search for files in the filesystem. It currently uses all files in the specified roms folder (dir).
+    /// Every member of an archive is checked, named like `archive.zip#member.nes`, and each archive is only decompressed once.
+    /// The loader is given the name and the contents of each rom.
     pub fn find_roms<F, T>(&mut self, dir: &str, sp: PathBuf, bin: PathBuf, mut loader: F)
     where
-        F: FnMut(String, &Path) -> Result<T, CartridgeError>,
+        F: FnMut(String, Vec<u8>, &Path) -> Result<T, CartridgeError>,
     {
         if !self.scan_complete {
             let mut changes = false;
@@ -295,10 +306,15 @@ impl RomListParser {
                 .filter(|e| !e.file_type().is_dir())
             {
                 let meta = entry.metadata();
-                if meta.is_ok() {
-                    let m = entry.clone().into_path();
-                    let name = m.clone().into_os_string().into_string().unwrap();
-                    let cart = loader(name.clone(), &sp);
+                if meta.is_err() {
+                    continue;
+                }
+                let path = entry.into_path();
+                crate::archive::for_each_rom(&path, |name, contents| {
+                    let m = PathBuf::from(&name);
+                    let cart = contents
+                        .map_err(|e| CartridgeError::FsError(e.kind().to_string()))
+                        .and_then(|contents| loader(name, contents, &sp));
                     match cart {
                         Ok(_cart) => {
                             let e = self.list.elements.entry(m.clone());
@@ -321,7 +337,7 @@ impl RomListParser {
                             });
                         }
                     }
-                }
+                });
             }
             if changes {
                 self.list.compile_time =
@@ -333,9 +349,10 @@ impl RomListParser {
     }
 
     /// Responsbile for checking to see if an update has been performed. An update consists of checking to see if any roms have changed since the last scan through the filesystem.
+    /// The loader is given the name and the contents of each rom, and each archive is only decompressed once.
     pub fn process_roms<F, T>(&mut self, sp: PathBuf, mut loader: F)
     where
-        F: FnMut(String, &Path) -> Result<T, CartridgeError>,
+        F: FnMut(String, Vec<u8>, &Path) -> Result<T, CartridgeError>,
         T: GetMapperNumber,
     {
         let compile_time = super::get_compile_time();
@@ -345,19 +362,50 @@ impl RomListParser {
             println!("Forcing rom refresh {} {}", compile_time, data_time);
         }
         if !self.update_complete {
-            for (p, entry) in self.list.elements.iter_mut() {
-                let metadata = p.metadata();
-                if let Ok(metadata) = metadata {
+            //the roms to check are grouped by the file that holds them, so that each archive is only read once
+            let mut changed = std::collections::BTreeMap::new();
+            for (p, entry) in self.list.elements.iter() {
+                let source = crate::archive::source_path(p);
+                if let Ok(metadata) = source.metadata() {
                     let modified = metadata.modified().unwrap_or(std::time::SystemTime::now());
                     let last_modified = entry.modified.unwrap_or(std::time::SystemTime::UNIX_EPOCH);
                     if modified > last_modified || force_refetch {
-                        let romcheck = loader(p.as_os_str().to_str().unwrap().to_string(), &sp);
+                        changed
+                            .entry(source)
+                            .or_insert_with(|| (modified, Vec::new()))
+                            .1
+                            .push(p.clone());
+                    }
+                }
+            }
+            for (source, (modified, mut roms)) in changed {
+                let elements = &mut self.list.elements;
+                crate::archive::for_each_rom(&source, |name, contents| {
+                    let p = PathBuf::from(&name);
+                    match roms.iter().position(|r| *r == p) {
+                        Some(index) => roms.swap_remove(index),
+                        None => return,
+                    };
+                    let romcheck = contents
+                        .map_err(|e| CartridgeError::FsError(e.kind().to_string()))
+                        .and_then(|contents| loader(name, contents, &sp));
+                    if let Some(entry) = elements.get_mut(&p) {
                         entry.result = Some(romcheck.map(|i| RomListResult {
                             mapper: i.mappernum(),
                             ranking: RomRanking::Neutral,
+                            correction: i.header_correction(),
                         }));
                         entry.modified = Some(modified);
                     }
+                });
+                //members that are no longer in the archive
+                for p in roms {
+                    if let Some(entry) = elements.get_mut(&p) {
+                        entry.result = Some(Err(CartridgeError::FsError(
+                            std::io::ErrorKind::NotFound.to_string(),
+                        )));
+                        entry.modified = Some(modified);
+                    }
                 }
             }
 
@@ -0,0 +1,46 @@
+//! Screenshots of the emulator, saved as png images.
+//! Details about the rom and the emulator are stored in text chunks of the png, so a screenshot can be traced back to where it came from.
+
+use crate::video::RgbImage;
+
+/// The details about a screenshot that are stored in the png
+pub struct ScreenshotInfo {
+    /// The name of the rom
+    pub rom_name: String,
+    /// The hash of the rom contents
+    pub rom_hash: String,
+    /// The frame number of the system when the screenshot was taken
+    pub frame_number: u64,
+}
+
+/// The file name for a new screenshot, based on the current time
+pub fn screenshot_name() -> String {
+    format!("{}.png", chrono::Local::now().format("%Y-%m-%d %H%M%S%.3f"))
+}
+
+/// Save an image as a png, with the details of the screenshot and the time the emulator was built stored in text chunks
+pub fn save_png(
+    path: &std::path::Path,
+    image: &RgbImage,
+    info: &ScreenshotInfo,
+) -> std::io::Result<()> {
+    let to_error = |e: png::EncodingError| std::io::Error::other(e.to_string());
+    let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    let mut encoder = png::Encoder::new(file, image.width as u32, image.height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let chunks = [
+        ("Rom", info.rom_name.clone()),
+        ("Rom hash", info.rom_hash.clone()),
+        ("Frame", info.frame_number.to_string()),
+        ("Build time", crate::COMPILE_TIME.to_string()),
+    ];
+    for (keyword, text) in chunks {
+        encoder
+            .add_text_chunk(keyword.to_string(), text)
+            .map_err(to_error)?;
+    }
+    let mut writer = encoder.write_header().map_err(to_error)?;
+    writer.write_image_data(&image.data).map_err(to_error)?;
+    writer.finish().map_err(to_error)
+}
@@ -2,6 +2,8 @@
 
 use egui_multiwin::egui;
 
+pub mod ntsc;
+
 /// The types of algorithms for scaling up the image
 #[derive(
     PartialEq,
@@ -20,16 +22,68 @@ pub enum ScalingAlgorithm {
     Scale3x,
     ///The eagle scaling algorithm
     Eagle,
+    ///An ntsc filter that simulates a composite video connection
+    NtscComposite,
+    ///An ntsc filter that simulates a s-video connection
+    NtscSVideo,
+    ///An ntsc filter that simulates a rgb video connection
+    NtscRgb,
 }
 
 impl ScalingAlgorithm {
-    /// Get the scaling factor
-    pub fn scale_factor(&self) -> f32 {
+    /// Get the horizontal and vertical scaling factors
+    pub fn scale_factor(&self) -> egui::Vec2 {
         match self {
-            ScalingAlgorithm::Scale2x => 2.0,
-            ScalingAlgorithm::Scale3x => 3.0,
-            ScalingAlgorithm::Eagle => 2.0,
+            ScalingAlgorithm::Scale2x => egui::vec2(2.0, 2.0),
+            ScalingAlgorithm::Scale3x => egui::vec2(3.0, 3.0),
+            ScalingAlgorithm::Eagle => egui::vec2(2.0, 2.0),
+            ScalingAlgorithm::NtscComposite
+            | ScalingAlgorithm::NtscSVideo
+            | ScalingAlgorithm::NtscRgb => egui::vec2(ntsc::HORIZONTAL_SCALE, 2.0),
+        }
+    }
+
+    /// Returns the preset for the ntsc filter, if this is an ntsc filter.
+    /// The ntsc filters need the raw colors of the console, so the system must run the filter itself.
+    pub fn ntsc_preset(&self) -> Option<ntsc::NtscPreset> {
+        match self {
+            ScalingAlgorithm::NtscComposite => Some(ntsc::NtscPreset::Composite),
+            ScalingAlgorithm::NtscSVideo => Some(ntsc::NtscPreset::SVideo),
+            ScalingAlgorithm::NtscRgb => Some(ntsc::NtscPreset::Rgb),
+            _ => None,
+        }
+    }
+}
+
+/// The number of pixels to remove from each edge of an image, hiding the overscan area that a tv would not show
+#[derive(PartialEq, serde::Serialize, serde::Deserialize, Clone, Copy, Debug, Default)]
+pub struct Overscan {
+    /// The number of rows to remove from the top
+    pub top: u8,
+    /// The number of rows to remove from the bottom
+    pub bottom: u8,
+    /// The number of columns to remove from the left
+    pub left: u8,
+    /// The number of columns to remove from the right
+    pub right: u8,
+}
+
+impl Overscan {
+    /// Crop a buffer of pixels of the given size, returning the remaining pixels, width, and height.
+    /// At least one pixel in each direction is always kept.
+    pub fn crop<T: Copy>(&self, pixels: &[T], width: u16, height: u16) -> (Vec<T>, u16, u16) {
+        let left = (self.left as u16).min(width - 1);
+        let right = (self.right as u16).min(width - 1 - left);
+        let top = (self.top as u16).min(height - 1);
+        let bottom = (self.bottom as u16).min(height - 1 - top);
+        let new_width = width - left - right;
+        let new_height = height - top - bottom;
+        let mut cropped = Vec::with_capacity(new_width as usize * new_height as usize);
+        for y in top..top + new_height {
+            let start = y as usize * width as usize + left as usize;
+            cropped.extend_from_slice(&pixels[start..start + new_width as usize]);
         }
+        (cropped, new_width, new_height)
     }
 }
 
@@ -72,6 +126,15 @@ impl PixelImage<egui::Color32> {
         }
     }
 
+    /// Converts to a rgb image
+    pub fn to_rgb_image(&self) -> RgbImage {
+        RgbImage {
+            data: self.to_gstreamer_vec(),
+            width: self.width,
+            height: self.height,
+        }
+    }
+
     /// Converts to a vector that can be eventually passed to a gstreamer pipeline
     pub fn to_gstreamer_vec(&self) -> Vec<u8> {
         let oc = self.width as usize * self.height as usize;
@@ -111,6 +174,15 @@ impl<T> PixelImage<T>
 where
     T: Default + Clone + Copy + std::cmp::PartialEq,
 {
+    /// Create an image from a list of pixels, row by row
+    pub fn new(pixels: Vec<T>, width: u16, height: u16) -> Self {
+        Self {
+            pixels,
+            width,
+            height,
+        }
+    }
+
     /// Retrieves the pixel for the image
     pub fn get_pixel(&self, pos: egui::Vec2) -> T {
         let x = (pos.x as usize).min(self.width as usize - 1);
@@ -119,6 +191,16 @@ where
         self.pixels[index]
     }
 
+    /// Remove the overscan area from the edges of the image
+    pub fn crop(self, overscan: &Overscan) -> PixelImage<T> {
+        let (pixels, width, height) = overscan.crop(&self.pixels, self.width, self.height);
+        PixelImage {
+            pixels,
+            width,
+            height,
+        }
+    }
+
     /// Resize the image using an optional resizing algorithm
     pub fn resize(self, scale: Option<ScalingAlgorithm>) -> PixelImage<T> {
         let pixels = self.pixels;
@@ -389,6 +471,19 @@ where
                     }
                     (newpixels, 2 * self.width as usize, 2 * self.height as usize)
                 }
+                ScalingAlgorithm::NtscComposite
+                | ScalingAlgorithm::NtscSVideo
+                | ScalingAlgorithm::NtscRgb => {
+                    //without the raw colors, only the size of the ntsc filter output can be matched
+                    let (w, h) = ntsc::NtscFilter::output_size(self.width, self.height);
+                    let mut newpixels = Vec::with_capacity(w as usize * h as usize);
+                    for y in 0..h as usize {
+                        for x in 0..w as usize {
+                            newpixels.push(pixels[(y / 2) * self.width as usize + x * 3 / 7]);
+                        }
+                    }
+                    (newpixels, w as usize, h as usize)
+                }
             },
         };
         PixelImage::<T> {
@@ -0,0 +1,270 @@
+//! An ntsc video filter for the nes ppu. The composite signal is rebuilt from the raw colors of the ppu, then decoded like a tv would.
+//! This gives the dot crawl, color fringing, and pixel aspect ratio of the real console.
+
+use egui_multiwin::egui;
+
+use super::PixelImage;
+
+/// The number of signal samples for each pixel of the ppu
+const SAMPLES_PER_PIXEL: usize = 8;
+/// The number of signal samples for one cycle of the color subcarrier
+const SAMPLES_PER_CYCLE: usize = 12;
+/// The change in phase from one scanline to the next, 341 pixels of 8 samples each
+const SCANLINE_PHASE: usize = (341 * SAMPLES_PER_PIXEL) % SAMPLES_PER_CYCLE;
+/// The number of ppu pixels in each group that is resampled
+const INPUT_PIXELS: usize = 7;
+/// The number of output pixels for every group of 7 pixels of the ppu. With the scanlines doubled,
+/// each pixel is 16/7 wide and 2 tall, which is the 8:7 pixel aspect ratio of the ppu.
+const OUTPUT_PIXELS: usize = 16;
+/// The horizontal scale of the output image compared to the image of the ppu
+pub const HORIZONTAL_SCALE: f32 = OUTPUT_PIXELS as f32 / INPUT_PIXELS as f32;
+
+/// The low voltage levels of the ntsc signal for the 4 brightness levels, relative to sync
+const LOW_LEVELS: [f32; 4] = [0.350, 0.518, 0.962, 1.550];
+/// The high voltage levels of the ntsc signal for the 4 brightness levels, relative to sync
+const HIGH_LEVELS: [f32; 4] = [1.094, 1.506, 1.962, 1.962];
+/// The voltage of black
+const BLACK: f32 = 0.518;
+/// The voltage of white
+const WHITE: f32 = 1.962;
+/// The amount the signal is reduced by an active emphasis bit
+const EMPHASIS_ATTENUATION: f32 = 0.746;
+/// The phase offset of the color burst, in samples, that lines up the hues with a tv
+pub const HUE_OFFSET: f32 = 3.9;
+
+/// The phase of the subcarrier at a pixel of a frame, given the phase of the first pixel of the frame.
+/// This is used to find the starting phase of a frame that has been cropped.
+pub fn phase_at(phase: u8, x: u16, y: u16) -> u8 {
+    ((phase as usize + x as usize * SAMPLES_PER_PIXEL + y as usize * SCANLINE_PHASE)
+        % SAMPLES_PER_CYCLE) as u8
+}
+
+/// Returns true when the square wave for the given color is high at the given phase
+fn in_color_phase(color: u8, phase: usize) -> bool {
+    (color as usize + phase) % SAMPLES_PER_CYCLE < 6
+}
+
+/// Calculate the level of the signal the ppu generates for a color, normalized so that black is 0.0 and white is 1.0.
+/// The color is the 6 bit color from palette ram, the emphasis is red, green, blue in bits 0, 1, 2, and the phase is 0-11.
+pub fn ppu_signal(entry: u8, emphasis: u8, phase: usize) -> f32 {
+    let color = entry & 0xf;
+    //colors 0xe and 0xf are always black
+    let level = if color > 0xd {
+        1
+    } else {
+        ((entry >> 4) & 3) as usize
+    };
+    let low = if color == 0 {
+        HIGH_LEVELS[level]
+    } else {
+        LOW_LEVELS[level]
+    };
+    let high = if color < 0xd {
+        HIGH_LEVELS[level]
+    } else {
+        LOW_LEVELS[level]
+    };
+    let mut signal = if in_color_phase(color, phase) {
+        high
+    } else {
+        low
+    };
+    let emphasized = ((emphasis & 1) != 0 && in_color_phase(0, phase))
+        || ((emphasis & 2) != 0 && in_color_phase(4, phase))
+        || ((emphasis & 4) != 0 && in_color_phase(8, phase));
+    if color < 0xe && emphasized {
+        signal *= EMPHASIS_ATTENUATION;
+    }
+    (signal - BLACK) / (WHITE - BLACK)
+}
+
+/// The angle of the color subcarrier at a phase, in radians
+pub fn subcarrier_angle(phase: usize) -> f32 {
+    std::f32::consts::PI * (phase as f32 + HUE_OFFSET) / 6.0
+}
+
+/// Convert a yiq color into rgb
+pub fn yiq_to_rgb(y: f32, i: f32, q: f32) -> [u8; 3] {
+    let rgb = [
+        y + 0.946882 * i + 0.623557 * q,
+        y - 0.274788 * i - 0.635691 * q,
+        y - 1.108545 * i + 1.709007 * q,
+    ];
+    rgb.map(|c| (c.clamp(0.0, 1.0) * 255.0) as u8)
+}
+
+/// Decode a single color over a full cycle of the subcarrier into yiq, without the influence of neighboring pixels.
+/// The hue is an extra rotation of the subcarrier, in radians.
+pub fn decode_yiq(entry: u8, emphasis: u8, hue: f32) -> (f32, f32, f32) {
+    let (mut y, mut i, mut q) = (0.0, 0.0, 0.0);
+    for phase in 0..SAMPLES_PER_CYCLE {
+        let signal = ppu_signal(entry, emphasis, phase);
+        let angle = subcarrier_angle(phase) + hue;
+        y += signal;
+        i += signal * angle.cos();
+        q += signal * angle.sin();
+    }
+    let n = SAMPLES_PER_CYCLE as f32;
+    (y / n, i / n, q / n)
+}
+
+/// Decode a single color over a full cycle of the subcarrier into rgb
+fn decode_color(entry: u8, emphasis: u8) -> [u8; 3] {
+    let (y, i, q) = decode_yiq(entry, emphasis, 0.0);
+    yiq_to_rgb(y, i, q)
+}
+
+/// The types of video connection the ntsc filter can simulate
+#[derive(
+    PartialEq,
+    strum::Display,
+    strum::EnumIter,
+    serde::Serialize,
+    serde::Deserialize,
+    Clone,
+    Copy,
+    Debug,
+)]
+pub enum NtscPreset {
+    /// Composite video, luma and chroma share a signal. This has dot crawl and color fringing.
+    Composite,
+    /// S-Video, luma and chroma are separate. The colors blur a little but there is no dot crawl.
+    SVideo,
+    /// Rgb video, every pixel is sharp. Only the aspect ratio is changed.
+    Rgb,
+}
+
+impl NtscPreset {
+    /// The number of samples averaged for the luma and the chroma
+    fn filter_widths(&self) -> (usize, usize) {
+        match self {
+            NtscPreset::Composite => (6, 24),
+            NtscPreset::SVideo => (4, 12),
+            NtscPreset::Rgb => (1, 1),
+        }
+    }
+}
+
+/// The ntsc filter
+pub struct NtscFilter {
+    /// The kind of video connection
+    preset: NtscPreset,
+    /// The decoded colors for the rgb preset, indexed by color and emphasis
+    colors: Vec<[u8; 3]>,
+}
+
+impl NtscFilter {
+    /// Create a new filter
+    pub fn new(preset: NtscPreset) -> Self {
+        let colors = if preset == NtscPreset::Rgb {
+            (0..512u16)
+                .map(|i| decode_color((i & 0x3f) as u8, (i >> 6) as u8))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        Self { preset, colors }
+    }
+
+    /// The kind of video connection the filter simulates
+    pub fn preset(&self) -> NtscPreset {
+        self.preset
+    }
+
+    /// The size of the output image for an input image of the given size
+    pub fn output_size(width: u16, height: u16) -> (u16, u16) {
+        let w = (width as usize * OUTPUT_PIXELS).div_ceil(INPUT_PIXELS);
+        (w as u16, height * 2)
+    }
+
+    /// Filter a frame from the ppu. Each entry of the colors is the 6 bit color in bits 0-5,
+    /// and the emphasis bits for red, green, blue in bits 6, 7, 8. The phase is the phase of the subcarrier,
+    /// in samples, of the first pixel of the frame.
+    pub fn filter(
+        &self,
+        colors: &[u16],
+        width: u16,
+        height: u16,
+        phase: u8,
+    ) -> PixelImage<egui::Color32> {
+        let (out_width, out_height) = Self::output_size(width, height);
+        let mut pixels = vec![egui::Color32::BLACK; out_width as usize * out_height as usize];
+        let samples = width as usize * SAMPLES_PER_PIXEL;
+        let mut composite = vec![0.0f32; samples];
+        let mut luma = vec![0.0f32; samples];
+        let (luma_width, chroma_width) = self.preset.filter_widths();
+        let cos: Vec<f32> = (0..SAMPLES_PER_CYCLE)
+            .map(|p| subcarrier_angle(p).cos())
+            .collect();
+        let sin: Vec<f32> = (0..SAMPLES_PER_CYCLE)
+            .map(|p| subcarrier_angle(p).sin())
+            .collect();
+
+        for (y, line) in colors.chunks_exact(width as usize).enumerate() {
+            if y >= height as usize {
+                break;
+            }
+            let line_phase = phase as usize + y * SCANLINE_PHASE;
+            let mut out_line = vec![egui::Color32::BLACK; out_width as usize];
+            if self.preset == NtscPreset::Rgb {
+                for (x, p) in out_line.iter_mut().enumerate() {
+                    let c = self.colors[line[x * INPUT_PIXELS / OUTPUT_PIXELS] as usize & 0x1ff];
+                    *p = egui::Color32::from_rgb(c[0], c[1], c[2]);
+                }
+            } else {
+                //build the signal for the scanline
+                for (x, c) in line.iter().enumerate() {
+                    let entry = (*c & 0x3f) as u8;
+                    let emphasis = ((*c >> 6) & 7) as u8;
+                    let level = (0..SAMPLES_PER_CYCLE)
+                        .map(|p| ppu_signal(entry, emphasis, p))
+                        .sum::<f32>()
+                        / SAMPLES_PER_CYCLE as f32;
+                    for s in 0..SAMPLES_PER_PIXEL {
+                        let index = x * SAMPLES_PER_PIXEL + s;
+                        let p = (line_phase + index) % SAMPLES_PER_CYCLE;
+                        composite[index] = ppu_signal(entry, emphasis, p);
+                        luma[index] = level;
+                    }
+                }
+                //decode the signal like a tv would
+                for (x, p) in out_line.iter_mut().enumerate() {
+                    let center =
+                        ((2 * x + 1) * INPUT_PIXELS * SAMPLES_PER_PIXEL) / (2 * OUTPUT_PIXELS);
+                    let window = |w: usize| {
+                        let start = center.saturating_sub(w / 2);
+                        start..(start + w).min(samples)
+                    };
+                    let lw = window(luma_width);
+                    let source = if self.preset == NtscPreset::Composite {
+                        &composite
+                    } else {
+                        &luma
+                    };
+                    let yv = source[lw.clone()].iter().sum::<f32>() / lw.len() as f32;
+                    let cw = window(chroma_width);
+                    let (mut iv, mut qv) = (0.0, 0.0);
+                    for k in cw.clone() {
+                        let chroma = composite[k] - luma[k];
+                        let p = (line_phase + k) % SAMPLES_PER_CYCLE;
+                        iv += chroma * cos[p];
+                        qv += chroma * sin[p];
+                    }
+                    let n = cw.len() as f32;
+                    let c = yiq_to_rgb(yv, iv / n, qv / n);
+                    *p = egui::Color32::from_rgb(c[0], c[1], c[2]);
+                }
+            }
+            //each scanline is doubled to make the pixel aspect ratio correct
+            let start = y * 2 * out_width as usize;
+            pixels[start..start + out_width as usize].copy_from_slice(&out_line);
+            pixels[start + out_width as usize..start + 2 * out_width as usize]
+                .copy_from_slice(&out_line);
+        }
+        PixelImage {
+            pixels,
+            width: out_width,
+            height: out_height,
+        }
+    }
+}
@@ -93,12 +93,16 @@ impl ApuSweep {
 
 mod length;
 use common_emulator::audio::{AudioProducerWithRate, AudioSample};
+use strum::{EnumCount, IntoEnumIterator};
+
+use crate::cartridge::{ExpansionAudio, ExpansionAudioChip};
+use crate::region::Region;
 use length::ApuLength;
 
 mod envelope;
 use envelope::ApuEnvelope;
 
-mod square;
+pub mod square;
 use square::ApuSquareChannel;
 
 mod noise;
@@ -110,6 +114,21 @@ use triangle::ApuTriangleChannel;
 mod dmc;
 use dmc::ApuDmcChannel;
 
+/// The audio channels of the apu
+#[derive(Copy, Clone, Debug, PartialEq, strum::EnumIter, strum::EnumCount, strum::Display)]
+pub enum ApuChannel {
+    /// The first square channel
+    Square1,
+    /// The second square channel
+    Square2,
+    /// The triangle channel
+    Triangle,
+    /// The noise channel
+    Noise,
+    /// The delta modulation channel
+    Dmc,
+}
+
 /// The nes apu
 #[non_exhaustive]
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
@@ -146,6 +165,14 @@ pub struct NesApu {
     always_clock: usize,
     /// Halt holders for the 4 channels
     pend_halt: [Option<bool>; 4],
+    /// The channels that are muted, indexed by ApuChannel
+    #[serde(skip)]
+    mute: [bool; ApuChannel::COUNT],
+    /// The expansion audio chips that are muted, indexed by ExpansionAudioChip
+    #[serde(skip)]
+    expansion_mute: [bool; ExpansionAudioChip::COUNT],
+    /// The region of the apu, determines the rate tables and the frame sequencer periods
+    region: Region,
 }
 
 impl NesApu {
@@ -171,6 +198,24 @@ impl NesApu {
             inhibit_length_clock: false,
             always_clock: 0,
             pend_halt: [None; 4],
+            mute: [false; ApuChannel::COUNT],
+            expansion_mute: [false; ExpansionAudioChip::COUNT],
+            region: Region::Ntsc,
+        }
+    }
+
+    /// Set the region of the apu
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.noise.set_pal(region.pal_apu());
+    }
+
+    /// The steps of the frame sequencer, in apu cycles
+    fn frame_sequencer_steps(&self) -> &'static [u32; 5] {
+        if self.region.pal_apu() {
+            &Self::PAL_FRAME_SEQUENCER
+        } else {
+            &Self::NTSC_FRAME_SEQUENCER
         }
     }
 
@@ -183,6 +228,26 @@ impl NesApu {
         self.always_clock = 0;
     }
 
+    /// Mute or unmute one of the channels of the apu
+    pub fn set_channel_muted(&mut self, channel: ApuChannel, mute: bool) {
+        self.mute[channel as usize] = mute;
+    }
+
+    /// Returns true when the channel is muted
+    pub fn channel_muted(&self, channel: ApuChannel) -> bool {
+        self.mute[channel as usize]
+    }
+
+    /// Mute or unmute one of the expansion audio chips of the cartridge
+    pub fn set_expansion_muted(&mut self, chip: ExpansionAudioChip, mute: bool) {
+        self.expansion_mute[chip as usize] = mute;
+    }
+
+    /// Returns true when the expansion audio chip is muted
+    pub fn expansion_muted(&self, chip: ExpansionAudioChip) -> bool {
+        self.expansion_mute[chip as usize]
+    }
+
     /// Get the irq line for the apu
     pub fn irq(&self) -> bool {
         (self.status & 0xc0) != 0 && (self.fclock & 0x40) == 0 || self.dmc.interrupt_flag
@@ -234,19 +299,20 @@ impl NesApu {
                 self.frame_sequencer_clock = 0;
             }
         }
+        let steps = self.frame_sequencer_steps();
         if (self.fclock & 0x80) == 0 {
             //4 step sequence
-            if self.frame_sequencer_clock == 14915 {
+            if self.frame_sequencer_clock == steps[3] + 1 {
                 self.set_interrupt_flag();
                 self.frame_sequencer_clock = 0;
-            } else if self.frame_sequencer_clock == 3728 && self.clock {
+            } else if self.frame_sequencer_clock == steps[0] && self.clock {
                 self.quarter_frame();
-            } else if self.frame_sequencer_clock == 7456 && self.clock {
+            } else if self.frame_sequencer_clock == steps[1] && self.clock {
                 self.quarter_frame();
                 self.half_frame();
-            } else if self.frame_sequencer_clock == 11185 && self.clock {
+            } else if self.frame_sequencer_clock == steps[2] && self.clock {
                 self.quarter_frame();
-            } else if self.frame_sequencer_clock == 14914 {
+            } else if self.frame_sequencer_clock == steps[3] {
                 self.set_interrupt_flag();
                 if self.clock {
                     self.quarter_frame();
@@ -255,16 +321,16 @@ impl NesApu {
             }
         } else {
             //5 step sequence
-            if self.frame_sequencer_clock == 18641 {
+            if self.frame_sequencer_clock == steps[4] + 1 {
                 self.frame_sequencer_clock = 0;
-            } else if self.frame_sequencer_clock == 3728 && self.clock {
+            } else if self.frame_sequencer_clock == steps[0] && self.clock {
                 self.quarter_frame();
-            } else if self.frame_sequencer_clock == 7456 && self.clock {
+            } else if self.frame_sequencer_clock == steps[1] && self.clock {
                 self.quarter_frame();
                 self.half_frame();
-            } else if self.frame_sequencer_clock == 11185 && self.clock {
+            } else if self.frame_sequencer_clock == steps[2] && self.clock {
                 self.quarter_frame();
-            } else if self.frame_sequencer_clock == 18640 && self.clock {
+            } else if self.frame_sequencer_clock == steps[4] && self.clock {
                 self.quarter_frame();
                 self.half_frame();
             }
@@ -304,18 +370,42 @@ impl NesApu {
         }
     }
 
-    /// Build an audio sample and run the audio filter
+    /// Mix the expansion audio from the cartridge, scaling each chip by its level relative to the apu
+    fn expansion_audio(&self, cart_audio: &ExpansionAudio) -> f32 {
+        ExpansionAudioChip::iter()
+            .filter(|chip| !self.expansion_muted(*chip))
+            .map(|chip| cart_audio.get(chip) * NesApu::EXPANSION_AUDIO_LEVELS[chip as usize])
+            .sum()
+    }
+
+    /// Build an audio sample and run the audio filter, mixing in any audio from the cartridge.
+    /// The mix is scaled by the combined full scale output of the apu and the expansion chips that the cartridge has.
     fn build_audio_sample(
         &mut self,
         filter: &mut Option<biquad::DirectForm1<f32>>,
+        cart_audio: &ExpansionAudio,
     ) -> Option<AudioSample> {
-        let audio = self.squares[0].audio()
-            + self.squares[1].audio()
-            + self.triangle.audio()
-            + self.noise.audio()
-            + self.dmc.audio();
+        let channels = [
+            self.squares[0].audio(),
+            self.squares[1].audio(),
+            self.triangle.audio(),
+            self.noise.audio(),
+            self.dmc.audio(),
+        ];
+        let audio = channels
+            .iter()
+            .zip(self.mute.iter())
+            .filter(|(_, mute)| !**mute)
+            .map(|(a, _)| a)
+            .sum::<f32>()
+            + self.expansion_audio(cart_audio);
+        let full_scale = NesApu::APU_FULL_SCALE
+            + cart_audio
+                .chips()
+                .map(|chip| NesApu::EXPANSION_AUDIO_LEVELS[chip as usize])
+                .sum::<f32>();
         if let Some(filter) = filter {
-            let e = filter.run(audio / 5.0);
+            let e = filter.run(audio * NesApu::MIX_LEVEL / full_scale);
             self.output_index += 1.0;
             Some(AudioSample::F32(e.min(1.0).max(0.0)))
         } else {
@@ -323,12 +413,13 @@ impl NesApu {
         }
     }
 
-    /// Clock the apu
+    /// Clock the apu, cart_audio is the current output of the expansion audio on the cartridge
     pub fn clock_slow(
         &mut self,
         sound: &mut Vec<&mut AudioProducerWithRate>,
         streams: &mut Vec<std::sync::Weak<std::sync::Mutex<AudioProducerWithRate>>>,
         filter: &mut Option<biquad::DirectForm1<f32>>,
+        cart_audio: &ExpansionAudio,
     ) {
         self.always_clock = self.always_clock.wrapping_add(1);
         self.frame_sequencer_clock();
@@ -361,7 +452,7 @@ impl NesApu {
         } else if self.sound_disabled_clock == 2048 {
             self.sound_disabled = false;
         }
-        if let Some(sample) = self.build_audio_sample(filter) {
+        if let Some(sample) = self.build_audio_sample(filter, cart_audio) {
             for p in sound {
                 p.fill_audio_buffer(sample);
                 p.fill_audio_buffer(sample);
@@ -376,11 +467,48 @@ impl NesApu {
         }
     }
 
+    /// The full scale output of the apu channels, the squares, triangle and noise reach 15/255 and the dmc reaches 127/255
+    const APU_FULL_SCALE: f32 = (4.0 * 15.0 + 127.0) / 255.0;
+
+    /// The level of the mixed output when every channel of the console and the cartridge is at full volume.
+    /// This is the level the apu has always been mixed at, so games without expansion audio are as loud as before.
+    const MIX_LEVEL: f32 = NesApu::APU_FULL_SCALE / 5.0;
+
+    /// The full scale output of each expansion audio chip, indexed by ExpansionAudioChip.
+    /// A single apu square channel at full volume outputs 15/255, the levels are relative to that.
+    /// These are approximations based on the nesdev wiki pages for each chip, the real levels vary between consoles and cartridges.
+    const EXPANSION_AUDIO_LEVELS: [f32; ExpansionAudioChip::COUNT] = [
+        //mmc5, the pulses are as loud as the apu pulses, two pulses plus the pcm channel at half level
+        157.5 / 255.0,
+        //vrc6, 61 steps on the scale of the apu pulses
+        61.0 / 255.0,
+        //vrc7, a single fm channel at full volume is about as loud as an apu square, the output is six channels
+        6.0 * 15.0 / 255.0,
+        //namco 163, a channel at full volume is 11 to 19.5 db louder than an apu square depending on the board, 12 db is used
+        3.98 * 15.0 / 255.0,
+        //sunsoft 5b, a channel at volume 12 is about as loud as an apu square, the volume steps are 1.5 db so full volume is 9 db louder.
+        //the output is three channels
+        3.0 * 2.82 * 15.0 / 255.0,
+        //fds, about 2.4 times as loud as an apu square channel
+        2.4 * 15.0 / 255.0,
+    ];
+
     /// A lookup table for setting the dmc rates
     const DMC_RATE_TABLE: [u16; 16] = [
         428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
     ];
 
+    /// A lookup table for setting the dmc rates on the pal apu
+    const PAL_DMC_RATE_TABLE: [u16; 16] = [
+        398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50,
+    ];
+
+    /// The frame sequencer steps of the ntsc apu. The first three steps, the last step of the 4 step sequence, and the last step of the 5 step sequence.
+    const NTSC_FRAME_SEQUENCER: [u32; 5] = [3728, 7456, 11185, 14914, 18640];
+
+    /// The frame sequencer steps of the pal apu
+    const PAL_FRAME_SEQUENCER: [u32; 5] = [4156, 8313, 12469, 16626, 20782];
+
     /// Write to an apu register
     pub fn write(&mut self, addr: u16, data: u8) {
         match addr {
@@ -444,7 +572,12 @@ impl NesApu {
             }
             0x10 => {
                 self.dmc.interrupt_flag = false;
-                self.dmc.rate = NesApu::DMC_RATE_TABLE[(data & 0xF) as usize] / 2 - 1;
+                let table = if self.region.pal_apu() {
+                    &NesApu::PAL_DMC_RATE_TABLE
+                } else {
+                    &NesApu::DMC_RATE_TABLE
+                };
+                self.dmc.rate = table[(data & 0xF) as usize] / 2 - 1;
                 self.dmc.interrupt_enable = (data & 0x80) != 0;
                 self.dmc.loop_flag = (data & 0x40) != 0;
             }
@@ -19,11 +19,17 @@ pub struct ApuNoiseChannel {
     pub envelope: ApuEnvelope,
     /// The shift counter for random noise generation
     shift_ctr: u16,
+    /// True when the periods of the pal apu are used
+    pal: bool,
 }
 /// The periods for the various noise channel settings. Units are clock cycles.
 const FREQ_TABLE: [u16; 16] = [
     4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
 ];
+/// The periods for the various noise channel settings on the pal apu. Units are clock cycles.
+const PAL_FREQ_TABLE: [u16; 16] = [
+    4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778,
+];
 
 impl ApuNoiseChannel {
     /// Create a new channel
@@ -35,15 +41,26 @@ impl ApuNoiseChannel {
             counter: 0,
             envelope: ApuEnvelope::new(),
             shift_ctr: 1,
+            pal: false,
         }
     }
 
+    /// Select the periods of the pal apu or the ntsc apu
+    pub fn set_pal(&mut self, pal: bool) {
+        self.pal = pal;
+    }
+
     /// clock the channel
     pub fn cycle(&mut self) {
         if self.counter > 0 {
             self.counter -= 1;
         } else {
-            self.counter = FREQ_TABLE[(self.registers[2] & 0xF) as usize] - 1;
+            let table = if self.pal {
+                &PAL_FREQ_TABLE
+            } else {
+                &FREQ_TABLE
+            };
+            self.counter = table[(self.registers[2] & 0xF) as usize] - 1;
 
             let shift = if (self.registers[3] & 0x80) != 0 {
                 6
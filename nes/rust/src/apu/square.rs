@@ -90,12 +90,12 @@ impl ApuSquareChannel {
     }
 
     /// Operates the sweep mechanism
-    fn sweep(&mut self) -> bool {
+    fn sweep(&self) -> bool {
         false
     }
 
     /// Return the audio sample for this channel
-    pub fn audio(&mut self) -> f32 {
+    pub fn audio(&self) -> f32 {
         if self.length.running()
             && DUTY_TABLE[self.get_duty_mode() as usize][self.duty_counter as usize] != 0
             && !self.sweep()
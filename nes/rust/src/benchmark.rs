@@ -8,6 +8,7 @@ mod emulator_data;
 mod genie;
 mod motherboard;
 mod ppu;
+mod region;
 pub mod windows;
 
 #[cfg(feature = "egui-multiwin")]
@@ -240,10 +241,10 @@ pub fn romlist_bench(c: &mut Criterion) {
                 "../test_roms",
                 std::path::PathBuf::new(),
                 std::path::PathBuf::new(),
-                |n, p| NesCartridge::load_cartridge(n, p),
+                |n, d, p| NesCartridge::load_cartridge_contents(n, d, p),
             );
-            list.process_roms(std::path::PathBuf::new(), |n, p| {
-                NesCartridge::load_cartridge(n, p)
+            list.process_roms(std::path::PathBuf::new(), |n, d, p| {
+                NesCartridge::load_cartridge_contents(n, d, p)
             });
         });
     });
@@ -255,10 +256,10 @@ pub fn romlist_bench(c: &mut Criterion) {
                 "../test_roms",
                 std::path::PathBuf::new(),
                 std::path::PathBuf::new(),
-                |n, p| NesCartridge::load_cartridge(n, p),
+                |n, d, p| NesCartridge::load_cartridge_contents(n, d, p),
             );
-            list.process_roms(std::path::PathBuf::new(), |n, p| {
-                NesCartridge::load_cartridge(n, p)
+            list.process_roms(std::path::PathBuf::new(), |n, d, p| {
+                NesCartridge::load_cartridge_contents(n, d, p)
             });
         });
     });
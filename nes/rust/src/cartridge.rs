@@ -1,30 +1,66 @@
 //! This module is responsible for cartridge related emulation, including mapper emulation.
 
+mod audio;
+mod database;
+mod fds;
 mod mapper00;
 mod mapper01;
 mod mapper02;
 mod mapper03;
 mod mapper04;
 mod mapper05;
+mod mapper07;
+mod mapper09;
+mod mapper10;
+mod mapper11;
+mod mapper19;
+mod mapper20;
+mod mapper21;
+mod mapper24;
 mod mapper34;
+mod mapper66;
+mod mapper69;
 mod mapper71;
+mod mapper85;
+mod nsf;
+mod nsf_player;
+mod unif;
+mod vrc_irq;
 
 use std::{
     collections::BTreeMap,
     path::{Path, PathBuf},
 };
 
+pub use audio::{ExpansionAudio, ExpansionAudioChip};
 use common_emulator::{storage::PersistentStorage, CartridgeError};
+pub use database::{
+    load_external as load_rom_database, unload_external as unload_rom_database, RomDatabase,
+};
 use mapper00::Mapper00;
 use mapper01::Mapper01;
 use mapper02::Mapper02;
 use mapper03::Mapper03;
 use mapper04::Mapper04;
 use mapper05::Mapper05;
+use mapper07::Mapper07;
+use mapper09::Mapper09;
+use mapper10::Mapper10;
+use mapper11::Mapper11;
+use mapper19::Mapper19;
+use mapper20::Mapper20;
+use mapper21::Mapper21;
+use mapper24::Mapper24;
 use mapper34::Mapper34;
+use mapper66::Mapper66;
+use mapper69::Mapper69;
 use mapper71::Mapper71;
+use mapper85::Mapper85;
+pub use nsf::NsfInfo;
+use nsf_player::NsfPlayer;
 
 use crate::genie::GameGenieCode;
+use crate::region::Region;
 
 /// All mappers must implement this.
 #[enum_dispatch::enum_dispatch]
@@ -52,12 +88,40 @@ trait NesMapperTrait {
     fn ppu_memory_cycle_write(&mut self, cart: &mut NesCartridgeData, data: u8);
     /// Peek at a ppu memory address
     fn ppu_peek_address(&self, adr: u16, cart: &NesCartridgeData) -> (bool, bool, Option<u8>);
+    /// Returns true when the mapper puts the console vram in the pattern tables at the address, like the namco 163
+    fn ppu_pattern_vram(&self, _addr: u16) -> bool {
+        false
+    }
+    /// Returns true when the mapper watches the exact fetch pattern of the ppu, like the scanline detection of the mmc5
+    fn exact_ppu_fetches(&self) -> bool {
+        false
+    }
     /// Modify a byte for the cartridge rom
     fn rom_byte_hack(&mut self, cart: &mut NesCartridgeData, addr: u32, new_byte: u8);
     /// Returns a list of registers used by the cartridge
     fn cartridge_registers(&self) -> BTreeMap<String, u8>;
     /// Retrieve the irq signal
     fn irq(&self) -> bool;
+    /// Runs once per cpu cycle, for mappers with logic clocked by the cpu like irq counters or disk drives
+    fn cpu_cycle(&mut self, _cart: &mut NesCartridgeData) {}
+    /// Insert a disk side into the drive, or eject the disk with None. Only used by the famicom disk system.
+    fn insert_disk(&mut self, _side: Option<usize>) {}
+    /// Returns the disk side in the drive, if there is one
+    fn inserted_disk(&self) -> Option<usize> {
+        None
+    }
+    /// Select the song that plays when the cpu is reset. Only used by the nsf player.
+    fn select_song(&mut self, _song: u8) {}
+    /// Returns the song being played and the number of cpu cycles since it started
+    fn current_song(&self) -> Option<(u8, u64)> {
+        None
+    }
+    /// Inform the mapper of the region of the console
+    fn set_region(&mut self, _region: Region) {}
+    /// Clocks the expansion audio of the cartridge, once per cpu cycle
+    fn clock_audio(&mut self, _cart: &mut NesCartridgeData) {}
+    /// Fill out the current output of any expansion audio chips on the cartridge
+    fn audio_sample(&self, _sample: &mut ExpansionAudio) {}
     /// Checks for active game genie codes and acts appropriately
     fn genie(&mut self, cart: &mut NesCartridgeData, addr: u16) -> Option<u8> {
         if cart.volatile.genie.len() > 0 {
@@ -120,8 +184,20 @@ pub enum NesMapper {
     Mapper03,
     Mapper04,
     Mapper05,
+    Mapper07,
+    Mapper09,
+    Mapper10,
+    Mapper11,
+    Mapper19,
+    Mapper20,
+    Mapper21,
+    Mapper24,
     Mapper34,
+    Mapper66,
+    Mapper69,
     Mapper71,
+    Mapper85,
+    NsfPlayer,
 }
 
 /// The trait for cpu memory reads and writes, implemented by devices on the bus
@@ -137,6 +213,101 @@ pub trait NesMemoryBusDevice {
     fn memory_cycle_write(&mut self, addr: u16, data: u8);
 }
 
+/// The cpu and ppu timing that a rom expects
+#[derive(serde::Serialize, serde::Deserialize, Copy, Clone, Debug, PartialEq, Default)]
+pub enum CpuTiming {
+    /// The ntsc nes and famicom, RP2C02 ppu
+    #[default]
+    Ntsc,
+    /// The pal nes, RP2C07 ppu
+    Pal,
+    /// The rom works with any timing
+    MultipleRegion,
+    /// The dendy famiclone, UMC 6527P ppu
+    Dendy,
+}
+
+/// The type of console that a rom is meant for
+#[derive(serde::Serialize, serde::Deserialize, Copy, Clone, Debug, PartialEq, Default)]
+pub enum ConsoleType {
+    /// A regular nes or famicom
+    #[default]
+    Nes,
+    /// The nintendo vs. system
+    VsSystem {
+        /// The ppu type used by the vs. system
+        ppu: u8,
+        /// The hardware type of the vs. system
+        hardware: u8,
+    },
+    /// The playchoice 10
+    Playchoice10,
+    /// Some other console, the number is the extended console type from the nes 2.0 header
+    Extended(u8),
+}
+
+/// Information about the hardware of the cartridge that comes from the header of the rom
+#[non_exhaustive]
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+pub struct NesHeader {
+    /// The size of the volatile prg ram in bytes
+    pub prg_ram_size: usize,
+    /// The size of the battery backed prg ram in bytes
+    pub prg_nvram_size: usize,
+    /// The size of the volatile chr ram in bytes
+    pub chr_ram_size: usize,
+    /// The size of the battery backed chr ram in bytes
+    pub chr_nvram_size: usize,
+    /// The cpu and ppu timing
+    pub timing: CpuTiming,
+    /// The type of console
+    pub console: ConsoleType,
+    /// The number of miscellaneous roms present after the chr rom
+    pub misc_roms: u8,
+    /// The default expansion device, from the list of expansion devices for the nes 2.0 format
+    pub expansion_device: u8,
+}
+
+impl NesHeader {
+    /// Calculate a ram size from a shift count in the nes 2.0 header
+    fn ram_size(shift: u8) -> usize {
+        if shift == 0 {
+            0
+        } else {
+            64 << shift
+        }
+    }
+
+    /// Parse bytes 10-15 of an nes 2.0 header, along with the console type from byte 7
+    fn parse_ines2(rom_contents: &[u8]) -> Self {
+        let console = match rom_contents[7] & 3 {
+            0 => ConsoleType::Nes,
+            1 => ConsoleType::VsSystem {
+                ppu: rom_contents[13] & 0xf,
+                hardware: rom_contents[13] >> 4,
+            },
+            2 => ConsoleType::Playchoice10,
+            _ => ConsoleType::Extended(rom_contents[13] & 0xf),
+        };
+        let timing = match rom_contents[12] & 3 {
+            0 => CpuTiming::Ntsc,
+            1 => CpuTiming::Pal,
+            2 => CpuTiming::MultipleRegion,
+            _ => CpuTiming::Dendy,
+        };
+        Self {
+            prg_ram_size: Self::ram_size(rom_contents[10] & 0xf),
+            prg_nvram_size: Self::ram_size(rom_contents[10] >> 4),
+            chr_ram_size: Self::ram_size(rom_contents[11] & 0xf),
+            chr_nvram_size: Self::ram_size(rom_contents[11] >> 4),
+            timing,
+            console,
+            misc_roms: rom_contents[14] & 3,
+            expansion_device: rom_contents[15] & 0x3f,
+        }
+    }
+}
+
 /// The data for a cartridge.
 #[non_exhaustive]
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -146,6 +317,8 @@ pub struct NesCartridgeData {
     pub nonvolatile: NonvolatileCartridgeData,
     /// The potentially volatile cartridge data
     pub volatile: VolatileCartridgeData,
+    /// The hardware described by the header of the rom
+    pub header: NesHeader,
 }
 
 /// Nonvolatile storage for cartridge data
@@ -161,6 +334,10 @@ pub struct NonvolatileCartridgeData {
     pub inst_rom: Option<Vec<u8>>,
     /// prom?
     pub prom: Option<(Vec<u8>, Vec<u8>)>,
+    /// The original disk sides of a famicom disk system image, in the fds file format
+    pub disk_sides: Vec<Vec<u8>>,
+    /// The miscellaneous roms that follow the chr rom in an nes 2.0 rom
+    pub misc_rom: Vec<u8>,
 }
 
 /// Volatile storage for cartridge data
@@ -170,14 +347,43 @@ pub struct VolatileCartridgeData {
     pub prg_ram: PersistentStorage,
     /// Battery backup for prog ram active?
     pub battery_backup: bool,
+    /// Ram internal to the mapper chip, like the sound ram of the namco 163. It is battery backed along with the prg ram.
+    pub mapper_ram: PersistentStorage,
     /// True for vertical mirroring, false for horizontal mirroring
     pub mirroring: bool,
     /// The mapper number
     pub mapper: u32,
+    /// The submapper number, only present in nes 2.0 roms
+    pub submapper: u8,
     /// Where chr-ram is stored
     pub chr_ram: Vec<u8>,
     /// A list of game genie codes
     pub genie: Vec<crate::genie::GameGenieCode>,
+    /// The disk sides of a famicom disk system image, as seen by the disk drive
+    pub disk: Vec<Vec<u8>>,
+    /// Set when the disk has been written and the changes have not been saved yet
+    pub disk_modified: bool,
+    /// The nametable arrangement fixed by the board, overriding the mirroring of the mapper. None when the mapper decides.
+    pub nametables: Option<FixedNametables>,
+    /// The extra nametable ram on the cartridge, for boards with four screen nametables
+    pub nametable_ram: Vec<u8>,
+}
+
+impl NesCartridgeData {
+    /// Returns true when the board has bus conflicts, where a write to rom is combined with the contents of the rom at that address.
+    /// Nes 2.0 submappers specify this for some of the discrete logic mappers, otherwise the most common board for the mapper decides.
+    pub fn bus_conflicts(&self) -> bool {
+        match (self.volatile.mapper, self.volatile.submapper) {
+            (2 | 3 | 7, 1) => false,
+            (2 | 3 | 7, 2) => true,
+            //aorom and anrom prevent bus conflicts
+            (7, _) => false,
+            //nina-001 has no registers in rom
+            (34, 1) => false,
+            (2 | 3 | 11 | 34 | 66, _) => true,
+            _ => false,
+        }
+    }
 }
 
 impl VolatileCartridgeData {
@@ -195,6 +401,17 @@ impl VolatileCartridgeData {
     }
 }
 
+/// Nametable arrangements that are wired on the board instead of selected by the mapper
+#[derive(Copy, Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum FixedNametables {
+    /// All four nametables are the first page of the console vram
+    SingleScreenA,
+    /// All four nametables are the second page of the console vram
+    SingleScreenB,
+    /// The first two nametables are the console vram, the other two are ram on the cartridge
+    FourScreen,
+}
+
 #[non_exhaustive]
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 /// The format that a rom file is loaded from
@@ -203,6 +420,12 @@ pub enum RomFormat {
     Ines1,
     /// The ines2 rom format
     Ines2,
+    /// A famicom disk system image, fds or qd
+    Fds,
+    /// The unif rom format, which names the board instead of the mapper
+    Unif,
+    /// An nsf or nsfe music file
+    Nsf,
 }
 
 /// A cartridge, including the mapper structure
@@ -224,6 +447,19 @@ pub struct NesCartridge {
     #[serde(skip)]
     /// The convenience name of the rom
     rom_name: String,
+    #[serde(skip)]
+    /// The path where save data is stored
+    save_path: PathBuf,
+    #[serde(skip)]
+    /// A description of the changes made to the header by the rom database
+    header_correction: Option<String>,
+    #[serde(skip)]
+    /// The patches applied to the rom when it was loaded
+    patches: Vec<PathBuf>,
+    /// The information about the music of an nsf file
+    nsf: Option<NsfInfo>,
+    /// The index into the cartridge nametable ram for the current ppu memory cycle
+    ppu_nametable: Option<usize>,
 }
 
 /// The data from a cartridge that needs to be saved when loading a save state
@@ -232,6 +468,8 @@ pub struct NesCartridgeBackup {
     data: NonvolatileCartridgeData,
     /// The convenience name of the rom
     rom_name: String,
+    /// The patches applied to the rom when it was loaded
+    patches: Vec<PathBuf>,
 }
 
 /// Calculate the sha256 of a chunk of data, and return it in a hex encoded string.
@@ -246,6 +484,10 @@ impl common_emulator::romlist::GetMapperNumber for NesCartridge {
     fn mappernum(&self) -> u32 {
         self.mappernum
     }
+
+    fn header_correction(&self) -> Option<String> {
+        self.header_correction.clone()
+    }
 }
 
 impl NesCartridge {
@@ -254,6 +496,142 @@ impl NesCartridge {
         self.mapper.irq()
     }
 
+    /// Clock the cartridge for a single cpu cycle
+    pub fn cpu_cycle(&mut self) {
+        self.mapper.cpu_cycle(&mut self.data);
+    }
+
+    /// The path of the ips patch that holds the changes made to the disk of a famicom disk system image
+    fn disk_patch_path(&self) -> PathBuf {
+        let mut pb = self.save_path.clone();
+        pb.push(format!("{}.ips", self.save));
+        pb
+    }
+
+    /// Returns true when the disk has changes that have not been saved yet
+    pub fn disk_modified(&self) -> bool {
+        self.data.volatile.disk_modified
+    }
+
+    /// Save the changes made to the disk as an ips patch of the original image, if there are any.
+    /// The changes stay pending when they cannot be saved.
+    pub fn flush_disk(&mut self) -> std::io::Result<()> {
+        if !self.data.volatile.disk_modified {
+            return Ok(());
+        }
+        let original = self.data.nonvolatile.disk_sides.concat();
+        let modified: Vec<u8> = self
+            .data
+            .volatile
+            .disk
+            .iter()
+            .flat_map(|s| fds::side_from_raw(s))
+            .collect();
+        let patch = common_emulator::patch::create_ips(&original, &modified);
+        std::fs::write(self.disk_patch_path(), patch)?;
+        self.data.volatile.disk_modified = false;
+        Ok(())
+    }
+
+    /// Retrieve the hardware information from the header of the rom
+    pub fn header(&self) -> &NesHeader {
+        &self.data.header
+    }
+
+    /// Retrieve the submapper number, 0 when the rom does not specify one
+    pub fn submapper(&self) -> u8 {
+        self.data.volatile.submapper
+    }
+
+    /// Retrieve the description of the changes made to the header of the rom by the rom database
+    pub fn header_correction(&self) -> Option<String> {
+        self.header_correction.clone()
+    }
+
+    /// Retrieve the list of patches applied to the rom
+    pub fn patches(&self) -> &[PathBuf] {
+        &self.patches
+    }
+
+    /// Returns true when the cartridge is a famicom disk system image
+    pub fn is_disk_system(&self) -> bool {
+        self.mappernum == 20
+    }
+
+    /// Load the bios for the famicom disk system
+    pub fn load_fds_bios(&mut self, p: &Path) -> Result<(), CartridgeError> {
+        let bios = std::fs::read(p).map_err(|e| CartridgeError::FsError(e.kind().to_string()))?;
+        if bios.len() < 8192 {
+            return Err(CartridgeError::RomTooShort);
+        } else if bios.len() > 8192 {
+            return Err(CartridgeError::RomTooLong);
+        }
+        self.data.nonvolatile.prg_rom = bios;
+        Ok(())
+    }
+
+    /// The number of disk sides of a famicom disk system image
+    pub fn disk_sides(&self) -> usize {
+        self.data.volatile.disk.len()
+    }
+
+    /// Returns the disk side currently in the drive
+    pub fn inserted_disk(&self) -> Option<usize> {
+        self.mapper.inserted_disk()
+    }
+
+    /// Insert the next disk side into the drive, going back to the first side after the last one.
+    /// The changes made to the disk are saved when it leaves the drive.
+    pub fn flip_disk(&mut self) -> std::io::Result<()> {
+        if self.disk_sides() == 0 {
+            return Ok(());
+        }
+        let next = match self.mapper.inserted_disk() {
+            Some(s) => (s + 1) % self.disk_sides(),
+            None => 0,
+        };
+        self.mapper.insert_disk(Some(next));
+        self.flush_disk()
+    }
+
+    /// Remove the disk from the drive, saving the changes made to it
+    pub fn eject_disk(&mut self) -> std::io::Result<()> {
+        self.mapper.insert_disk(None);
+        self.flush_disk()
+    }
+
+    /// Retrieve the information about the music of an nsf file, None for anything else
+    pub fn nsf(&self) -> Option<&NsfInfo> {
+        self.nsf.as_ref()
+    }
+
+    /// Select the song of an nsf file to play, it starts when the cpu is reset
+    pub fn select_song(&mut self, song: u8) {
+        self.mapper.select_song(song);
+    }
+
+    /// Returns the song of an nsf file being played, and the number of cpu cycles since it started
+    pub fn current_song(&self) -> Option<(u8, u64)> {
+        self.mapper.current_song()
+    }
+
+    /// Inform the cartridge of the region of the console
+    pub fn set_region(&mut self, region: Region) {
+        self.mapper.set_region(region);
+    }
+
+    /// Clock the expansion audio of the cartridge for a single cpu cycle
+    pub fn clock_audio(&mut self) {
+        self.mapper.clock_audio(&mut self.data);
+    }
+
+    /// Retrieve the output of the expansion audio chips of the cartridge
+    pub fn audio_sample(&self) -> ExpansionAudio {
+        let mut sample = ExpansionAudio::default();
+        self.mapper.audio_sample(&mut sample);
+        sample
+    }
+
     /// "Parses" an obsolete ines rom
     fn load_obsolete_ines(_name: String, _rom_contents: &[u8]) -> Result<Self, CartridgeError> {
         Err(CartridgeError::IncompatibleRom)
@@ -264,15 +642,21 @@ impl NesCartridge {
         NesCartridgeBackup {
             data: self.data.nonvolatile.clone(),
             rom_name: self.rom_name.clone(),
+            patches: self.patches.clone(),
         }
     }
 
     /// Restore previously saved data after loading a save state.
     pub fn restore_cart_data(&mut self, old_data: NesCartridgeBackup, mut pb: PathBuf) {
         self.data.nonvolatile = old_data.data;
+        self.save_path = pb.clone();
+        let mut mpb = pb.clone();
         pb.push(format!("{}.prgram", self.save));
         self.data.volatile.prg_ram.upgrade_to_persistent(pb);
+        mpb.push(format!("{}.mapperram", self.save));
+        self.data.volatile.mapper_ram.upgrade_to_persistent(mpb);
         self.rom_name = old_data.rom_name;
+        self.patches = old_data.patches;
     }
 
     /// Retrieve the hash of the rom contents
@@ -290,6 +674,14 @@ impl NesCartridge {
         format!("{}.save", self.save)
     }
 
+    /// The size of the ram internal to the mapper chip
+    fn mapper_ram_size(mapper: u32) -> usize {
+        match mapper {
+            19 => 128,
+            _ => 0,
+        }
+    }
+
     /// Builds a mapper for the rom
     fn get_mapper(mapper: u32, rom_data: &NesCartridgeData) -> Result<NesMapper, CartridgeError> {
         let mapper = match mapper {
@@ -299,8 +691,20 @@ impl NesCartridge {
             3 => mapper03::Mapper03::new(rom_data),
             4 => mapper04::Mapper04::new(rom_data),
             5 => mapper05::Mapper05::new(rom_data),
+            7 => mapper07::Mapper07::new(rom_data),
+            9 => mapper09::Mapper09::new(rom_data),
+            10 => mapper10::Mapper10::new(rom_data),
+            11 => mapper11::Mapper11::new(rom_data),
+            19 => mapper19::Mapper19::new(rom_data),
+            20 => mapper20::Mapper20::new(rom_data),
+            21 | 22 | 23 | 25 => mapper21::Mapper21::new(rom_data, mapper),
+            24 => mapper24::Mapper24::new(rom_data, false),
+            26 => mapper24::Mapper24::new(rom_data, true),
             34 => mapper34::Mapper34::new(rom_data),
+            66 => mapper66::Mapper66::new(rom_data),
+            69 => mapper69::Mapper69::new(rom_data),
             71 => mapper71::Mapper71::new(rom_data),
+            85 => mapper85::Mapper85::new(rom_data),
             _ => {
                 return Err(CartridgeError::IncompatibleMapper(mapper));
             }
@@ -392,14 +796,44 @@ impl NesCartridge {
         }
 
         let mappernum = (rom_contents[6] >> 4) | (rom_contents[7] & 0xf0);
+        let mapper_ram = vec![0; Self::mapper_ram_size(mappernum as u32)];
+
+        let battery = (rom_contents[6] & 2) != 0;
+        let header = NesHeader {
+            prg_ram_size: if battery { 0 } else { ram_size },
+            prg_nvram_size: if battery { ram_size } else { 0 },
+            chr_ram_size: chr_ram.len(),
+            chr_nvram_size: 0,
+            timing: if (rom_contents[9] & 1) != 0 {
+                CpuTiming::Pal
+            } else {
+                CpuTiming::Ntsc
+            },
+            console: match rom_contents[7] & 3 {
+                1 => ConsoleType::VsSystem {
+                    ppu: 0,
+                    hardware: 0,
+                },
+                2 => ConsoleType::Playchoice10,
+                _ => ConsoleType::Nes,
+            },
+            misc_roms: 0,
+            expansion_device: 0,
+        };
 
         let vol = VolatileCartridgeData {
             prg_ram: PersistentStorage::Volatile(prg_ram),
             battery_backup: (rom_contents[6] & 2) != 0,
+            mapper_ram: PersistentStorage::Volatile(mapper_ram),
             mirroring: (rom_contents[6] & 1) != 0,
             mapper: mappernum as u32,
+            submapper: 0,
             chr_ram,
             genie: Vec::new(),
+            disk: Vec::new(),
+            disk_modified: false,
+            nametables: None,
+            nametable_ram: Vec::new(),
         };
 
         let nonvol = NonvolatileCartridgeData {
@@ -408,11 +842,14 @@ impl NesCartridge {
             chr_rom,
             inst_rom,
             prom: None,
+            disk_sides: Vec::new(),
+            misc_rom: Vec::new(),
         };
 
         let rom_data = NesCartridgeData {
             volatile: vol,
             nonvolatile: nonvol,
+            header,
         };
         let mapper = Self::get_mapper(mappernum as u32, &rom_data)?;
 
@@ -442,6 +879,11 @@ impl NesCartridge {
                 .unwrap()
                 .to_string(),
             rom_name: name.to_owned(),
+            save_path: PathBuf::new(),
+            header_correction: None,
+            patches: Vec::new(),
+            nsf: None,
+            ppu_nametable: None,
         })
     }
 
@@ -454,13 +896,9 @@ impl NesCartridge {
         {
             return Err(CartridgeError::InvalidRom);
         }
-        let prg_rom_size = if (rom_contents[9] & 0xF) < 0xF {
-            (rom_contents[4] as usize | ((rom_contents[9] & 0xF) as usize) << 8) * 16384
-        } else {
-            let mult = (rom_contents[4] & 3) as usize * 2 + 1;
-            let exp = 2 << ((rom_contents[4] >> 2) as usize);
-            exp * mult
-        };
+        let prg_rom_size = database::rom_size(rom_contents[4], rom_contents[9] & 0xF, 16384, true)
+            .filter(|s| *s <= rom_contents.len())
+            .ok_or(CartridgeError::RomTooShort)?;
 
         let mut file_offset: usize = 16;
         let trainer = if (rom_contents[6] & (1 << 2)) != 0 {
@@ -486,13 +924,9 @@ impl NesCartridge {
         }
         file_offset += prg_rom_size;
 
-        let chr_rom_size = if (rom_contents[9] & 0xF) < 0xF {
-            (rom_contents[4] as usize | ((rom_contents[9] & 0xF0) as usize) << 4) * 8192
-        } else {
-            let mult = (rom_contents[4] & 3) as usize * 2 + 1;
-            let exp = 2 << ((rom_contents[4] >> 2) as usize);
-            exp * mult
-        };
+        let chr_rom_size = database::rom_size(rom_contents[5], rom_contents[9] >> 4, 8192, true)
+            .filter(|s| *s <= rom_contents.len())
+            .ok_or(CartridgeError::RomTooShort)?;
 
         let mut chr_rom = Vec::with_capacity(chr_rom_size);
 
@@ -506,6 +940,17 @@ impl NesCartridge {
             file_offset += chr_rom_size;
         }
 
+        let header = NesHeader::parse_ines2(rom_contents);
+
+        //the miscellaneous roms take up the rest of the file
+        let misc_rom = if header.misc_roms > 0 {
+            let misc = rom_contents[file_offset.min(rom_contents.len())..].to_vec();
+            file_offset += misc.len();
+            misc
+        } else {
+            Vec::new()
+        };
+
         if file_offset < rom_contents.len() {
             return Err(CartridgeError::RomTooLong);
             //println!("Didn't use the entire rom file, I should report this as a failure");
@@ -513,15 +958,34 @@ impl NesCartridge {
 
         let mappernum = (rom_contents[6] >> 4) as u16
             | (rom_contents[7] & 0xf0) as u16
-            | (rom_contents[8] as u16) << 8;
+            | ((rom_contents[8] & 0xf) as u16) << 8;
+        let mapper_ram = vec![0; Self::mapper_ram_size(mappernum as u32)];
+
+        //volatile and battery backed prg ram share the same storage
+        let ram_size = header.prg_ram_size + header.prg_nvram_size;
+        let mut prg_ram = Vec::with_capacity(ram_size);
+        for _i in 0..ram_size {
+            prg_ram.push(rand::random());
+        }
+        let chr_ram_size = header.chr_ram_size + header.chr_nvram_size;
+        let mut chr_ram = Vec::with_capacity(chr_ram_size);
+        for _i in 0..chr_ram_size {
+            chr_ram.push(rand::random());
+        }
 
         let vol = VolatileCartridgeData {
-            prg_ram: PersistentStorage::Volatile(Vec::new()),
+            prg_ram: PersistentStorage::Volatile(prg_ram),
             battery_backup: (rom_contents[6] & 2) != 0,
+            mapper_ram: PersistentStorage::Volatile(mapper_ram),
             mirroring: (rom_contents[6] & 1) != 0,
             mapper: mappernum as u32,
-            chr_ram: Vec::new(),
+            submapper: rom_contents[8] >> 4,
+            chr_ram,
             genie: Vec::new(),
+            disk: Vec::new(),
+            disk_modified: false,
+            nametables: None,
+            nametable_ram: Vec::new(),
         };
 
         let nonvol = NonvolatileCartridgeData {
@@ -530,11 +994,14 @@ impl NesCartridge {
             chr_rom,
             inst_rom: None,
             prom: None,
+            disk_sides: Vec::new(),
+            misc_rom,
         };
 
         let rom_data = NesCartridgeData {
             nonvolatile: nonvol,
             volatile: vol,
+            header,
         };
 
         let mapper = NesCartridge::get_mapper(mappernum as u32, &rom_data)?;
@@ -556,16 +1023,113 @@ impl NesCartridge {
                 .unwrap()
                 .to_string(),
             rom_name: name.to_owned(),
+            save_path: PathBuf::new(),
+            header_correction: None,
+            patches: Vec::new(),
+            nsf: None,
+            ppu_nametable: None,
         })
     }
 
-    /// Load a cartridge, returning an error or the new cartridge
-    pub fn load_cartridge(name: String, sp: &Path) -> Result<Self, CartridgeError> {
-        let rom_contents = std::fs::read(name.clone());
-        if let Err(e) = rom_contents {
-            return Err(CartridgeError::FsError(e.kind().to_string()));
+    /// Parses a famicom disk system image, applying any changes previously saved for the disk
+    fn load_fds(
+        name: String,
+        rom_contents: &[u8],
+        sp: &Path,
+        save: String,
+    ) -> Result<Self, CartridgeError> {
+        let disk_sides = fds::parse_disk_image(rom_contents).ok_or(CartridgeError::InvalidRom)?;
+
+        let mut current = disk_sides.concat();
+        let mut ppb = sp.to_path_buf();
+        ppb.push(format!("{}.ips", save));
+        if let Ok(patch) = std::fs::read(&ppb) {
+            if let Err(e) = common_emulator::patch::apply_ips(&mut current, &patch) {
+                println!(
+                    "Failed to apply saved disk changes {}: {:?}",
+                    ppb.display(),
+                    e
+                );
+                current = disk_sides.concat();
+            }
+        }
+        current.resize(disk_sides.len() * fds::FDS_SIDE_SIZE, 0);
+        let disk = current
+            .chunks(fds::FDS_SIDE_SIZE)
+            .map(fds::raw_side)
+            .collect();
+
+        let mut prg_ram = Vec::with_capacity(32768);
+        for _i in 0..32768 {
+            prg_ram.push(rand::random());
         }
-        let rom_contents = rom_contents.unwrap();
+        let mut chr_ram = Vec::with_capacity(8192);
+        for _i in 0..8192 {
+            chr_ram.push(rand::random());
+        }
+
+        let vol = VolatileCartridgeData {
+            prg_ram: PersistentStorage::Volatile(prg_ram),
+            battery_backup: false,
+            mapper_ram: PersistentStorage::Volatile(Vec::new()),
+            mirroring: false,
+            mapper: 20,
+            submapper: 0,
+            chr_ram,
+            genie: Vec::new(),
+            disk,
+            disk_modified: false,
+            nametables: None,
+            nametable_ram: Vec::new(),
+        };
+
+        //the bios is loaded separately
+        let nonvol = NonvolatileCartridgeData {
+            trainer: None,
+            prg_rom: Vec::new(),
+            chr_rom: Vec::new(),
+            inst_rom: None,
+            prom: None,
+            disk_sides,
+            misc_rom: Vec::new(),
+        };
+
+        let rom_data = NesCartridgeData {
+            nonvolatile: nonvol,
+            volatile: vol,
+            header: NesHeader {
+                prg_ram_size: 32768,
+                chr_ram_size: 8192,
+                ..Default::default()
+            },
+        };
+
+        let mapper = NesCartridge::get_mapper(20, &rom_data)?;
+
+        let hash = calc_sha256(rom_contents);
+        Ok(Self {
+            data: rom_data,
+            mapper,
+            mappernum: 20,
+            rom_format: RomFormat::Fds,
+            hash,
+            save,
+            rom_name: name.to_owned(),
+            save_path: sp.to_path_buf(),
+            header_correction: None,
+            patches: Vec::new(),
+            nsf: None,
+            ppu_nametable: None,
+        })
+    }
+
+    /// Parses a rom with an ines header, correcting the header from the database when the rom is known
+    fn load_ines(
+        name: String,
+        rom_contents: Vec<u8>,
+        rom_database: Option<&RomDatabase>,
+        header_correction: &mut Option<String>,
+    ) -> Result<Self, CartridgeError> {
         if rom_contents.len() < 16 {
             return Err(CartridgeError::InvalidRom);
         }
@@ -576,7 +1140,17 @@ impl NesCartridge {
         {
             return Err(CartridgeError::InvalidRom);
         }
-        let mut cart = if (rom_contents[7] & 0xC) == 8 {
+        let entry = rom_database
+            .zip(database::rom_data_range(&rom_contents))
+            .and_then(|(db, (start, len))| db.lookup(&rom_contents[start..start + len]));
+        let rom_contents = if let Some(entry) = entry {
+            let (contents, correction) = database::correct_rom(&rom_contents, &entry);
+            *header_correction = correction;
+            contents
+        } else {
+            rom_contents
+        };
+        if (rom_contents[7] & 0xC) == 8 {
             Self::load_ines2(name, &rom_contents)
         } else if (rom_contents[7] & 0xC) == 4 {
             Self::load_obsolete_ines(name, &rom_contents)
@@ -590,13 +1164,259 @@ impl NesCartridge {
         } else {
             //or ines 0.7
             Self::load_obsolete_ines(name, &rom_contents)
+        }
+    }
+
+    /// Parses a unif format rom, converting the name of the board into one of the supported mappers
+    fn load_unif(name: String, rom_contents: &[u8]) -> Result<Self, CartridgeError> {
+        let rom = unif::parse(rom_contents)?;
+        let (mappernum, submapper) = unif::board_mapper(&rom.board)
+            .ok_or(CartridgeError::UnknownBoard(rom.board.clone()))?;
+
+        let mut chr_ram = Vec::new();
+        if rom.chr_rom.is_empty() {
+            for _i in 0..8192 {
+                chr_ram.push(rand::random());
+            }
+        }
+        let prg_ram_size = unif::board_prg_ram_size(&rom.board);
+        let mut prg_ram = Vec::with_capacity(prg_ram_size);
+        for _i in 0..prg_ram_size {
+            prg_ram.push(rand::random());
+        }
+        let mapper_ram = vec![0; Self::mapper_ram_size(mappernum)];
+        let nametable_ram = if rom.nametables == Some(FixedNametables::FourScreen) {
+            vec![0; 2048]
+        } else {
+            Vec::new()
+        };
+
+        let header = NesHeader {
+            prg_ram_size: if rom.battery { 0 } else { prg_ram_size },
+            prg_nvram_size: if rom.battery { prg_ram_size } else { 0 },
+            chr_ram_size: chr_ram.len(),
+            chr_nvram_size: 0,
+            timing: rom.timing,
+            ..Default::default()
+        };
+
+        let vol = VolatileCartridgeData {
+            prg_ram: PersistentStorage::Volatile(prg_ram),
+            battery_backup: rom.battery,
+            mapper_ram: PersistentStorage::Volatile(mapper_ram),
+            mirroring: rom.mirroring.unwrap_or(false),
+            mapper: mappernum,
+            submapper,
+            chr_ram,
+            genie: Vec::new(),
+            disk: Vec::new(),
+            disk_modified: false,
+            nametables: rom.nametables,
+            nametable_ram,
+        };
+
+        let nonvol = NonvolatileCartridgeData {
+            trainer: None,
+            prg_rom: rom.prg_rom,
+            chr_rom: rom.chr_rom,
+            inst_rom: None,
+            prom: None,
+            disk_sides: Vec::new(),
+            misc_rom: Vec::new(),
+        };
+
+        let rom_data = NesCartridgeData {
+            volatile: vol,
+            nonvolatile: nonvol,
+            header,
+        };
+        let mapper = Self::get_mapper(mappernum, &rom_data)?;
+
+        let hash = calc_sha256(rom_contents);
+        Ok(Self {
+            data: rom_data,
+            mapper,
+            mappernum,
+            rom_format: RomFormat::Unif,
+            hash,
+            save: common_emulator::archive::rom_file_name(&name),
+            rom_name: name.to_owned(),
+            save_path: PathBuf::new(),
+            header_correction: None,
+            patches: Vec::new(),
+            nsf: None,
+            ppu_nametable: None,
+        })
+    }
+
+    /// Parses an nsf or nsfe music file, building a cartridge that plays the music
+    fn load_nsf(name: String, rom_contents: &[u8]) -> Result<Self, CartridgeError> {
+        let file = nsf::NsfFile::parse(rom_contents)?;
+        let prg_rom = file.prg_rom()?;
+
+        //the famicom disk system has ram from 0x6000-0xdfff
+        let prg_ram_size = if file.uses_chip(ExpansionAudioChip::Fds) {
+            0xa000
+        } else {
+            8192
+        };
+        let mapper_ram_size = if file.uses_chip(ExpansionAudioChip::Namco163) {
+            128
+        } else {
+            0
+        };
+
+        let vol = VolatileCartridgeData {
+            prg_ram: PersistentStorage::Volatile(vec![0; prg_ram_size]),
+            battery_backup: false,
+            mapper_ram: PersistentStorage::Volatile(vec![0; mapper_ram_size]),
+            mirroring: true,
+            mapper: 31,
+            submapper: 0,
+            chr_ram: vec![0; 8192],
+            genie: Vec::new(),
+            disk: Vec::new(),
+            disk_modified: false,
+            nametables: None,
+            nametable_ram: Vec::new(),
+        };
+
+        let nonvol = NonvolatileCartridgeData {
+            trainer: None,
+            prg_rom,
+            chr_rom: Vec::new(),
+            inst_rom: None,
+            prom: None,
+            disk_sides: Vec::new(),
+            misc_rom: Vec::new(),
+        };
+
+        let rom_data = NesCartridgeData {
+            volatile: vol,
+            nonvolatile: nonvol,
+            header: NesHeader {
+                prg_ram_size,
+                chr_ram_size: 8192,
+                timing: file.timing,
+                ..Default::default()
+            },
+        };
+        let mapper = NsfPlayer::new(&rom_data, &file);
+
+        let hash = calc_sha256(rom_contents);
+        Ok(Self {
+            data: rom_data,
+            mapper,
+            //the bankswitching of nsf files is the same as mapper 31
+            mappernum: 31,
+            rom_format: RomFormat::Nsf,
+            hash,
+            save: common_emulator::archive::rom_file_name(&name),
+            rom_name: name.to_owned(),
+            save_path: PathBuf::new(),
+            header_correction: None,
+            patches: Vec::new(),
+            nsf: Some(file.info),
+            ppu_nametable: None,
+        })
+    }
+
+    /// Load a cartridge, returning an error or the new cartridge. Any patches next to the rom are applied.
+    pub fn load_cartridge(name: String, sp: &Path) -> Result<Self, CartridgeError> {
+        let patches = common_emulator::patch::find_patches(Path::new(&name));
+        Self::load_cartridge_with_patches(name, sp, &patches)
+    }
+
+    /// Load a cartridge from the contents of a rom that has already been read, returning an error or the new cartridge.
+    /// Any patches next to the rom are applied.
+    pub fn load_cartridge_contents(
+        name: String,
+        rom_contents: Vec<u8>,
+        sp: &Path,
+    ) -> Result<Self, CartridgeError> {
+        let patches = common_emulator::patch::find_patches(Path::new(&name));
+        let rom_database = database::external();
+        Self::load_contents_with_patches(name, rom_contents, sp, &patches, rom_database.as_deref())
+    }
+
+    /// Load a cartridge from the contents of a rom that has already been read, correcting the header with the given database
+    /// instead of the one selected by the user. Any patches next to the rom are applied.
+    pub fn load_cartridge_contents_with_database(
+        name: String,
+        rom_contents: Vec<u8>,
+        sp: &Path,
+        rom_database: Option<&RomDatabase>,
+    ) -> Result<Self, CartridgeError> {
+        let patches = common_emulator::patch::find_patches(Path::new(&name));
+        Self::load_contents_with_patches(name, rom_contents, sp, &patches, rom_database)
+    }
+
+    /// Load a cartridge, applying the given list of patches in order before parsing the rom.
+    pub fn load_cartridge_with_patches(
+        name: String,
+        sp: &Path,
+        patches: &[PathBuf],
+    ) -> Result<Self, CartridgeError> {
+        let rom_contents = common_emulator::archive::read_rom(&name)
+            .map_err(|e| CartridgeError::FsError(e.kind().to_string()))?;
+        let rom_database = database::external();
+        Self::load_contents_with_patches(name, rom_contents, sp, patches, rom_database.as_deref())
+    }
+
+    /// Load a cartridge from the contents of a rom, applying the given list of patches in order before parsing the rom.
+    /// The header is corrected when the rom is in the database.
+    fn load_contents_with_patches(
+        name: String,
+        mut rom_contents: Vec<u8>,
+        sp: &Path,
+        patches: &[PathBuf],
+        rom_database: Option<&RomDatabase>,
+    ) -> Result<Self, CartridgeError> {
+        for p in patches {
+            let patch =
+                std::fs::read(p).map_err(|e| CartridgeError::FsError(e.kind().to_string()))?;
+            common_emulator::patch::apply_patch(&mut rom_contents, &patch)?;
+        }
+        //patched roms get their own save files
+        let mut save = common_emulator::archive::rom_file_name(&name);
+        for p in patches {
+            if let Some(n) = p.file_name() {
+                save.push('+');
+                save.push_str(&n.to_string_lossy());
+            }
+        }
+        if fds::is_disk_image(&rom_contents) {
+            let mut cart = Self::load_fds(name, &rom_contents, sp, save);
+            if let Ok(c) = &mut cart {
+                c.patches = patches.to_vec();
+            }
+            return cart;
+        }
+        let original_hash = calc_sha256(&rom_contents);
+        let mut header_correction = None;
+        let mut cart = if rom_contents.starts_with(unif::UNIF_MAGIC) {
+            Self::load_unif(name, &rom_contents)
+        } else if nsf::is_nsf(&rom_contents) {
+            Self::load_nsf(name, &rom_contents)
+        } else {
+            Self::load_ines(name, rom_contents, rom_database, &mut header_correction)
         };
 
         if let Ok(c) = &mut cart {
+            c.save_path = sp.to_path_buf();
+            c.hash = original_hash;
+            c.header_correction = header_correction;
+            c.save = save;
+            c.patches = patches.to_vec();
             let mut pb: PathBuf = sp.to_path_buf();
+            let mut mpb = pb.clone();
             pb.push(format!("{}.prgram", c.save));
+            mpb.push(format!("{}.mapperram", c.save));
             if c.data.volatile.battery_backup {
                 c.data.volatile.prg_ram.convert_to_nonvolatile(pb);
+                if !c.data.volatile.mapper_ram.is_empty() {
+                    c.data.volatile.mapper_ram.convert_to_nonvolatile(mpb);
+                }
             }
         }
 
@@ -650,25 +1470,80 @@ impl NesCartridge {
         self.mapper.memory_cycle_nop();
     }
 
+    /// Apply the nametables fixed by the board to a ppu address.
+    /// Returns None when the mapper decides, otherwise A10 for the console vram or the index into the cartridge nametable ram.
+    fn fixed_nametable(&self, addr: u16) -> Option<(bool, Option<usize>)> {
+        if !(0x2000..=0x3eff).contains(&addr) {
+            return None;
+        }
+        match self.data.volatile.nametables? {
+            FixedNametables::SingleScreenA => Some((false, None)),
+            FixedNametables::SingleScreenB => Some((true, None)),
+            FixedNametables::FourScreen => {
+                if (addr & 0x800) == 0 {
+                    Some(((addr & 0x400) != 0, None))
+                } else {
+                    Some((false, Some((addr & 0x7ff) as usize)))
+                }
+            }
+        }
+    }
+
     /// Perform a peek on ppu memory
     pub fn ppu_peek_1(&self, addr: u16) -> (bool, bool, Option<u8>) {
-        self.mapper.ppu_peek_address(addr, &self.data)
+        let (a10, cs, data) = self.mapper.ppu_peek_address(addr, &self.data);
+        let ram = &self.data.volatile.nametable_ram;
+        match self.fixed_nametable(addr) {
+            Some((a10, None)) => (a10, false, data),
+            Some((_, Some(i))) => (false, true, ram.get(i).copied()),
+            None => (a10, cs, data),
+        }
+    }
+
+    /// Returns true when the console vram is selected for an address in the pattern tables, 0x0000-0x1fff
+    pub fn ppu_pattern_vram(&self, addr: u16) -> bool {
+        addr < 0x2000 && self.mapper.ppu_pattern_vram(addr)
+    }
+
+    /// Returns true when the mapper needs the ppu to perform the exact memory fetches of the real ppu
+    pub fn exact_ppu_fetches(&self) -> bool {
+        self.mapper.exact_ppu_fetches()
     }
 
     /// Run a ppu address cycle
     #[must_use]
     pub fn ppu_cycle_1(&mut self, addr: u16) -> (bool, bool) {
-        self.mapper.ppu_memory_cycle_address(addr)
+        let (a10, cs) = self.mapper.ppu_memory_cycle_address(addr);
+        self.ppu_nametable = None;
+        match self.fixed_nametable(addr) {
+            Some((a10, None)) => (a10, false),
+            Some((_, Some(i))) => {
+                self.ppu_nametable = Some(i);
+                (false, true)
+            }
+            None => (a10, cs),
+        }
     }
 
     /// Run a ppu write cyle
     pub fn ppu_cycle_write(&mut self, data: u8) {
+        if let Some(i) = self.ppu_nametable {
+            if let Some(d) = self.data.volatile.nametable_ram.get_mut(i) {
+                *d = data;
+            }
+            return;
+        }
         self.mapper.ppu_memory_cycle_write(&mut self.data, data);
     }
 
     /// Run a ppu read cycle
     pub fn ppu_cycle_read(&mut self) -> u8 {
-        if let Some(a) = self.mapper.ppu_memory_cycle_read(&mut self.data) {
+        let a = self.mapper.ppu_memory_cycle_read(&mut self.data);
+        let a = match self.ppu_nametable {
+            Some(i) => self.data.volatile.nametable_ram.get(i).copied(),
+            None => a,
+        };
+        if let Some(a) = a {
             a
         } else {
             //TODO implement open bus behavior
@@ -0,0 +1,62 @@
+//! Expansion audio chips that can be found on cartridges.
+
+pub mod fds;
+pub mod mmc5;
+pub mod namco163;
+pub mod sunsoft5b;
+pub mod vrc6;
+pub mod vrc7;
+
+use strum::{EnumCount, IntoEnumIterator};
+
+/// The expansion audio chips that a cartridge can contain
+#[derive(
+    serde::Serialize,
+    serde::Deserialize,
+    Copy,
+    Clone,
+    Debug,
+    PartialEq,
+    strum::EnumIter,
+    strum::EnumCount,
+    strum::Display,
+)]
+pub enum ExpansionAudioChip {
+    /// The nintendo mmc5, two pulse channels and a pcm channel
+    Mmc5,
+    /// The konami vrc6, two pulse channels and a sawtooth channel
+    Vrc6,
+    /// The konami vrc7, six fm synthesis channels
+    Vrc7,
+    /// The namco 163, up to eight wavetable channels
+    Namco163,
+    /// The sunsoft 5b, three square channels with envelope and noise
+    Sunsoft5b,
+    /// The famicom disk system, a wavetable channel with modulation
+    Fds,
+}
+
+/// A single sample from every expansion audio chip of a cartridge.
+/// Each chip output is normalized to the full scale range of that chip, 0.0 to 1.0.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ExpansionAudio {
+    /// The output for each chip, indexed by ExpansionAudioChip. None for chips that the cartridge does not have.
+    samples: [Option<f32>; ExpansionAudioChip::COUNT],
+}
+
+impl ExpansionAudio {
+    /// Set the output of a chip
+    pub fn set(&mut self, chip: ExpansionAudioChip, sample: f32) {
+        self.samples[chip as usize] = Some(sample);
+    }
+
+    /// Retrieve the output of a chip
+    pub fn get(&self, chip: ExpansionAudioChip) -> f32 {
+        self.samples[chip as usize].unwrap_or(0.0)
+    }
+
+    /// Iterate over the chips that the cartridge has
+    pub fn chips(&self) -> impl Iterator<Item = ExpansionAudioChip> + '_ {
+        ExpansionAudioChip::iter().filter(|chip| self.samples[*chip as usize].is_some())
+    }
+}
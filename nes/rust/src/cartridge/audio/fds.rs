@@ -0,0 +1,273 @@
+//! The expansion audio of the famicom disk system, a single wavetable channel with a frequency modulation unit.
+
+/// A volume or modulation envelope of the fds audio
+#[non_exhaustive]
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct FdsEnvelope {
+    /// The control register, direct mode, direction, and speed
+    control: u8,
+    /// The current gain of the envelope
+    gain: u8,
+    /// The counter for the envelope period
+    counter: u32,
+}
+
+impl FdsEnvelope {
+    /// Create a new envelope
+    fn new() -> Self {
+        Self {
+            control: 0x80,
+            gain: 0,
+            counter: 0,
+        }
+    }
+
+    /// Write the control register, in direct mode the gain is set immediately
+    fn write(&mut self, data: u8) {
+        self.control = data;
+        self.counter = 0;
+        if (data & 0x80) != 0 {
+            self.gain = data & 0x3f;
+        }
+    }
+
+    /// Clock the envelope for a single cpu cycle, master is the envelope speed multiplier
+    fn clock(&mut self, master: u8) {
+        if (self.control & 0x80) != 0 || master == 0 {
+            return;
+        }
+        self.counter += 1;
+        let period = 8 * ((self.control & 0x3f) as u32 + 1) * master as u32;
+        if self.counter >= period {
+            self.counter = 0;
+            if (self.control & 0x40) != 0 {
+                if self.gain < 32 {
+                    self.gain += 1;
+                }
+            } else if self.gain > 0 {
+                self.gain -= 1;
+            }
+        }
+    }
+}
+
+/// The complete audio unit of the famicom disk system
+#[non_exhaustive]
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct FdsAudio {
+    /// The 64 entry wavetable, 6 bits per entry
+    wave: Vec<u8>,
+    /// The 64 entry modulation table, 3 bits per entry
+    modulation_table: Vec<u8>,
+    /// The volume envelope, 0x4080
+    volume: FdsEnvelope,
+    /// The modulation envelope, 0x4084
+    modulation: FdsEnvelope,
+    /// The 12 bit frequency of the wave, 0x4082 and 0x4083
+    wave_frequency: u16,
+    /// True when the wave is halted, 0x4083 bit 7
+    wave_halt: bool,
+    /// True when the envelopes are halted, 0x4083 bit 6
+    envelope_halt: bool,
+    /// The accumulator for the wave position
+    wave_accumulator: u32,
+    /// The 12 bit frequency of the modulation unit, 0x4086 and 0x4087
+    modulation_frequency: u16,
+    /// True when the modulation unit is halted, 0x4087 bit 7
+    modulation_halt: bool,
+    /// The accumulator for the modulation table position
+    modulation_accumulator: u32,
+    /// The position in the modulation table
+    modulation_position: u8,
+    /// The 7 bit signed modulation counter, 0x4085
+    modulation_counter: i8,
+    /// True when the wavetable can be written, 0x4089 bit 7
+    wave_write: bool,
+    /// The master volume, 0x4089 bits 0-1
+    master_volume: u8,
+    /// The envelope speed multiplier, 0x408a
+    envelope_speed: u8,
+    /// The most recent output of the wave
+    output: u8,
+}
+
+impl FdsAudio {
+    /// Create a new audio unit
+    pub fn new() -> Self {
+        Self {
+            wave: vec![0; 64],
+            modulation_table: vec![0; 64],
+            volume: FdsEnvelope::new(),
+            modulation: FdsEnvelope::new(),
+            wave_frequency: 0,
+            wave_halt: true,
+            envelope_halt: true,
+            wave_accumulator: 0,
+            modulation_frequency: 0,
+            modulation_halt: true,
+            modulation_accumulator: 0,
+            modulation_position: 0,
+            modulation_counter: 0,
+            wave_write: false,
+            master_volume: 0,
+            envelope_speed: 0xe8,
+            output: 0,
+        }
+    }
+
+    /// Perform a read of the audio registers, without side effects
+    pub fn read(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x4040..=0x407f => Some(self.wave[(addr & 0x3f) as usize] | 0x40),
+            0x4090 => Some(self.volume.gain | 0x40),
+            0x4092 => Some(self.modulation.gain | 0x40),
+            _ => None,
+        }
+    }
+
+    /// Write to the audio registers
+    pub fn write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x4040..=0x407f => {
+                if self.wave_write {
+                    self.wave[(addr & 0x3f) as usize] = data & 0x3f;
+                }
+            }
+            0x4080 => self.volume.write(data),
+            0x4082 => {
+                self.wave_frequency = (self.wave_frequency & 0xf00) | data as u16;
+            }
+            0x4083 => {
+                self.wave_frequency = (self.wave_frequency & 0xff) | ((data & 0xf) as u16) << 8;
+                self.wave_halt = (data & 0x80) != 0;
+                self.envelope_halt = (data & 0x40) != 0;
+                if self.wave_halt {
+                    self.wave_accumulator = 0;
+                }
+            }
+            0x4084 => self.modulation.write(data),
+            0x4085 => {
+                //sign extend the 7 bit value
+                self.modulation_counter = ((data & 0x7f) << 1) as i8 >> 1;
+            }
+            0x4086 => {
+                self.modulation_frequency = (self.modulation_frequency & 0xf00) | data as u16;
+            }
+            0x4087 => {
+                self.modulation_frequency =
+                    (self.modulation_frequency & 0xff) | ((data & 0xf) as u16) << 8;
+                self.modulation_halt = (data & 0x80) != 0;
+                if self.modulation_halt {
+                    self.modulation_accumulator = 0;
+                }
+            }
+            0x4088 => {
+                //the table can only be written while the modulation unit is halted, each write fills two entries
+                if self.modulation_halt {
+                    let pos = self.modulation_position as usize;
+                    self.modulation_table[pos] = data & 7;
+                    self.modulation_table[(pos + 1) & 0x3f] = data & 7;
+                    self.modulation_position = (self.modulation_position + 2) & 0x3f;
+                }
+            }
+            0x4089 => {
+                self.wave_write = (data & 0x80) != 0;
+                self.master_volume = data & 3;
+            }
+            0x408a => {
+                self.envelope_speed = data;
+            }
+            _ => {}
+        }
+    }
+
+    /// Calculate the pitch of the wave after frequency modulation
+    fn modulated_pitch(&self) -> i32 {
+        let pitch = self.wave_frequency as i32;
+        let counter = self.modulation_counter as i32;
+        let mut temp = counter * self.modulation.gain as i32;
+        let remainder = temp & 0xf;
+        temp >>= 4;
+        if remainder > 0 && (temp & 0x80) == 0 {
+            if counter < 0 {
+                temp -= 1;
+            } else {
+                temp += 2;
+            }
+        }
+        if temp >= 192 {
+            temp -= 256;
+        } else if temp < -64 {
+            temp += 256;
+        }
+        temp *= pitch;
+        let remainder = temp & 0x3f;
+        temp >>= 6;
+        if remainder >= 32 {
+            temp += 1;
+        }
+        (pitch + temp).max(0)
+    }
+
+    /// Clock the modulation unit for a single cpu cycle
+    fn clock_modulation(&mut self) {
+        if self.modulation_halt || self.modulation_frequency == 0 {
+            return;
+        }
+        self.modulation_accumulator += self.modulation_frequency as u32;
+        if self.modulation_accumulator < 0x10000 {
+            return;
+        }
+        self.modulation_accumulator &= 0xffff;
+        let step = self.modulation_table[self.modulation_position as usize];
+        self.modulation_position = (self.modulation_position + 1) & 0x3f;
+        let adjust = match step {
+            0 => 0,
+            1 => 1,
+            2 => 2,
+            3 => 4,
+            5 => -4,
+            6 => -2,
+            7 => -1,
+            _ => {
+                self.modulation_counter = 0;
+                return;
+            }
+        };
+        //the counter wraps as a 7 bit signed value
+        let counter = (self.modulation_counter as i32 + adjust) & 0x7f;
+        self.modulation_counter = if counter >= 64 {
+            (counter - 128) as i8
+        } else {
+            counter as i8
+        };
+    }
+
+    /// Clock the audio unit for a single cpu cycle
+    pub fn cycle(&mut self) {
+        if !self.wave_halt && !self.envelope_halt {
+            self.volume.clock(self.envelope_speed);
+            self.modulation.clock(self.envelope_speed);
+        }
+        self.clock_modulation();
+        if !self.wave_halt {
+            let pitch = if self.modulation_halt {
+                self.wave_frequency as u32
+            } else {
+                self.modulated_pitch() as u32
+            };
+            self.wave_accumulator = (self.wave_accumulator + pitch) & 0x3fffff;
+        }
+        //the output holds while the wavetable is writable
+        if !self.wave_write {
+            self.output = self.wave[(self.wave_accumulator >> 16) as usize];
+        }
+    }
+
+    /// Returns the audio output of the fds
+    pub fn audio(&self) -> f32 {
+        let gain = self.volume.gain.min(32) as f32;
+        let master = [1.0, 2.0 / 3.0, 2.0 / 4.0, 2.0 / 5.0][self.master_volume as usize];
+        self.output as f32 * gain * master / (63.0 * 32.0)
+    }
+}
@@ -0,0 +1,156 @@
+//! The expansion audio of the nintendo mmc5, two pulse channels and a pcm channel.
+
+use crate::apu::square::ApuSquareChannel;
+use crate::apu::ApuSweepAddition;
+
+/// The number of cpu cycles between clocks of the length counters and envelopes, about 240 hz.
+const MMC5_FRAME_PERIOD: u16 = 7457;
+
+/// The complete audio unit of the mmc5
+#[non_exhaustive]
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct Mmc5Audio {
+    /// The two pulse channels, these work like the apu pulse channels without the sweep unit
+    pulses: [ApuSquareChannel; 2],
+    /// The channel enable register at 0x5015
+    status: u8,
+    /// The pcm control register at 0x5010
+    pcm_control: u8,
+    /// The current output level of the pcm channel
+    pcm: u8,
+    /// The pcm irq flag, set when a zero is read in pcm read mode
+    pcm_irq: bool,
+    /// The divider for clocking length counters and envelopes
+    frame_counter: u16,
+    /// Used to clock the pulse channels every other cpu cycle
+    clock: bool,
+}
+
+impl Mmc5Audio {
+    /// Create a new audio unit
+    pub fn new() -> Self {
+        Self {
+            pulses: [
+                ApuSquareChannel::new(ApuSweepAddition::TwosComplement),
+                ApuSquareChannel::new(ApuSweepAddition::TwosComplement),
+            ],
+            status: 0,
+            pcm_control: 0,
+            pcm: 0,
+            pcm_irq: false,
+            frame_counter: 0,
+            clock: false,
+        }
+    }
+
+    /// Returns true when the pcm channel is in read mode
+    fn pcm_read_mode(&self) -> bool {
+        (self.pcm_control & 1) != 0
+    }
+
+    /// Returns the irq output of the pcm channel
+    pub fn irq(&self) -> bool {
+        self.pcm_irq && (self.pcm_control & 0x80) != 0
+    }
+
+    /// Dump the value of an audio register without side effects
+    pub fn dump(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x5010 => Some((self.irq() as u8) << 7 | (self.pcm_control & 1)),
+            0x5015 => Some(
+                self.pulses[0].length.running() as u8
+                    | (self.pulses[1].length.running() as u8) << 1,
+            ),
+            _ => None,
+        }
+    }
+
+    /// Read an audio register, reading 0x5010 acknowledges the pcm irq
+    pub fn read(&mut self, addr: u16) -> Option<u8> {
+        let r = self.dump(addr);
+        if addr == 0x5010 {
+            self.pcm_irq = false;
+        }
+        r
+    }
+
+    /// Monitors cpu reads of 0x8000-0xbfff, which feed the pcm channel in read mode
+    pub fn pcm_read(&mut self, data: u8) {
+        if self.pcm_read_mode() {
+            if data == 0 {
+                self.pcm_irq = true;
+            } else {
+                self.pcm = data;
+            }
+        }
+    }
+
+    /// Write to one of the audio registers, 0x5000 - 0x5015
+    pub fn write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x5000..=0x5007 => {
+                let i = ((addr >> 2) & 1) as usize;
+                let reg = (addr & 3) as usize;
+                let pulse = &mut self.pulses[i];
+                match reg {
+                    0 => {
+                        pulse.registers[0] = data;
+                        pulse.length.set_halt((data & 0x20) != 0);
+                    }
+                    //there is no sweep unit
+                    1 => {}
+                    2 => pulse.registers[2] = data,
+                    _ => {
+                        pulse.registers[3] = data;
+                        if pulse.length_enabled {
+                            pulse.length.set_length(data >> 3);
+                        }
+                        pulse.envelope.restart();
+                    }
+                }
+            }
+            0x5010 => {
+                self.pcm_control = data;
+            }
+            0x5011 => {
+                //writes of zero are ignored
+                if !self.pcm_read_mode() && data != 0 {
+                    self.pcm = data;
+                }
+            }
+            0x5015 => {
+                self.status = data;
+                for (i, pulse) in self.pulses.iter_mut().enumerate() {
+                    pulse.length_enabled = (data & (1 << i)) != 0;
+                    if !pulse.length_enabled {
+                        pulse.length.stop();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Clock the audio unit for a single cpu cycle
+    pub fn cycle(&mut self) {
+        if self.clock {
+            self.pulses[0].cycle();
+            self.pulses[1].cycle();
+        }
+        self.clock ^= true;
+        self.frame_counter += 1;
+        if self.frame_counter >= MMC5_FRAME_PERIOD {
+            self.frame_counter = 0;
+            for pulse in &mut self.pulses {
+                pulse.envelope_clock();
+                pulse.length.clock();
+            }
+        }
+    }
+
+    /// Returns the audio output of the mmc5, the 8 bit pcm channel is mixed at half level
+    pub fn audio(&self) -> f32 {
+        let pulses = (self.pulses[0].audio() + self.pulses[1].audio()) * 255.0;
+        (pulses + self.pcm as f32 / 2.0) / 157.5
+    }
+}
@@ -0,0 +1,93 @@
+//! The expansion audio of the namco 163, up to eight wavetable channels stored in the internal sound ram.
+
+use common_emulator::storage::PersistentStorage;
+
+/// The number of cpu cycles it takes to update a single channel
+const NAMCO163_CHANNEL_CYCLES: u8 = 15;
+
+/// The complete audio unit of the namco 163.
+/// The channel registers live in the upper part of the sound ram, channel 7 is at 0x78 and channel 0 is at 0x40.
+#[non_exhaustive]
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct Namco163Audio {
+    /// The output of the channel that was updated most recently, centered on zero
+    output: i8,
+    /// The channel that gets updated next
+    channel: u8,
+    /// The counter for updating channels
+    counter: u8,
+    /// True when the sound is disabled
+    disabled: bool,
+}
+
+impl Namco163Audio {
+    /// Create a new audio unit
+    pub fn new() -> Self {
+        Self {
+            output: 0,
+            channel: 7,
+            counter: 0,
+            disabled: false,
+        }
+    }
+
+    /// Enable or disable the sound output
+    pub fn set_disabled(&mut self, d: bool) {
+        self.disabled = d;
+    }
+
+    /// Update a single channel, advancing its phase and fetching the next sample from the wavetable
+    fn update_channel(&mut self, ram: &mut PersistentStorage, channel: u8) {
+        let base = 0x40 + channel as usize * 8;
+        let freq =
+            ram[base] as u32 | (ram[base + 2] as u32) << 8 | ((ram[base + 4] & 3) as u32) << 16;
+        let phase =
+            ram[base + 1] as u32 | (ram[base + 3] as u32) << 8 | (ram[base + 5] as u32) << 16;
+        let length = 256 - (ram[base + 4] & 0xfc) as u32;
+        let phase = (phase + freq) % (length << 16);
+        ram[base + 1] = phase as u8;
+        ram[base + 3] = (phase >> 8) as u8;
+        ram[base + 5] = (phase >> 16) as u8;
+
+        let index = ((phase >> 16) + ram[base + 6] as u32) & 0xff;
+        let byte = ram[(index >> 1) as usize];
+        let sample = if (index & 1) != 0 {
+            byte >> 4
+        } else {
+            byte & 0xf
+        };
+        self.output = (sample as i8 - 8) * (ram[base + 7] & 0xf) as i8;
+    }
+
+    /// Clock the audio unit for a single cpu cycle, the channels are updated one at a time
+    pub fn cycle(&mut self, ram: &mut PersistentStorage) {
+        if self.disabled || ram.len() < 128 {
+            return;
+        }
+        self.counter += 1;
+        if self.counter < NAMCO163_CHANNEL_CYCLES {
+            return;
+        }
+        self.counter = 0;
+        let active = ((ram[0x7f] >> 4) & 7) + 1;
+        let first = 8 - active;
+        if self.channel < first || self.channel > 7 {
+            self.channel = 7;
+        }
+        self.update_channel(ram, self.channel);
+        self.channel = if self.channel == first {
+            7
+        } else {
+            self.channel - 1
+        };
+    }
+
+    /// Returns the audio output of the namco 163, silence is at the middle of the range.
+    /// Like the real chip, only the channel that was updated most recently is output, so the channels take turns for 15 cycles each.
+    pub fn audio(&self) -> f32 {
+        if self.disabled {
+            return 0.5;
+        }
+        (self.output as f32 + 128.0) / 255.0
+    }
+}
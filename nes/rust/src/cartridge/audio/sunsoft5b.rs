@@ -0,0 +1,223 @@
+//! The expansion audio of the sunsoft 5b, a variant of the ay-3-8910 with three square channels, noise, and an envelope.
+
+/// The envelope generator of the 5b, shared by all three channels
+#[non_exhaustive]
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct Sunsoft5bEnvelope {
+    /// The counter based on the envelope period
+    counter: u16,
+    /// The current step of the envelope, 0-31
+    step: u8,
+    /// True when the envelope is counting up
+    attack: bool,
+    /// True when the envelope has finished and is holding a value
+    holding: bool,
+    /// The value held once the envelope finishes
+    held: u8,
+}
+
+impl Sunsoft5bEnvelope {
+    /// Create a new envelope
+    fn new() -> Self {
+        Self {
+            counter: 0,
+            step: 0,
+            attack: false,
+            holding: true,
+            held: 0,
+        }
+    }
+
+    /// Restart the envelope with a new shape
+    fn restart(&mut self, shape: u8) {
+        self.counter = 0;
+        self.step = 0;
+        self.attack = (shape & 4) != 0;
+        self.holding = false;
+    }
+
+    /// Advance the envelope by a single step
+    fn clock(&mut self, shape: u8) {
+        if self.holding {
+            return;
+        }
+        self.step += 1;
+        if self.step > 31 {
+            let continue_flag = (shape & 8) != 0;
+            let alternate = (shape & 2) != 0;
+            let hold = (shape & 1) != 0;
+            if !continue_flag {
+                self.holding = true;
+                self.held = 0;
+            } else if hold {
+                if alternate {
+                    self.attack = !self.attack;
+                }
+                self.holding = true;
+                self.held = if self.attack { 31 } else { 0 };
+            } else {
+                self.step = 0;
+                if alternate {
+                    self.attack = !self.attack;
+                }
+            }
+        }
+    }
+
+    /// The current output level of the envelope, 0-31
+    fn level(&self) -> u8 {
+        if self.holding {
+            self.held
+        } else if self.attack {
+            self.step
+        } else {
+            31 - self.step
+        }
+    }
+}
+
+/// The complete audio unit of the sunsoft 5b
+#[non_exhaustive]
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct Sunsoft5bAudio {
+    /// The selected register for writes
+    select: u8,
+    /// The sixteen internal registers
+    registers: [u8; 16],
+    /// The counters for the tone channels
+    tone_counters: [u16; 3],
+    /// The output of the tone channels
+    tone_outputs: [bool; 3],
+    /// The counter for the noise generator
+    noise_counter: u8,
+    /// The shift register of the noise generator
+    noise: u32,
+    /// The envelope generator
+    envelope: Sunsoft5bEnvelope,
+    /// Divides the cpu clock down to the clock for the tone channels and envelope
+    prescaler: u8,
+    /// Used to clock the noise at half the rate of the tone channels
+    noise_clock: bool,
+}
+
+impl Sunsoft5bAudio {
+    /// Create a new audio unit
+    pub fn new() -> Self {
+        Self {
+            select: 0,
+            registers: [0; 16],
+            tone_counters: [0; 3],
+            tone_outputs: [false; 3],
+            noise_counter: 0,
+            noise: 1,
+            envelope: Sunsoft5bEnvelope::new(),
+            prescaler: 0,
+            noise_clock: false,
+        }
+    }
+
+    /// Write the register select port, 0xc000
+    pub fn write_select(&mut self, data: u8) {
+        self.select = data;
+    }
+
+    /// Write to the selected register, 0xe000
+    pub fn write_data(&mut self, data: u8) {
+        if (self.select & 0xf0) != 0 {
+            return;
+        }
+        let reg = (self.select & 0xf) as usize;
+        self.registers[reg] = data;
+        if reg == 0xd {
+            self.envelope.restart(data);
+        }
+    }
+
+    /// Retrieve the register select and the internal registers
+    pub fn registers(&self) -> (u8, &[u8; 16]) {
+        (self.select, &self.registers)
+    }
+
+    /// The period of a tone channel
+    fn tone_period(&self, channel: usize) -> u16 {
+        let p = self.registers[channel * 2] as u16
+            | ((self.registers[channel * 2 + 1] & 0xf) as u16) << 8;
+        p.max(1)
+    }
+
+    /// The period of the envelope
+    fn envelope_period(&self) -> u16 {
+        (self.registers[0xb] as u16 | (self.registers[0xc] as u16) << 8).max(1)
+    }
+
+    /// Clock the audio unit for a single cpu cycle
+    pub fn cycle(&mut self) {
+        self.prescaler += 1;
+        if self.prescaler < 16 {
+            return;
+        }
+        self.prescaler = 0;
+        let periods = [
+            self.tone_period(0),
+            self.tone_period(1),
+            self.tone_period(2),
+        ];
+        let tones = self
+            .tone_counters
+            .iter_mut()
+            .zip(self.tone_outputs.iter_mut());
+        for ((counter, output), period) in tones.zip(periods) {
+            *counter += 1;
+            if *counter >= period {
+                *counter = 0;
+                *output ^= true;
+            }
+        }
+        self.noise_clock ^= true;
+        if self.noise_clock {
+            self.noise_counter += 1;
+            if self.noise_counter >= (self.registers[6] & 0x1f).max(1) {
+                self.noise_counter = 0;
+                let bit = (self.noise ^ (self.noise >> 3)) & 1;
+                self.noise = (self.noise >> 1) | (bit << 16);
+            }
+        }
+        self.envelope.counter += 1;
+        if self.envelope.counter >= self.envelope_period() {
+            self.envelope.counter = 0;
+            self.envelope.clock(self.registers[0xd]);
+        }
+    }
+
+    /// Convert a 5 bit level into an output, each step is 1.5 db
+    fn level(index: u8) -> f32 {
+        if index == 0 {
+            0.0
+        } else {
+            10.0f32.powf((index as f32 - 31.0) * 1.5 / 20.0)
+        }
+    }
+
+    /// Returns the audio output of the 5b
+    pub fn audio(&self) -> f32 {
+        let mixer = self.registers[7];
+        let noise = (self.noise & 1) != 0;
+        let mut sum = 0.0;
+        for (i, tone) in self.tone_outputs.iter().enumerate() {
+            let tone_on = *tone || (mixer & (1 << i)) != 0;
+            let noise_on = noise || (mixer & (8 << i)) != 0;
+            if tone_on && noise_on {
+                let volume = self.registers[8 + i];
+                let index = if (volume & 0x10) != 0 {
+                    self.envelope.level()
+                } else if (volume & 0xf) != 0 {
+                    (volume & 0xf) * 2 + 1
+                } else {
+                    0
+                };
+                sum += Self::level(index);
+            }
+        }
+        sum / 3.0
+    }
+}
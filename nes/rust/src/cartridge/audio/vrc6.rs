@@ -0,0 +1,198 @@
+//! The expansion audio of the konami vrc6, two pulse channels and a sawtooth channel.
+
+/// A pulse channel of the vrc6
+#[non_exhaustive]
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct Vrc6Pulse {
+    /// The channel registers, mode/duty/volume, period low, enable/period high
+    registers: [u8; 3],
+    /// The counter based on the period of the channel
+    freq_counter: u16,
+    /// The current step of the duty cycle, counts down from 15
+    duty_counter: u8,
+}
+
+impl Vrc6Pulse {
+    /// Create a new pulse channel
+    fn new() -> Self {
+        Self {
+            registers: [0; 3],
+            freq_counter: 0,
+            duty_counter: 15,
+        }
+    }
+
+    /// Returns true when the channel is enabled
+    fn enabled(&self) -> bool {
+        (self.registers[2] & 0x80) != 0
+    }
+
+    /// Calculates the period of the channel from the registers
+    fn period(&self) -> u16 {
+        self.registers[1] as u16 | ((self.registers[2] & 0xF) as u16) << 8
+    }
+
+    /// Write to one of the registers of the channel
+    fn write(&mut self, reg: u16, data: u8) {
+        self.registers[reg as usize] = data;
+        if reg == 2 && !self.enabled() {
+            self.duty_counter = 15;
+        }
+    }
+
+    /// Clock the channel, with the period shift from the frequency control register
+    fn cycle(&mut self, shift: u8) {
+        if !self.enabled() {
+            return;
+        }
+        if self.freq_counter > 0 {
+            self.freq_counter -= 1;
+        } else {
+            self.freq_counter = self.period() >> shift;
+            self.duty_counter = self.duty_counter.wrapping_sub(1) & 0xF;
+        }
+    }
+
+    /// Return the output level of the channel, 0-15
+    fn audio(&self) -> u8 {
+        let duty = (self.registers[0] >> 4) & 7;
+        let ignore_duty = (self.registers[0] & 0x80) != 0;
+        if self.enabled() && (ignore_duty || self.duty_counter <= duty) {
+            self.registers[0] & 0xF
+        } else {
+            0
+        }
+    }
+}
+
+/// The sawtooth channel of the vrc6
+#[non_exhaustive]
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct Vrc6Saw {
+    /// The channel registers, accumulator rate, period low, enable/period high
+    registers: [u8; 3],
+    /// The counter based on the period of the channel
+    freq_counter: u16,
+    /// Counts the clocks of the period divider, the accumulator is reset every 14 clocks
+    step: u8,
+    /// The accumulator, the upper 5 bits are the output
+    accumulator: u8,
+}
+
+impl Vrc6Saw {
+    /// Create a new sawtooth channel
+    fn new() -> Self {
+        Self {
+            registers: [0; 3],
+            freq_counter: 0,
+            step: 0,
+            accumulator: 0,
+        }
+    }
+
+    /// Returns true when the channel is enabled
+    fn enabled(&self) -> bool {
+        (self.registers[2] & 0x80) != 0
+    }
+
+    /// Calculates the period of the channel from the registers
+    fn period(&self) -> u16 {
+        self.registers[1] as u16 | ((self.registers[2] & 0xF) as u16) << 8
+    }
+
+    /// Write to one of the registers of the channel
+    fn write(&mut self, reg: u16, data: u8) {
+        self.registers[reg as usize] = data;
+        if reg == 2 && !self.enabled() {
+            self.accumulator = 0;
+            self.step = 0;
+        }
+    }
+
+    /// Clock the channel, with the period shift from the frequency control register
+    fn cycle(&mut self, shift: u8) {
+        if !self.enabled() {
+            return;
+        }
+        if self.freq_counter > 0 {
+            self.freq_counter -= 1;
+        } else {
+            self.freq_counter = self.period() >> shift;
+            self.step += 1;
+            if self.step == 14 {
+                self.step = 0;
+                self.accumulator = 0;
+            } else if (self.step & 1) == 0 {
+                self.accumulator = self.accumulator.wrapping_add(self.registers[0] & 0x3F);
+            }
+        }
+    }
+
+    /// Return the output level of the channel, 0-31
+    fn audio(&self) -> u8 {
+        self.accumulator >> 3
+    }
+}
+
+/// The complete audio unit of the vrc6
+#[non_exhaustive]
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct Vrc6Audio {
+    /// The two pulse channels
+    pulses: [Vrc6Pulse; 2],
+    /// The sawtooth channel
+    saw: Vrc6Saw,
+    /// The frequency control register, halt and period shifting
+    freq_control: u8,
+}
+
+impl Vrc6Audio {
+    /// Create a new audio unit
+    pub fn new() -> Self {
+        Self {
+            pulses: [Vrc6Pulse::new(), Vrc6Pulse::new()],
+            saw: Vrc6Saw::new(),
+            freq_control: 0,
+        }
+    }
+
+    /// Write to an audio register, the address must already have the a0/a1 lines corrected for the board.
+    pub fn write(&mut self, addr: u16, data: u8) {
+        let reg = addr & 3;
+        match (addr & 0xF000, reg) {
+            (0x9000, 3) => {
+                self.freq_control = data & 7;
+            }
+            (0x9000, _) => self.pulses[0].write(reg, data),
+            (0xa000, 3) => {}
+            (0xa000, _) => self.pulses[1].write(reg, data),
+            (0xb000, 3) => {}
+            (0xb000, _) => self.saw.write(reg, data),
+            _ => {}
+        }
+    }
+
+    /// Clock the audio unit, once per cpu cycle
+    pub fn cycle(&mut self) {
+        if (self.freq_control & 1) != 0 {
+            return;
+        }
+        let shift = if (self.freq_control & 4) != 0 {
+            8
+        } else if (self.freq_control & 2) != 0 {
+            4
+        } else {
+            0
+        };
+        self.pulses[0].cycle(shift);
+        self.pulses[1].cycle(shift);
+        self.saw.cycle(shift);
+    }
+
+    /// Return the audio sample for the unit, normalized to the full scale output
+    pub fn audio(&self) -> f32 {
+        let sum =
+            self.pulses[0].audio() as u16 + self.pulses[1].audio() as u16 + self.saw.audio() as u16;
+        sum as f32 / 61.0
+    }
+}
@@ -0,0 +1,503 @@
+//! The expansion audio of the konami vrc7, a six channel subset of the yamaha ym2413 (opll) fm synthesizer.
+//! All of the synthesis is done with integer math so that the output is identical for savestates and netplay.
+
+/// The number of cpu cycles for each sample of the synthesizer. The chip divides its clock by 72, and the cpu runs at half of that clock.
+const VRC7_SAMPLE_CYCLES: u8 = 36;
+
+/// The number of samples for a complete cycle of the tremolo, about 3.7 hz
+const VRC7_AM_PERIOD: u16 = 26 * 512;
+
+/// The maximum attenuation of the envelope, each step is 0.375 db
+const VRC7_ENVELOPE_MAX: u8 = 127;
+
+/// The built in instruments of the vrc7, instrument 0 is the custom instrument in registers 0-7
+const VRC7_PATCHES: [[u8; 8]; 15] = [
+    [0x03, 0x21, 0x05, 0x06, 0xE8, 0x81, 0x42, 0x27],
+    [0x13, 0x41, 0x14, 0x0D, 0xD8, 0xF6, 0x23, 0x12],
+    [0x11, 0x11, 0x08, 0x08, 0xFA, 0xB2, 0x20, 0x12],
+    [0x31, 0x61, 0x0C, 0x07, 0xA8, 0x64, 0x61, 0x27],
+    [0x32, 0x21, 0x1E, 0x06, 0xE1, 0x76, 0x01, 0x28],
+    [0x02, 0x01, 0x06, 0x00, 0xA3, 0xE2, 0xF4, 0xF4],
+    [0x21, 0x61, 0x1D, 0x07, 0x82, 0x81, 0x11, 0x07],
+    [0x23, 0x21, 0x22, 0x17, 0xA2, 0x72, 0x01, 0x17],
+    [0x35, 0x11, 0x25, 0x00, 0x40, 0x73, 0x72, 0x01],
+    [0xB5, 0x01, 0x0F, 0x0F, 0xA8, 0xA5, 0x51, 0x02],
+    [0x17, 0xC1, 0x24, 0x07, 0xF8, 0xF8, 0x22, 0x12],
+    [0x71, 0x23, 0x11, 0x06, 0x65, 0x74, 0x18, 0x16],
+    [0x01, 0x02, 0xD3, 0x05, 0xC9, 0x95, 0x03, 0x02],
+    [0x61, 0x63, 0x0C, 0x00, 0x94, 0xC0, 0x33, 0xF6],
+    [0x21, 0x72, 0x0D, 0x00, 0xC1, 0xD5, 0x56, 0x06],
+];
+
+/// The frequency multipliers of the operators, doubled so that they are all integers
+const VRC7_MULTIPLIERS: [u32; 16] = [1, 2, 4, 6, 8, 10, 12, 14, 16, 18, 20, 20, 24, 24, 30, 30];
+
+/// The key scale level attenuation for the upper four bits of the frequency at the highest octave, in units of 0.375 db
+const VRC7_KEY_SCALE_LEVELS: [u32; 16] = [
+    0, 48, 64, 74, 80, 86, 90, 94, 96, 100, 102, 104, 106, 108, 110, 112,
+];
+
+/// The eight steps of the vibrato, applied to the upper bits of the frequency
+const VRC7_VIBRATO: [i32; 8] = [0, 1, 2, 1, 0, -1, -2, -1];
+
+/// The negative log2 of a quarter of a sine wave, scaled by 256. This is the same as the logsin rom of the real chip,
+/// -log2(sin((i + 0.5) * pi / 512)) * 256 rounded to an integer.
+const VRC7_LOGSIN: [u16; 256] = [
+    2137, 1731, 1543, 1419, 1326, 1252, 1190, 1137, 1091, 1050, 1013, 979, 949, 920, 894, 869, 846,
+    825, 804, 785, 767, 749, 732, 717, 701, 687, 672, 659, 646, 633, 621, 609, 598, 587, 576, 566,
+    556, 546, 536, 527, 518, 509, 501, 492, 484, 476, 468, 461, 453, 446, 439, 432, 425, 418, 411,
+    405, 399, 392, 386, 380, 375, 369, 363, 358, 352, 347, 341, 336, 331, 326, 321, 316, 311, 307,
+    302, 297, 293, 289, 284, 280, 276, 271, 267, 263, 259, 255, 251, 248, 244, 240, 236, 233, 229,
+    226, 222, 219, 215, 212, 209, 205, 202, 199, 196, 193, 190, 187, 184, 181, 178, 175, 172, 169,
+    167, 164, 161, 159, 156, 153, 151, 148, 146, 143, 141, 138, 136, 134, 131, 129, 127, 125, 122,
+    120, 118, 116, 114, 112, 110, 108, 106, 104, 102, 100, 98, 96, 94, 92, 91, 89, 87, 85, 83, 82,
+    80, 78, 77, 75, 74, 72, 70, 69, 67, 66, 64, 63, 62, 60, 59, 57, 56, 55, 53, 52, 51, 49, 48, 47,
+    46, 45, 43, 42, 41, 40, 39, 38, 37, 36, 35, 34, 33, 32, 31, 30, 29, 28, 27, 26, 25, 24, 23, 23,
+    22, 21, 20, 20, 19, 18, 17, 17, 16, 15, 15, 14, 13, 13, 12, 12, 11, 10, 10, 9, 9, 8, 8, 7, 7,
+    7, 6, 6, 5, 5, 5, 4, 4, 4, 3, 3, 3, 2, 2, 2, 2, 1, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0,
+];
+
+/// The fractional part of the exponent rom of the real chip, (2^(i/256) - 1) * 1024 rounded to an integer
+const VRC7_EXP: [u16; 256] = [
+    0, 3, 6, 8, 11, 14, 17, 20, 22, 25, 28, 31, 34, 37, 40, 42, 45, 48, 51, 54, 57, 60, 63, 66, 69,
+    72, 75, 78, 81, 84, 87, 90, 93, 96, 99, 102, 105, 108, 111, 114, 117, 120, 123, 126, 130, 133,
+    136, 139, 142, 145, 148, 152, 155, 158, 161, 164, 168, 171, 174, 177, 181, 184, 187, 190, 194,
+    197, 200, 204, 207, 210, 214, 217, 220, 224, 227, 231, 234, 237, 241, 244, 248, 251, 255, 258,
+    262, 265, 268, 272, 276, 279, 283, 286, 290, 293, 297, 300, 304, 308, 311, 315, 318, 322, 326,
+    329, 333, 337, 340, 344, 348, 352, 355, 359, 363, 367, 370, 374, 378, 382, 385, 389, 393, 397,
+    401, 405, 409, 412, 416, 420, 424, 428, 432, 436, 440, 444, 448, 452, 456, 460, 464, 468, 472,
+    476, 480, 484, 488, 492, 496, 501, 505, 509, 513, 517, 521, 526, 530, 534, 538, 542, 547, 551,
+    555, 560, 564, 568, 572, 577, 581, 585, 590, 594, 599, 603, 607, 612, 616, 621, 625, 630, 634,
+    639, 643, 648, 652, 657, 661, 666, 670, 675, 680, 684, 689, 693, 698, 703, 708, 712, 717, 722,
+    726, 731, 736, 741, 745, 750, 755, 760, 765, 770, 774, 779, 784, 789, 794, 799, 804, 809, 814,
+    819, 824, 829, 834, 839, 844, 849, 854, 859, 864, 869, 874, 880, 885, 890, 895, 900, 906, 911,
+    916, 921, 927, 932, 937, 942, 948, 953, 959, 964, 969, 975, 980, 986, 991, 996, 1002, 1007,
+    1013, 1018,
+];
+
+/// The states of the envelope generator of an operator
+#[derive(serde::Serialize, serde::Deserialize, Debug, Copy, Clone, PartialEq)]
+enum Vrc7EnvelopeState {
+    /// The envelope is rising after a key on
+    Attack,
+    /// The envelope is falling to the sustain level
+    Decay,
+    /// The envelope is at the sustain level, percussive instruments continue to fall
+    Sustain,
+    /// The envelope is falling after a key off
+    Release,
+    /// The operator is silent
+    Off,
+}
+
+/// A single operator, each channel has a modulator and a carrier
+#[non_exhaustive]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Copy, Clone)]
+pub struct Vrc7Operator {
+    /// The 19 bit phase accumulator
+    phase: u32,
+    /// The attenuation of the envelope
+    envelope: u8,
+    /// Quarter steps of the envelope that have not been applied yet
+    envelope_fraction: u8,
+    /// The state of the envelope generator
+    state: Vrc7EnvelopeState,
+}
+
+impl Vrc7Operator {
+    /// Create a new operator
+    fn new() -> Self {
+        Self {
+            phase: 0,
+            envelope: VRC7_ENVELOPE_MAX,
+            envelope_fraction: 0,
+            state: Vrc7EnvelopeState::Off,
+        }
+    }
+
+    /// Start the operator for a key on
+    fn key_on(&mut self) {
+        self.phase = 0;
+        self.envelope_fraction = 0;
+        self.state = Vrc7EnvelopeState::Attack;
+    }
+
+    /// Release the operator for a key off
+    fn key_off(&mut self) {
+        if self.state != Vrc7EnvelopeState::Off {
+            self.state = Vrc7EnvelopeState::Release;
+        }
+    }
+
+    /// The 4 bit rate of the current envelope state. op is 0 for the modulator and 1 for the carrier.
+    fn rate(&self, patch: &[u8; 8], op: usize, sustain: bool) -> u8 {
+        let percussive = (patch[op] & 0x20) == 0;
+        match self.state {
+            Vrc7EnvelopeState::Attack => patch[4 + op] >> 4,
+            Vrc7EnvelopeState::Decay => patch[4 + op] & 0xf,
+            Vrc7EnvelopeState::Sustain => {
+                if percussive {
+                    patch[6 + op] & 0xf
+                } else {
+                    0
+                }
+            }
+            Vrc7EnvelopeState::Release => {
+                if sustain {
+                    5
+                } else {
+                    patch[6 + op] & 0xf
+                }
+            }
+            Vrc7EnvelopeState::Off => 0,
+        }
+    }
+
+    /// Advance the envelope by a number of quarter steps
+    fn advance_envelope(&mut self, quarter_steps: u32, rate: u8, sustain_level: u8) {
+        let total = self.envelope_fraction as u32 + quarter_steps;
+        self.envelope_fraction = (total & 3) as u8;
+        let steps = total >> 2;
+        match self.state {
+            Vrc7EnvelopeState::Attack => {
+                if rate == 15 {
+                    self.envelope = 0;
+                } else {
+                    for _ in 0..steps {
+                        self.envelope = self.envelope.saturating_sub((self.envelope >> 3) + 1);
+                    }
+                }
+                if self.envelope == 0 {
+                    self.state = Vrc7EnvelopeState::Decay;
+                }
+            }
+            Vrc7EnvelopeState::Decay => {
+                let target = sustain_level * 8;
+                self.envelope = (self.envelope as u32 + steps).min(target as u32) as u8;
+                if self.envelope >= target {
+                    self.state = Vrc7EnvelopeState::Sustain;
+                }
+            }
+            Vrc7EnvelopeState::Sustain => {
+                self.envelope = (self.envelope as u32 + steps).min(VRC7_ENVELOPE_MAX as u32) as u8;
+            }
+            Vrc7EnvelopeState::Release => {
+                self.envelope = (self.envelope as u32 + steps).min(VRC7_ENVELOPE_MAX as u32) as u8;
+                if self.envelope >= VRC7_ENVELOPE_MAX {
+                    self.state = Vrc7EnvelopeState::Off;
+                }
+            }
+            Vrc7EnvelopeState::Off => {}
+        }
+    }
+
+    /// Compute the output of the operator for a phase offset and attenuation, the result is a signed 13 bit value
+    fn output(&self, modulation: i32, attenuation: u32, half_wave: bool) -> i32 {
+        if self.state == Vrc7EnvelopeState::Off || attenuation >= VRC7_ENVELOPE_MAX as u32 {
+            return 0;
+        }
+        let index = ((self.phase >> 9) as i32).wrapping_add(modulation) as u32 & 0x3ff;
+        let negative = (index & 0x200) != 0;
+        if negative && half_wave {
+            return 0;
+        }
+        let quarter = if (index & 0x100) != 0 {
+            !index & 0xff
+        } else {
+            index & 0xff
+        };
+        let level = VRC7_LOGSIN[quarter as usize] as u32 + (attenuation << 4);
+        let shift = level >> 8;
+        if shift >= 12 {
+            return 0;
+        }
+        let magnitude = ((VRC7_EXP[(!level & 0xff) as usize] as i32 | 0x400) << 1) >> shift;
+        if negative {
+            -magnitude
+        } else {
+            magnitude
+        }
+    }
+}
+
+/// A single fm channel
+#[non_exhaustive]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Copy, Clone)]
+pub struct Vrc7Channel {
+    /// The modulator and the carrier
+    operators: [Vrc7Operator; 2],
+    /// The two most recent outputs of the modulator, used for feedback
+    feedback: [i32; 2],
+}
+
+impl Vrc7Channel {
+    /// Create a new channel
+    fn new() -> Self {
+        Self {
+            operators: [Vrc7Operator::new(); 2],
+            feedback: [0; 2],
+        }
+    }
+}
+
+/// The complete audio unit of the vrc7
+#[non_exhaustive]
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct Vrc7Audio {
+    /// The selected register for writes
+    select: u8,
+    /// The custom instrument, registers 0-7
+    custom: [u8; 8],
+    /// The lower 8 bits of the frequency for each channel, registers 0x10-0x15
+    frequency: [u8; 6],
+    /// The sustain, key, octave, and upper frequency bit for each channel, registers 0x20-0x25
+    control: [u8; 6],
+    /// The instrument and volume for each channel, registers 0x30-0x35
+    instrument: [u8; 6],
+    /// The six channels
+    channels: [Vrc7Channel; 6],
+    /// Counts cpu cycles for each sample
+    counter: u8,
+    /// Counts samples for the envelope generators
+    envelope_counter: u32,
+    /// Counts samples for the tremolo
+    am_counter: u16,
+    /// Counts samples for the vibrato
+    pm_counter: u16,
+    /// The sum of all channels for the most recent sample
+    output: i32,
+    /// True when the sound is held in reset by the mapper
+    silenced: bool,
+}
+
+impl Vrc7Audio {
+    /// Create a new audio unit
+    pub fn new() -> Self {
+        Self {
+            select: 0,
+            custom: [0; 8],
+            frequency: [0; 6],
+            control: [0; 6],
+            instrument: [0; 6],
+            channels: [Vrc7Channel::new(); 6],
+            counter: 0,
+            envelope_counter: 0,
+            am_counter: 0,
+            pm_counter: 0,
+            output: 0,
+            silenced: false,
+        }
+    }
+
+    /// Hold the sound in reset or release it, the reset clears all registers
+    pub fn set_silenced(&mut self, s: bool) {
+        if s {
+            let select = self.select;
+            *self = Self::new();
+            self.select = select;
+        }
+        self.silenced = s;
+    }
+
+    /// Write the register select port, 0x9010
+    pub fn write_select(&mut self, data: u8) {
+        self.select = data;
+    }
+
+    /// Write to the selected register, 0x9030
+    pub fn write_data(&mut self, data: u8) {
+        if self.silenced {
+            return;
+        }
+        match self.select {
+            r @ 0..=7 => {
+                self.custom[r as usize] = data;
+            }
+            r @ 0x10..=0x15 => {
+                self.frequency[(r & 0xf) as usize] = data;
+            }
+            r @ 0x20..=0x25 => {
+                let ch = (r & 0xf) as usize;
+                let old = self.control[ch];
+                self.control[ch] = data;
+                let key_old = (old & 0x10) != 0;
+                let key_new = (data & 0x10) != 0;
+                if key_new && !key_old {
+                    for o in self.channels[ch].operators.iter_mut() {
+                        o.key_on();
+                    }
+                } else if !key_new && key_old {
+                    for o in self.channels[ch].operators.iter_mut() {
+                        o.key_off();
+                    }
+                }
+            }
+            r @ 0x30..=0x35 => {
+                self.instrument[(r & 0xf) as usize] = data;
+            }
+            _ => {}
+        }
+    }
+
+    /// Retrieve the register select and the address and contents of every internal register
+    pub fn registers(&self) -> (u8, Vec<(u8, u8)>) {
+        let mut r = Vec::new();
+        for (i, d) in self.custom.iter().enumerate() {
+            r.push((i as u8, *d));
+        }
+        for (i, ((f, c), n)) in self
+            .frequency
+            .iter()
+            .zip(self.control.iter())
+            .zip(self.instrument.iter())
+            .enumerate()
+        {
+            r.push((0x10 + i as u8, *f));
+            r.push((0x20 + i as u8, *c));
+            r.push((0x30 + i as u8, *n));
+        }
+        (self.select, r)
+    }
+
+    /// The instrument used by a channel
+    fn patch(&self, ch: usize) -> [u8; 8] {
+        match self.instrument[ch] >> 4 {
+            0 => self.custom,
+            i => VRC7_PATCHES[i as usize - 1],
+        }
+    }
+
+    /// The number of quarter steps to move an envelope for the current sample
+    fn envelope_steps(&self, rate: u8, key_scale: u8, patch: u8) -> u32 {
+        if rate == 0 {
+            return 0;
+        }
+        let ksr = if (patch & 0x10) != 0 {
+            key_scale
+        } else {
+            key_scale >> 2
+        };
+        let rate = (rate * 4 + ksr).min(63);
+        let shift = 14 - (rate >> 2) as i32;
+        let base = 4 + (rate & 3) as u32;
+        if shift > 0 {
+            if (self.envelope_counter & ((1 << shift) - 1)) == 0 {
+                base
+            } else {
+                0
+            }
+        } else {
+            base << -shift
+        }
+    }
+
+    /// The attenuation from the key scale level for an operator, in units of 0.375 db
+    fn key_scale_level(fnum: u32, block: u32, ksl: u8) -> u32 {
+        if ksl == 0 {
+            return 0;
+        }
+        let level = VRC7_KEY_SCALE_LEVELS[(fnum >> 5) as usize].saturating_sub(16 * (7 - block));
+        level >> (3 - ksl)
+    }
+
+    /// Advance one operator of a channel by a sample and return its output. op is 0 for the modulator and 1 for the carrier.
+    fn clock_operator(
+        &mut self,
+        ch: usize,
+        op: usize,
+        modulation: i32,
+        attenuation: u32,
+        am: u32,
+        pm_step: usize,
+    ) -> i32 {
+        let patch = self.patch(ch);
+        let fnum = self.frequency[ch] as u32 | ((self.control[ch] & 1) as u32) << 8;
+        let block = ((self.control[ch] >> 1) & 7) as u32;
+        let sustain = (self.control[ch] & 0x20) != 0;
+        let key_scale = ((block << 1) | (fnum >> 8)) as u8;
+
+        let operator = self.channels[ch].operators[op];
+        let rate = operator.rate(&patch, op, sustain);
+        let steps = self.envelope_steps(rate, key_scale, patch[op]);
+        let ksl = Self::key_scale_level(fnum, block, patch[2 + op] >> 6);
+
+        let fnum = if (patch[op] & 0x40) != 0 {
+            let delta = VRC7_VIBRATO[pm_step] * (fnum >> 6) as i32;
+            let delta = if delta < 0 {
+                -((-delta) >> 1)
+            } else {
+                delta >> 1
+            };
+            (fnum as i32 + delta) as u32
+        } else {
+            fnum
+        };
+        let increment = ((fnum << block) * VRC7_MULTIPLIERS[(patch[op] & 0xf) as usize]) >> 2;
+
+        let am = if (patch[op] & 0x80) != 0 { am } else { 0 };
+        let half_wave = (patch[3] & (8 << op)) != 0;
+
+        let operator = &mut self.channels[ch].operators[op];
+        operator.advance_envelope(steps, rate, patch[6 + op] >> 4);
+        let out = operator.output(
+            modulation,
+            operator.envelope as u32 + attenuation + ksl + am,
+            half_wave,
+        );
+        operator.phase = (operator.phase + increment) & 0x7ffff;
+        out
+    }
+
+    /// Generate the next sample for a channel
+    fn render_channel(&mut self, ch: usize, am: u32, pm_step: usize) -> i32 {
+        let patch = self.patch(ch);
+        let feedback = patch[3] & 7;
+        let fb = &self.channels[ch].feedback;
+        let modulation = if feedback != 0 {
+            (fb[0] + fb[1]) >> (9 - feedback)
+        } else {
+            0
+        };
+        let total_level = (patch[2] & 0x3f) as u32 * 2;
+        let modulator = self.clock_operator(ch, 0, modulation, total_level, am, pm_step);
+        let fb = &mut self.channels[ch].feedback;
+        fb[1] = fb[0];
+        fb[0] = modulator;
+        let volume = (self.instrument[ch] & 0xf) as u32 * 8;
+        self.clock_operator(ch, 1, modulator, volume, am, pm_step)
+    }
+
+    /// Generate the next sample for all channels
+    fn render(&mut self) {
+        self.envelope_counter = self.envelope_counter.wrapping_add(1);
+        self.am_counter = (self.am_counter + 1) % VRC7_AM_PERIOD;
+        self.pm_counter = (self.pm_counter + 1) & 0x1fff;
+        let am_step = (self.am_counter >> 9) as u32;
+        let am = if am_step < 13 { am_step } else { 25 - am_step };
+        let pm_step = (self.pm_counter >> 10) as usize;
+        let mut sum = 0;
+        for ch in 0..6 {
+            sum += self.render_channel(ch, am, pm_step);
+        }
+        self.output = sum;
+    }
+
+    /// Clock the audio unit for a single cpu cycle, a new sample is generated every 36 cycles
+    pub fn cycle(&mut self) {
+        if self.silenced {
+            return;
+        }
+        self.counter += 1;
+        if self.counter < VRC7_SAMPLE_CYCLES {
+            return;
+        }
+        self.counter = 0;
+        self.render();
+    }
+
+    /// Returns the audio output of the vrc7, from 0.0 to 1.0. The fm waves are signed, so silence is at the middle of the range.
+    pub fn audio(&self) -> f32 {
+        let sample = (self.output as f32 / (6.0 * 4096.0)).clamp(-1.0, 1.0);
+        (sample + 1.0) / 2.0
+    }
+}
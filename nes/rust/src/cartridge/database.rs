@@ -0,0 +1,398 @@
+//! A database of known good headers for roms, used to correct roms with bad or obsolete headers.
+//! Roms are identified by a hash of the prg rom and chr rom together, so the header does not affect the lookup.
+//! The database is loaded from a file selected by the user, no roms are known until one is selected.
+//! That file can be the nes 2.0 xml database (nes20db.xml), or a toml file with a list of games.
+//!
+//! ```toml
+//! [[game]]
+//! name = "The name of the game"
+//! hash = "sha256 of prg rom and chr rom"
+//! mapper = 0
+//! submapper = 0      # optional, defaults to 0
+//! mirroring = "H"    # H for horizontal, V for vertical, 4 for four screen
+//! prg_ram = 0        # optional, size in bytes, the size in the header of the rom is kept when left out
+//! prg_nvram = 0      # optional, size in bytes, battery backed, the size in the header of the rom is kept when left out
+//! chr_ram = 0        # optional, size in bytes, the size in the header of the rom is kept when left out
+//! chr_nvram = 0      # optional, size in bytes, battery backed, the size in the header of the rom is kept when left out
+//! battery = false    # optional, true for battery backed memory that is not ram, like an eeprom
+//! timing = "Ntsc"    # optional, Ntsc, Pal, MultipleRegion, or Dendy
+//! ```
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use super::CpuTiming;
+
+/// The nametable mirroring of a rom in the database
+#[derive(serde::Serialize, serde::Deserialize, Copy, Clone, Debug, PartialEq)]
+pub enum DatabaseMirroring {
+    /// Horizontal mirroring
+    #[serde(rename = "H")]
+    Horizontal,
+    /// Vertical mirroring
+    #[serde(rename = "V")]
+    Vertical,
+    /// Four screen mirroring
+    #[serde(rename = "4")]
+    FourScreen,
+}
+
+/// A single rom in the database
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct DatabaseEntry {
+    /// The name of the game
+    pub name: String,
+    /// The sha256 of the prg rom and chr rom, in lowercase hex
+    pub hash: String,
+    /// The mapper number
+    pub mapper: u16,
+    /// The submapper number
+    #[serde(default)]
+    pub submapper: u8,
+    /// The nametable mirroring
+    pub mirroring: DatabaseMirroring,
+    /// The size of the volatile prg ram in bytes, None to keep the size from the header of the rom
+    #[serde(default)]
+    pub prg_ram: Option<usize>,
+    /// The size of the battery backed prg ram in bytes, None to keep the size from the header of the rom
+    #[serde(default)]
+    pub prg_nvram: Option<usize>,
+    /// The size of the volatile chr ram in bytes, None to keep the size from the header of the rom
+    #[serde(default)]
+    pub chr_ram: Option<usize>,
+    /// The size of the battery backed chr ram in bytes, None to keep the size from the header of the rom
+    #[serde(default)]
+    pub chr_nvram: Option<usize>,
+    /// The rom has battery backed memory that is not described by the ram sizes, like an eeprom
+    #[serde(default)]
+    pub battery: bool,
+    /// The region of the rom
+    #[serde(default)]
+    pub timing: CpuTiming,
+}
+
+/// The file layout of the database
+#[derive(serde::Deserialize)]
+struct DatabaseFile {
+    /// All of the roms in the file
+    #[serde(default)]
+    game: Vec<DatabaseEntry>,
+}
+
+/// A database of roms, indexed by the hash of the prg rom and chr rom
+pub struct RomDatabase {
+    /// The roms in the database, the key is the lowercase hash
+    entries: HashMap<String, DatabaseEntry>,
+}
+
+/// The database loaded from the file selected by the user
+static EXTERNAL_DATABASE: RwLock<Option<Arc<RomDatabase>>> = RwLock::new(None);
+
+/// Calculate a digest of some data, returning it as lowercase hex
+fn hex_digest(algorithm: &'static ring::digest::Algorithm, data: &[u8]) -> String {
+    data_encoding::HEXLOWER.encode(ring::digest::digest(algorithm, data).as_ref())
+}
+
+impl RomDatabase {
+    /// Load a database file.
+    /// Files ending in .xml are parsed as the nes 2.0 xml database, everything else as toml.
+    pub fn load(path: &std::path::Path) -> std::io::Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        let entries = if path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("xml"))
+        {
+            parse_xml(&text)
+        } else {
+            toml::from_str::<DatabaseFile>(&text)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))?
+                .game
+        };
+        Ok(Self {
+            entries: entries
+                .into_iter()
+                .map(|e| (e.hash.to_lowercase(), e))
+                .collect(),
+        })
+    }
+
+    /// The number of roms in the database
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true when the database has no roms
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Look up a rom by its prg and chr rom. Toml databases use the sha256 of the data, and the nes 2.0 xml database uses the sha1.
+    pub fn lookup(&self, rom: &[u8]) -> Option<DatabaseEntry> {
+        let sha256 = hex_digest(&ring::digest::SHA256, rom);
+        let sha1 = hex_digest(&ring::digest::SHA1_FOR_LEGACY_USE_ONLY, rom);
+        self.entries
+            .get(&sha256)
+            .or_else(|| self.entries.get(&sha1))
+            .cloned()
+    }
+}
+
+/// Retrieve the database loaded from the file selected by the user
+pub fn external() -> Option<Arc<RomDatabase>> {
+    EXTERNAL_DATABASE.read().ok().and_then(|db| db.clone())
+}
+
+/// Find the first tag with the given name in a game of the xml database, returning the text inside the tag
+fn xml_tag<'a>(game: &'a str, name: &str) -> Option<&'a str> {
+    let start = game.find(&format!("<{} ", name))?;
+    let len = game[start..].find('>')?;
+    Some(&game[start..start + len])
+}
+
+/// Find the value of an attribute in a tag of the xml database
+fn xml_attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let start = tag.find(&format!(" {}=\"", name))? + name.len() + 3;
+    let len = tag[start..].find('"')?;
+    Some(&tag[start..start + len])
+}
+
+/// Parse a single game of the nes 2.0 xml database. Memory that the database does not list is not present on the board.
+fn parse_xml_game(game: &str) -> Option<DatabaseEntry> {
+    let number = |tag: &str, attribute: &str| {
+        xml_tag(game, tag)
+            .and_then(|t| xml_attribute(t, attribute))
+            .and_then(|v| v.parse::<usize>().ok())
+    };
+    let pcb = xml_tag(game, "pcb")?;
+    //the name of the rom file is stored in a comment at the start of the game
+    let name = game
+        .split_once("<!--")
+        .and_then(|(_, c)| c.split_once("-->"))
+        .map(|(c, _)| c.trim().rsplit(['\\', '/']).next().unwrap_or(c).to_string())
+        .unwrap_or_default();
+    Some(DatabaseEntry {
+        name,
+        hash: xml_attribute(xml_tag(game, "rom")?, "sha1")?.to_lowercase(),
+        mapper: xml_attribute(pcb, "mapper")?.parse().ok()?,
+        submapper: xml_attribute(pcb, "submapper")
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(0),
+        mirroring: match xml_attribute(pcb, "mirroring") {
+            Some("V") => DatabaseMirroring::Vertical,
+            Some("4") => DatabaseMirroring::FourScreen,
+            _ => DatabaseMirroring::Horizontal,
+        },
+        prg_ram: Some(number("prgram", "size").unwrap_or(0)),
+        prg_nvram: Some(number("prgnvram", "size").unwrap_or(0)),
+        chr_ram: Some(number("chrram", "size").unwrap_or(0)),
+        chr_nvram: Some(number("chrnvram", "size").unwrap_or(0)),
+        battery: xml_attribute(pcb, "battery") == Some("1"),
+        timing: match number("console", "region") {
+            Some(1) => CpuTiming::Pal,
+            Some(2) => CpuTiming::MultipleRegion,
+            Some(3) => CpuTiming::Dendy,
+            _ => CpuTiming::Ntsc,
+        },
+    })
+}
+
+/// Parse the nes 2.0 xml database
+fn parse_xml(text: &str) -> Vec<DatabaseEntry> {
+    text.split("<game>")
+        .skip(1)
+        .filter_map(|g| parse_xml_game(g.split("</game>").next().unwrap_or(g)))
+        .collect()
+}
+
+/// Load a database file selected by the user, replacing any database previously loaded from a file.
+/// Returns the number of roms loaded.
+pub fn load_external(path: &std::path::Path) -> std::io::Result<usize> {
+    let db = RomDatabase::load(path)?;
+    let count = db.len();
+    if let Ok(mut external) = EXTERNAL_DATABASE.write() {
+        *external = Some(Arc::new(db));
+    }
+    Ok(count)
+}
+
+/// Forget the database loaded from a file, no headers are corrected after this
+pub fn unload_external() {
+    if let Ok(mut external) = EXTERNAL_DATABASE.write() {
+        *external = None;
+    }
+}
+
+/// Calculate the shift count for a ram size in the nes 2.0 header
+fn ram_shift(size: usize) -> u8 {
+    if size < 128 {
+        0
+    } else {
+        (size / 64).ilog2().min(15) as u8
+    }
+}
+
+/// The parts of a header that the database can correct
+#[derive(PartialEq)]
+struct HeaderSummary {
+    /// The mapper number
+    mapper: u16,
+    /// The submapper number
+    submapper: u8,
+    /// The mirroring bits of byte 6
+    mirroring: u8,
+    /// True when the rom has battery backed memory
+    battery: bool,
+}
+
+impl HeaderSummary {
+    /// Summarize the header of a rom, the same way that the loaders interpret it
+    fn new(rom_contents: &[u8]) -> Self {
+        let ines2 = (rom_contents[7] & 0xC) == 8;
+        let ines1 = (rom_contents[7] & 0xC) == 0 && rom_contents[12..16].iter().all(|b| *b == 0);
+        let mut mapper = (rom_contents[6] >> 4) as u16;
+        if ines2 || ines1 {
+            mapper |= (rom_contents[7] & 0xf0) as u16;
+        }
+        if ines2 {
+            mapper |= ((rom_contents[8] & 0xf) as u16) << 8;
+        }
+        Self {
+            mapper,
+            submapper: if ines2 { rom_contents[8] >> 4 } else { 0 },
+            mirroring: rom_contents[6] & 9,
+            battery: (rom_contents[6] & 2) != 0,
+        }
+    }
+}
+
+/// Calculate the size of the prg or chr rom from the least and most significant parts of the size in the header.
+/// The unit is the size used when the size is not in the exponent form of nes 2.0. Returns None when the size does not fit in a usize.
+pub fn rom_size(lsb: u8, msb: u8, unit: usize, ines2: bool) -> Option<usize> {
+    if !ines2 {
+        (lsb as usize).checked_mul(unit)
+    } else if msb < 0xf {
+        (lsb as usize | (msb as usize) << 8).checked_mul(unit)
+    } else {
+        1usize
+            .checked_shl((lsb >> 2) as u32)?
+            .checked_mul((lsb & 3) as usize * 2 + 1)
+    }
+}
+
+/// Returns the start and length of the prg and chr rom, as described by the header.
+pub fn rom_data_range(rom_contents: &[u8]) -> Option<(usize, usize)> {
+    let ines2 = (rom_contents[7] & 0xC) == 8;
+    let prg = rom_size(rom_contents[4], rom_contents[9] & 0xf, 16384, ines2)?;
+    let chr = rom_size(rom_contents[5], rom_contents[9] >> 4, 8192, ines2)?;
+    let start: usize = if (rom_contents[6] & 4) != 0 { 528 } else { 16 };
+    let len = prg.checked_add(chr)?;
+    if start.checked_add(len)? > rom_contents.len() {
+        return None;
+    }
+    Some((start, len))
+}
+
+/// The sizes of prg ram, battery backed prg ram, chr ram, and battery backed chr ram, the same way that the loaders interpret the header
+fn header_ram_sizes(rom_contents: &[u8]) -> [usize; 4] {
+    let size = |shift: u8| if shift == 0 { 0 } else { 64 << shift };
+    if (rom_contents[7] & 0xC) == 8 {
+        [
+            size(rom_contents[10] & 0xf),
+            size(rom_contents[10] >> 4),
+            size(rom_contents[11] & 0xf),
+            size(rom_contents[11] >> 4),
+        ]
+    } else {
+        let battery = (rom_contents[6] & 2) != 0;
+        let prg_ram = if battery || rom_contents[8] == 0 {
+            8192
+        } else {
+            rom_contents[8] as usize * 8192
+        };
+        let chr_ram = if rom_contents[5] == 0 { 8192 } else { 0 };
+        if battery {
+            [0, prg_ram, chr_ram, 0]
+        } else {
+            [prg_ram, 0, chr_ram, 0]
+        }
+    }
+}
+
+/// Rebuild the rom with an nes 2.0 header from the database entry.
+/// Returns the new rom contents, and a description of the correction when it changes how the rom is interpreted.
+pub fn correct_rom(rom_contents: &[u8], entry: &DatabaseEntry) -> (Vec<u8>, Option<String>) {
+    let ines2 = (rom_contents[7] & 0xC) == 8;
+    let (start, len) = rom_data_range(rom_contents).unwrap_or((16, rom_contents.len() - 16));
+    let mut header = [0u8; 16];
+    header[0..4].copy_from_slice(b"NES\x1a");
+    header[4] = rom_contents[4];
+    header[5] = rom_contents[5];
+    let mirroring = match entry.mirroring {
+        DatabaseMirroring::Horizontal => 0,
+        DatabaseMirroring::Vertical => 1,
+        DatabaseMirroring::FourScreen => 8,
+    };
+    let header_ram = header_ram_sizes(rom_contents);
+    let entry_ram = [
+        entry.prg_ram,
+        entry.prg_nvram,
+        entry.chr_ram,
+        entry.chr_nvram,
+    ];
+    let [prg_ram, prg_nvram, chr_ram, chr_nvram]: [usize; 4] =
+        std::array::from_fn(|i| entry_ram[i].unwrap_or(header_ram[i]));
+    let battery = prg_nvram > 0 || chr_nvram > 0 || entry.battery;
+    header[6] = ((entry.mapper & 0xf) as u8) << 4
+        | (rom_contents[6] & 4)
+        | (battery as u8) << 1
+        | mirroring;
+    header[7] = (entry.mapper & 0xf0) as u8 | 8;
+    header[8] = entry.submapper << 4 | ((entry.mapper >> 8) & 0xf) as u8;
+    header[10] = ram_shift(prg_ram) | ram_shift(prg_nvram) << 4;
+    header[11] = ram_shift(chr_ram) | ram_shift(chr_nvram) << 4;
+    header[12] = match entry.timing {
+        CpuTiming::Ntsc => 0,
+        CpuTiming::Pal => 1,
+        CpuTiming::MultipleRegion => 2,
+        CpuTiming::Dendy => 3,
+    };
+    let mut end = start + len;
+    if ines2 {
+        //keep the rom sizes, console type, miscellaneous roms, and expansion device
+        header[7] |= rom_contents[7] & 3;
+        header[9] = rom_contents[9];
+        header[13] = rom_contents[13];
+        header[14] = rom_contents[14];
+        header[15] = rom_contents[15];
+        if (rom_contents[14] & 3) != 0 {
+            end = rom_contents.len();
+        }
+    }
+    let mut contents = header.to_vec();
+    contents.extend_from_slice(&rom_contents[16..end]);
+
+    let old = HeaderSummary::new(rom_contents);
+    let new = HeaderSummary::new(&contents);
+    let mut changes = Vec::new();
+    if old.mapper != new.mapper {
+        changes.push(format!("mapper {} to {}", old.mapper, new.mapper));
+    }
+    if old.submapper != new.submapper {
+        changes.push(format!("submapper {} to {}", old.submapper, new.submapper));
+    }
+    if old.mirroring != new.mirroring {
+        changes.push("mirroring".to_string());
+    }
+    if old.battery != new.battery {
+        changes.push("battery".to_string());
+    }
+    if rom_contents.len() != contents.len() {
+        changes.push("extra data removed".to_string());
+    }
+    let correction = if changes.is_empty() {
+        None
+    } else {
+        Some(format!("{}: {}", entry.name, changes.join(", ")))
+    };
+    (contents, correction)
+}
@@ -0,0 +1,130 @@
+//! Conversion of famicom disk system images between the file formats and the bit level layout seen by the disk drive.
+
+/// The size of a single disk side in the fds file format
+pub const FDS_SIDE_SIZE: usize = 65500;
+/// The size of a single disk side in the qd file format
+pub const QD_SIDE_SIZE: usize = 65536;
+/// The size of a single disk side as seen by the drive
+const RAW_SIDE_SIZE: usize = 0x14000;
+/// The number of bytes of gap before the first block, 28300 bits
+const LEAD_IN: usize = 3537;
+/// The number of bytes of gap between blocks, 976 bits
+const BLOCK_GAP: usize = 122;
+/// The byte that ends a gap and marks the start of a block
+const GAP_END: u8 = 0x80;
+
+/// Returns the length of the block that starts at pos, along with the updated size of the next file
+fn block_length(side: &[u8], pos: usize, file_size: &mut usize) -> Option<usize> {
+    let len = match side.get(pos)? {
+        1 => 56,
+        2 => 2,
+        3 => {
+            let lo = *side.get(pos + 13)? as usize;
+            let hi = *side.get(pos + 14)? as usize;
+            *file_size = lo | hi << 8;
+            16
+        }
+        4 => 1 + *file_size,
+        _ => return None,
+    };
+    if pos + len > side.len() {
+        return None;
+    }
+    Some(len)
+}
+
+/// Find all of the blocks of a disk side, as (start, length) pairs. Qd images follow each block with two crc bytes.
+fn disk_blocks(side: &[u8], crc: bool) -> Vec<(usize, usize)> {
+    let mut blocks = Vec::new();
+    let mut file_size = 0;
+    let mut pos = 0;
+    while let Some(len) = block_length(side, pos, &mut file_size) {
+        blocks.push((pos, len));
+        pos += len;
+        if crc {
+            pos += 2;
+        }
+    }
+    blocks
+}
+
+/// Returns true if the contents look like a famicom disk system image, with or without the header
+pub fn is_disk_image(contents: &[u8]) -> bool {
+    contents.starts_with(b"FDS\x1a") || contents.starts_with(b"\x01*NINTENDO-HVC*")
+}
+
+/// Split a disk image into sides, each in the layout of the fds file format. Both fds and qd images are accepted.
+pub fn parse_disk_image(contents: &[u8]) -> Option<Vec<Vec<u8>>> {
+    let contents = if contents.starts_with(b"FDS\x1a") {
+        &contents[16.min(contents.len())..]
+    } else {
+        contents
+    };
+    let qd = !contents.is_empty()
+        && contents.len() % QD_SIDE_SIZE == 0
+        && contents.len() % FDS_SIDE_SIZE != 0;
+    let side_size = if qd { QD_SIDE_SIZE } else { FDS_SIDE_SIZE };
+    let mut sides = Vec::new();
+    for chunk in contents.chunks(side_size) {
+        if !chunk.starts_with(b"\x01*NINTENDO-HVC*") {
+            break;
+        }
+        let side = if qd {
+            let mut side = Vec::with_capacity(FDS_SIDE_SIZE);
+            for (start, len) in disk_blocks(chunk, true) {
+                side.extend_from_slice(&chunk[start..start + len]);
+            }
+            side.resize(FDS_SIDE_SIZE, 0);
+            side
+        } else {
+            let mut side = chunk.to_vec();
+            side.resize(FDS_SIDE_SIZE, 0);
+            side
+        };
+        sides.push(side);
+    }
+    if sides.is_empty() {
+        None
+    } else {
+        Some(sides)
+    }
+}
+
+/// Convert a side in the fds file format to the layout seen by the drive, adding the gaps, gap end markers, and crc bytes.
+pub fn raw_side(side: &[u8]) -> Vec<u8> {
+    let mut raw = vec![0; LEAD_IN];
+    for (start, len) in disk_blocks(side, false) {
+        raw.push(GAP_END);
+        raw.extend_from_slice(&side[start..start + len]);
+        //the crc is not checked, so it is left empty
+        raw.extend_from_slice(&[0, 0]);
+        raw.extend(std::iter::repeat(0).take(BLOCK_GAP));
+    }
+    raw.resize(RAW_SIDE_SIZE.max(raw.len()), 0);
+    raw
+}
+
+/// Convert a side in the layout seen by the drive back into the fds file format
+pub fn side_from_raw(raw: &[u8]) -> Vec<u8> {
+    let mut side = Vec::with_capacity(FDS_SIDE_SIZE);
+    let mut file_size = 0;
+    let mut pos = 0;
+    loop {
+        while raw.get(pos) == Some(&0) {
+            pos += 1;
+        }
+        if raw.get(pos) != Some(&GAP_END) {
+            break;
+        }
+        pos += 1;
+        let len = if let Some(len) = block_length(raw, pos, &mut file_size) {
+            len
+        } else {
+            break;
+        };
+        side.extend_from_slice(&raw[pos..pos + len]);
+        pos += len + 2;
+    }
+    side.resize(FDS_SIDE_SIZE, 0);
+    side
+}
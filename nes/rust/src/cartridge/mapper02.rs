@@ -15,6 +15,8 @@ pub struct Mapper02 {
     ppu_address: u16,
     /// The bank select register
     bank: u8,
+    /// True when writes to the bank select register conflict with the rom
+    bus_conflicts: bool,
 }
 
 impl Mapper02 {
@@ -24,6 +26,7 @@ impl Mapper02 {
             mirror_vertical: d.volatile.mirroring,
             ppu_address: 0,
             bank: 0xff,
+            bus_conflicts: d.bus_conflicts(),
         })
     }
     /// Check the mirroring bit for the ppu addressing.
@@ -138,7 +141,11 @@ impl NesMapperTrait for Mapper02 {
             }
         }
         if addr >= 0x8000 {
-            self.bank = data;
+            self.bank = if self.bus_conflicts {
+                data & self.memory_cycle_dump(cart, addr).unwrap_or(0xff)
+            } else {
+                data
+            };
         }
     }
 
@@ -15,6 +15,8 @@ pub struct Mapper03 {
     ppu_address: u16,
     /// The bank select register
     bank: u8,
+    /// True when writes to the bank select register conflict with the rom
+    bus_conflicts: bool,
 }
 
 impl Mapper03 {
@@ -24,6 +26,7 @@ impl Mapper03 {
             mirror_vertical: d.volatile.mirroring,
             ppu_address: 0,
             bank: 0,
+            bus_conflicts: d.bus_conflicts(),
         })
     }
     /// Check the mirroring bit for the ppu addressing.
@@ -116,7 +119,11 @@ impl NesMapperTrait for Mapper03 {
 
     fn memory_cycle_write(&mut self, cart: &mut NesCartridgeData, addr: u16, data: u8) {
         if addr >= 0x8000 {
-            self.bank = data;
+            self.bank = if self.bus_conflicts {
+                data & self.memory_cycle_dump(cart, addr).unwrap_or(0xff)
+            } else {
+                data
+            };
         } else if (0x6000..=0x7fff).contains(&addr) {
             if cart.nonvolatile.trainer.is_some() && (0x7000..=0x71ff).contains(&addr) {
                 let c = cart.nonvolatile.trainer.as_mut().unwrap();
@@ -1,7 +1,9 @@
-//! Implements mapper 05
+//! Implements mapper 05, also known as MMC5
 
 use std::collections::BTreeMap;
 
+use crate::cartridge::audio::mmc5::Mmc5Audio;
+use crate::cartridge::audio::{ExpansionAudio, ExpansionAudioChip};
 use crate::cartridge::NesCartridgeData;
 use crate::cartridge::{NesMapper, NesMapperTrait};
 
@@ -14,12 +16,18 @@ use serde_with::Bytes;
 pub struct Mapper05 {
     /// The ppu address for ppu addressing
     ppu_address: u16,
-    /// First group of registers 0x5105 - 0x5107
+    /// First group of registers 0x5100 - 0x5107
     registers: [u8; 8],
     /// Second group of registers 0x5113 - 0x5117
     registers2: [u8; 5],
     /// Third group of registers, 0x5120 - 0x5130
     registers3: [u8; 17],
+    /// The chr banks of 0x5120 - 0x5127, including the upper bits from 0x5130
+    chr_a: [u16; 8],
+    /// The chr banks of 0x5128 - 0x512b, including the upper bits from 0x5130
+    chr_b: [u16; 4],
+    /// True when the last chr bank register written was one of 0x5128 - 0x512b
+    last_chr_b: bool,
     /// Fourth group of registers: 0x5200 - 0x5206
     registers4: [u8; 7],
     /// A block of internal ram
@@ -37,10 +45,18 @@ pub struct Mapper05 {
     address_match: u8,
     /// The current detected scanline
     scanline: u8,
-    /// Number of idle commands detected on ppu
+    /// Number of cpu cycles since the ppu last read something
     idle: u8,
-    /// Number of tiles fetched
-    tile_count: u8,
+    /// Number of ppu reads since the start of the scanline was detected
+    fetch_count: u8,
+    /// The internal ram byte for the tile being fetched, used in extended attribute mode
+    exram_tile: u8,
+    /// True when the tile being fetched comes from the vertical split
+    split_tile: bool,
+    /// The nametable data for the current ppu fetch, when the mmc5 overrides the nametable
+    nametable_override: Option<u8>,
+    /// The expansion audio
+    audio: Mmc5Audio,
 }
 
 impl Mapper05 {
@@ -51,7 +67,10 @@ impl Mapper05 {
             registers: [0xff; 8],
             registers2: [0xff; 5],
             registers3: [0xff; 17],
-            registers4: [0xff; 7],
+            chr_a: [0xff; 8],
+            chr_b: [0xff; 4],
+            last_chr_b: false,
+            registers4: [0; 7],
             int_ram: [0xff; 1024],
             ppuctrl: 0,
             ppumask: 0,
@@ -60,213 +79,226 @@ impl Mapper05 {
             address_match: 0,
             scanline: 0,
             idle: 0,
-            tile_count: 0,
+            fetch_count: 0,
+            exram_tile: 0,
+            split_tile: false,
+            nametable_override: None,
+            audio: Mmc5Audio::new(),
         })
     }
-    /// Check the mirroring bit for the ppu addressing.
-    fn check_mirroring(&self, addr: u16) -> (bool, bool) {
-        let a10 = if (self.registers[2] & 1) == 0 {
-            (addr & 1 << 10) != 0
-        } else {
-            (addr & 1 << 11) != 0
-        };
-        (a10, false)
+
+    /// Returns the mode of the internal ram, 0x5104
+    fn exram_mode(&self) -> u8 {
+        self.registers[4] & 3
     }
-    /// Perform a ppu read operation
-    fn ppu_read(&self, addr: u16, cart: &NesCartridgeData) -> Option<u8> {
+
+    /// Check the nametable mapping register for the ppu addressing.
+    /// Nametables sourced from the mmc5 (internal ram or fill mode) disable the internal vram.
+    fn check_mirroring(&self, addr: u16) -> (bool, bool) {
         match addr {
-            0..=0x03ff => match self.registers[1] & 3 {
-                0 => {
-                    let a = self.registers3[7] as u32;
-                    let big_addr: u32 = a << 13;
-                    let addr: u32 = addr as u32 | big_addr;
-                    let addr = addr & (cart.nonvolatile.chr_rom.len() as u32 - 1);
-                    Some(cart.nonvolatile.chr_rom[addr as usize])
-                }
-                1 => {
-                    todo!();
-                }
-                2 => {
-                    todo!();
-                }
-                3 => {
-                    let a = self.registers3[0] as u32;
-                    let big_addr: u32 = a << 10;
-                    let addr: u32 = 0x3ff & addr as u32 | big_addr;
-                    let addr = addr & (cart.nonvolatile.chr_rom.len() as u32 - 1);
-                    Some(cart.nonvolatile.chr_rom[addr as usize])
-                }
-                _ => unreachable!(),
-            },
-            0x400..=0x7ff => match self.registers[1] & 3 {
-                0 => {
-                    let a = self.registers3[7] as u32;
-                    let big_addr: u32 = a << 13;
-                    let addr: u32 = addr as u32 | big_addr;
-                    let addr = addr & (cart.nonvolatile.chr_rom.len() as u32 - 1);
-                    Some(cart.nonvolatile.chr_rom[addr as usize])
-                }
-                1 => {
-                    todo!();
-                }
-                2 => {
-                    todo!();
-                }
-                3 => {
-                    let a = self.registers3[1] as u32;
-                    let big_addr: u32 = a << 10;
-                    let addr: u32 = 0x3ff & addr as u32 | big_addr;
-                    let addr = addr & (cart.nonvolatile.chr_rom.len() as u32 - 1);
-                    Some(cart.nonvolatile.chr_rom[addr as usize])
-                }
-                _ => unreachable!(),
-            },
-            0x800..=0xbff => match self.registers[1] & 3 {
-                0 => {
-                    let a = self.registers3[7] as u32;
-                    let big_addr: u32 = a << 13;
-                    let addr: u32 = addr as u32 | big_addr;
-                    let addr = addr & (cart.nonvolatile.chr_rom.len() as u32 - 1);
-                    Some(cart.nonvolatile.chr_rom[addr as usize])
-                }
-                1 => {
-                    todo!();
-                }
-                2 => {
-                    todo!();
-                }
-                3 => {
-                    let a = self.registers3[2] as u32;
-                    let big_addr: u32 = a << 10;
-                    let addr: u32 = 0x3ff & addr as u32 | big_addr;
-                    let addr = addr & (cart.nonvolatile.chr_rom.len() as u32 - 1);
-                    Some(cart.nonvolatile.chr_rom[addr as usize])
-                }
-                _ => unreachable!(),
-            },
-            0xc00..=0xfff => match self.registers[1] & 3 {
-                0 => {
-                    let a = self.registers3[7] as u32;
-                    let big_addr: u32 = a << 13;
-                    let addr: u32 = addr as u32 | big_addr;
-                    let addr = addr & (cart.nonvolatile.chr_rom.len() as u32 - 1);
-                    Some(cart.nonvolatile.chr_rom[addr as usize])
-                }
-                1 => {
-                    todo!();
-                }
-                2 => {
-                    todo!();
-                }
-                3 => {
-                    let a = self.registers3[3] as u32;
-                    let big_addr: u32 = a << 10;
-                    let addr: u32 = 0x3ff & addr as u32 | big_addr;
-                    let addr = addr & (cart.nonvolatile.chr_rom.len() as u32 - 1);
-                    Some(cart.nonvolatile.chr_rom[addr as usize])
-                }
-                _ => unreachable!(),
-            },
-            0x1000..=0x13ff => match self.registers[1] & 3 {
-                0 => {
-                    let a = self.registers3[7] as u32;
-                    let big_addr: u32 = a << 13;
-                    let addr: u32 = addr as u32 | big_addr;
-                    let addr = addr & (cart.nonvolatile.chr_rom.len() as u32 - 1);
-                    Some(cart.nonvolatile.chr_rom[addr as usize])
-                }
-                1 => {
-                    todo!();
-                }
-                2 => {
-                    todo!();
-                }
-                3 => {
-                    let a = self.registers3[4] as u32;
-                    let big_addr: u32 = a << 10;
-                    let addr: u32 = 0x3ff & addr as u32 | big_addr;
-                    let addr = addr & (cart.nonvolatile.chr_rom.len() as u32 - 1);
-                    Some(cart.nonvolatile.chr_rom[addr as usize])
-                }
-                _ => unreachable!(),
-            },
-            0x1400..=0x17ff => match self.registers[1] & 3 {
-                0 => {
-                    let a = self.registers3[7] as u32;
-                    let big_addr: u32 = a << 13;
-                    let addr: u32 = addr as u32 | big_addr;
-                    let addr = addr & (cart.nonvolatile.chr_rom.len() as u32 - 1);
-                    Some(cart.nonvolatile.chr_rom[addr as usize])
-                }
-                1 => {
-                    todo!();
-                }
-                2 => {
-                    todo!();
-                }
-                3 => {
-                    let a = self.registers3[5] as u32;
-                    let big_addr: u32 = a << 10;
-                    let addr: u32 = 0x3ff & addr as u32 | big_addr;
-                    let addr = addr & (cart.nonvolatile.chr_rom.len() as u32 - 1);
-                    Some(cart.nonvolatile.chr_rom[addr as usize])
-                }
-                _ => unreachable!(),
-            },
-            0x1800..=0x1bff => match self.registers[1] & 3 {
-                0 => {
-                    let a = self.registers3[7] as u32;
-                    let big_addr: u32 = a << 13;
-                    let addr: u32 = addr as u32 | big_addr;
-                    let addr = addr & (cart.nonvolatile.chr_rom.len() as u32 - 1);
-                    Some(cart.nonvolatile.chr_rom[addr as usize])
-                }
-                1 => {
-                    todo!();
-                }
-                2 => {
-                    todo!();
-                }
-                3 => {
-                    let a = self.registers3[6] as u32;
-                    let big_addr: u32 = a << 10;
-                    let addr: u32 = 0x3ff & addr as u32 | big_addr;
-                    let addr = addr & (cart.nonvolatile.chr_rom.len() as u32 - 1);
-                    Some(cart.nonvolatile.chr_rom[addr as usize])
-                }
-                _ => unreachable!(),
-            },
-            0x1c00..=0x1fff => match self.registers[1] & 3 {
-                0 => {
-                    let a = self.registers3[7] as u32;
-                    let big_addr: u32 = a << 13;
-                    let addr: u32 = addr as u32 | big_addr;
-                    let addr = addr & (cart.nonvolatile.chr_rom.len() as u32 - 1);
-                    Some(cart.nonvolatile.chr_rom[addr as usize])
+            0x2000..=0x3eff => {
+                let quadrant = (addr >> 10) & 3;
+                match (self.registers[5] >> (quadrant * 2)) & 3 {
+                    0 => (false, false),
+                    1 => (true, false),
+                    _ => (false, true),
                 }
-                1 => {
-                    todo!();
+            }
+            _ => (false, false),
+        }
+    }
+
+    /// Read a nametable that is mapped to internal ram or to fill mode
+    fn mapped_nametable_read(&self, addr: u16) -> Option<u8> {
+        let quadrant = (addr >> 10) & 3;
+        let attribute = (addr & 0x3ff) >= 0x3c0;
+        match (self.registers[5] >> (quadrant * 2)) & 3 {
+            2 => {
+                if self.exram_mode() < 2 {
+                    Some(self.int_ram[(addr & 0x3ff) as usize])
+                } else {
+                    Some(0)
                 }
-                2 => {
-                    todo!();
+            }
+            3 => {
+                if attribute {
+                    Some((self.registers[7] & 3) * 0x55)
+                } else {
+                    Some(self.registers[6])
                 }
-                3 => {
-                    let a = self.registers3[7] as u32;
-                    let big_addr: u32 = a << 10;
-                    let addr: u32 = 0x3ff & addr as u32 | big_addr;
-                    let addr = addr & (cart.nonvolatile.chr_rom.len() as u32 - 1);
-                    Some(cart.nonvolatile.chr_rom[addr as usize])
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns true when the ppu is currently rendering a frame
+    fn rendering(&self) -> bool {
+        self.get_inframe() && (self.ppumask & 0x18) != 0
+    }
+
+    /// Returns true when the ppu is fetching sprite data
+    fn sprite_fetch(&self) -> bool {
+        (128..160).contains(&self.fetch_count)
+    }
+
+    /// Returns the tile column being fetched by the ppu for the background, and true if it is for the next scanline.
+    /// The first two tiles of a scanline are fetched at the end of the previous scanline.
+    fn fetch_tile(&self) -> Option<(u8, bool)> {
+        match self.fetch_count {
+            0..=127 => Some((self.fetch_count / 4 + 2, false)),
+            128..=159 => None,
+            160..=167 => Some(((self.fetch_count - 160) / 4, true)),
+            _ => Some((2, true)),
+        }
+    }
+
+    /// Returns true when the given tile column is inside the vertical split region
+    fn split_active(&self, tile: u8) -> bool {
+        let control = self.registers4[0];
+        if (control & 0x80) == 0 || self.exram_mode() >= 2 {
+            return false;
+        }
+        let threshold = control & 0x1f;
+        if (control & 0x40) != 0 {
+            tile >= threshold
+        } else {
+            tile < threshold
+        }
+    }
+
+    /// Calculate the vertical scroll position in the split region
+    fn split_y(&self, next_line: bool) -> u16 {
+        let line = self.scanline as u16 + next_line as u16;
+        (self.registers4[1] as u16 + line) % 240
+    }
+
+    /// Calculate the nametable data the mmc5 substitutes while rendering, for the vertical split and extended attributes
+    fn render_override(&self, addr: u16) -> Option<u8> {
+        if !self.rendering() || !(0x2000..=0x3eff).contains(&addr) {
+            return None;
+        }
+        let (tile, next_line) = self.fetch_tile()?;
+        let tile = tile & 0x1f;
+        let attribute = (addr & 0x3ff) >= 0x3c0;
+        if self.split_active(tile) {
+            let y = self.split_y(next_line);
+            if attribute {
+                let index = 0x3c0 + (y / 32) * 8 + tile as u16 / 4;
+                let shift = ((y / 16) & 1) * 4 + ((tile as u16 / 2) & 1) * 2;
+                let palette = (self.int_ram[index as usize] >> shift) & 3;
+                Some(palette * 0x55)
+            } else {
+                let index = (y / 8) * 32 + tile as u16;
+                Some(self.int_ram[index as usize])
+            }
+        } else if attribute && self.exram_mode() == 1 {
+            Some((self.exram_tile >> 6) * 0x55)
+        } else {
+            None
+        }
+    }
+
+    /// Returns true when chr banks should come from 0x5128 - 0x512b
+    fn use_chr_b(&self) -> bool {
+        if (self.ppuctrl & 0x20) != 0 && self.rendering() {
+            !self.sprite_fetch()
+        } else {
+            self.last_chr_b
+        }
+    }
+
+    /// Calculate the address into chr memory for the given ppu address, using the normal chr banks
+    fn chr_address(&self, addr: u16, set_b: bool) -> u32 {
+        let a = addr as usize;
+        let (bank, shift) = if set_b {
+            match self.registers[1] & 3 {
+                0 => (self.chr_b[3], 13),
+                1 => (self.chr_b[3], 12),
+                2 => (self.chr_b[((a >> 11) & 1) * 2 + 1], 11),
+                _ => (self.chr_b[(a >> 10) & 3], 10),
+            }
+        } else {
+            match self.registers[1] & 3 {
+                0 => (self.chr_a[7], 13),
+                1 => (self.chr_a[((a >> 12) & 1) * 4 + 3], 12),
+                2 => (self.chr_a[((a >> 11) & 3) * 2 + 1], 11),
+                _ => (self.chr_a[(a >> 10) & 7], 10),
+            }
+        };
+        ((bank as u32) << shift) | (addr as u32 & ((1 << shift) - 1))
+    }
+
+    /// Calculate the address into chr memory for a fetch by the ppu, including the split and extended attributes
+    fn render_chr_address(&self, addr: u16) -> u32 {
+        if self.rendering() && !self.sprite_fetch() {
+            if self.split_tile {
+                let y = self.split_y(self.fetch_tile().map(|(_, n)| n).unwrap_or(false));
+                let bank = self.registers4[2] as u32;
+                return (bank << 12) | (addr & 0xff8) as u32 | (y & 7) as u32;
+            } else if self.exram_mode() == 1 {
+                let bank =
+                    (self.exram_tile & 0x3f) as u32 | ((self.registers3[16] & 3) as u32) << 6;
+                return (bank << 12) | (addr & 0xfff) as u32;
+            }
+        }
+        self.chr_address(addr, self.use_chr_b())
+    }
+
+    /// Perform a ppu read operation, render is true for fetches done by the ppu while rendering
+    fn ppu_read(&self, addr: u16, cart: &NesCartridgeData, render: bool) -> Option<u8> {
+        let v = Vec::new();
+        let chr = if !cart.volatile.chr_ram.is_empty() {
+            &cart.volatile.chr_ram
+        } else if !cart.nonvolatile.chr_rom.is_empty() {
+            &cart.nonvolatile.chr_rom
+        } else {
+            &v
+        };
+        match addr {
+            0..=0x1fff => {
+                if chr.is_empty() {
+                    return None;
                 }
-                _ => unreachable!(),
-            },
-            0x2000..=0x3eff => {
-                //nametables
-                todo!();
+                let addr2 = if render {
+                    self.render_chr_address(addr)
+                } else {
+                    self.chr_address(addr, self.last_chr_b)
+                };
+                let addr2 = addr2 & (chr.len() as u32 - 1);
+                Some(chr[addr2 as usize])
             }
+            0x2000..=0x3eff => self.mapped_nametable_read(addr),
             _ => None,
         }
     }
 
+    /// Calculate the prg address for a cpu address. Returns true and the address for rom, false and the address for ram.
+    fn prg_address(&self, addr: u16) -> Option<(bool, u32)> {
+        let r = &self.registers2;
+        let (reg, mask, always_rom) = match (self.registers[0] & 3, addr) {
+            (_, 0x6000..=0x7fff) => (r[0] & 0x7f, 0, false),
+            (0, 0x8000..=0xffff) => (r[4], 3, true),
+            (1, 0x8000..=0xbfff) => (r[2], 1, false),
+            (1, 0xc000..=0xffff) => (r[4], 1, true),
+            (2, 0x8000..=0xbfff) => (r[2], 1, false),
+            (2, 0xc000..=0xdfff) => (r[3], 0, false),
+            (3, 0x8000..=0x9fff) => (r[1], 0, false),
+            (3, 0xa000..=0xbfff) => (r[2], 0, false),
+            (3, 0xc000..=0xdfff) => (r[3], 0, false),
+            (_, 0xe000..=0xffff) => (r[4], 0, true),
+            _ => return None,
+        };
+        let bank = ((reg & 0x7f & !mask) | ((addr >> 13) as u8 & mask)) as u32;
+        let rom = always_rom || (reg & 0x80) != 0;
+        let bank = if rom { bank } else { bank & 7 };
+        Some((rom, (bank << 13) | (addr & 0x1fff) as u32))
+    }
+
+    /// Returns true when the prg ram write protection is disabled
+    fn prg_ram_writable(&self) -> bool {
+        (self.registers[2] & 3) == 2 && (self.registers[3] & 3) == 1
+    }
+
     /// Get the inframe flag
     fn get_inframe(&self) -> bool {
         (self.irq & 0x40) != 0
@@ -283,18 +315,63 @@ impl Mapper05 {
 
     /// Set the irq pending flag
     fn set_irq(&mut self) {
-        println!("Set mmc5 irq");
         self.irq |= 0x80;
     }
+
+    /// Watch the reads done by the ppu for the start of each scanline, three reads of the same nametable address in a row.
+    fn detect_scanline(&mut self, addr: u16) {
+        let t1 = (0x2000..=0x2fff).contains(&addr);
+        let mut detected = false;
+        if t1 && Some(addr) == self.last_ppu_address {
+            self.address_match += 1;
+            if self.address_match == 2 {
+                detected = true;
+                if !self.get_inframe() {
+                    self.set_inframe(true);
+                    self.scanline = 0;
+                } else {
+                    self.scanline = self.scanline.wrapping_add(1);
+                    if self.scanline == self.registers4[3] {
+                        self.set_irq();
+                    }
+                }
+            }
+        } else {
+            self.address_match = 0;
+        }
+        self.last_ppu_address = Some(addr);
+        self.fetch_count = if detected {
+            1
+        } else {
+            self.fetch_count.saturating_add(1)
+        };
+    }
+
+    /// Leave the frame, done when the ppu stops rendering
+    fn leave_frame(&mut self) {
+        self.set_inframe(false);
+        self.last_ppu_address = None;
+    }
 }
 
 impl NesMapperTrait for Mapper05 {
     fn irq(&self) -> bool {
-        let i = (self.irq & 0x80) != 0;
-        if i {
-            println!("mmc5 irq fire");
+        ((self.irq & 0x80) != 0 && (self.registers4[4] & 0x80) != 0) || self.audio.irq()
+    }
+
+    fn cpu_cycle(&mut self, _cart: &mut NesCartridgeData) {
+        self.idle = self.idle.saturating_add(1);
+        if self.idle == 3 {
+            self.leave_frame();
         }
-        i
+    }
+
+    fn clock_audio(&mut self, _cart: &mut NesCartridgeData) {
+        self.audio.cycle();
+    }
+
+    fn audio_sample(&self, sample: &mut ExpansionAudio) {
+        sample.set(ExpansionAudioChip::Mmc5, self.audio.audio());
     }
 
     fn cartridge_registers(&self) -> BTreeMap<String, u8> {
@@ -311,12 +388,15 @@ impl NesMapperTrait for Mapper05 {
         for i in 0..7 {
             hm.insert(format!("{:x}", 0x5200 + i), self.registers4[i]);
         }
+        hm.insert("Scanline".to_string(), self.scanline);
+        hm.insert("IRQ Status".to_string(), self.irq);
         hm.insert("Mapper".to_string(), 5);
         hm
     }
 
     fn memory_cycle_dump(&self, cart: &NesCartridgeData, addr: u16) -> Option<u8> {
         match addr {
+            0x5010 | 0x5015 => self.audio.dump(addr),
             0x5200..=0x5203 => {
                 let i = addr & 7;
                 Some(self.registers4[i as usize])
@@ -332,146 +412,49 @@ impl NesMapperTrait for Mapper05 {
                 let mul = (mul >> 8) as u8;
                 Some(mul)
             }
-            0x6000..=0x7fff => {
-                let a = (self.registers2[0] & 0x7f) as u32;
-                let addr = (addr as u32 & 0x1FFF) | (a << 13);
-                let addr = addr & (cart.volatile.prg_ram.len() as u32 - 1);
-                Some(cart.volatile.prg_ram[addr as usize])
-            }
-            0x8000..=0x9fff => match self.registers[0] & 3 {
-                0 => {
-                    let a = (self.registers2[4] & 0x7f) as u32;
-                    let addr = (addr as u32 & 0x1FFF) | (a << 13);
-                    let addr = addr & (cart.nonvolatile.prg_rom.len() as u32 - 1);
-                    Some(cart.nonvolatile.prg_rom[addr as usize])
-                }
-                1 | 2 => {
-                    let a = (self.registers2[2] & 0x7e) as u32;
-                    let addr = (addr as u32 & 0x3FFF) | (a << 13);
-                    let rom = (self.registers2[2] & 0x80) != 0;
-                    if rom {
-                        let addr = addr & (cart.nonvolatile.prg_rom.len() as u32 - 1);
-                        Some(cart.nonvolatile.prg_rom[addr as usize])
-                    } else {
-                        let addr = addr & (cart.volatile.prg_ram.len() as u32 - 1);
-                        Some(cart.volatile.prg_ram[addr as usize])
-                    }
-                }
-                3 => {
-                    let a = (self.registers2[1] & 0x7f) as u32;
-                    let addr = (addr as u32 & 0x1FFF) | (a << 13);
-                    let rom = (self.registers2[1] & 0x80) != 0;
-                    if rom {
-                        let addr = addr & (cart.nonvolatile.prg_rom.len() as u32 - 1);
-                        Some(cart.nonvolatile.prg_rom[addr as usize])
-                    } else {
-                        let addr = addr & (cart.volatile.prg_ram.len() as u32 - 1);
-                        Some(cart.volatile.prg_ram[addr as usize])
-                    }
-                }
-                _ => unreachable!(),
-            },
-            0xa000..=0xbfff => match self.registers[0] & 3 {
-                0 => {
-                    let a = (self.registers2[4] & 0x7c) as u32;
-                    let addr = (addr as u32 & 0x7FFF) | (a << 13);
-                    let addr = addr & (cart.nonvolatile.prg_rom.len() as u32 - 1);
-                    Some(cart.nonvolatile.prg_rom[addr as usize])
-                }
-                1 | 2 => {
-                    let a = (self.registers2[2] & 0x7e) as u32;
-                    let addr = (addr as u32 & 0x1FFF) | (a << 13);
-                    let rom = (self.registers2[2] & 0x80) != 0;
-                    if rom {
-                        let addr = addr & (cart.nonvolatile.prg_rom.len() as u32 - 1);
-                        Some(cart.nonvolatile.prg_rom[addr as usize])
-                    } else {
-                        let addr = addr & (cart.volatile.prg_ram.len() as u32 - 1);
-                        Some(cart.volatile.prg_ram[addr as usize])
-                    }
-                }
-                3 => {
-                    let a = (self.registers2[2] & 0x7f) as u32;
-                    let addr = (addr as u32 & 0x1FFF) | (a << 13);
-                    let rom = (self.registers2[2] & 0x80) != 0;
-                    if rom {
-                        let addr = addr & (cart.nonvolatile.prg_rom.len() as u32 - 1);
-                        Some(cart.nonvolatile.prg_rom[addr as usize])
-                    } else {
-                        let addr = addr & (cart.volatile.prg_ram.len() as u32 - 1);
-                        Some(cart.volatile.prg_ram[addr as usize])
-                    }
-                }
-                _ => unreachable!(),
-            },
-            0xc000..=0xdfff => match self.registers[0] & 3 {
-                0 => {
-                    let a = (self.registers2[4] & 0x7c) as u32;
-                    let addr = (addr as u32 & 0x7FFF) | (a << 13);
-                    let addr = addr & (cart.nonvolatile.prg_rom.len() as u32 - 1);
-                    Some(cart.nonvolatile.prg_rom[addr as usize])
-                }
-                1 => {
-                    let a = (self.registers2[4] & 0x7e) as u32;
-                    let addr = (addr as u32 & 0x3FFF) | (a << 13);
-                    let addr = addr & (cart.nonvolatile.prg_rom.len() as u32 - 1);
-                    Some(cart.nonvolatile.prg_rom[addr as usize])
+            0x5c00..=0x5fff => {
+                if self.exram_mode() >= 2 {
+                    Some(self.int_ram[(addr & 0x3ff) as usize])
+                } else {
+                    None
                 }
-                2 | 3 => {
-                    let a = (self.registers2[3] & 0x7f) as u32;
-                    let addr = (addr as u32 & 0x1FFF) | (a << 13);
-                    let rom = (self.registers2[3] & 0x80) != 0;
-                    if rom {
-                        let addr = addr & (cart.nonvolatile.prg_rom.len() as u32 - 1);
-                        Some(cart.nonvolatile.prg_rom[addr as usize])
+            }
+            0x6000..=0xffff => match self.prg_address(addr)? {
+                (true, a) => {
+                    let a = a & (cart.nonvolatile.prg_rom.len() as u32 - 1);
+                    Some(cart.nonvolatile.prg_rom[a as usize])
+                }
+                (false, a) => {
+                    if cart.volatile.prg_ram.is_empty() {
+                        None
                     } else {
-                        let addr = addr & (cart.volatile.prg_ram.len() as u32 - 1);
-                        Some(cart.volatile.prg_ram[addr as usize])
+                        let a = a as usize % cart.volatile.prg_ram.len();
+                        Some(cart.volatile.prg_ram[a])
                     }
                 }
-                _ => unreachable!(),
             },
-            0xe000..=0xffff => {
-                let a = (self.registers2[4] & 0x7c) as u32;
-                let addr = (addr as u32 & 0x7FFF) | (a << 13);
-                let addr = addr & (cart.nonvolatile.prg_rom.len() as u32 - 1);
-                Some(cart.nonvolatile.prg_rom[addr as usize])
-            }
             _ => None,
         }
     }
 
-    fn other_memory_read(&mut self, cart: &mut NesCartridgeData, addr: u16) {
-        match addr {
-            //pattern table
-            0..=0x1fff => {
-                //increment tile fetch count
-                if (self.ppuctrl & 0x20) != 0 {
-                    //if count is START, chr banks = sprite
-                    //if count is END, chr banks = background
-                }
-            }
-            0x2000..=0x3eff => {
-                todo!();
-            }
-            _ => {}
-        }
-    }
-
     fn memory_cycle_read(&mut self, cart: &mut NesCartridgeData, addr: u16) -> Option<u8> {
-        match addr {
-            0xfffa | 0xfffb => {
-                self.set_inframe(false);
-                self.last_ppu_address = None;
-            }
-            _ => {}
+        if matches!(addr, 0xfffa | 0xfffb) {
+            self.leave_frame();
         }
         let r = self.memory_cycle_dump(cart, addr);
 
         match addr {
-            0x5203 => {
+            0x5010 => {
+                self.audio.read(addr);
+            }
+            0x5204 => {
                 self.irq &= !0x80;
             }
+            0x8000..=0xbfff => {
+                if let Some(d) = r {
+                    self.audio.pcm_read(d);
+                }
+            }
             _ => {}
         }
         r
@@ -481,6 +464,9 @@ impl NesMapperTrait for Mapper05 {
 
     fn memory_cycle_write(&mut self, cart: &mut NesCartridgeData, addr: u16, data: u8) {
         match addr {
+            0x5000..=0x5015 => {
+                self.audio.write(addr, data);
+            }
             0x5100..=0x5107 => {
                 let i = addr & 7;
                 self.registers[i as usize] = data;
@@ -490,53 +476,47 @@ impl NesMapperTrait for Mapper05 {
                 self.registers2[i as usize] = data;
             }
             0x5120..=0x5130 => {
-                let i = addr - 0x5120;
-                self.registers3[i as usize] = data;
+                let i = (addr - 0x5120) as usize;
+                self.registers3[i] = data;
+                let bank = data as u16 | ((self.registers3[16] & 3) as u16) << 8;
+                match i {
+                    0..=7 => {
+                        self.chr_a[i] = bank;
+                        self.last_chr_b = false;
+                    }
+                    8..=11 => {
+                        self.chr_b[i - 8] = bank;
+                        self.last_chr_b = true;
+                    }
+                    _ => {}
+                }
             }
             0x5200..=0x5206 => {
                 let i = addr - 0x5200;
                 self.registers4[i as usize] = data;
             }
             0x5c00..=0x5fff => {
-                match self.registers[4] & 3 {
+                match self.exram_mode() {
                     // internal ram is read only
                     3 => {}
-                    // writes work normally when rendering is enabled
+                    2 => {
+                        self.int_ram[(addr & 0x3ff) as usize] = data;
+                    }
+                    // writes only work while rendering
                     _ => {
-                        let data = if (self.ppumask & 0x18) != 0 { data } else { 0 };
-                        let i = addr & 0x3ff;
-                        self.int_ram[i as usize] = data;
+                        let data = if self.get_inframe() { data } else { 0 };
+                        self.int_ram[(addr & 0x3ff) as usize] = data;
+                    }
+                }
+            }
+            0x6000..=0xdfff => {
+                if let Some((false, a)) = self.prg_address(addr) {
+                    if self.prg_ram_writable() && !cart.volatile.prg_ram.is_empty() {
+                        let a = a as usize % cart.volatile.prg_ram.len();
+                        cart.volatile.prg_ram[a] = data;
                     }
                 }
             }
-            0x6000..=0x7fff => match self.registers[0] & 3 {
-                0 => {}
-                1 => {}
-                2 => {}
-                3 => {}
-                _ => unreachable!(),
-            },
-            0x8000..=0x9fff => match self.registers[0] & 3 {
-                0 => {}
-                1 => {}
-                2 => {}
-                3 => {}
-                _ => unreachable!(),
-            },
-            0xa000..=0xbfff => match self.registers[0] & 3 {
-                0 => {}
-                1 => {}
-                2 => {}
-                3 => {}
-                _ => unreachable!(),
-            },
-            0xc000..=0xdfff => match self.registers[0] & 3 {
-                0 => {}
-                1 => {}
-                2 => {}
-                3 => {}
-                _ => unreachable!(),
-            },
             0xe000..=0xffff => {
                 //always rom so do nothing here
             }
@@ -551,8 +531,7 @@ impl NesMapperTrait for Mapper05 {
             }
             0x2001 => {
                 if (data & 0x18) == 0 {
-                    self.set_inframe(false);
-                    self.last_ppu_address = None;
+                    self.leave_frame();
                 }
                 self.ppumask = data;
             }
@@ -560,56 +539,68 @@ impl NesMapperTrait for Mapper05 {
         }
     }
 
+    fn exact_ppu_fetches(&self) -> bool {
+        true
+    }
+
     fn ppu_peek_address(&self, addr: u16, cart: &NesCartridgeData) -> (bool, bool, Option<u8>) {
         let (mirror, thing) = self.check_mirroring(addr);
-        let data = self.ppu_read(addr, cart);
+        let data = self.ppu_read(addr, cart, false);
         (mirror, thing, data)
     }
 
     fn ppu_memory_cycle_address(&mut self, addr: u16) -> (bool, bool) {
         self.ppu_address = addr;
-        self.check_mirroring(addr)
+        if (0x2000..=0x3eff).contains(&addr) && (addr & 0x3ff) < 0x3c0 && !self.sprite_fetch() {
+            self.split_tile = self.rendering()
+                && self
+                    .fetch_tile()
+                    .map(|(t, _)| self.split_active(t))
+                    .unwrap_or(false);
+            self.exram_tile = self.int_ram[(addr & 0x3ff) as usize];
+        }
+        self.nametable_override = self.render_override(addr);
+        if self.nametable_override.is_some() {
+            (false, true)
+        } else {
+            self.check_mirroring(addr)
+        }
     }
 
     fn ppu_memory_cycle_read(&mut self, cart: &mut NesCartridgeData) -> Option<u8> {
-        if (0..=0x1fff).contains(&self.ppu_address) {
-            self.tile_count += 1;
-        }
-        let t1 = (0x2000..=0x2fff).contains(&self.ppu_address);
-        if t1 {
-            println!("mmc5 address range match {:X}", self.ppu_address);
-        }
-        if t1 && Some(self.ppu_address) == self.last_ppu_address {
-            self.address_match += 1;
-            println!("mmc5 address match {}", self.address_match);
-            if self.address_match == 2 {
-                if !self.get_inframe() {
-                    self.set_inframe(true);
-                    self.scanline = 0;
-                } else {
-                    self.scanline += 1;
-                    if self.scanline == self.registers4[3] {
-                        self.set_irq();
-                    }
-                }
-                self.tile_count = 0;
-            }
+        let addr = self.ppu_address;
+        let r = if let Some(d) = self.nametable_override {
+            Some(d)
         } else {
-            self.address_match = 0;
-        }
+            self.ppu_read(addr, cart, true)
+        };
         self.idle = 0;
-        self.last_ppu_address = Some(self.ppu_address);
-        self.ppu_read(self.ppu_address, cart)
+        self.detect_scanline(addr);
+        r
     }
 
     fn ppu_memory_cycle_write(&mut self, cart: &mut NesCartridgeData, data: u8) {
-        self.idle += 1;
-        if self.idle == 3 {
-            self.set_inframe(false);
-            self.last_ppu_address = None;
-        }
         let addr = self.ppu_address;
+        match addr {
+            0..=0x1fff => {
+                if !cart.volatile.chr_ram.is_empty() {
+                    let addr2 = self.chr_address(addr, self.last_chr_b)
+                        & (cart.volatile.chr_ram.len() as u32 - 1);
+                    cart.volatile.chr_ram[addr2 as usize] = data;
+                }
+            }
+            0x2000..=0x3eff => {
+                let quadrant = (addr >> 10) & 3;
+                if (self.registers[5] >> (quadrant * 2)) & 3 == 2 && self.exram_mode() < 2 {
+                    self.int_ram[(addr & 0x3ff) as usize] = data;
+                }
+            }
+            _ => {}
+        }
     }
 
-    fn rom_byte_hack(&mut self, _cart: &mut NesCartridgeData, _addr: u32, _new_byte: u8) {}
+    fn rom_byte_hack(&mut self, cart: &mut NesCartridgeData, addr: u32, new_byte: u8) {
+        let addr = addr as usize % cart.nonvolatile.prg_rom.len();
+        cart.nonvolatile.prg_rom[addr] = new_byte;
+    }
 }
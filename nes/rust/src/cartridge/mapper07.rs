@@ -0,0 +1,136 @@
+//! Implements mapper 07, the axrom boards with single screen mirroring
+
+use std::collections::BTreeMap;
+
+use crate::cartridge::NesCartridgeData;
+use crate::cartridge::{NesMapper, NesMapperTrait};
+
+/// Mapper 07
+#[non_exhaustive]
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Mapper07 {
+    /// The ppu address for ppu addressing
+    ppu_address: u16,
+    /// The bank select register, 32kb prg bank and nametable select
+    bank: u8,
+    /// True when writes to the bank select register conflict with the rom
+    bus_conflicts: bool,
+}
+
+impl Mapper07 {
+    /// Create a new mapper07
+    pub fn new(d: &NesCartridgeData) -> NesMapper {
+        NesMapper::from(Self {
+            ppu_address: 0,
+            bank: 0,
+            bus_conflicts: d.bus_conflicts(),
+        })
+    }
+
+    /// Check the mirroring bit for the ppu addressing, both nametables are selected by the bank register.
+    fn check_mirroring(&self, _addr: u16) -> (bool, bool) {
+        ((self.bank & 0x10) != 0, false)
+    }
+
+    /// Perform a ppu read operation
+    fn ppu_read(&self, addr: u16, cart: &NesCartridgeData) -> Option<u8> {
+        let v = Vec::new();
+        let chr = if !cart.volatile.chr_ram.is_empty() {
+            &cart.volatile.chr_ram
+        } else if !cart.nonvolatile.chr_rom.is_empty() {
+            &cart.nonvolatile.chr_rom
+        } else {
+            &v
+        };
+        if chr.is_empty() || addr >= 0x2000 {
+            return None;
+        }
+        Some(chr[addr as usize & (chr.len() - 1)])
+    }
+}
+
+impl NesMapperTrait for Mapper07 {
+    fn irq(&self) -> bool {
+        false
+    }
+
+    fn cartridge_registers(&self) -> BTreeMap<String, u8> {
+        let mut hm = BTreeMap::new();
+        hm.insert("PRG bank".to_string(), self.bank & 0xf);
+        hm.insert("Nametable".to_string(), (self.bank >> 4) & 1);
+        hm.insert("Mapper".to_string(), 7);
+        hm
+    }
+
+    fn memory_cycle_dump(&self, cart: &NesCartridgeData, addr: u16) -> Option<u8> {
+        match addr {
+            0x6000..=0x7fff => {
+                if cart.volatile.prg_ram.is_empty() {
+                    None
+                } else {
+                    let addr2 = (addr & 0x1fff) as usize % cart.volatile.prg_ram.len();
+                    Some(cart.volatile.prg_ram[addr2])
+                }
+            }
+            0x8000..=0xffff => {
+                let addr2 = ((self.bank & 0xf) as u32) << 15 | (addr & 0x7fff) as u32;
+                let addr3 = addr2 & (cart.nonvolatile.prg_rom.len() as u32 - 1);
+                Some(cart.nonvolatile.prg_rom[addr3 as usize])
+            }
+            _ => None,
+        }
+    }
+
+    fn memory_cycle_read(&mut self, cart: &mut NesCartridgeData, addr: u16) -> Option<u8> {
+        self.memory_cycle_dump(cart, addr)
+    }
+
+    fn memory_cycle_nop(&mut self) {}
+
+    fn memory_cycle_write(&mut self, cart: &mut NesCartridgeData, addr: u16, data: u8) {
+        match addr {
+            0x6000..=0x7fff => {
+                if !cart.volatile.prg_ram.is_empty() {
+                    let addr2 = (addr & 0x1fff) as usize % cart.volatile.prg_ram.len();
+                    cart.volatile.prg_ram[addr2] = data;
+                }
+            }
+            0x8000..=0xffff => {
+                self.bank = if self.bus_conflicts {
+                    data & self.memory_cycle_dump(cart, addr).unwrap_or(0xff)
+                } else {
+                    data
+                };
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_peek_address(&self, addr: u16, cart: &NesCartridgeData) -> (bool, bool, Option<u8>) {
+        let (mirror, thing) = self.check_mirroring(addr);
+        let data = self.ppu_read(addr, cart);
+        (mirror, thing, data)
+    }
+
+    fn ppu_memory_cycle_address(&mut self, addr: u16) -> (bool, bool) {
+        self.ppu_address = addr;
+        self.check_mirroring(addr)
+    }
+
+    fn ppu_memory_cycle_read(&mut self, cart: &mut NesCartridgeData) -> Option<u8> {
+        self.ppu_read(self.ppu_address, cart)
+    }
+
+    fn ppu_memory_cycle_write(&mut self, cart: &mut NesCartridgeData, data: u8) {
+        if cart.volatile.chr_ram.is_empty() || self.ppu_address >= 0x2000 {
+            return;
+        }
+        let addr2 = self.ppu_address as usize & (cart.volatile.chr_ram.len() - 1);
+        cart.volatile.chr_ram[addr2] = data;
+    }
+
+    fn rom_byte_hack(&mut self, cart: &mut NesCartridgeData, addr: u32, new_byte: u8) {
+        let addr = addr as usize % cart.nonvolatile.prg_rom.len();
+        cart.nonvolatile.prg_rom[addr] = new_byte;
+    }
+}
@@ -0,0 +1,204 @@
+//! Implements mapper 10, also known as MMC4
+
+use std::collections::BTreeMap;
+
+use crate::cartridge::NesCartridgeData;
+use crate::cartridge::{NesMapper, NesMapperTrait};
+
+/// Mapper 10
+#[non_exhaustive]
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Mapper10 {
+    /// True when mirroring is horizontal
+    mirror_horizontal: bool,
+    /// The ppu address for ppu addressing
+    ppu_address: u16,
+    /// The 16kb prg rom bank for 0x8000
+    prg_bank: u8,
+    /// The chr banks, $FD/0000, $FE/0000, $FD/1000, $FE/1000
+    chr_banks: [u8; 4],
+    /// The two chr latches, holding either 0xFD or 0xFE
+    latches: [u8; 2],
+}
+
+impl Mapper10 {
+    /// Create a new Mapper10
+    pub fn new(_d: &NesCartridgeData) -> NesMapper {
+        NesMapper::from(Self {
+            mirror_horizontal: false,
+            ppu_address: 0,
+            prg_bank: 0,
+            chr_banks: [0; 4],
+            latches: [0xFE; 2],
+        })
+    }
+
+    /// Check the mirroring bit for the ppu addressing.
+    fn check_mirroring(&self, addr: u16) -> (bool, bool) {
+        let a10 = if !self.mirror_horizontal {
+            (addr & 1 << 10) != 0
+        } else {
+            (addr & 1 << 11) != 0
+        };
+        (a10, false)
+    }
+
+    /// Update the chr latches, based on the address that the ppu just finished fetching from.
+    fn update_latches(&mut self, addr: u16) {
+        match addr {
+            0x0fd8..=0x0fdf => self.latches[0] = 0xFD,
+            0x0fe8..=0x0fef => self.latches[0] = 0xFE,
+            0x1fd8..=0x1fdf => self.latches[1] = 0xFD,
+            0x1fe8..=0x1fef => self.latches[1] = 0xFE,
+            _ => {}
+        }
+    }
+
+    /// Calculate the address into chr memory for the given ppu address
+    fn chr_address(&self, addr: u16) -> u32 {
+        let half = ((addr >> 12) & 1) as usize;
+        let select = if self.latches[half] == 0xFD { 0 } else { 1 };
+        let bank = (self.chr_banks[half * 2 + select] & 0x1F) as u32;
+        (bank << 12) | (addr & 0xfff) as u32
+    }
+
+    /// Perform a ppu read operation
+    fn ppu_read(&self, addr: u16, cart: &NesCartridgeData) -> Option<u8> {
+        let v = Vec::new();
+        let chr = if !cart.volatile.chr_ram.is_empty() {
+            &cart.volatile.chr_ram
+        } else if !cart.nonvolatile.chr_rom.is_empty() {
+            &cart.nonvolatile.chr_rom
+        } else {
+            &v
+        };
+        if chr.is_empty() {
+            return None;
+        }
+        match addr {
+            0..=0x1fff => {
+                let addr2 = self.chr_address(addr) & (chr.len() as u32 - 1);
+                Some(chr[addr2 as usize])
+            }
+            _ => None,
+        }
+    }
+}
+
+impl NesMapperTrait for Mapper10 {
+    fn irq(&self) -> bool {
+        false
+    }
+
+    fn cartridge_registers(&self) -> BTreeMap<String, u8> {
+        let mut hm = BTreeMap::new();
+        hm.insert("PRG bank".to_string(), self.prg_bank);
+        hm.insert("CHR FD/0000".to_string(), self.chr_banks[0]);
+        hm.insert("CHR FE/0000".to_string(), self.chr_banks[1]);
+        hm.insert("CHR FD/1000".to_string(), self.chr_banks[2]);
+        hm.insert("CHR FE/1000".to_string(), self.chr_banks[3]);
+        hm.insert("Latch 0".to_string(), self.latches[0]);
+        hm.insert("Latch 1".to_string(), self.latches[1]);
+        hm.insert("Mirroring".to_string(), self.mirror_horizontal as u8);
+        hm.insert("Mapper".to_string(), 10);
+        hm
+    }
+
+    fn memory_cycle_dump(&self, cart: &NesCartridgeData, addr: u16) -> Option<u8> {
+        match addr {
+            0x6000..=0x7fff => {
+                if cart.nonvolatile.trainer.is_some() && (0x7000..=0x71ff).contains(&addr) {
+                    let c = cart.nonvolatile.trainer.as_ref().unwrap();
+                    let addr = addr & 0x1ff;
+                    Some(c[addr as usize])
+                } else {
+                    let mut addr2 = addr & 0x1fff;
+                    if !cart.volatile.prg_ram.is_empty() {
+                        addr2 %= cart.volatile.prg_ram.len() as u16;
+                        Some(cart.volatile.prg_ram[addr2 as usize])
+                    } else {
+                        None
+                    }
+                }
+            }
+            0x8000..=0xbfff => {
+                let bank = ((self.prg_bank & 0xF) as u32) << 14;
+                let addr2 = (addr & 0x3fff) as u32 | bank;
+                let addr3 = addr2 & (cart.nonvolatile.prg_rom.len() as u32 - 1);
+                Some(cart.nonvolatile.prg_rom[addr3 as usize])
+            }
+            0xc000..=0xffff => {
+                //fixed to the last bank
+                let addr2 = (addr & 0x3fff) as u32 | (0xFFFF << 14);
+                let addr3 = addr2 & (cart.nonvolatile.prg_rom.len() as u32 - 1);
+                Some(cart.nonvolatile.prg_rom[addr3 as usize])
+            }
+            _ => None,
+        }
+    }
+
+    fn memory_cycle_read(&mut self, cart: &mut NesCartridgeData, addr: u16) -> Option<u8> {
+        self.memory_cycle_dump(cart, addr)
+    }
+
+    fn memory_cycle_nop(&mut self) {}
+
+    fn memory_cycle_write(&mut self, cart: &mut NesCartridgeData, addr: u16, data: u8) {
+        match addr {
+            0x6000..=0x7fff => {
+                if cart.nonvolatile.trainer.is_some() && (0x7000..=0x71ff).contains(&addr) {
+                    let c = cart.nonvolatile.trainer.as_mut().unwrap();
+                    let addr = addr & 0x1ff;
+                    c[addr as usize] = data;
+                } else {
+                    let mut addr2 = addr & 0x1fff;
+                    if !cart.volatile.prg_ram.is_empty() {
+                        addr2 %= cart.volatile.prg_ram.len() as u16;
+                        cart.volatile.prg_ram[addr2 as usize] = data;
+                    }
+                }
+            }
+            0xa000..=0xafff => {
+                self.prg_bank = data & 0xF;
+            }
+            0xb000..=0xefff => {
+                let i = (addr - 0xb000) >> 12;
+                self.chr_banks[i as usize] = data & 0x1F;
+            }
+            0xf000..=0xffff => {
+                self.mirror_horizontal = (data & 1) != 0;
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_peek_address(&self, addr: u16, cart: &NesCartridgeData) -> (bool, bool, Option<u8>) {
+        let (mirror, thing) = self.check_mirroring(addr);
+        let data = self.ppu_read(addr, cart);
+        (mirror, thing, data)
+    }
+
+    fn ppu_memory_cycle_address(&mut self, addr: u16) -> (bool, bool) {
+        //The latches switch after the fetch of the tile that triggers them has completed
+        self.update_latches(self.ppu_address);
+        self.ppu_address = addr;
+        self.check_mirroring(addr)
+    }
+
+    fn ppu_memory_cycle_read(&mut self, cart: &mut NesCartridgeData) -> Option<u8> {
+        self.ppu_read(self.ppu_address, cart)
+    }
+
+    fn ppu_memory_cycle_write(&mut self, cart: &mut NesCartridgeData, data: u8) {
+        if cart.volatile.chr_ram.is_empty() || self.ppu_address >= 0x2000 {
+            return;
+        }
+        let addr2 = self.chr_address(self.ppu_address) & (cart.volatile.chr_ram.len() as u32 - 1);
+        cart.volatile.chr_ram[addr2 as usize] = data;
+    }
+
+    fn rom_byte_hack(&mut self, cart: &mut NesCartridgeData, addr: u32, new_byte: u8) {
+        let addr = addr as usize % cart.nonvolatile.prg_rom.len();
+        cart.nonvolatile.prg_rom[addr] = new_byte;
+    }
+}
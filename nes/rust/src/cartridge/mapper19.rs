@@ -0,0 +1,287 @@
+//! Implements mapper 19, the namco 163
+
+use std::collections::BTreeMap;
+
+use crate::cartridge::audio::namco163::Namco163Audio;
+use crate::cartridge::audio::{ExpansionAudio, ExpansionAudioChip};
+use crate::cartridge::NesCartridgeData;
+use crate::cartridge::{NesMapper, NesMapperTrait};
+
+/// Mapper 19
+#[non_exhaustive]
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Mapper19 {
+    /// The ppu address for ppu addressing
+    ppu_address: u16,
+    /// The 1kb chr banks for 0x0000 - 0x1fff
+    chr_banks: [u8; 8],
+    /// The 1kb banks for the four nametables, values 0xe0 and above select the internal vram
+    nametable_banks: [u8; 4],
+    /// The registers at 0xe000, 0xe800, and 0xf000 selecting the 8kb prg banks
+    prg_banks: [u8; 3],
+    /// The irq counter, the upper bit is the enable
+    irq_counter: u16,
+    /// True when the irq is pending
+    irq_pending: bool,
+    /// The sound ram address port at 0xf800, also controls prg ram write protection
+    ram_address: u8,
+    /// The expansion audio
+    audio: Namco163Audio,
+}
+
+impl Mapper19 {
+    /// Create a new Mapper19
+    pub fn new(_d: &NesCartridgeData) -> NesMapper {
+        NesMapper::from(Self {
+            ppu_address: 0,
+            chr_banks: [0; 8],
+            nametable_banks: [0xe0; 4],
+            prg_banks: [0; 3],
+            irq_counter: 0,
+            irq_pending: false,
+            ram_address: 0,
+            audio: Namco163Audio::new(),
+        })
+    }
+
+    /// Returns true when a chr bank of 0xe0 and above selects the internal vram in the pattern tables.
+    /// Bits 6 and 7 of 0xe800 prevent this for 0x0000-0x0fff and 0x1000-0x1fff.
+    fn pattern_vram(&self, addr: u16) -> bool {
+        let disable = if addr < 0x1000 { 0x40 } else { 0x80 };
+        addr < 0x2000
+            && self.chr_banks[(addr >> 10) as usize] >= 0xe0
+            && (self.prg_banks[1] & disable) == 0
+    }
+
+    /// Check the nametable banks for the ppu addressing.
+    /// Nametables mapped to chr rom disable the internal vram.
+    fn check_mirroring(&self, addr: u16) -> (bool, bool) {
+        match addr {
+            0..=0x1fff if self.pattern_vram(addr) => {
+                ((self.chr_banks[(addr >> 10) as usize] & 1) != 0, false)
+            }
+            0x2000..=0x3eff => {
+                let bank = self.nametable_banks[((addr >> 10) & 3) as usize];
+                if bank >= 0xe0 {
+                    ((bank & 1) != 0, false)
+                } else {
+                    (false, true)
+                }
+            }
+            _ => (false, false),
+        }
+    }
+
+    /// Perform a ppu read operation
+    fn ppu_read(&self, addr: u16, cart: &NesCartridgeData) -> Option<u8> {
+        let v = Vec::new();
+        let chr = if !cart.volatile.chr_ram.is_empty() {
+            &cart.volatile.chr_ram
+        } else if !cart.nonvolatile.chr_rom.is_empty() {
+            &cart.nonvolatile.chr_rom
+        } else {
+            &v
+        };
+        if chr.is_empty() {
+            return None;
+        }
+        let bank = match addr {
+            0..=0x1fff if self.pattern_vram(addr) => return None,
+            0..=0x1fff => self.chr_banks[(addr >> 10) as usize],
+            0x2000..=0x3eff => {
+                let bank = self.nametable_banks[((addr >> 10) & 3) as usize];
+                if bank >= 0xe0 {
+                    return None;
+                }
+                bank
+            }
+            _ => return None,
+        };
+        let addr2 = ((bank as u32) << 10 | (addr & 0x3ff) as u32) & (chr.len() as u32 - 1);
+        Some(chr[addr2 as usize])
+    }
+
+    /// Returns true when prg ram writes are allowed for the given address
+    fn prg_ram_writable(&self, addr: u16) -> bool {
+        let section = (addr - 0x6000) >> 11;
+        (self.ram_address & 0xf0) == 0x40 && (self.ram_address & (1 << section)) == 0
+    }
+
+    /// Acknowledge the irq
+    fn irq_ack(&mut self) {
+        self.irq_pending = false;
+    }
+}
+
+impl NesMapperTrait for Mapper19 {
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn cpu_cycle(&mut self, _cart: &mut NesCartridgeData) {
+        if (self.irq_counter & 0x8000) != 0 && (self.irq_counter & 0x7fff) != 0x7fff {
+            self.irq_counter += 1;
+            if (self.irq_counter & 0x7fff) == 0x7fff {
+                self.irq_pending = true;
+            }
+        }
+    }
+
+    fn clock_audio(&mut self, cart: &mut NesCartridgeData) {
+        self.audio.cycle(&mut cart.volatile.mapper_ram);
+    }
+
+    fn audio_sample(&self, sample: &mut ExpansionAudio) {
+        sample.set(ExpansionAudioChip::Namco163, self.audio.audio());
+    }
+
+    fn cartridge_registers(&self) -> BTreeMap<String, u8> {
+        let mut hm = BTreeMap::new();
+        for (i, b) in self.chr_banks.iter().enumerate() {
+            hm.insert(format!("CHR bank {}", i), *b);
+        }
+        for (i, b) in self.nametable_banks.iter().enumerate() {
+            hm.insert(format!("Nametable bank {}", i), *b);
+        }
+        hm.insert("PRG bank 8000".to_string(), self.prg_banks[0]);
+        hm.insert("PRG bank A000".to_string(), self.prg_banks[1]);
+        hm.insert("PRG bank C000".to_string(), self.prg_banks[2]);
+        hm.insert("IRQ Counter low".to_string(), self.irq_counter as u8);
+        hm.insert(
+            "IRQ Counter high".to_string(),
+            (self.irq_counter >> 8) as u8,
+        );
+        hm.insert("IRQ Pending".to_string(), self.irq_pending as u8);
+        hm.insert("Sound ram address".to_string(), self.ram_address);
+        hm.insert("Mapper".to_string(), 19);
+        hm
+    }
+
+    fn memory_cycle_dump(&self, cart: &NesCartridgeData, addr: u16) -> Option<u8> {
+        match addr {
+            0x4800..=0x4fff => {
+                let ram = &cart.volatile.mapper_ram;
+                if ram.is_empty() {
+                    None
+                } else {
+                    Some(ram[(self.ram_address & 0x7f) as usize])
+                }
+            }
+            0x5000..=0x57ff => Some(self.irq_counter as u8),
+            0x5800..=0x5fff => Some((self.irq_counter >> 8) as u8),
+            0x6000..=0x7fff => {
+                if cart.volatile.prg_ram.is_empty() {
+                    None
+                } else {
+                    let addr2 = (addr & 0x1fff) as usize % cart.volatile.prg_ram.len();
+                    Some(cart.volatile.prg_ram[addr2])
+                }
+            }
+            0x8000..=0xdfff => {
+                let bank = (self.prg_banks[((addr - 0x8000) >> 13) as usize] & 0x3f) as u32;
+                let addr2 = (bank << 13) | (addr & 0x1fff) as u32;
+                let addr3 = addr2 & (cart.nonvolatile.prg_rom.len() as u32 - 1);
+                Some(cart.nonvolatile.prg_rom[addr3 as usize])
+            }
+            0xe000..=0xffff => {
+                //fixed to the last bank
+                let addr2 = (addr & 0x1fff) as u32 | (0xFFFF << 13);
+                let addr3 = addr2 & (cart.nonvolatile.prg_rom.len() as u32 - 1);
+                Some(cart.nonvolatile.prg_rom[addr3 as usize])
+            }
+            _ => None,
+        }
+    }
+
+    fn memory_cycle_read(&mut self, cart: &mut NesCartridgeData, addr: u16) -> Option<u8> {
+        let r = self.memory_cycle_dump(cart, addr);
+        if (0x4800..=0x4fff).contains(&addr) && (self.ram_address & 0x80) != 0 {
+            self.ram_address = 0x80 | (self.ram_address.wrapping_add(1) & 0x7f);
+        }
+        r
+    }
+
+    fn memory_cycle_nop(&mut self) {}
+
+    fn memory_cycle_write(&mut self, cart: &mut NesCartridgeData, addr: u16, data: u8) {
+        match addr {
+            0x4800..=0x4fff => {
+                let ram = &mut cart.volatile.mapper_ram;
+                if !ram.is_empty() {
+                    ram[(self.ram_address & 0x7f) as usize] = data;
+                }
+                if (self.ram_address & 0x80) != 0 {
+                    self.ram_address = 0x80 | (self.ram_address.wrapping_add(1) & 0x7f);
+                }
+            }
+            0x5000..=0x57ff => {
+                self.irq_counter = (self.irq_counter & 0xff00) | data as u16;
+                self.irq_ack();
+            }
+            0x5800..=0x5fff => {
+                self.irq_counter = (self.irq_counter & 0xff) | (data as u16) << 8;
+                self.irq_ack();
+            }
+            0x6000..=0x7fff => {
+                if self.prg_ram_writable(addr) && !cart.volatile.prg_ram.is_empty() {
+                    let addr2 = (addr & 0x1fff) as usize % cart.volatile.prg_ram.len();
+                    cart.volatile.prg_ram[addr2] = data;
+                }
+            }
+            0x8000..=0xbfff => {
+                self.chr_banks[((addr - 0x8000) >> 11) as usize] = data;
+            }
+            0xc000..=0xdfff => {
+                self.nametable_banks[((addr - 0xc000) >> 11) as usize] = data;
+            }
+            0xe000..=0xf7ff => {
+                let i = ((addr - 0xe000) >> 11) as usize;
+                self.prg_banks[i] = data;
+                if i == 0 {
+                    self.audio.set_disabled((data & 0x40) != 0);
+                }
+            }
+            0xf800..=0xffff => {
+                self.ram_address = data;
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_peek_address(&self, addr: u16, cart: &NesCartridgeData) -> (bool, bool, Option<u8>) {
+        let (mirror, thing) = self.check_mirroring(addr);
+        let data = self.ppu_read(addr, cart);
+        (mirror, thing, data)
+    }
+
+    fn ppu_memory_cycle_address(&mut self, addr: u16) -> (bool, bool) {
+        self.ppu_address = addr;
+        self.check_mirroring(addr)
+    }
+
+    fn ppu_pattern_vram(&self, addr: u16) -> bool {
+        self.pattern_vram(addr)
+    }
+
+    fn ppu_memory_cycle_read(&mut self, cart: &mut NesCartridgeData) -> Option<u8> {
+        self.ppu_read(self.ppu_address, cart)
+    }
+
+    fn ppu_memory_cycle_write(&mut self, cart: &mut NesCartridgeData, data: u8) {
+        if cart.volatile.chr_ram.is_empty()
+            || self.ppu_address >= 0x2000
+            || self.pattern_vram(self.ppu_address)
+        {
+            return;
+        }
+        let bank = self.chr_banks[(self.ppu_address >> 10) as usize] as u32;
+        let addr2 = (bank << 10 | (self.ppu_address & 0x3ff) as u32)
+            & (cart.volatile.chr_ram.len() as u32 - 1);
+        cart.volatile.chr_ram[addr2 as usize] = data;
+    }
+
+    fn rom_byte_hack(&mut self, cart: &mut NesCartridgeData, addr: u32, new_byte: u8) {
+        let addr = addr as usize % cart.nonvolatile.prg_rom.len();
+        cart.nonvolatile.prg_rom[addr] = new_byte;
+    }
+}
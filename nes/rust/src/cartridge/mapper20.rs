@@ -0,0 +1,434 @@
+//! Implements the famicom disk system ram adapter and disk drive, using the reserved mapper number 20.
+
+use std::collections::BTreeMap;
+
+use crate::cartridge::audio::fds::FdsAudio;
+use crate::cartridge::audio::{ExpansionAudio, ExpansionAudioChip};
+use crate::cartridge::NesCartridgeData;
+use crate::cartridge::{NesMapper, NesMapperTrait};
+
+/// The number of cpu cycles that a disk takes to be recognized after insertion, about half a second
+const DISK_INSERT_DELAY: u32 = 900000;
+/// The number of cpu cycles for the head to return to the start of the disk
+const HEAD_RETURN_DELAY: u32 = 50000;
+/// The number of cpu cycles needed to transfer a single byte to or from the disk
+const BYTE_DELAY: u32 = 150;
+
+/// Mapper 20, the famicom disk system
+#[non_exhaustive]
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Mapper20 {
+    /// The ppu address for ppu addressing
+    ppu_address: u16,
+    /// The reload value of the irq timer, 0x4020 and 0x4021
+    timer_reload: u16,
+    /// The irq timer counter
+    timer_counter: u16,
+    /// True when the irq timer reloads after expiring
+    timer_repeat: bool,
+    /// True when the irq timer is counting
+    timer_enabled: bool,
+    /// The irq from the timer
+    timer_irq: bool,
+    /// True when the disk registers are enabled, 0x4023 bit 0
+    disk_io: bool,
+    /// True when the sound registers are enabled, 0x4023 bit 1
+    sound_io: bool,
+    /// The control register, 0x4025
+    control: u8,
+    /// The data to write to the disk, 0x4024
+    write_data: u8,
+    /// The data most recently read from the disk, 0x4031
+    read_data: u8,
+    /// Set when a byte has been transferred to or from the disk
+    transfer_complete: bool,
+    /// The irq from the disk transfer
+    disk_irq: bool,
+    /// True when the motor is running and the head is moving across the disk
+    scanning: bool,
+    /// True when the head has reached the end of the disk
+    end_of_head: bool,
+    /// True when a gap end marker has been read
+    gap_ended: bool,
+    /// The position of the head on the disk side
+    position: usize,
+    /// The number of cpu cycles until the next byte transfer
+    delay: u32,
+    /// The disk side currently in the drive
+    side: Option<usize>,
+    /// The disk side that will be in the drive after the insert delay
+    next_side: Option<usize>,
+    /// The number of cpu cycles until the next disk side is inserted
+    insert_delay: u32,
+    /// True when data has been written to the disk since the last save
+    written: bool,
+    /// The expansion audio
+    audio: FdsAudio,
+}
+
+impl Mapper20 {
+    /// Create a new mapper20, with the first disk side inserted
+    pub fn new(d: &NesCartridgeData) -> NesMapper {
+        NesMapper::from(Self {
+            ppu_address: 0,
+            timer_reload: 0,
+            timer_counter: 0,
+            timer_repeat: false,
+            timer_enabled: false,
+            timer_irq: false,
+            disk_io: false,
+            sound_io: false,
+            control: 0,
+            write_data: 0,
+            read_data: 0,
+            transfer_complete: false,
+            disk_irq: false,
+            scanning: false,
+            end_of_head: true,
+            gap_ended: false,
+            position: 0,
+            delay: 0,
+            side: if d.volatile.disk.is_empty() {
+                None
+            } else {
+                Some(0)
+            },
+            next_side: None,
+            insert_delay: 0,
+            written: false,
+            audio: FdsAudio::new(),
+        })
+    }
+
+    /// Check the mirroring bit for the ppu addressing.
+    fn check_mirroring(&self, addr: u16) -> (bool, bool) {
+        let a10 = if (self.control & 8) != 0 {
+            (addr & (1 << 11)) != 0
+        } else {
+            (addr & (1 << 10)) != 0
+        };
+        (a10, false)
+    }
+
+    /// Perform a ppu read operation
+    fn ppu_read(&self, addr: u16, cart: &NesCartridgeData) -> Option<u8> {
+        if cart.volatile.chr_ram.is_empty() || addr >= 0x2000 {
+            return None;
+        }
+        Some(cart.volatile.chr_ram[addr as usize & (cart.volatile.chr_ram.len() - 1)])
+    }
+
+    /// Returns true when the motor is on
+    fn motor_on(&self) -> bool {
+        (self.control & 1) != 0
+    }
+
+    /// Returns true when the drive is in read mode
+    fn read_mode(&self) -> bool {
+        (self.control & 4) != 0
+    }
+
+    /// Returns true when the drive writes the crc instead of data
+    fn crc_control(&self) -> bool {
+        (self.control & 0x10) != 0
+    }
+
+    /// Returns true when the drive is ready to transfer data
+    fn disk_ready(&self) -> bool {
+        (self.control & 0x40) != 0
+    }
+
+    /// Returns true when the disk transfer irq is enabled
+    fn disk_irq_enabled(&self) -> bool {
+        (self.control & 0x80) != 0
+    }
+
+    /// Mark the disk as modified, if anything was written to it
+    fn finish_writing(&mut self, cart: &mut NesCartridgeData) {
+        if self.written {
+            self.written = false;
+            cart.volatile.disk_modified = true;
+        }
+    }
+
+    /// Run the disk drive for a single cpu cycle
+    fn drive_cycle(&mut self, cart: &mut NesCartridgeData) {
+        if self.insert_delay > 0 {
+            self.insert_delay -= 1;
+            if self.insert_delay == 0 {
+                self.side = self.next_side.take();
+            }
+        }
+        let side = match self.side {
+            Some(s) if s < cart.volatile.disk.len() && self.motor_on() => s,
+            _ => {
+                self.end_of_head = true;
+                self.scanning = false;
+                return;
+            }
+        };
+        //the transfer reset bit holds the head at the start of the disk
+        if (self.control & 2) != 0 && !self.scanning {
+            return;
+        }
+        if self.end_of_head {
+            self.delay = HEAD_RETURN_DELAY;
+            self.end_of_head = false;
+            self.position = 0;
+            self.gap_ended = false;
+            return;
+        }
+        if self.delay > 0 {
+            self.delay -= 1;
+            return;
+        }
+        self.scanning = true;
+        let mut need_irq = self.disk_irq_enabled();
+        let disk = &mut cart.volatile.disk[side];
+        if self.read_mode() {
+            let data = disk.get(self.position).copied().unwrap_or(0);
+            if !self.disk_ready() {
+                self.gap_ended = false;
+            } else if data != 0 && !self.gap_ended {
+                //the gap end marker is not passed on to the bios
+                self.gap_ended = true;
+                need_irq = false;
+            }
+            if self.gap_ended {
+                self.transfer_complete = true;
+                self.read_data = data;
+                if need_irq {
+                    self.disk_irq = true;
+                }
+            }
+        } else {
+            let mut data = 0;
+            if !self.crc_control() {
+                self.transfer_complete = true;
+                data = self.write_data;
+                if need_irq {
+                    self.disk_irq = true;
+                }
+            }
+            if !self.disk_ready() {
+                data = 0;
+            }
+            if let Some(d) = disk.get_mut(self.position) {
+                *d = data;
+                self.written = true;
+            }
+            self.gap_ended = false;
+        }
+        self.position += 1;
+        if self.position >= disk.len() {
+            self.control &= !1;
+            self.finish_writing(cart);
+        } else {
+            self.delay = BYTE_DELAY;
+        }
+    }
+}
+
+impl NesMapperTrait for Mapper20 {
+    fn irq(&self) -> bool {
+        self.timer_irq || self.disk_irq
+    }
+
+    fn cpu_cycle(&mut self, cart: &mut NesCartridgeData) {
+        if self.timer_enabled && self.disk_io {
+            if self.timer_counter == 0 {
+                self.timer_irq = true;
+                self.timer_counter = self.timer_reload;
+                if !self.timer_repeat {
+                    self.timer_enabled = false;
+                }
+            } else {
+                self.timer_counter -= 1;
+            }
+        }
+        self.drive_cycle(cart);
+    }
+
+    fn clock_audio(&mut self, _cart: &mut NesCartridgeData) {
+        self.audio.cycle();
+    }
+
+    fn audio_sample(&self, sample: &mut ExpansionAudio) {
+        sample.set(ExpansionAudioChip::Fds, self.audio.audio());
+    }
+
+    fn insert_disk(&mut self, side: Option<usize>) {
+        self.side = None;
+        self.next_side = side;
+        self.insert_delay = if side.is_some() { DISK_INSERT_DELAY } else { 0 };
+    }
+
+    fn inserted_disk(&self) -> Option<usize> {
+        self.side.or(self.next_side)
+    }
+
+    fn cartridge_registers(&self) -> BTreeMap<String, u8> {
+        let mut hm = BTreeMap::new();
+        hm.insert("Timer reload low".to_string(), self.timer_reload as u8);
+        hm.insert(
+            "Timer reload high".to_string(),
+            (self.timer_reload >> 8) as u8,
+        );
+        hm.insert(
+            "Timer control".to_string(),
+            self.timer_repeat as u8 | (self.timer_enabled as u8) << 1,
+        );
+        hm.insert(
+            "Master IO".to_string(),
+            self.disk_io as u8 | (self.sound_io as u8) << 1,
+        );
+        hm.insert("Control".to_string(), self.control);
+        hm.insert(
+            "Disk side".to_string(),
+            self.side.map(|s| s as u8).unwrap_or(0xff),
+        );
+        hm.insert("IRQ timer".to_string(), self.timer_irq as u8);
+        hm.insert("IRQ disk".to_string(), self.disk_irq as u8);
+        hm.insert("Mapper".to_string(), 20);
+        hm
+    }
+
+    fn memory_cycle_dump(&self, cart: &NesCartridgeData, addr: u16) -> Option<u8> {
+        match addr {
+            0x4030 if self.disk_io => Some(
+                self.timer_irq as u8
+                    | (self.transfer_complete as u8) << 1
+                    | (self.end_of_head as u8) << 6,
+            ),
+            0x4031 if self.disk_io => Some(self.read_data),
+            0x4032 if self.disk_io => {
+                let no_disk = self.side.is_none();
+                Some(
+                    0x40 | no_disk as u8
+                        | ((no_disk || !self.scanning) as u8) << 1
+                        | (no_disk as u8) << 2,
+                )
+            }
+            0x4033 if self.disk_io => Some(0x80),
+            0x4040..=0x4092 if self.sound_io => self.audio.read(addr),
+            0x6000..=0xdfff => {
+                if cart.volatile.prg_ram.is_empty() {
+                    None
+                } else {
+                    let addr2 = (addr - 0x6000) as usize % cart.volatile.prg_ram.len();
+                    Some(cart.volatile.prg_ram[addr2])
+                }
+            }
+            0xe000..=0xffff => {
+                if cart.nonvolatile.prg_rom.is_empty() {
+                    None
+                } else {
+                    let addr2 = (addr & 0x1fff) as usize % cart.nonvolatile.prg_rom.len();
+                    Some(cart.nonvolatile.prg_rom[addr2])
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn memory_cycle_read(&mut self, cart: &mut NesCartridgeData, addr: u16) -> Option<u8> {
+        let val = self.memory_cycle_dump(cart, addr);
+        if self.disk_io {
+            match addr {
+                0x4030 => {
+                    self.timer_irq = false;
+                    self.disk_irq = false;
+                    self.transfer_complete = false;
+                }
+                0x4031 => {
+                    self.disk_irq = false;
+                    self.transfer_complete = false;
+                }
+                _ => {}
+            }
+        }
+        val
+    }
+
+    fn memory_cycle_nop(&mut self) {}
+
+    fn memory_cycle_write(&mut self, cart: &mut NesCartridgeData, addr: u16, data: u8) {
+        match addr {
+            0x4020 if self.disk_io => {
+                self.timer_reload = (self.timer_reload & 0xff00) | data as u16;
+            }
+            0x4021 if self.disk_io => {
+                self.timer_reload = (self.timer_reload & 0xff) | (data as u16) << 8;
+            }
+            0x4022 if self.disk_io => {
+                self.timer_repeat = (data & 1) != 0;
+                self.timer_enabled = (data & 2) != 0;
+                if self.timer_enabled {
+                    self.timer_counter = self.timer_reload;
+                } else {
+                    self.timer_irq = false;
+                }
+            }
+            0x4023 => {
+                self.disk_io = (data & 1) != 0;
+                self.sound_io = (data & 2) != 0;
+                if !self.disk_io {
+                    self.timer_enabled = false;
+                    self.timer_irq = false;
+                    self.disk_irq = false;
+                }
+            }
+            0x4024 if self.disk_io => {
+                self.write_data = data;
+                self.transfer_complete = false;
+                self.disk_irq = false;
+            }
+            0x4025 if self.disk_io => {
+                self.control = data;
+                self.disk_irq = false;
+                if self.read_mode() || !self.motor_on() {
+                    self.finish_writing(cart);
+                }
+            }
+            0x4040..=0x408a if self.sound_io => self.audio.write(addr, data),
+            0x6000..=0xdfff => {
+                if !cart.volatile.prg_ram.is_empty() {
+                    let addr2 = (addr - 0x6000) as usize % cart.volatile.prg_ram.len();
+                    cart.volatile.prg_ram[addr2] = data;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_peek_address(&self, addr: u16, cart: &NesCartridgeData) -> (bool, bool, Option<u8>) {
+        let (mirror, thing) = self.check_mirroring(addr);
+        let data = self.ppu_read(addr, cart);
+        (mirror, thing, data)
+    }
+
+    fn ppu_memory_cycle_address(&mut self, addr: u16) -> (bool, bool) {
+        self.ppu_address = addr;
+        self.check_mirroring(addr)
+    }
+
+    fn ppu_memory_cycle_read(&mut self, cart: &mut NesCartridgeData) -> Option<u8> {
+        self.ppu_read(self.ppu_address, cart)
+    }
+
+    fn ppu_memory_cycle_write(&mut self, cart: &mut NesCartridgeData, data: u8) {
+        if cart.volatile.chr_ram.is_empty() || self.ppu_address >= 0x2000 {
+            return;
+        }
+        let addr2 = self.ppu_address as usize & (cart.volatile.chr_ram.len() - 1);
+        cart.volatile.chr_ram[addr2] = data;
+    }
+
+    fn rom_byte_hack(&mut self, cart: &mut NesCartridgeData, addr: u32, new_byte: u8) {
+        if cart.nonvolatile.prg_rom.is_empty() {
+            return;
+        }
+        let addr = addr as usize % cart.nonvolatile.prg_rom.len();
+        cart.nonvolatile.prg_rom[addr] = new_byte;
+    }
+}
@@ -0,0 +1,303 @@
+//! Implements mappers 21, 22, 23, and 25, the konami vrc2 and vrc4 family.
+//! The boards differ in which cpu address lines are connected to the register select lines of the chip.
+
+use std::collections::BTreeMap;
+
+use crate::cartridge::vrc_irq::VrcIrq;
+use crate::cartridge::NesCartridgeData;
+use crate::cartridge::{NesMapper, NesMapperTrait};
+
+/// Mappers 21, 22, 23, and 25
+#[non_exhaustive]
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Mapper21 {
+    /// The mapper number
+    mapper: u32,
+    /// The cpu address lines connected to the A0 register select line of the chip
+    a0_lines: u16,
+    /// The cpu address lines connected to the A1 register select line of the chip
+    a1_lines: u16,
+    /// True for the vrc2, false for the vrc4
+    vrc2: bool,
+    /// True when the rom does not say which chip is present. The vrc2 is assumed until a register that only the vrc4 has is written.
+    detect_chip: bool,
+    /// The ppu address for ppu addressing
+    ppu_address: u16,
+    /// The prg banks for 0x8000 (or 0xc000) and 0xa000
+    prg_banks: [u8; 2],
+    /// The 1kb chr banks, each written as two nibbles
+    chr_banks: [u16; 8],
+    /// The mirroring register
+    mirroring: u8,
+    /// The vrc4 control register at 0x9002, prg swap mode and prg ram enable
+    control: u8,
+    /// The single bit latch of the vrc2, for boards without prg ram
+    microwire: u8,
+    /// The irq counter of the vrc4
+    irq: VrcIrq,
+}
+
+impl Mapper21 {
+    /// Create a new Mapper21, the mapper number and submapper select the wiring of the board
+    pub fn new(d: &NesCartridgeData, mapper: u32) -> NesMapper {
+        let (a0_lines, a1_lines, vrc2) = match (mapper, d.volatile.submapper) {
+            //vrc4a
+            (21, 1) => (0x2, 0x4, false),
+            //vrc4c
+            (21, 2) => (0x40, 0x80, false),
+            (21, _) => (0x42, 0x84, false),
+            //vrc2a
+            (22, _) => (0x2, 0x1, true),
+            //vrc4f
+            (23, 1) => (0x1, 0x2, false),
+            //vrc4e
+            (23, 2) => (0x4, 0x8, false),
+            //vrc2b
+            (23, 3) => (0x1, 0x2, true),
+            (23, _) => (0x5, 0xa, false),
+            //vrc4b
+            (25, 1) => (0x2, 0x1, false),
+            //vrc4d
+            (25, 2) => (0x8, 0x4, false),
+            //vrc2c
+            (25, 3) => (0x2, 0x1, true),
+            (_, _) => (0xa, 0x5, false),
+        };
+        //ines 1.0 roms share mappers 23 and 25 between the vrc2 and vrc4, but the vrc2 can only address 256kb of chr rom
+        let detect_chip = matches!((mapper, d.volatile.submapper), (23 | 25, 0))
+            && d.nonvolatile.chr_rom.len() <= 0x40000;
+        NesMapper::from(Self {
+            mapper,
+            a0_lines,
+            a1_lines,
+            vrc2: vrc2 || detect_chip,
+            detect_chip,
+            ppu_address: 0,
+            prg_banks: [0; 2],
+            chr_banks: [0; 8],
+            mirroring: 0,
+            control: 0,
+            microwire: 0,
+            irq: VrcIrq::new(),
+        })
+    }
+
+    /// Convert a cpu address into the address as seen by the chip
+    fn fix_address(&self, addr: u16) -> u16 {
+        let a0 = (addr & self.a0_lines) != 0;
+        let a1 = (addr & self.a1_lines) != 0;
+        (addr & 0xf000) | (a1 as u16) << 1 | a0 as u16
+    }
+
+    /// Check the mirroring bit for the ppu addressing.
+    fn check_mirroring(&self, addr: u16) -> (bool, bool) {
+        let a10 = match self.mirroring & 3 {
+            0 => (addr & 1 << 10) != 0,
+            1 => (addr & 1 << 11) != 0,
+            2 => false,
+            _ => true,
+        };
+        (a10, false)
+    }
+
+    /// Calculate the address into chr memory for the given ppu address
+    fn chr_address(&self, addr: u16) -> u32 {
+        let bank = self.chr_banks[(addr >> 10) as usize & 7] as u32;
+        //the vrc2a ignores the lowest bit of the chr bank
+        let bank = if self.mapper == 22 { bank >> 1 } else { bank };
+        (bank << 10) | (addr & 0x3ff) as u32
+    }
+
+    /// Perform a ppu read operation
+    fn ppu_read(&self, addr: u16, cart: &NesCartridgeData) -> Option<u8> {
+        let v = Vec::new();
+        let chr = if !cart.volatile.chr_ram.is_empty() {
+            &cart.volatile.chr_ram
+        } else if !cart.nonvolatile.chr_rom.is_empty() {
+            &cart.nonvolatile.chr_rom
+        } else {
+            &v
+        };
+        if chr.is_empty() {
+            return None;
+        }
+        match addr {
+            0..=0x1fff => {
+                let addr2 = self.chr_address(addr) & (chr.len() as u32 - 1);
+                Some(chr[addr2 as usize])
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns true when the prg ram is enabled
+    fn prg_ram_enabled(&self) -> bool {
+        self.vrc2 || (self.control & 1) != 0
+    }
+
+    /// Returns true when the vrc4 swaps the 0x8000 and 0xc000 prg banks
+    fn prg_swap(&self) -> bool {
+        !self.vrc2 && (self.control & 2) != 0
+    }
+
+    /// Write one nibble of a chr bank register
+    fn write_chr(&mut self, addr: u16, data: u8) {
+        let index = (((addr - 0xb000) >> 12) * 2 + ((addr >> 1) & 1)) as usize;
+        let bank = self.chr_banks[index];
+        self.chr_banks[index] = if (addr & 1) != 0 {
+            (bank & 0xf) | ((data & 0x1f) as u16) << 4
+        } else {
+            (bank & 0x1f0) | (data & 0xf) as u16
+        };
+    }
+}
+
+impl NesMapperTrait for Mapper21 {
+    fn irq(&self) -> bool {
+        self.irq.irq()
+    }
+
+    fn cpu_cycle(&mut self, _cart: &mut NesCartridgeData) {
+        if !self.vrc2 {
+            self.irq.cycle();
+        }
+    }
+
+    fn cartridge_registers(&self) -> BTreeMap<String, u8> {
+        let mut hm = BTreeMap::new();
+        hm.insert("PRG bank 0".to_string(), self.prg_banks[0]);
+        hm.insert("PRG bank 1".to_string(), self.prg_banks[1]);
+        for (i, b) in self.chr_banks.iter().enumerate() {
+            hm.insert(format!("CHR bank {} low", i), (*b & 0xf) as u8);
+            hm.insert(format!("CHR bank {} high", i), (*b >> 4) as u8);
+        }
+        hm.insert("Mirroring".to_string(), self.mirroring);
+        if self.vrc2 {
+            hm.insert("Microwire".to_string(), self.microwire);
+        } else {
+            hm.insert("Control".to_string(), self.control);
+            hm.insert("IRQ LATCH".to_string(), self.irq.latch());
+            hm.insert("IRQ Counter".to_string(), self.irq.counter());
+            hm.insert("IRQ Control".to_string(), self.irq.control());
+            hm.insert("IRQ Pending".to_string(), self.irq.irq() as u8);
+        }
+        hm.insert("Mapper".to_string(), self.mapper as u8);
+        hm
+    }
+
+    fn memory_cycle_dump(&self, cart: &NesCartridgeData, addr: u16) -> Option<u8> {
+        match addr {
+            0x6000..=0x7fff => {
+                if !cart.volatile.prg_ram.is_empty() {
+                    if self.prg_ram_enabled() {
+                        let addr2 = (addr & 0x1fff) as usize % cart.volatile.prg_ram.len();
+                        Some(cart.volatile.prg_ram[addr2])
+                    } else {
+                        None
+                    }
+                } else if self.vrc2 && addr < 0x7000 {
+                    //the upper bits are open bus
+                    Some(((addr >> 8) as u8 & 0xfe) | self.microwire)
+                } else {
+                    None
+                }
+            }
+            0x8000..=0xffff => {
+                let banks = (cart.nonvolatile.prg_rom.len() >> 13) as u32;
+                let bank = match (addr, self.prg_swap()) {
+                    (0x8000..=0x9fff, false) | (0xc000..=0xdfff, true) => {
+                        (self.prg_banks[0] & 0x1f) as u32
+                    }
+                    (0xa000..=0xbfff, _) => (self.prg_banks[1] & 0x1f) as u32,
+                    (0x8000..=0xdfff, _) => banks.wrapping_sub(2),
+                    _ => banks.wrapping_sub(1),
+                };
+                let addr2 = (bank << 13) | (addr & 0x1fff) as u32;
+                let addr3 = addr2 & (cart.nonvolatile.prg_rom.len() as u32 - 1);
+                Some(cart.nonvolatile.prg_rom[addr3 as usize])
+            }
+            _ => None,
+        }
+    }
+
+    fn memory_cycle_read(&mut self, cart: &mut NesCartridgeData, addr: u16) -> Option<u8> {
+        self.memory_cycle_dump(cart, addr)
+    }
+
+    fn memory_cycle_nop(&mut self) {}
+
+    fn memory_cycle_write(&mut self, cart: &mut NesCartridgeData, addr: u16, data: u8) {
+        if (0x6000..=0x7fff).contains(&addr) {
+            if !cart.volatile.prg_ram.is_empty() {
+                if self.prg_ram_enabled() {
+                    let addr2 = (addr & 0x1fff) as usize % cart.volatile.prg_ram.len();
+                    cart.volatile.prg_ram[addr2] = data;
+                }
+            } else if self.vrc2 && addr < 0x7000 {
+                self.microwire = data & 1;
+            }
+            return;
+        }
+        let addr = self.fix_address(addr);
+        if self.detect_chip && matches!(addr, 0x9002..=0x9003 | 0xf000..=0xf003) {
+            //the vrc2 only has the mirroring register at 0x9000-0x9003, and nothing at 0xf000-0xf003
+            self.detect_chip = false;
+            self.vrc2 = false;
+        }
+        match addr {
+            0x8000..=0x8003 => {
+                self.prg_banks[0] = data & 0x1f;
+            }
+            0x9000..=0x9003 => {
+                if self.vrc2 {
+                    self.mirroring = data & 1;
+                } else if addr < 0x9002 {
+                    self.mirroring = data & 3;
+                } else if addr == 0x9002 {
+                    self.control = data;
+                }
+            }
+            0xa000..=0xa003 => {
+                self.prg_banks[1] = data & 0x1f;
+            }
+            0xb000..=0xe003 => {
+                self.write_chr(addr, data);
+            }
+            0xf000..=0xf003 if !self.vrc2 => match addr & 3 {
+                0 => self.irq.write_latch_low(data),
+                1 => self.irq.write_latch_high(data),
+                2 => self.irq.write_control(data),
+                _ => self.irq.acknowledge(),
+            },
+            _ => {}
+        }
+    }
+
+    fn ppu_peek_address(&self, addr: u16, cart: &NesCartridgeData) -> (bool, bool, Option<u8>) {
+        let (mirror, thing) = self.check_mirroring(addr);
+        let data = self.ppu_read(addr, cart);
+        (mirror, thing, data)
+    }
+
+    fn ppu_memory_cycle_address(&mut self, addr: u16) -> (bool, bool) {
+        self.ppu_address = addr;
+        self.check_mirroring(addr)
+    }
+
+    fn ppu_memory_cycle_read(&mut self, cart: &mut NesCartridgeData) -> Option<u8> {
+        self.ppu_read(self.ppu_address, cart)
+    }
+
+    fn ppu_memory_cycle_write(&mut self, cart: &mut NesCartridgeData, data: u8) {
+        if cart.volatile.chr_ram.is_empty() || self.ppu_address >= 0x2000 {
+            return;
+        }
+        let addr2 = self.chr_address(self.ppu_address) & (cart.volatile.chr_ram.len() as u32 - 1);
+        cart.volatile.chr_ram[addr2 as usize] = data;
+    }
+
+    fn rom_byte_hack(&mut self, cart: &mut NesCartridgeData, addr: u32, new_byte: u8) {
+        let addr = addr as usize % cart.nonvolatile.prg_rom.len();
+        cart.nonvolatile.prg_rom[addr] = new_byte;
+    }
+}
@@ -0,0 +1,259 @@
+//! Implements mappers 24 and 26, the konami vrc6
+
+use std::collections::BTreeMap;
+
+use crate::cartridge::audio::vrc6::Vrc6Audio;
+use crate::cartridge::audio::{ExpansionAudio, ExpansionAudioChip};
+use crate::cartridge::vrc_irq::VrcIrq;
+use crate::cartridge::NesCartridgeData;
+use crate::cartridge::{NesMapper, NesMapperTrait};
+
+/// Mapper 24 and 26
+#[non_exhaustive]
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Mapper24 {
+    /// True for mapper 26, where the a0 and a1 lines are swapped
+    swap_address: bool,
+    /// The ppu address for ppu addressing
+    ppu_address: u16,
+    /// The 16kb prg bank at 0x8000 and the 8kb prg bank at 0xc000
+    prg_banks: [u8; 2],
+    /// The 8 chr bank registers
+    chr_banks: [u8; 8],
+    /// The ppu banking control register at 0xb003
+    control: u8,
+    /// The irq counter
+    irq: VrcIrq,
+    /// The expansion audio
+    audio: Vrc6Audio,
+}
+
+impl Mapper24 {
+    /// Create a new Mapper24, swap should be true for mapper 26
+    pub fn new(_d: &NesCartridgeData, swap: bool) -> NesMapper {
+        NesMapper::from(Self {
+            swap_address: swap,
+            ppu_address: 0,
+            prg_banks: [0; 2],
+            chr_banks: [0; 8],
+            control: 0,
+            irq: VrcIrq::new(),
+            audio: Vrc6Audio::new(),
+        })
+    }
+
+    /// Convert a cpu address into the address as seen by the vrc6 chip
+    fn fix_address(&self, addr: u16) -> u16 {
+        if self.swap_address {
+            (addr & !3) | ((addr & 1) << 1) | ((addr & 2) >> 1)
+        } else {
+            addr
+        }
+    }
+
+    /// Check the mirroring bit for the ppu addressing.
+    fn check_mirroring(&self, addr: u16) -> (bool, bool) {
+        let a10 = match (self.control >> 2) & 3 {
+            0 => (addr & 1 << 10) != 0,
+            1 => (addr & 1 << 11) != 0,
+            2 => false,
+            _ => true,
+        };
+        (a10, false)
+    }
+
+    /// Calculate the address into chr memory for the given ppu address
+    fn chr_address(&self, addr: u16) -> u32 {
+        match (self.control & 3, addr) {
+            (0, _) => {
+                let bank = self.chr_banks[(addr >> 10) as usize & 7] as u32;
+                (bank << 10) | (addr & 0x3ff) as u32
+            }
+            (1, _) => {
+                let bank = self.chr_banks[(addr >> 11) as usize & 3] as u32;
+                (bank << 11) | (addr & 0x7ff) as u32
+            }
+            (_, 0..=0xfff) => {
+                let bank = self.chr_banks[(addr >> 10) as usize & 3] as u32;
+                (bank << 10) | (addr & 0x3ff) as u32
+            }
+            (_, _) => {
+                let bank = self.chr_banks[4 + ((addr >> 11) as usize & 1)] as u32;
+                (bank << 11) | (addr & 0x7ff) as u32
+            }
+        }
+    }
+
+    /// Perform a ppu read operation
+    fn ppu_read(&self, addr: u16, cart: &NesCartridgeData) -> Option<u8> {
+        let v = Vec::new();
+        let chr = if !cart.volatile.chr_ram.is_empty() {
+            &cart.volatile.chr_ram
+        } else if !cart.nonvolatile.chr_rom.is_empty() {
+            &cart.nonvolatile.chr_rom
+        } else {
+            &v
+        };
+        if chr.is_empty() {
+            return None;
+        }
+        match addr {
+            0..=0x1fff => {
+                let addr2 = self.chr_address(addr) & (chr.len() as u32 - 1);
+                Some(chr[addr2 as usize])
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns true when the prg ram is enabled
+    fn prg_ram_enabled(&self) -> bool {
+        (self.control & 0x80) != 0
+    }
+}
+
+impl NesMapperTrait for Mapper24 {
+    fn irq(&self) -> bool {
+        self.irq.irq()
+    }
+
+    fn cpu_cycle(&mut self, _cart: &mut NesCartridgeData) {
+        self.irq.cycle();
+    }
+
+    fn clock_audio(&mut self, _cart: &mut NesCartridgeData) {
+        self.audio.cycle();
+    }
+
+    fn audio_sample(&self, sample: &mut ExpansionAudio) {
+        sample.set(ExpansionAudioChip::Vrc6, self.audio.audio());
+    }
+
+    fn cartridge_registers(&self) -> BTreeMap<String, u8> {
+        let mut hm = BTreeMap::new();
+        hm.insert("PRG bank 8000".to_string(), self.prg_banks[0]);
+        hm.insert("PRG bank C000".to_string(), self.prg_banks[1]);
+        for (i, b) in self.chr_banks.iter().enumerate() {
+            hm.insert(format!("CHR bank {}", i), *b);
+        }
+        hm.insert("Control".to_string(), self.control);
+        hm.insert("IRQ LATCH".to_string(), self.irq.latch());
+        hm.insert("IRQ Counter".to_string(), self.irq.counter());
+        hm.insert("IRQ Control".to_string(), self.irq.control());
+        hm.insert("IRQ Pending".to_string(), self.irq.irq() as u8);
+        hm.insert(
+            "Mapper".to_string(),
+            if self.swap_address { 26 } else { 24 },
+        );
+        hm
+    }
+
+    fn memory_cycle_dump(&self, cart: &NesCartridgeData, addr: u16) -> Option<u8> {
+        match addr {
+            0x6000..=0x7fff => {
+                let mut addr2 = addr & 0x1fff;
+                if self.prg_ram_enabled() && !cart.volatile.prg_ram.is_empty() {
+                    addr2 %= cart.volatile.prg_ram.len() as u16;
+                    Some(cart.volatile.prg_ram[addr2 as usize])
+                } else {
+                    None
+                }
+            }
+            0x8000..=0xbfff => {
+                let bank = ((self.prg_banks[0] & 0xF) as u32) << 14;
+                let addr2 = (addr & 0x3fff) as u32 | bank;
+                let addr3 = addr2 & (cart.nonvolatile.prg_rom.len() as u32 - 1);
+                Some(cart.nonvolatile.prg_rom[addr3 as usize])
+            }
+            0xc000..=0xdfff => {
+                let bank = ((self.prg_banks[1] & 0x1F) as u32) << 13;
+                let addr2 = (addr & 0x1fff) as u32 | bank;
+                let addr3 = addr2 & (cart.nonvolatile.prg_rom.len() as u32 - 1);
+                Some(cart.nonvolatile.prg_rom[addr3 as usize])
+            }
+            0xe000..=0xffff => {
+                //fixed to the last bank
+                let addr2 = (addr & 0x1fff) as u32 | (0xFFFF << 13);
+                let addr3 = addr2 & (cart.nonvolatile.prg_rom.len() as u32 - 1);
+                Some(cart.nonvolatile.prg_rom[addr3 as usize])
+            }
+            _ => None,
+        }
+    }
+
+    fn memory_cycle_read(&mut self, cart: &mut NesCartridgeData, addr: u16) -> Option<u8> {
+        self.memory_cycle_dump(cart, addr)
+    }
+
+    fn memory_cycle_nop(&mut self) {}
+
+    fn memory_cycle_write(&mut self, cart: &mut NesCartridgeData, addr: u16, data: u8) {
+        if (0x6000..=0x7fff).contains(&addr) {
+            let mut addr2 = addr & 0x1fff;
+            if self.prg_ram_enabled() && !cart.volatile.prg_ram.is_empty() {
+                addr2 %= cart.volatile.prg_ram.len() as u16;
+                cart.volatile.prg_ram[addr2 as usize] = data;
+            }
+            return;
+        }
+        let addr = self.fix_address(addr);
+        match addr & 0xF003 {
+            0x8000..=0x8003 => {
+                self.prg_banks[0] = data;
+            }
+            0x9000..=0x9003 | 0xa000..=0xa002 | 0xb000..=0xb002 => {
+                self.audio.write(addr, data);
+            }
+            0xb003 => {
+                self.control = data;
+            }
+            0xc000..=0xc003 => {
+                self.prg_banks[1] = data;
+            }
+            0xd000..=0xd003 => {
+                self.chr_banks[(addr & 3) as usize] = data;
+            }
+            0xe000..=0xe003 => {
+                self.chr_banks[4 + (addr & 3) as usize] = data;
+            }
+            0xf000 => {
+                self.irq.write_latch(data);
+            }
+            0xf001 => {
+                self.irq.write_control(data);
+            }
+            0xf002 => {
+                self.irq.acknowledge();
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_peek_address(&self, addr: u16, cart: &NesCartridgeData) -> (bool, bool, Option<u8>) {
+        let (mirror, thing) = self.check_mirroring(addr);
+        let data = self.ppu_read(addr, cart);
+        (mirror, thing, data)
+    }
+
+    fn ppu_memory_cycle_address(&mut self, addr: u16) -> (bool, bool) {
+        self.ppu_address = addr;
+        self.check_mirroring(addr)
+    }
+
+    fn ppu_memory_cycle_read(&mut self, cart: &mut NesCartridgeData) -> Option<u8> {
+        self.ppu_read(self.ppu_address, cart)
+    }
+
+    fn ppu_memory_cycle_write(&mut self, cart: &mut NesCartridgeData, data: u8) {
+        if cart.volatile.chr_ram.is_empty() || self.ppu_address >= 0x2000 {
+            return;
+        }
+        let addr2 = self.chr_address(self.ppu_address) & (cart.volatile.chr_ram.len() as u32 - 1);
+        cart.volatile.chr_ram[addr2 as usize] = data;
+    }
+
+    fn rom_byte_hack(&mut self, cart: &mut NesCartridgeData, addr: u32, new_byte: u8) {
+        let addr = addr as usize % cart.nonvolatile.prg_rom.len();
+        cart.nonvolatile.prg_rom[addr] = new_byte;
+    }
+}
@@ -1,11 +1,12 @@
-//! Implements mapper34
+//! Implements mapper34, which covers two different boards.
+//! The bnrom board has a single 32kb prg bank register in rom space, and the nina-001 board has registers for prg and two 4kb chr banks at the end of prg ram.
 
 use std::collections::BTreeMap;
 
 use crate::cartridge::NesCartridgeData;
 use crate::cartridge::{NesMapper, NesMapperTrait};
 
-/// Mapper00
+/// Mapper34
 #[non_exhaustive]
 #[derive(serde::Serialize, serde::Deserialize)]
 pub struct Mapper34 {
@@ -13,22 +14,34 @@ pub struct Mapper34 {
     mirror_vertical: bool,
     /// The address for ppu memory cycles
     ppu_address: u16,
-    /// The bank select register
+    /// True for the nina-001 board, false for the bnrom board
+    nina: bool,
+    /// The 32kb prg bank select register
     bank: u8,
-    /// Extra registers
-    regs: [u8; 2],
+    /// The 4kb chr bank registers of the nina-001
+    chr_banks: [u8; 2],
+    /// True when writes to the bank select register conflict with the rom
+    bus_conflicts: bool,
 }
 
 impl Mapper34 {
-    /// Create a new mapper
+    /// Create a new mapper, the submapper selects the board. Without a submapper, the nina-001 is detected by having more than 8kb of chr rom.
     pub fn new(d: &NesCartridgeData) -> NesMapper {
+        let nina = match d.volatile.submapper {
+            1 => true,
+            2 => false,
+            _ => d.nonvolatile.chr_rom.len() > 8192,
+        };
         NesMapper::from(Self {
             mirror_vertical: d.volatile.mirroring,
             ppu_address: 0,
-            bank: 0xff,
-            regs: [0; 2],
+            nina,
+            bank: 0,
+            chr_banks: [0, 1],
+            bus_conflicts: !nina && d.bus_conflicts(),
         })
     }
+
     /// Check the mirroring bit for the ppu addressing.
     fn check_mirroring(&self, addr: u16) -> (bool, bool) {
         let a10 = if self.mirror_vertical {
@@ -38,6 +51,33 @@ impl Mapper34 {
         };
         (a10, false)
     }
+
+    /// Calculate the address into chr memory for the given ppu address
+    fn chr_address(&self, addr: u16) -> u32 {
+        if self.nina {
+            let bank = (self.chr_banks[(addr >> 12) as usize & 1] & 0xf) as u32;
+            bank << 12 | (addr & 0xfff) as u32
+        } else {
+            addr as u32
+        }
+    }
+
+    /// Perform a ppu read operation
+    fn ppu_read(&self, addr: u16, cart: &NesCartridgeData) -> Option<u8> {
+        let v = Vec::new();
+        let chr = if !cart.volatile.chr_ram.is_empty() {
+            &cart.volatile.chr_ram
+        } else if !cart.nonvolatile.chr_rom.is_empty() {
+            &cart.nonvolatile.chr_rom
+        } else {
+            &v
+        };
+        if chr.is_empty() || addr >= 0x2000 {
+            return None;
+        }
+        let addr2 = self.chr_address(addr) & (chr.len() as u32 - 1);
+        Some(chr[addr2 as usize])
+    }
 }
 
 impl NesMapperTrait for Mapper34 {
@@ -48,7 +88,13 @@ impl NesMapperTrait for Mapper34 {
     fn cartridge_registers(&self) -> BTreeMap<String, u8> {
         let mut hm = BTreeMap::new();
         hm.insert("Mirror".to_string(), self.mirror_vertical as u8);
-        hm.insert("Mapper".to_string(), 0);
+        hm.insert("NINA-001".to_string(), self.nina as u8);
+        hm.insert("PRG bank".to_string(), self.bank);
+        if self.nina {
+            hm.insert("CHR bank 0".to_string(), self.chr_banks[0]);
+            hm.insert("CHR bank 1".to_string(), self.chr_banks[1]);
+        }
+        hm.insert("Mapper".to_string(), 34);
         hm
     }
 
@@ -69,57 +115,24 @@ impl NesMapperTrait for Mapper34 {
                     }
                 }
             }
-            0x8000..=0xbfff => {
-                let addr2 = addr & 0x3fff;
-                let mut addr3 = addr2 as u32 % cart.nonvolatile.prg_rom.len() as u32;
-                addr3 |= (self.bank as u32 & 0xF) << 14;
-                let addr4 = addr3;
-                Some(
-                    cart.nonvolatile.prg_rom[addr4 as usize & (cart.nonvolatile.prg_rom.len() - 1)],
-                )
-            }
-            0xc000..=0xffff => {
-                let addr2 = addr & 0x3fff;
-                let mut addr3 = addr2 as u32 % cart.nonvolatile.prg_rom.len() as u32;
-                addr3 |= 0xFFFF << 14;
-                let addr4 = addr3;
-                Some(
-                    cart.nonvolatile.prg_rom[addr4 as usize & (cart.nonvolatile.prg_rom.len() - 1)],
-                )
+            0x8000..=0xffff => {
+                let addr2 = (self.bank as u32) << 15 | (addr & 0x7fff) as u32;
+                let addr3 = addr2 & (cart.nonvolatile.prg_rom.len() as u32 - 1);
+                Some(cart.nonvolatile.prg_rom[addr3 as usize])
             }
             _ => None,
         }
     }
 
     fn memory_cycle_read(&mut self, cart: &mut NesCartridgeData, addr: u16) -> Option<u8> {
-        match addr {
-            0x6000..=0x7fff => {
-                let mut addr2 = addr & 0x1fff;
-                if !cart.volatile.prg_ram.is_empty() {
-                    addr2 %= cart.volatile.prg_ram.len() as u16;
-                    Some(cart.volatile.prg_ram[addr2 as usize])
-                } else {
-                    None
-                }
-            }
-            0x8000..=0xffff => {
-                let addr2 = addr & 0x7fff;
-                let mut addr3 = addr2 as u32 % cart.nonvolatile.prg_rom.len() as u32;
-                addr3 |= (self.bank as u32 & 0xF) << 15;
-                let addr4 = addr3;
-                Some(
-                    cart.nonvolatile.prg_rom[addr4 as usize & (cart.nonvolatile.prg_rom.len() - 1)],
-                )
-            }
-            _ => None,
-        }
+        self.memory_cycle_dump(cart, addr)
     }
 
     fn memory_cycle_nop(&mut self) {}
 
     fn memory_cycle_write(&mut self, cart: &mut NesCartridgeData, addr: u16, data: u8) {
         match addr {
-            0x6000..=0x7ffc => {
+            0x6000..=0x7fff => {
                 if cart.nonvolatile.trainer.is_some() && (0x7000..=0x71ff).contains(&addr) {
                     let c = cart.nonvolatile.trainer.as_mut().unwrap();
                     let addr = addr & 0x1ff;
@@ -131,18 +144,24 @@ impl NesMapperTrait for Mapper34 {
                         cart.volatile.prg_ram[addr2 as usize] = data;
                     }
                 }
-            }
-            0x7ffd => {
-                self.bank = data;
-            }
-            0x7ffe => {
-                self.regs[1] = data;
-            }
-            0x7fff => {
-                self.regs[2] = data;
+                //the registers of the nina-001 are written along with the ram
+                if self.nina {
+                    match addr {
+                        0x7ffd => self.bank = data & 1,
+                        0x7ffe => self.chr_banks[0] = data & 0xf,
+                        0x7fff => self.chr_banks[1] = data & 0xf,
+                        _ => {}
+                    }
+                }
             }
             0x8000..=0xffff => {
-                self.bank = data;
+                if !self.nina {
+                    self.bank = if self.bus_conflicts {
+                        data & self.memory_cycle_dump(cart, addr).unwrap_or(0xff)
+                    } else {
+                        data
+                    };
+                }
             }
             _ => {}
         }
@@ -150,8 +169,8 @@ impl NesMapperTrait for Mapper34 {
 
     fn ppu_peek_address(&self, addr: u16, cart: &NesCartridgeData) -> (bool, bool, Option<u8>) {
         let (mirror, thing) = self.check_mirroring(addr);
-        let data = cart.nonvolatile.chr_rom[(addr as usize) % cart.nonvolatile.chr_rom.len()];
-        (mirror, thing, Some(data))
+        let data = self.ppu_read(addr, cart);
+        (mirror, thing, data)
     }
 
     fn ppu_memory_cycle_address(&mut self, addr: u16) -> (bool, bool) {
@@ -160,25 +179,14 @@ impl NesMapperTrait for Mapper34 {
     }
 
     fn ppu_memory_cycle_read(&mut self, cart: &mut NesCartridgeData) -> Option<u8> {
-        let mut v = Vec::new();
-        let chr = if !cart.volatile.chr_ram.is_empty() {
-            &mut cart.volatile.chr_ram
-        } else if !cart.nonvolatile.chr_rom.is_empty() {
-            &mut cart.nonvolatile.chr_rom
-        } else {
-            &mut v
-        };
-        if chr.is_empty() {
-            return None;
-        }
-        Some(chr[(self.ppu_address as usize) % chr.len()])
+        self.ppu_read(self.ppu_address, cart)
     }
 
     fn ppu_memory_cycle_write(&mut self, cart: &mut NesCartridgeData, data: u8) {
-        if cart.volatile.chr_ram.is_empty() {
+        if cart.volatile.chr_ram.is_empty() || self.ppu_address >= 0x2000 {
             return;
         }
-        let addr2 = self.ppu_address as u32 % cart.volatile.chr_ram.len() as u32;
+        let addr2 = self.chr_address(self.ppu_address) & (cart.volatile.chr_ram.len() as u32 - 1);
         cart.volatile.chr_ram[addr2 as usize] = data;
     }
 
@@ -0,0 +1,157 @@
+//! Implements mapper 66, the gxrom and mhrom boards
+
+use std::collections::BTreeMap;
+
+use crate::cartridge::NesCartridgeData;
+use crate::cartridge::{NesMapper, NesMapperTrait};
+
+/// Mapper 66
+#[non_exhaustive]
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Mapper66 {
+    /// True when mirroring is vertical
+    mirror_vertical: bool,
+    /// The ppu address for ppu addressing
+    ppu_address: u16,
+    /// The bank select register, prg bank in bits 4-5 and chr bank in bits 0-1
+    bank: u8,
+    /// True when writes to the bank select register conflict with the rom
+    bus_conflicts: bool,
+}
+
+impl Mapper66 {
+    /// Create a new mapper66
+    pub fn new(d: &NesCartridgeData) -> NesMapper {
+        NesMapper::from(Self {
+            mirror_vertical: d.volatile.mirroring,
+            ppu_address: 0,
+            bank: 0,
+            bus_conflicts: d.bus_conflicts(),
+        })
+    }
+
+    /// Check the mirroring bit for the ppu addressing.
+    fn check_mirroring(&self, addr: u16) -> (bool, bool) {
+        let a10 = if self.mirror_vertical {
+            (addr & 1 << 10) != 0
+        } else {
+            (addr & 1 << 11) != 0
+        };
+        (a10, false)
+    }
+
+    /// The selected 32kb prg bank
+    fn prg_bank(&self) -> u32 {
+        ((self.bank >> 4) & 3) as u32
+    }
+
+    /// The selected 8kb chr bank
+    fn chr_bank(&self) -> u32 {
+        (self.bank & 3) as u32
+    }
+
+    /// Perform a ppu read operation
+    fn ppu_read(&self, addr: u16, cart: &NesCartridgeData) -> Option<u8> {
+        let v = Vec::new();
+        let chr = if !cart.volatile.chr_ram.is_empty() {
+            &cart.volatile.chr_ram
+        } else if !cart.nonvolatile.chr_rom.is_empty() {
+            &cart.nonvolatile.chr_rom
+        } else {
+            &v
+        };
+        if chr.is_empty() || addr >= 0x2000 {
+            return None;
+        }
+        let addr2 = (self.chr_bank() << 13 | addr as u32) & (chr.len() as u32 - 1);
+        Some(chr[addr2 as usize])
+    }
+}
+
+impl NesMapperTrait for Mapper66 {
+    fn irq(&self) -> bool {
+        false
+    }
+
+    fn cartridge_registers(&self) -> BTreeMap<String, u8> {
+        let mut hm = BTreeMap::new();
+        hm.insert("Mirror".to_string(), self.mirror_vertical as u8);
+        hm.insert("PRG bank".to_string(), self.prg_bank() as u8);
+        hm.insert("CHR bank".to_string(), self.chr_bank() as u8);
+        hm.insert("Mapper".to_string(), 66);
+        hm
+    }
+
+    fn memory_cycle_dump(&self, cart: &NesCartridgeData, addr: u16) -> Option<u8> {
+        match addr {
+            0x6000..=0x7fff => {
+                if cart.volatile.prg_ram.is_empty() {
+                    None
+                } else {
+                    let addr2 = (addr & 0x1fff) as usize % cart.volatile.prg_ram.len();
+                    Some(cart.volatile.prg_ram[addr2])
+                }
+            }
+            0x8000..=0xffff => {
+                let addr2 = self.prg_bank() << 15 | (addr & 0x7fff) as u32;
+                let addr3 = addr2 & (cart.nonvolatile.prg_rom.len() as u32 - 1);
+                Some(cart.nonvolatile.prg_rom[addr3 as usize])
+            }
+            _ => None,
+        }
+    }
+
+    fn memory_cycle_read(&mut self, cart: &mut NesCartridgeData, addr: u16) -> Option<u8> {
+        self.memory_cycle_dump(cart, addr)
+    }
+
+    fn memory_cycle_nop(&mut self) {}
+
+    fn memory_cycle_write(&mut self, cart: &mut NesCartridgeData, addr: u16, data: u8) {
+        match addr {
+            0x6000..=0x7fff => {
+                if !cart.volatile.prg_ram.is_empty() {
+                    let addr2 = (addr & 0x1fff) as usize % cart.volatile.prg_ram.len();
+                    cart.volatile.prg_ram[addr2] = data;
+                }
+            }
+            0x8000..=0xffff => {
+                self.bank = if self.bus_conflicts {
+                    data & self.memory_cycle_dump(cart, addr).unwrap_or(0xff)
+                } else {
+                    data
+                };
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_peek_address(&self, addr: u16, cart: &NesCartridgeData) -> (bool, bool, Option<u8>) {
+        let (mirror, thing) = self.check_mirroring(addr);
+        let data = self.ppu_read(addr, cart);
+        (mirror, thing, data)
+    }
+
+    fn ppu_memory_cycle_address(&mut self, addr: u16) -> (bool, bool) {
+        self.ppu_address = addr;
+        self.check_mirroring(addr)
+    }
+
+    fn ppu_memory_cycle_read(&mut self, cart: &mut NesCartridgeData) -> Option<u8> {
+        self.ppu_read(self.ppu_address, cart)
+    }
+
+    fn ppu_memory_cycle_write(&mut self, cart: &mut NesCartridgeData, data: u8) {
+        if cart.volatile.chr_ram.is_empty() || self.ppu_address >= 0x2000 {
+            return;
+        }
+        let addr2 = (self.chr_bank() << 13 | self.ppu_address as u32)
+            & (cart.volatile.chr_ram.len() as u32 - 1);
+        cart.volatile.chr_ram[addr2 as usize] = data;
+    }
+
+    fn rom_byte_hack(&mut self, cart: &mut NesCartridgeData, addr: u32, new_byte: u8) {
+        let addr = addr as usize % cart.nonvolatile.prg_rom.len();
+        cart.nonvolatile.prg_rom[addr] = new_byte;
+    }
+}
@@ -0,0 +1,271 @@
+//! Implements mapper 69, the sunsoft fme-7, 5a, and 5b
+
+use std::collections::BTreeMap;
+
+use crate::cartridge::audio::sunsoft5b::Sunsoft5bAudio;
+use crate::cartridge::audio::{ExpansionAudio, ExpansionAudioChip};
+use crate::cartridge::NesCartridgeData;
+use crate::cartridge::{NesMapper, NesMapperTrait};
+
+/// Mapper 69
+#[non_exhaustive]
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Mapper69 {
+    /// The ppu address for ppu addressing
+    ppu_address: u16,
+    /// The command register, selecting which register the parameter register writes to
+    command: u8,
+    /// The 1kb chr banks, commands 0-7
+    chr_banks: [u8; 8],
+    /// The prg banks for 0x6000, 0x8000, 0xa000, and 0xc000, commands 8-0xb
+    prg_banks: [u8; 4],
+    /// The mirroring mode, command 0xc
+    mirroring: u8,
+    /// The irq control register, command 0xd
+    irq_control: u8,
+    /// The irq counter, commands 0xe and 0xf
+    irq_counter: u16,
+    /// True when the irq is pending
+    irq_pending: bool,
+    /// The expansion audio of the 5b
+    audio: Sunsoft5bAudio,
+}
+
+impl Mapper69 {
+    /// Create a new Mapper69
+    pub fn new(_d: &NesCartridgeData) -> NesMapper {
+        NesMapper::from(Self {
+            ppu_address: 0,
+            command: 0,
+            chr_banks: [0; 8],
+            prg_banks: [0; 4],
+            mirroring: 0,
+            irq_control: 0,
+            irq_counter: 0,
+            irq_pending: false,
+            audio: Sunsoft5bAudio::new(),
+        })
+    }
+
+    /// Check the mirroring bit for the ppu addressing.
+    fn check_mirroring(&self, addr: u16) -> (bool, bool) {
+        let a10 = match self.mirroring & 3 {
+            0 => (addr & 1 << 10) != 0,
+            1 => (addr & 1 << 11) != 0,
+            2 => false,
+            _ => true,
+        };
+        (a10, false)
+    }
+
+    /// Calculate the address into chr memory for the given ppu address
+    fn chr_address(&self, addr: u16) -> u32 {
+        let bank = self.chr_banks[(addr >> 10) as usize & 7] as u32;
+        (bank << 10) | (addr & 0x3ff) as u32
+    }
+
+    /// Perform a ppu read operation
+    fn ppu_read(&self, addr: u16, cart: &NesCartridgeData) -> Option<u8> {
+        let v = Vec::new();
+        let chr = if !cart.volatile.chr_ram.is_empty() {
+            &cart.volatile.chr_ram
+        } else if !cart.nonvolatile.chr_rom.is_empty() {
+            &cart.nonvolatile.chr_rom
+        } else {
+            &v
+        };
+        if chr.is_empty() {
+            return None;
+        }
+        match addr {
+            0..=0x1fff => {
+                let addr2 = self.chr_address(addr) & (chr.len() as u32 - 1);
+                Some(chr[addr2 as usize])
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns true when 0x6000 is mapped to prg ram
+    fn prg_ram_selected(&self) -> bool {
+        (self.prg_banks[0] & 0x40) != 0
+    }
+
+    /// Returns true when the prg ram at 0x6000 is enabled
+    fn prg_ram_enabled(&self) -> bool {
+        (self.prg_banks[0] & 0x80) != 0
+    }
+
+    /// Write to the register selected by the command register
+    fn write_parameter(&mut self, data: u8) {
+        match self.command & 0xf {
+            i @ 0..=7 => {
+                self.chr_banks[i as usize] = data;
+            }
+            i @ 8..=0xb => {
+                self.prg_banks[i as usize - 8] = data;
+            }
+            0xc => {
+                self.mirroring = data & 3;
+            }
+            0xd => {
+                self.irq_control = data;
+                self.irq_pending = false;
+            }
+            0xe => {
+                self.irq_counter = (self.irq_counter & 0xff00) | data as u16;
+            }
+            _ => {
+                self.irq_counter = (self.irq_counter & 0xff) | (data as u16) << 8;
+            }
+        }
+    }
+}
+
+impl NesMapperTrait for Mapper69 {
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn cpu_cycle(&mut self, _cart: &mut NesCartridgeData) {
+        if (self.irq_control & 0x80) != 0 {
+            self.irq_counter = self.irq_counter.wrapping_sub(1);
+            if self.irq_counter == 0xffff && (self.irq_control & 1) != 0 {
+                self.irq_pending = true;
+            }
+        }
+    }
+
+    fn clock_audio(&mut self, _cart: &mut NesCartridgeData) {
+        self.audio.cycle();
+    }
+
+    fn audio_sample(&self, sample: &mut ExpansionAudio) {
+        sample.set(ExpansionAudioChip::Sunsoft5b, self.audio.audio());
+    }
+
+    fn cartridge_registers(&self) -> BTreeMap<String, u8> {
+        let mut hm = BTreeMap::new();
+        hm.insert("Command".to_string(), self.command);
+        for (i, b) in self.chr_banks.iter().enumerate() {
+            hm.insert(format!("CHR bank {}", i), *b);
+        }
+        hm.insert("PRG bank 6000".to_string(), self.prg_banks[0]);
+        hm.insert("PRG bank 8000".to_string(), self.prg_banks[1]);
+        hm.insert("PRG bank A000".to_string(), self.prg_banks[2]);
+        hm.insert("PRG bank C000".to_string(), self.prg_banks[3]);
+        hm.insert("Mirroring".to_string(), self.mirroring);
+        hm.insert("IRQ Control".to_string(), self.irq_control);
+        hm.insert("IRQ Counter low".to_string(), self.irq_counter as u8);
+        hm.insert(
+            "IRQ Counter high".to_string(),
+            (self.irq_counter >> 8) as u8,
+        );
+        hm.insert("IRQ Pending".to_string(), self.irq_pending as u8);
+        let (select, regs) = self.audio.registers();
+        hm.insert("Audio select".to_string(), select);
+        for (i, r) in regs.iter().enumerate() {
+            hm.insert(format!("Audio register {:X}", i), *r);
+        }
+        hm.insert("Mapper".to_string(), 69);
+        hm
+    }
+
+    fn memory_cycle_dump(&self, cart: &NesCartridgeData, addr: u16) -> Option<u8> {
+        match addr {
+            0x6000..=0x7fff => {
+                let bank = (self.prg_banks[0] & 0x3f) as u32;
+                if self.prg_ram_selected() {
+                    if self.prg_ram_enabled() && !cart.volatile.prg_ram.is_empty() {
+                        let addr2 = ((bank << 13) | (addr & 0x1fff) as u32) as usize
+                            % cart.volatile.prg_ram.len();
+                        Some(cart.volatile.prg_ram[addr2])
+                    } else {
+                        None
+                    }
+                } else {
+                    let addr2 = (bank << 13) | (addr & 0x1fff) as u32;
+                    let addr3 = addr2 & (cart.nonvolatile.prg_rom.len() as u32 - 1);
+                    Some(cart.nonvolatile.prg_rom[addr3 as usize])
+                }
+            }
+            0x8000..=0xdfff => {
+                let i = 1 + ((addr - 0x8000) >> 13) as usize;
+                let bank = (self.prg_banks[i] & 0x3f) as u32;
+                let addr2 = (bank << 13) | (addr & 0x1fff) as u32;
+                let addr3 = addr2 & (cart.nonvolatile.prg_rom.len() as u32 - 1);
+                Some(cart.nonvolatile.prg_rom[addr3 as usize])
+            }
+            0xe000..=0xffff => {
+                //fixed to the last bank
+                let addr2 = (addr & 0x1fff) as u32 | (0xFFFF << 13);
+                let addr3 = addr2 & (cart.nonvolatile.prg_rom.len() as u32 - 1);
+                Some(cart.nonvolatile.prg_rom[addr3 as usize])
+            }
+            _ => None,
+        }
+    }
+
+    fn memory_cycle_read(&mut self, cart: &mut NesCartridgeData, addr: u16) -> Option<u8> {
+        self.memory_cycle_dump(cart, addr)
+    }
+
+    fn memory_cycle_nop(&mut self) {}
+
+    fn memory_cycle_write(&mut self, cart: &mut NesCartridgeData, addr: u16, data: u8) {
+        match addr {
+            0x6000..=0x7fff => {
+                if self.prg_ram_selected()
+                    && self.prg_ram_enabled()
+                    && !cart.volatile.prg_ram.is_empty()
+                {
+                    let bank = (self.prg_banks[0] & 0x3f) as u32;
+                    let addr2 = ((bank << 13) | (addr & 0x1fff) as u32) as usize
+                        % cart.volatile.prg_ram.len();
+                    cart.volatile.prg_ram[addr2] = data;
+                }
+            }
+            0x8000..=0x9fff => {
+                self.command = data & 0xf;
+            }
+            0xa000..=0xbfff => {
+                self.write_parameter(data);
+            }
+            0xc000..=0xdfff => {
+                self.audio.write_select(data);
+            }
+            0xe000..=0xffff => {
+                self.audio.write_data(data);
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_peek_address(&self, addr: u16, cart: &NesCartridgeData) -> (bool, bool, Option<u8>) {
+        let (mirror, thing) = self.check_mirroring(addr);
+        let data = self.ppu_read(addr, cart);
+        (mirror, thing, data)
+    }
+
+    fn ppu_memory_cycle_address(&mut self, addr: u16) -> (bool, bool) {
+        self.ppu_address = addr;
+        self.check_mirroring(addr)
+    }
+
+    fn ppu_memory_cycle_read(&mut self, cart: &mut NesCartridgeData) -> Option<u8> {
+        self.ppu_read(self.ppu_address, cart)
+    }
+
+    fn ppu_memory_cycle_write(&mut self, cart: &mut NesCartridgeData, data: u8) {
+        if cart.volatile.chr_ram.is_empty() || self.ppu_address >= 0x2000 {
+            return;
+        }
+        let addr2 = self.chr_address(self.ppu_address) & (cart.volatile.chr_ram.len() as u32 - 1);
+        cart.volatile.chr_ram[addr2 as usize] = data;
+    }
+
+    fn rom_byte_hack(&mut self, cart: &mut NesCartridgeData, addr: u32, new_byte: u8) {
+        let addr = addr as usize % cart.nonvolatile.prg_rom.len();
+        cart.nonvolatile.prg_rom[addr] = new_byte;
+    }
+}
@@ -1,11 +1,11 @@
-//! Implements mapper71
+//! Implements mapper71, the camerica boards. The board used by fire hawk also has a single screen mirroring register.
 
 use std::collections::BTreeMap;
 
 use crate::cartridge::NesCartridgeData;
 use crate::cartridge::{NesMapper, NesMapperTrait};
 
-/// Mapper00
+/// Mapper71
 #[non_exhaustive]
 #[derive(serde::Serialize, serde::Deserialize)]
 pub struct Mapper71 {
@@ -15,6 +15,12 @@ pub struct Mapper71 {
     ppu_address: u16,
     /// The bank select register
     bank: u8,
+    /// The submapper, 1 is the fire hawk board
+    submapper: u8,
+    /// True when the single screen mirroring register is in use
+    single_screen: bool,
+    /// The nametable selected for single screen mirroring
+    nametable: bool,
 }
 
 impl Mapper71 {
@@ -24,11 +30,16 @@ impl Mapper71 {
             mirror_vertical: d.volatile.mirroring,
             ppu_address: 0,
             bank: 0xff,
+            submapper: d.volatile.submapper,
+            single_screen: d.volatile.submapper == 1,
+            nametable: false,
         })
     }
     /// Check the mirroring bit for the ppu addressing.
     fn check_mirroring(&self, addr: u16) -> (bool, bool) {
-        let a10 = if self.mirror_vertical {
+        let a10 = if self.single_screen {
+            self.nametable
+        } else if self.mirror_vertical {
             (addr & (1 << 10)) != 0
         } else {
             (addr & (1 << 11)) != 0
@@ -45,7 +56,11 @@ impl NesMapperTrait for Mapper71 {
     fn cartridge_registers(&self) -> BTreeMap<String, u8> {
         let mut hm = BTreeMap::new();
         hm.insert("Mirror".to_string(), self.mirror_vertical as u8);
-        hm.insert("Mapper".to_string(), 0);
+        hm.insert("PRG bank".to_string(), self.bank);
+        if self.single_screen {
+            hm.insert("Nametable".to_string(), self.nametable as u8);
+        }
+        hm.insert("Mapper".to_string(), 71);
         hm
     }
 
@@ -140,12 +155,31 @@ impl NesMapperTrait for Mapper71 {
         if (0xc000..=0xffff).contains(&addr) {
             self.bank = data;
         }
+        //without a submapper, fire hawk is detected by its first write to the mirroring register
+        let mirroring_register = match self.submapper {
+            1 => (0x8000..=0x9fff).contains(&addr),
+            0 => (0x9000..=0x9fff).contains(&addr),
+            _ => false,
+        };
+        if mirroring_register {
+            self.single_screen = true;
+            self.nametable = (data & 0x10) != 0;
+        }
     }
 
     fn ppu_peek_address(&self, addr: u16, cart: &NesCartridgeData) -> (bool, bool, Option<u8>) {
         let (mirror, thing) = self.check_mirroring(addr);
-        let data = cart.nonvolatile.chr_rom[(addr as usize) % cart.nonvolatile.chr_rom.len()];
-        (mirror, thing, Some(data))
+        let chr = if !cart.volatile.chr_ram.is_empty() {
+            &cart.volatile.chr_ram[..]
+        } else {
+            &cart.nonvolatile.chr_rom[..]
+        };
+        let data = if chr.is_empty() {
+            None
+        } else {
+            Some(chr[(addr as usize) % chr.len()])
+        };
+        (mirror, thing, data)
     }
 
     fn ppu_memory_cycle_address(&mut self, addr: u16) -> (bool, bool) {
@@ -0,0 +1,252 @@
+//! Implements mapper 85, the konami vrc7
+
+use std::collections::BTreeMap;
+
+use crate::cartridge::audio::vrc7::Vrc7Audio;
+use crate::cartridge::audio::{ExpansionAudio, ExpansionAudioChip};
+use crate::cartridge::vrc_irq::VrcIrq;
+use crate::cartridge::NesCartridgeData;
+use crate::cartridge::{NesMapper, NesMapperTrait};
+
+/// Mapper 85
+#[non_exhaustive]
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Mapper85 {
+    /// The cpu address lines that select the second register of each pair, a4 for the vrc7a and a3 for the vrc7b
+    register_lines: u16,
+    /// The ppu address for ppu addressing
+    ppu_address: u16,
+    /// The prg banks for 0x8000, 0xa000, and 0xc000
+    prg_banks: [u8; 3],
+    /// The 1kb chr banks
+    chr_banks: [u8; 8],
+    /// The control register at 0xe000, mirroring, sound reset, and prg ram enable
+    control: u8,
+    /// The irq counter
+    irq: VrcIrq,
+    /// The expansion audio
+    audio: Vrc7Audio,
+}
+
+impl Mapper85 {
+    /// Create a new Mapper85, the submapper selects the wiring of the board
+    pub fn new(d: &NesCartridgeData) -> NesMapper {
+        let register_lines = match d.volatile.submapper {
+            //vrc7b
+            1 => 0x8,
+            //vrc7a
+            2 => 0x10,
+            _ => 0x18,
+        };
+        NesMapper::from(Self {
+            register_lines,
+            ppu_address: 0,
+            prg_banks: [0; 3],
+            chr_banks: [0; 8],
+            control: 0,
+            irq: VrcIrq::new(),
+            audio: Vrc7Audio::new(),
+        })
+    }
+
+    /// Convert a cpu address into the register address as seen by the chip
+    fn fix_address(&self, addr: u16) -> u16 {
+        let second = (addr & self.register_lines) != 0;
+        (addr & 0xf000) | (second as u16) << 4
+    }
+
+    /// Check the mirroring bits for the ppu addressing.
+    fn check_mirroring(&self, addr: u16) -> (bool, bool) {
+        let a10 = match self.control & 3 {
+            0 => (addr & 1 << 10) != 0,
+            1 => (addr & 1 << 11) != 0,
+            2 => false,
+            _ => true,
+        };
+        (a10, false)
+    }
+
+    /// Calculate the address into chr memory for the given ppu address
+    fn chr_address(&self, addr: u16) -> u32 {
+        let bank = self.chr_banks[(addr >> 10) as usize & 7] as u32;
+        (bank << 10) | (addr & 0x3ff) as u32
+    }
+
+    /// Perform a ppu read operation
+    fn ppu_read(&self, addr: u16, cart: &NesCartridgeData) -> Option<u8> {
+        let v = Vec::new();
+        let chr = if !cart.volatile.chr_ram.is_empty() {
+            &cart.volatile.chr_ram
+        } else if !cart.nonvolatile.chr_rom.is_empty() {
+            &cart.nonvolatile.chr_rom
+        } else {
+            &v
+        };
+        if chr.is_empty() {
+            return None;
+        }
+        match addr {
+            0..=0x1fff => {
+                let addr2 = self.chr_address(addr) & (chr.len() as u32 - 1);
+                Some(chr[addr2 as usize])
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns true when the prg ram is enabled
+    fn prg_ram_enabled(&self) -> bool {
+        (self.control & 0x80) != 0
+    }
+}
+
+impl NesMapperTrait for Mapper85 {
+    fn irq(&self) -> bool {
+        self.irq.irq()
+    }
+
+    fn cpu_cycle(&mut self, _cart: &mut NesCartridgeData) {
+        self.irq.cycle();
+    }
+
+    fn clock_audio(&mut self, _cart: &mut NesCartridgeData) {
+        self.audio.cycle();
+    }
+
+    fn audio_sample(&self, sample: &mut ExpansionAudio) {
+        sample.set(ExpansionAudioChip::Vrc7, self.audio.audio());
+    }
+
+    fn cartridge_registers(&self) -> BTreeMap<String, u8> {
+        let mut hm = BTreeMap::new();
+        hm.insert("PRG bank 8000".to_string(), self.prg_banks[0]);
+        hm.insert("PRG bank A000".to_string(), self.prg_banks[1]);
+        hm.insert("PRG bank C000".to_string(), self.prg_banks[2]);
+        for (i, b) in self.chr_banks.iter().enumerate() {
+            hm.insert(format!("CHR bank {}", i), *b);
+        }
+        hm.insert("Control".to_string(), self.control);
+        hm.insert("IRQ LATCH".to_string(), self.irq.latch());
+        hm.insert("IRQ Counter".to_string(), self.irq.counter());
+        hm.insert("IRQ Control".to_string(), self.irq.control());
+        hm.insert("IRQ Pending".to_string(), self.irq.irq() as u8);
+        let (select, regs) = self.audio.registers();
+        hm.insert("Audio select".to_string(), select);
+        for (a, r) in regs {
+            hm.insert(format!("Audio register {:02X}", a), r);
+        }
+        hm.insert("Mapper".to_string(), 85);
+        hm
+    }
+
+    fn memory_cycle_dump(&self, cart: &NesCartridgeData, addr: u16) -> Option<u8> {
+        match addr {
+            0x6000..=0x7fff => {
+                if self.prg_ram_enabled() && !cart.volatile.prg_ram.is_empty() {
+                    let addr2 = (addr & 0x1fff) as usize % cart.volatile.prg_ram.len();
+                    Some(cart.volatile.prg_ram[addr2])
+                } else {
+                    None
+                }
+            }
+            0x8000..=0xffff => {
+                let bank = match addr {
+                    0x8000..=0xdfff => {
+                        (self.prg_banks[((addr - 0x8000) >> 13) as usize] & 0x3f) as u32
+                    }
+                    //fixed to the last bank
+                    _ => 0xFFFF,
+                };
+                let addr2 = (bank << 13) | (addr & 0x1fff) as u32;
+                let addr3 = addr2 & (cart.nonvolatile.prg_rom.len() as u32 - 1);
+                Some(cart.nonvolatile.prg_rom[addr3 as usize])
+            }
+            _ => None,
+        }
+    }
+
+    fn memory_cycle_read(&mut self, cart: &mut NesCartridgeData, addr: u16) -> Option<u8> {
+        self.memory_cycle_dump(cart, addr)
+    }
+
+    fn memory_cycle_nop(&mut self) {}
+
+    fn memory_cycle_write(&mut self, cart: &mut NesCartridgeData, addr: u16, data: u8) {
+        if (0x6000..=0x7fff).contains(&addr) {
+            if self.prg_ram_enabled() && !cart.volatile.prg_ram.is_empty() {
+                let addr2 = (addr & 0x1fff) as usize % cart.volatile.prg_ram.len();
+                cart.volatile.prg_ram[addr2] = data;
+            }
+            return;
+        }
+        //the audio ports are decoded with a4 and a5 on every board
+        match addr & 0xf030 {
+            0x9010 => {
+                self.audio.write_select(data);
+                return;
+            }
+            0x9030 => {
+                self.audio.write_data(data);
+                return;
+            }
+            _ => {}
+        }
+        match self.fix_address(addr) {
+            0x8000 => {
+                self.prg_banks[0] = data & 0x3f;
+            }
+            0x8010 => {
+                self.prg_banks[1] = data & 0x3f;
+            }
+            0x9000 => {
+                self.prg_banks[2] = data & 0x3f;
+            }
+            a @ 0xa000..=0xd010 => {
+                let index = (((a - 0xa000) >> 12) * 2 + ((a >> 4) & 1)) as usize;
+                self.chr_banks[index] = data;
+            }
+            0xe000 => {
+                self.control = data;
+                self.audio.set_silenced((data & 0x40) != 0);
+            }
+            0xe010 => {
+                self.irq.write_latch(data);
+            }
+            0xf000 => {
+                self.irq.write_control(data);
+            }
+            0xf010 => {
+                self.irq.acknowledge();
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_peek_address(&self, addr: u16, cart: &NesCartridgeData) -> (bool, bool, Option<u8>) {
+        let (mirror, thing) = self.check_mirroring(addr);
+        let data = self.ppu_read(addr, cart);
+        (mirror, thing, data)
+    }
+
+    fn ppu_memory_cycle_address(&mut self, addr: u16) -> (bool, bool) {
+        self.ppu_address = addr;
+        self.check_mirroring(addr)
+    }
+
+    fn ppu_memory_cycle_read(&mut self, cart: &mut NesCartridgeData) -> Option<u8> {
+        self.ppu_read(self.ppu_address, cart)
+    }
+
+    fn ppu_memory_cycle_write(&mut self, cart: &mut NesCartridgeData, data: u8) {
+        if cart.volatile.chr_ram.is_empty() || self.ppu_address >= 0x2000 {
+            return;
+        }
+        let addr2 = self.chr_address(self.ppu_address) & (cart.volatile.chr_ram.len() as u32 - 1);
+        cart.volatile.chr_ram[addr2 as usize] = data;
+    }
+
+    fn rom_byte_hack(&mut self, cart: &mut NesCartridgeData, addr: u32, new_byte: u8) {
+        let addr = addr as usize % cart.nonvolatile.prg_rom.len();
+        cart.nonvolatile.prg_rom[addr] = new_byte;
+    }
+}
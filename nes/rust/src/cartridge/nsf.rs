@@ -0,0 +1,364 @@
+//! Parsing of nsf and nsfe music files, which contain the music code of a game without the rest of the game.
+
+use common_emulator::CartridgeError;
+
+use super::{CpuTiming, ExpansionAudioChip};
+
+/// The magic bytes at the start of an nsf file
+const NSF_MAGIC: &[u8] = b"NESM\x1a";
+/// The magic bytes at the start of an nsfe file
+const NSFE_MAGIC: &[u8] = b"NSFE";
+/// The size of the header of an nsf file
+const NSF_HEADER_SIZE: usize = 0x80;
+/// The default play rate for ntsc, in microseconds
+const DEFAULT_NTSC_RATE: u16 = 16639;
+/// The default play rate for pal, in microseconds
+const DEFAULT_PAL_RATE: u16 = 19997;
+
+/// A single track of an nsf file
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct NsfTrack {
+    /// The song number, as given to the init routine
+    pub song: u8,
+    /// The name of the track, when the file has one
+    pub name: Option<String>,
+    /// The length of the track, when the file has one
+    pub duration: Option<std::time::Duration>,
+}
+
+/// The information about an nsf file that is shown to the user
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+pub struct NsfInfo {
+    /// The name of the game or album
+    pub title: String,
+    /// The composer
+    pub artist: String,
+    /// The copyright holder
+    pub copyright: String,
+    /// The tracks in the order they should be played
+    pub tracks: Vec<NsfTrack>,
+    /// The song to play first
+    pub starting_song: u8,
+    /// The expansion audio chips used by the music
+    pub chips: Vec<ExpansionAudioChip>,
+}
+
+/// The contents of an nsf or nsfe file
+pub struct NsfFile {
+    /// The address the data is loaded at
+    pub load: u16,
+    /// The address of the init routine
+    pub init: u16,
+    /// The address of the play routine
+    pub play: u16,
+    /// The initial banks for 0x8000-0xffff, None when the music is not bankswitched
+    pub banks: Option<[u8; 8]>,
+    /// The play rate for ntsc, in microseconds
+    pub ntsc_rate: u16,
+    /// The play rate for pal, in microseconds
+    pub pal_rate: u16,
+    /// The expansion audio bits from the header
+    pub chip_flags: u8,
+    /// The region the music was made for
+    pub timing: CpuTiming,
+    /// The music code and data
+    pub data: Vec<u8>,
+    /// The information for the user
+    pub info: NsfInfo,
+}
+
+/// The size of a bank of an nsf file
+pub const NSF_BANK_SIZE: usize = 4096;
+
+/// Returns true when the contents look like an nsf or nsfe file
+pub fn is_nsf(contents: &[u8]) -> bool {
+    contents.starts_with(NSF_MAGIC) || contents.starts_with(NSFE_MAGIC)
+}
+
+/// Read a little endian u16
+fn read_u16(contents: &[u8], pos: usize) -> Option<u16> {
+    let bytes = contents.get(pos..pos + 2)?;
+    Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+/// Read a little endian u32
+fn read_u32(contents: &[u8], pos: usize) -> Option<u32> {
+    let bytes = contents.get(pos..pos + 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Convert a null terminated string
+fn read_string(data: &[u8]) -> String {
+    let end = data.iter().position(|b| *b == 0).unwrap_or(data.len());
+    let s = String::from_utf8_lossy(&data[..end]).trim().to_string();
+    if s == "<?>" {
+        String::new()
+    } else {
+        s
+    }
+}
+
+/// Convert the region byte of an nsf file into the timing of the music
+fn region_timing(region: u8) -> CpuTiming {
+    if (region & 2) != 0 {
+        CpuTiming::MultipleRegion
+    } else if (region & 1) != 0 {
+        CpuTiming::Pal
+    } else {
+        CpuTiming::Ntsc
+    }
+}
+
+/// Split data into a list of null terminated strings
+fn read_strings(data: &[u8]) -> Vec<String> {
+    let mut strings: Vec<String> = data.split(|b| *b == 0).map(read_string).collect();
+    //the last string is terminated, so the split leaves an empty string at the end
+    if data.last() == Some(&0) {
+        strings.pop();
+    }
+    strings
+}
+
+impl NsfFile {
+    /// Parse an nsf or nsfe file
+    pub fn parse(contents: &[u8]) -> Result<Self, CartridgeError> {
+        if contents.starts_with(NSF_MAGIC) {
+            Self::parse_nsf(contents)
+        } else if contents.starts_with(NSFE_MAGIC) {
+            let mut file = Self {
+                load: 0,
+                init: 0,
+                play: 0,
+                banks: None,
+                ntsc_rate: DEFAULT_NTSC_RATE,
+                pal_rate: DEFAULT_PAL_RATE,
+                chip_flags: 0,
+                timing: CpuTiming::Ntsc,
+                data: Vec::new(),
+                info: NsfInfo::default(),
+            };
+            let mut songs = None;
+            file.parse_chunks(&contents[4..], &mut songs)?;
+            let songs = songs.ok_or(CartridgeError::HeaderNotFound)?;
+            if file.data.is_empty() {
+                return Err(CartridgeError::RomTooShort);
+            }
+            file.finish_tracks(songs);
+            Ok(file)
+        } else {
+            Err(CartridgeError::InvalidRom)
+        }
+    }
+
+    /// Parse an nsf file, including any nsfe metadata that follows the data of an nsf2 file
+    fn parse_nsf(contents: &[u8]) -> Result<Self, CartridgeError> {
+        if contents.len() <= NSF_HEADER_SIZE {
+            return Err(CartridgeError::RomTooShort);
+        }
+        let header = &contents[0..NSF_HEADER_SIZE];
+        let banks: [u8; 8] = header[0x70..0x78].try_into().unwrap();
+        let program_length =
+            header[0x7d] as usize | (header[0x7e] as usize) << 8 | (header[0x7f] as usize) << 16;
+        let (data, metadata) = if header[5] >= 2 && program_length != 0 {
+            let end = NSF_HEADER_SIZE + program_length;
+            if end > contents.len() {
+                return Err(CartridgeError::RomTooShort);
+            }
+            (&contents[NSF_HEADER_SIZE..end], &contents[end..])
+        } else {
+            (&contents[NSF_HEADER_SIZE..], &contents[0..0])
+        };
+        let rate = |r: u16, default: u16| if r == 0 { default } else { r };
+        let mut file = Self {
+            load: read_u16(header, 8).unwrap(),
+            init: read_u16(header, 0xa).unwrap(),
+            play: read_u16(header, 0xc).unwrap(),
+            banks: if banks.iter().any(|b| *b != 0) {
+                Some(banks)
+            } else {
+                None
+            },
+            ntsc_rate: rate(read_u16(header, 0x6e).unwrap(), DEFAULT_NTSC_RATE),
+            pal_rate: rate(read_u16(header, 0x78).unwrap(), DEFAULT_PAL_RATE),
+            chip_flags: header[0x7b],
+            timing: region_timing(header[0x7a]),
+            data: data.to_vec(),
+            info: NsfInfo {
+                title: read_string(&header[0xe..0x2e]),
+                artist: read_string(&header[0x2e..0x4e]),
+                copyright: read_string(&header[0x4e..0x6e]),
+                tracks: Vec::new(),
+                starting_song: header[7].saturating_sub(1),
+                chips: Vec::new(),
+            },
+        };
+        if !metadata.is_empty() {
+            let mut songs = Some(header[6]);
+            file.parse_chunks(metadata, &mut songs)?;
+        }
+        file.finish_tracks(header[6]);
+        Ok(file)
+    }
+
+    /// Parse the chunks of an nsfe file. The song count is filled in by the info chunk.
+    fn parse_chunks(
+        &mut self,
+        contents: &[u8],
+        songs: &mut Option<u8>,
+    ) -> Result<(), CartridgeError> {
+        let mut names = Vec::new();
+        let mut times = Vec::new();
+        let mut playlist = None;
+        let mut pos = 0;
+        while pos < contents.len() {
+            let len = read_u32(contents, pos).ok_or(CartridgeError::RomTooShort)? as usize;
+            let id = contents
+                .get(pos + 4..pos + 8)
+                .ok_or(CartridgeError::RomTooShort)?;
+            pos += 8;
+            let data = contents
+                .get(pos..pos + len)
+                .ok_or(CartridgeError::RomTooShort)?;
+            pos += len;
+            match id {
+                b"INFO" => {
+                    if data.len() < 8 {
+                        return Err(CartridgeError::RomTooShort);
+                    }
+                    self.load = read_u16(data, 0).unwrap();
+                    self.init = read_u16(data, 2).unwrap();
+                    self.play = read_u16(data, 4).unwrap();
+                    self.timing = region_timing(data[6]);
+                    self.chip_flags = data[7];
+                    *songs = Some(data.get(8).copied().unwrap_or(1));
+                    self.info.starting_song = data.get(9).copied().unwrap_or(0);
+                }
+                b"DATA" => self.data = data.to_vec(),
+                b"BANK" => {
+                    let mut banks = [0; 8];
+                    for (b, d) in banks.iter_mut().zip(data) {
+                        *b = *d;
+                    }
+                    self.banks = Some(banks);
+                }
+                b"RATE" => {
+                    if let Some(r) = read_u16(data, 0) {
+                        self.ntsc_rate = r;
+                    }
+                    if let Some(r) = read_u16(data, 2) {
+                        self.pal_rate = r;
+                    }
+                }
+                b"auth" => {
+                    let mut strings = read_strings(data).into_iter();
+                    self.info.title = strings.next().unwrap_or_default();
+                    self.info.artist = strings.next().unwrap_or_default();
+                    self.info.copyright = strings.next().unwrap_or_default();
+                }
+                b"tlbl" => names = read_strings(data),
+                b"time" => {
+                    times = data
+                        .chunks_exact(4)
+                        .map(|t| i32::from_le_bytes([t[0], t[1], t[2], t[3]]))
+                        .collect();
+                }
+                b"plst" => playlist = Some(data.to_vec()),
+                b"NEND" => break,
+                _ => {
+                    //chunks starting with an uppercase letter are required to play the music correctly
+                    if id[0].is_ascii_uppercase() {
+                        return Err(CartridgeError::IncompatibleRom);
+                    }
+                }
+            }
+        }
+        let count = songs.unwrap_or(names.len().max(times.len()) as u8);
+        let order: Vec<u8> = playlist.unwrap_or_else(|| (0..count).collect());
+        self.info.tracks = order
+            .into_iter()
+            .map(|song| NsfTrack {
+                song,
+                name: names.get(song as usize).filter(|n| !n.is_empty()).cloned(),
+                duration: times
+                    .get(song as usize)
+                    .filter(|t| **t >= 0)
+                    .map(|t| std::time::Duration::from_millis(*t as u64)),
+            })
+            .collect();
+        Ok(())
+    }
+
+    /// Fill out the track list when the file did not have one, and the list of expansion audio chips
+    fn finish_tracks(&mut self, songs: u8) {
+        if self.info.tracks.is_empty() {
+            self.info.tracks = (0..songs)
+                .map(|song| NsfTrack {
+                    song,
+                    name: None,
+                    duration: None,
+                })
+                .collect();
+        }
+        let chips = [
+            ExpansionAudioChip::Vrc6,
+            ExpansionAudioChip::Vrc7,
+            ExpansionAudioChip::Fds,
+            ExpansionAudioChip::Mmc5,
+            ExpansionAudioChip::Namco163,
+            ExpansionAudioChip::Sunsoft5b,
+        ];
+        self.info.chips = chips
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| (self.chip_flags & (1 << i)) != 0)
+            .map(|(_, c)| c)
+            .collect();
+    }
+
+    /// Returns true when the music uses the expansion audio chip
+    pub fn uses_chip(&self, chip: ExpansionAudioChip) -> bool {
+        self.info.chips.contains(&chip)
+    }
+
+    /// The address of the first bank. The famicom disk system has banks for 0x6000-0x7fff as well.
+    fn base_address(&self) -> u16 {
+        if self.uses_chip(ExpansionAudioChip::Fds) {
+            0x6000
+        } else {
+            0x8000
+        }
+    }
+
+    /// Build the prg rom, padded so that the data sits at the correct offset of the first bank
+    pub fn prg_rom(&self) -> Result<Vec<u8>, CartridgeError> {
+        let padding = if self.banks.is_some() {
+            (self.load as usize) & (NSF_BANK_SIZE - 1)
+        } else {
+            (self.load as usize)
+                .checked_sub(self.base_address() as usize)
+                .ok_or(CartridgeError::InvalidRom)?
+        };
+        let mut prg = vec![0; padding];
+        prg.extend_from_slice(&self.data);
+        let len = prg.len().div_ceil(NSF_BANK_SIZE).max(1) * NSF_BANK_SIZE;
+        prg.resize(len, 0);
+        Ok(prg)
+    }
+
+    /// The initial banks for 0x6000-0xffff
+    pub fn initial_banks(&self) -> [u8; 10] {
+        let mut banks = [0; 10];
+        if let Some(b) = self.banks {
+            banks[2..].copy_from_slice(&b);
+            //the disk system banks for 0x6000-0x7fff start with the same banks as 0xe000-0xffff
+            banks[0] = b[6];
+            banks[1] = b[7];
+        } else {
+            let first = ((self.base_address() - 0x6000) >> 12) as usize;
+            for (i, b) in banks[first..].iter_mut().enumerate() {
+                *b = i as u8;
+            }
+        }
+        banks
+    }
+}
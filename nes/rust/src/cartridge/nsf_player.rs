@@ -0,0 +1,513 @@
+//! Implements a cartridge that plays nsf music. The bankswitching at 0x5ff8-0x5fff is the same as mapper 31.
+//! A small driver program at 0x4100 calls the init routine of the music, then calls the play routine from a timer irq.
+
+use std::collections::BTreeMap;
+
+use crate::cartridge::audio::fds::FdsAudio;
+use crate::cartridge::audio::mmc5::Mmc5Audio;
+use crate::cartridge::audio::namco163::Namco163Audio;
+use crate::cartridge::audio::sunsoft5b::Sunsoft5bAudio;
+use crate::cartridge::audio::vrc6::Vrc6Audio;
+use crate::cartridge::audio::vrc7::Vrc7Audio;
+use crate::cartridge::audio::{ExpansionAudio, ExpansionAudioChip};
+use crate::cartridge::nsf::{NsfFile, NSF_BANK_SIZE};
+use crate::cartridge::NesCartridgeData;
+use crate::cartridge::{NesMapper, NesMapperTrait};
+use crate::region::Region;
+
+/// The address of the driver program
+const DRIVER_ADDRESS: u16 = 0x4100;
+
+/// The driver program. It clears ram and the apu, starts the song, then calls the play routine every time the play timer expires.
+#[rustfmt::skip]
+const DRIVER: [u8; 0x74] = [
+    //0x4100 reset: sei, cld, ldx #$ff, txs
+    0x78, 0xd8, 0xa2, 0xff, 0x9a,
+    //0x4105 lda #0, sta $2000, sta $2001, tax
+    0xa9, 0x00, 0x8d, 0x00, 0x20, 0x8d, 0x01, 0x20, 0xaa,
+    //0x410e clear the cpu ram
+    0x95, 0x00, 0x9d, 0x00, 0x01, 0x9d, 0x00, 0x02, 0x9d, 0x00, 0x03,
+    0x9d, 0x00, 0x04, 0x9d, 0x00, 0x05, 0x9d, 0x00, 0x06, 0x9d, 0x00, 0x07,
+    0xe8, 0xd0, 0xe6,
+    //0x4128 clear 0x4000-0x4013
+    0xa2, 0x13, 0x9d, 0x00, 0x40, 0xca, 0x10, 0xfa,
+    //0x4130 sta $4015, lda #$0f, sta $4015, lda #$40, sta $4017
+    0x8d, 0x15, 0x40, 0xa9, 0x0f, 0x8d, 0x15, 0x40, 0xa9, 0x40, 0x8d, 0x17, 0x40,
+    //0x413d sta $41f6 to start the song, lda $41f0 (song), ldx $41f1 (region), jsr init
+    0x8d, 0xf6, 0x41, 0xad, 0xf0, 0x41, 0xae, 0xf1, 0x41, 0x20, 0x50, 0x41,
+    //0x4149 sta $41f7 to start the play timer, cli, then wait forever
+    0x8d, 0xf7, 0x41, 0x58, 0x4c, 0x4d, 0x41,
+    //0x4150 jmp ($41f2) to init, 0x4153 jmp ($41f4) to play
+    0x6c, 0xf2, 0x41, 0x6c, 0xf4, 0x41,
+    //0x4156 irq: save registers
+    0x48, 0x8a, 0x48, 0x98, 0x48,
+    //0x415b bit $4015, bpl, acknowledge the dmc irq by writing the last value of $4010 ($41f9) to $4010
+    0x2c, 0x15, 0x40, 0x10, 0x06, 0xad, 0xf9, 0x41, 0x8d, 0x10, 0x40,
+    //0x4166 call play if the play timer expired
+    0xad, 0xf8, 0x41, 0xf0, 0x03, 0x20, 0x53, 0x41,
+    //0x416e restore registers, 0x4173 rti
+    0x68, 0xa8, 0x68, 0xaa, 0x68, 0x40,
+];
+
+/// The address of the nmi handler in the driver
+const DRIVER_NMI: u16 = 0x4173;
+/// The address of the irq handler in the driver
+const DRIVER_IRQ: u16 = 0x4156;
+
+/// The nsf player
+#[non_exhaustive]
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct NsfPlayer {
+    /// The address for ppu memory cycles
+    ppu_address: u16,
+    /// The banks for 0x6000-0xffff, 0x5ff6-0x5fff
+    banks: [u8; 10],
+    /// The banks when the song starts
+    initial_banks: [u8; 10],
+    /// True when the famicom disk system is used, which makes 0x6000-0xdfff ram
+    fds: bool,
+    /// The song to play
+    song: u8,
+    /// The region of the console
+    region: Region,
+    /// The address of the init routine
+    init: u16,
+    /// The address of the play routine
+    play: u16,
+    /// The play rate for ntsc, in microseconds
+    ntsc_rate: u16,
+    /// The play rate for pal, in microseconds
+    pal_rate: u16,
+    /// Counts cpu cycles for the play timer
+    play_counter: u32,
+    /// True when the play timer is running
+    play_enabled: bool,
+    /// The irq from the play timer
+    play_irq: bool,
+    /// The last value written to the dmc control register, 0x4010, so the driver can acknowledge the dmc irq
+    dmc_control: u8,
+    /// The number of cpu cycles since the song started
+    song_cycles: u64,
+    /// The expansion ram of the mmc5, 0x5c00-0x5ff5
+    exram: Vec<u8>,
+    /// The multiplier of the mmc5
+    multiplier: [u8; 2],
+    /// The address register of the namco 163 sound ram
+    namco_address: u8,
+    /// The audio of the vrc6
+    vrc6: Option<Vrc6Audio>,
+    /// The audio of the vrc7
+    vrc7: Option<Vrc7Audio>,
+    /// The audio of the famicom disk system
+    fds_audio: Option<FdsAudio>,
+    /// The audio of the mmc5
+    mmc5: Option<Mmc5Audio>,
+    /// The audio of the namco 163
+    namco163: Option<Namco163Audio>,
+    /// The audio of the sunsoft 5b
+    sunsoft5b: Option<Sunsoft5bAudio>,
+}
+
+impl NsfPlayer {
+    /// Create a new nsf player for the file
+    pub fn new(_d: &NesCartridgeData, file: &NsfFile) -> NesMapper {
+        let mut player = Self {
+            ppu_address: 0,
+            banks: file.initial_banks(),
+            initial_banks: file.initial_banks(),
+            fds: file.uses_chip(ExpansionAudioChip::Fds),
+            song: file.info.starting_song,
+            region: Region::Ntsc,
+            init: file.init,
+            play: file.play,
+            ntsc_rate: file.ntsc_rate,
+            pal_rate: file.pal_rate,
+            play_counter: 0,
+            play_enabled: false,
+            play_irq: false,
+            dmc_control: 0,
+            song_cycles: 0,
+            exram: Vec::new(),
+            multiplier: [0; 2],
+            namco_address: 0,
+            vrc6: None,
+            vrc7: None,
+            fds_audio: None,
+            mmc5: None,
+            namco163: None,
+            sunsoft5b: None,
+        };
+        player.reset_chips(file.info.chips.as_slice());
+        NesMapper::from(player)
+    }
+
+    /// Create fresh expansion audio chips
+    fn reset_chips(&mut self, chips: &[ExpansionAudioChip]) {
+        let used = |c| chips.contains(&c);
+        self.vrc6 = used(ExpansionAudioChip::Vrc6).then(Vrc6Audio::new);
+        self.vrc7 = used(ExpansionAudioChip::Vrc7).then(Vrc7Audio::new);
+        self.fds_audio = used(ExpansionAudioChip::Fds).then(FdsAudio::new);
+        self.mmc5 = used(ExpansionAudioChip::Mmc5).then(Mmc5Audio::new);
+        self.namco163 = used(ExpansionAudioChip::Namco163).then(Namco163Audio::new);
+        self.sunsoft5b = used(ExpansionAudioChip::Sunsoft5b).then(Sunsoft5bAudio::new);
+        self.exram = if self.mmc5.is_some() {
+            vec![0; 1024]
+        } else {
+            Vec::new()
+        };
+    }
+
+    /// Returns the list of expansion audio chips in use
+    fn chips(&self) -> Vec<ExpansionAudioChip> {
+        let mut chips = Vec::new();
+        if self.vrc6.is_some() {
+            chips.push(ExpansionAudioChip::Vrc6);
+        }
+        if self.vrc7.is_some() {
+            chips.push(ExpansionAudioChip::Vrc7);
+        }
+        if self.fds_audio.is_some() {
+            chips.push(ExpansionAudioChip::Fds);
+        }
+        if self.mmc5.is_some() {
+            chips.push(ExpansionAudioChip::Mmc5);
+        }
+        if self.namco163.is_some() {
+            chips.push(ExpansionAudioChip::Namco163);
+        }
+        if self.sunsoft5b.is_some() {
+            chips.push(ExpansionAudioChip::Sunsoft5b);
+        }
+        chips
+    }
+
+    /// Start the selected song, restoring the banks and clearing the ram and expansion audio
+    fn start_song(&mut self, cart: &mut NesCartridgeData) {
+        let chips = self.chips();
+        self.reset_chips(&chips);
+        self.multiplier = [0; 2];
+        self.namco_address = 0;
+        for i in 0..cart.volatile.prg_ram.len() {
+            cart.volatile.prg_ram[i] = 0;
+        }
+        for i in 0..cart.volatile.mapper_ram.len() {
+            cart.volatile.mapper_ram[i] = 0;
+        }
+        self.banks = self.initial_banks;
+        if self.fds {
+            for slot in 0..self.banks.len() {
+                self.load_fds_bank(cart, slot);
+            }
+        }
+        self.play_enabled = false;
+        self.play_irq = false;
+        self.play_counter = 0;
+        self.song_cycles = 0;
+    }
+
+    /// Copy a bank into the ram of the famicom disk system
+    fn load_fds_bank(&self, cart: &mut NesCartridgeData, slot: usize) {
+        let rom = &cart.nonvolatile.prg_rom;
+        let start = (self.banks[slot] as usize * NSF_BANK_SIZE) % rom.len();
+        let offset = slot * NSF_BANK_SIZE;
+        for i in 0..NSF_BANK_SIZE {
+            let addr = offset + i;
+            if addr < cart.volatile.prg_ram.len() {
+                cart.volatile.prg_ram[addr] = rom[start + i];
+            }
+        }
+    }
+
+    /// Read the banked memory at 0x6000-0xffff
+    fn prg_dump(&self, cart: &NesCartridgeData, addr: u16) -> Option<u8> {
+        if self.fds || addr < 0x8000 {
+            let ram = &cart.volatile.prg_ram;
+            if ram.is_empty() {
+                None
+            } else {
+                Some(ram[(addr - 0x6000) as usize % ram.len()])
+            }
+        } else {
+            let rom = &cart.nonvolatile.prg_rom;
+            let slot = ((addr - 0x6000) as usize) / NSF_BANK_SIZE;
+            let addr2 = self.banks[slot] as usize * NSF_BANK_SIZE + (addr as usize & 0xfff);
+            Some(rom[addr2 % rom.len()])
+        }
+    }
+
+    /// The number of cpu cycles between calls to the play routine
+    fn play_period(&self) -> u32 {
+        let rate = if self.region == Region::Ntsc {
+            self.ntsc_rate
+        } else {
+            self.pal_rate
+        };
+        (rate as f32 * self.region.cpu_frequency() / 1_000_000.0).max(1.0) as u32
+    }
+}
+
+impl NesMapperTrait for NsfPlayer {
+    fn irq(&self) -> bool {
+        self.play_irq || self.mmc5.as_ref().map(|a| a.irq()).unwrap_or(false)
+    }
+
+    fn cpu_cycle(&mut self, _cart: &mut NesCartridgeData) {
+        if self.play_enabled {
+            self.song_cycles += 1;
+            self.play_counter += 1;
+            if self.play_counter >= self.play_period() {
+                self.play_counter = 0;
+                self.play_irq = true;
+            }
+        }
+    }
+
+    fn clock_audio(&mut self, cart: &mut NesCartridgeData) {
+        if let Some(a) = &mut self.vrc6 {
+            a.cycle();
+        }
+        if let Some(a) = &mut self.vrc7 {
+            a.cycle();
+        }
+        if let Some(a) = &mut self.fds_audio {
+            a.cycle();
+        }
+        if let Some(a) = &mut self.mmc5 {
+            a.cycle();
+        }
+        if let Some(a) = &mut self.namco163 {
+            a.cycle(&mut cart.volatile.mapper_ram);
+        }
+        if let Some(a) = &mut self.sunsoft5b {
+            a.cycle();
+        }
+    }
+
+    fn audio_sample(&self, sample: &mut ExpansionAudio) {
+        if let Some(a) = &self.vrc6 {
+            sample.set(ExpansionAudioChip::Vrc6, a.audio());
+        }
+        if let Some(a) = &self.vrc7 {
+            sample.set(ExpansionAudioChip::Vrc7, a.audio());
+        }
+        if let Some(a) = &self.fds_audio {
+            sample.set(ExpansionAudioChip::Fds, a.audio());
+        }
+        if let Some(a) = &self.mmc5 {
+            sample.set(ExpansionAudioChip::Mmc5, a.audio());
+        }
+        if let Some(a) = &self.namco163 {
+            sample.set(ExpansionAudioChip::Namco163, a.audio());
+        }
+        if let Some(a) = &self.sunsoft5b {
+            sample.set(ExpansionAudioChip::Sunsoft5b, a.audio());
+        }
+    }
+
+    fn select_song(&mut self, song: u8) {
+        self.song = song;
+    }
+
+    fn current_song(&self) -> Option<(u8, u64)> {
+        Some((self.song, self.song_cycles))
+    }
+
+    fn set_region(&mut self, region: Region) {
+        self.region = region;
+    }
+
+    fn cartridge_registers(&self) -> BTreeMap<String, u8> {
+        let mut hm = BTreeMap::new();
+        for (i, b) in self.banks.iter().enumerate() {
+            hm.insert(format!("Bank {:X}", 0x5ff6 + i), *b);
+        }
+        hm.insert("Song".to_string(), self.song);
+        hm.insert("Play timer".to_string(), self.play_enabled as u8);
+        hm.insert("IRQ".to_string(), self.play_irq as u8);
+        hm.insert("Mapper".to_string(), 31);
+        hm
+    }
+
+    fn memory_cycle_dump(&self, cart: &NesCartridgeData, addr: u16) -> Option<u8> {
+        match addr {
+            0x4040..=0x4092 => self.fds_audio.as_ref().and_then(|a| a.read(addr)),
+            0x41f0 => Some(self.song),
+            //the init routine is told 0 for ntsc and 1 for pal
+            0x41f1 => Some((self.region != Region::Ntsc) as u8),
+            0x41f2 => Some(self.init as u8),
+            0x41f3 => Some((self.init >> 8) as u8),
+            0x41f4 => Some(self.play as u8),
+            0x41f5 => Some((self.play >> 8) as u8),
+            0x41f8 => Some(self.play_irq as u8),
+            0x41f9 => Some(self.dmc_control),
+            0x4100..=0x41ff => DRIVER.get((addr - DRIVER_ADDRESS) as usize).copied(),
+            0x4800..=0x4fff if self.namco163.is_some() => {
+                let ram = &cart.volatile.mapper_ram;
+                if ram.is_empty() {
+                    None
+                } else {
+                    Some(ram[(self.namco_address & 0x7f) as usize])
+                }
+            }
+            0x5010 | 0x5015 => self.mmc5.as_ref().and_then(|a| a.dump(addr)),
+            0x5205 if self.mmc5.is_some() => {
+                Some((self.multiplier[0] as u16 * self.multiplier[1] as u16) as u8)
+            }
+            0x5206 if self.mmc5.is_some() => {
+                Some(((self.multiplier[0] as u16 * self.multiplier[1] as u16) >> 8) as u8)
+            }
+            0x5c00..=0x5ff5 if !self.exram.is_empty() => Some(self.exram[(addr & 0x3ff) as usize]),
+            //the driver takes over the interrupt vectors
+            0xfffa => Some(DRIVER_NMI as u8),
+            0xfffb => Some((DRIVER_NMI >> 8) as u8),
+            0xfffc => Some(DRIVER_ADDRESS as u8),
+            0xfffd => Some((DRIVER_ADDRESS >> 8) as u8),
+            0xfffe => Some(DRIVER_IRQ as u8),
+            0xffff => Some((DRIVER_IRQ >> 8) as u8),
+            0x6000..=0xffff => self.prg_dump(cart, addr),
+            _ => None,
+        }
+    }
+
+    fn memory_cycle_read(&mut self, cart: &mut NesCartridgeData, addr: u16) -> Option<u8> {
+        let r = self.memory_cycle_dump(cart, addr);
+        match addr {
+            0x41f8 => self.play_irq = false,
+            0x4800..=0x4fff if (self.namco_address & 0x80) != 0 => {
+                self.namco_address = 0x80 | (self.namco_address.wrapping_add(1) & 0x7f);
+            }
+            0x5010 => {
+                if let Some(a) = &mut self.mmc5 {
+                    a.read(addr);
+                }
+            }
+            _ => {}
+        }
+        r
+    }
+
+    fn memory_cycle_nop(&mut self) {}
+
+    fn other_memory_write(&mut self, addr: u16, data: u8) {
+        if addr == 0x4010 {
+            self.dmc_control = data;
+        }
+    }
+
+    fn memory_cycle_write(&mut self, cart: &mut NesCartridgeData, addr: u16, data: u8) {
+        match addr {
+            0x4040..=0x408a => {
+                if let Some(a) = &mut self.fds_audio {
+                    a.write(addr, data);
+                }
+            }
+            0x41f6 => self.start_song(cart),
+            0x41f7 => {
+                self.play_enabled = true;
+                self.play_counter = 0;
+            }
+            0x4800..=0x4fff if self.namco163.is_some() => {
+                let ram = &mut cart.volatile.mapper_ram;
+                if !ram.is_empty() {
+                    ram[(self.namco_address & 0x7f) as usize] = data;
+                }
+                if (self.namco_address & 0x80) != 0 {
+                    self.namco_address = 0x80 | (self.namco_address.wrapping_add(1) & 0x7f);
+                }
+            }
+            0x5000..=0x5015 => {
+                if let Some(a) = &mut self.mmc5 {
+                    a.write(addr, data);
+                }
+            }
+            0x5205 | 0x5206 => self.multiplier[(addr - 0x5205) as usize] = data,
+            0x5c00..=0x5ff5 => {
+                if !self.exram.is_empty() {
+                    self.exram[(addr & 0x3ff) as usize] = data;
+                }
+            }
+            0x5ff6..=0x5fff => {
+                let slot = (addr - 0x5ff6) as usize;
+                //0x5ff6 and 0x5ff7 only exist for the famicom disk system
+                if slot >= 2 || self.fds {
+                    self.banks[slot] = data;
+                    if self.fds {
+                        self.load_fds_bank(cart, slot);
+                    }
+                }
+            }
+            0x6000..=0x7fff => {
+                let ram = &mut cart.volatile.prg_ram;
+                if !ram.is_empty() {
+                    let len = ram.len();
+                    ram[(addr - 0x6000) as usize % len] = data;
+                }
+            }
+            0x8000..=0xdfff if self.fds => {
+                let ram = &mut cart.volatile.prg_ram;
+                let addr2 = (addr - 0x6000) as usize;
+                if addr2 < ram.len() {
+                    ram[addr2] = data;
+                }
+            }
+            _ => {}
+        }
+        if let Some(a) = &mut self.vrc6 {
+            if matches!(addr, 0x9000..=0x9003 | 0xa000..=0xa002 | 0xb000..=0xb002) {
+                a.write(addr, data);
+            }
+        }
+        if let Some(a) = &mut self.vrc7 {
+            match addr {
+                0x9010 => a.write_select(data),
+                0x9030 => a.write_data(data),
+                _ => {}
+            }
+        }
+        if let Some(a) = &mut self.sunsoft5b {
+            match addr {
+                0xc000..=0xdfff => a.write_select(data),
+                0xe000..=0xffff => a.write_data(data),
+                _ => {}
+            }
+        }
+        if self.namco163.is_some() && (0xf800..=0xffff).contains(&addr) {
+            self.namco_address = data;
+        }
+    }
+
+    fn ppu_peek_address(&self, addr: u16, cart: &NesCartridgeData) -> (bool, bool, Option<u8>) {
+        let a10 = (addr & (1 << 10)) != 0;
+        let data = if addr < 0x2000 {
+            Some(cart.volatile.chr_ram[addr as usize % cart.volatile.chr_ram.len()])
+        } else {
+            None
+        };
+        (a10, false, data)
+    }
+
+    fn ppu_memory_cycle_address(&mut self, addr: u16) -> (bool, bool) {
+        self.ppu_address = addr;
+        ((addr & (1 << 10)) != 0, false)
+    }
+
+    fn ppu_memory_cycle_read(&mut self, cart: &mut NesCartridgeData) -> Option<u8> {
+        if self.ppu_address < 0x2000 {
+            Some(cart.volatile.chr_ram[self.ppu_address as usize % cart.volatile.chr_ram.len()])
+        } else {
+            None
+        }
+    }
+
+    fn ppu_memory_cycle_write(&mut self, cart: &mut NesCartridgeData, data: u8) {
+        if self.ppu_address < 0x2000 {
+            let len = cart.volatile.chr_ram.len();
+            cart.volatile.chr_ram[self.ppu_address as usize % len] = data;
+        }
+    }
+
+    fn rom_byte_hack(&mut self, cart: &mut NesCartridgeData, addr: u32, new_byte: u8) {
+        let addr = addr as usize % cart.nonvolatile.prg_rom.len();
+        cart.nonvolatile.prg_rom[addr] = new_byte;
+    }
+}
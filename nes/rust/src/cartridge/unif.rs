@@ -0,0 +1,178 @@
+//! Parsing of the unif rom format, which describes a cartridge by the name of its board instead of a mapper number.
+
+use common_emulator::CartridgeError;
+
+use super::{CpuTiming, FixedNametables};
+
+/// The magic bytes at the start of a unif rom
+pub const UNIF_MAGIC: &[u8] = b"UNIF";
+/// The size of the unif header, the magic, the revision, and padding
+const UNIF_HEADER_SIZE: usize = 32;
+
+/// The contents of a unif rom
+pub struct UnifRom {
+    /// The name of the board, with any prefix like NES- or UNL- removed
+    pub board: String,
+    /// The prg rom, from the PRG0 through PRGF chunks
+    pub prg_rom: Vec<u8>,
+    /// The chr rom, from the CHR0 through CHRF chunks
+    pub chr_rom: Vec<u8>,
+    /// The mirroring, true for vertical. None when the mirroring is not fixed.
+    pub mirroring: Option<bool>,
+    /// The single screen or four screen nametables wired on the board, from the MIRR chunk
+    pub nametables: Option<FixedNametables>,
+    /// True when the cartridge has battery backed ram
+    pub battery: bool,
+    /// The region of the rom
+    pub timing: CpuTiming,
+}
+
+/// Remove the prefixes that are commonly added to board names
+fn strip_board_prefix(board: &str) -> &str {
+    for prefix in ["NES-", "UNL-", "HVC-", "BTL-", "BMC-", "IREM-", "TAITO-"] {
+        if let Some(b) = board.strip_prefix(prefix) {
+            return b;
+        }
+    }
+    board
+}
+
+/// Convert the name of a board into a mapper number and submapper number
+pub fn board_mapper(board: &str) -> Option<(u32, u8)> {
+    let m = match board {
+        "NROM" | "NROM-128" | "NROM-256" | "RROM" | "RROM-128" => (0, 0),
+        "SAROM" | "SBROM" | "SCROM" | "SC1ROM" | "SEROM" | "SFROM" | "SGROM" | "SHROM"
+        | "SJROM" | "SKROM" | "SLROM" | "SL1ROM" | "SL2ROM" | "SL3ROM" | "SLRROM" | "SMROM"
+        | "SNROM" | "SOROM" | "SUROM" | "SXROM" => (1, 0),
+        "UNROM" | "UOROM" => (2, 0),
+        "CNROM" => (3, 0),
+        "TBROM" | "TEROM" | "TFROM" | "TGROM" | "TKROM" | "TLROM" | "TL1ROM" | "TL2ROM"
+        | "TNROM" | "TR1ROM" | "TSROM" | "TVROM" | "B4" => (4, 0),
+        "EKROM" | "ELROM" | "ETROM" | "EWROM" => (5, 0),
+        "AMROM" => (7, 2),
+        "ANROM" | "AN1ROM" | "AOROM" => (7, 1),
+        "PEEOROM" | "PNROM" => (9, 0),
+        "FJROM" | "FKROM" => (10, 0),
+        "COLORDREAMS-74*377" => (11, 0),
+        "NINA-001" => (34, 1),
+        "BNROM" => (34, 2),
+        "GNROM" | "MHROM" => (66, 0),
+        "JLROM" | "JSROM" | "BTR" => (69, 0),
+        "CAMERICA-BF9093" | "BF9093" => (71, 0),
+        "CAMERICA-BF9097" | "BF9097" => (71, 1),
+        _ => return None,
+    };
+    Some(m)
+}
+
+/// The size of the prg ram on a board, in bytes
+pub fn board_prg_ram_size(board: &str) -> usize {
+    match board {
+        "SAROM" | "SJROM" | "SKROM" | "SNROM" | "SUROM" => 8192,
+        "SOROM" => 16384,
+        "SXROM" => 32768,
+        "TKROM" | "TNROM" | "TSROM" => 8192,
+        "EKROM" => 8192,
+        "ETROM" => 16384,
+        "EWROM" => 32768,
+        "FJROM" | "FKROM" => 8192,
+        "NINA-001" => 8192,
+        "JSROM" | "BTR" => 8192,
+        _ => 0,
+    }
+}
+
+/// Read a little endian u32 from the rom
+fn read_u32(contents: &[u8], pos: usize) -> Option<u32> {
+    let bytes = contents.get(pos..pos + 4)?;
+    Some(u32::from_le_bytes(bytes.try_into().ok()?))
+}
+
+/// Parse a unif rom into its chunks
+pub fn parse(contents: &[u8]) -> Result<UnifRom, CartridgeError> {
+    if !contents.starts_with(UNIF_MAGIC) {
+        return Err(CartridgeError::InvalidRom);
+    }
+    if contents.len() < UNIF_HEADER_SIZE {
+        return Err(CartridgeError::RomTooShort);
+    }
+    let mut board = None;
+    let mut prg: [Option<&[u8]>; 16] = [None; 16];
+    let mut chr: [Option<&[u8]>; 16] = [None; 16];
+    let mut mirroring = None;
+    let mut nametables = None;
+    let mut battery = false;
+    let mut timing = CpuTiming::Ntsc;
+
+    let mut pos = UNIF_HEADER_SIZE;
+    while pos < contents.len() {
+        let id = contents
+            .get(pos..pos + 4)
+            .ok_or(CartridgeError::RomTooShort)?;
+        let len = read_u32(contents, pos + 4).ok_or(CartridgeError::RomTooShort)? as usize;
+        pos += 8;
+        let data = contents
+            .get(pos..pos + len)
+            .ok_or(CartridgeError::RomTooShort)?;
+        pos += len;
+        //the last character of the rom chunks is a hex digit
+        let index = (id[3] as char).to_digit(16).map(|i| i as usize);
+        match (&id[0..3], index) {
+            (b"PRG", Some(i)) => prg[i] = Some(data),
+            (b"CHR", Some(i)) => chr[i] = Some(data),
+            _ => match id {
+                b"MAPR" => {
+                    let end = data.iter().position(|b| *b == 0).unwrap_or(data.len());
+                    board = Some(String::from_utf8_lossy(&data[..end]).trim().to_string());
+                }
+                b"MIRR" => {
+                    //5 means the mapper controls the mirroring
+                    (mirroring, nametables) = match data.first() {
+                        Some(0) => (Some(false), None),
+                        Some(1) => (Some(true), None),
+                        Some(2) => (None, Some(FixedNametables::SingleScreenA)),
+                        Some(3) => (None, Some(FixedNametables::SingleScreenB)),
+                        Some(4) => (None, Some(FixedNametables::FourScreen)),
+                        _ => (None, None),
+                    };
+                }
+                b"BATR" => {
+                    battery = data.first().map(|b| *b != 0).unwrap_or(true);
+                }
+                b"TVCI" => {
+                    timing = match data.first() {
+                        Some(1) => CpuTiming::Pal,
+                        Some(2) => CpuTiming::MultipleRegion,
+                        _ => CpuTiming::Ntsc,
+                    };
+                }
+                //other chunks like the name and dumper information are not needed
+                _ => {}
+            },
+        }
+    }
+
+    let board = board.ok_or(CartridgeError::HeaderNotFound)?;
+    let prg_rom: Vec<u8> = prg
+        .iter()
+        .flatten()
+        .flat_map(|c| c.iter().copied())
+        .collect();
+    let chr_rom: Vec<u8> = chr
+        .iter()
+        .flatten()
+        .flat_map(|c| c.iter().copied())
+        .collect();
+    if prg_rom.is_empty() {
+        return Err(CartridgeError::RomTooShort);
+    }
+    Ok(UnifRom {
+        board: strip_board_prefix(&board).to_string(),
+        prg_rom,
+        chr_rom,
+        mirroring,
+        nametables,
+        battery,
+        timing,
+    })
+}
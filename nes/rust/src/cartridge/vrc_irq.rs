@@ -0,0 +1,107 @@
+//! The irq counter shared by the konami vrc4, vrc6, and vrc7 mappers.
+
+/// The konami vrc irq counter, with its scanline prescaler
+#[non_exhaustive]
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct VrcIrq {
+    /// The value reloaded into the counter
+    latch: u8,
+    /// The irq counter, counts up and triggers the irq when it overflows
+    counter: u8,
+    /// The prescaler that approximates scanlines from cpu cycles
+    prescaler: i16,
+    /// The control register, enable after acknowledge, enable, cycle mode
+    control: u8,
+    /// Indicates that an irq is pending
+    pending: bool,
+}
+
+impl VrcIrq {
+    /// Create a new irq counter
+    pub fn new() -> Self {
+        Self {
+            latch: 0,
+            counter: 0,
+            prescaler: 341,
+            control: 0,
+            pending: false,
+        }
+    }
+
+    /// Retrieve the latch value
+    pub fn latch(&self) -> u8 {
+        self.latch
+    }
+
+    /// Retrieve the counter value
+    pub fn counter(&self) -> u8 {
+        self.counter
+    }
+
+    /// Retrieve the control register
+    pub fn control(&self) -> u8 {
+        self.control
+    }
+
+    /// Write the entire latch
+    pub fn write_latch(&mut self, data: u8) {
+        self.latch = data;
+    }
+
+    /// Write the low 4 bits of the latch
+    pub fn write_latch_low(&mut self, data: u8) {
+        self.latch = (self.latch & 0xF0) | (data & 0xF);
+    }
+
+    /// Write the high 4 bits of the latch
+    pub fn write_latch_high(&mut self, data: u8) {
+        self.latch = (self.latch & 0x0F) | (data << 4);
+    }
+
+    /// Write the control register
+    pub fn write_control(&mut self, data: u8) {
+        self.control = data & 7;
+        self.pending = false;
+        if (self.control & 2) != 0 {
+            self.counter = self.latch;
+            self.prescaler = 341;
+        }
+    }
+
+    /// Acknowledge the irq
+    pub fn acknowledge(&mut self) {
+        self.pending = false;
+        self.control = (self.control & !2) | ((self.control & 1) << 1);
+    }
+
+    /// Clock the counter itself
+    fn clock_counter(&mut self) {
+        if self.counter == 0xff {
+            self.counter = self.latch;
+            self.pending = true;
+        } else {
+            self.counter += 1;
+        }
+    }
+
+    /// Clock the irq logic, once per cpu cycle
+    pub fn cycle(&mut self) {
+        if (self.control & 2) == 0 {
+            return;
+        }
+        if (self.control & 4) != 0 {
+            self.clock_counter();
+        } else {
+            self.prescaler -= 3;
+            if self.prescaler <= 0 {
+                self.prescaler += 341;
+                self.clock_counter();
+            }
+        }
+    }
+
+    /// Retrieve the irq signal
+    pub fn irq(&self) -> bool {
+        self.pending
+    }
+}
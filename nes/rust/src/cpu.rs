@@ -156,8 +156,7 @@ impl NesCpuPeripherals {
         self.ppu.get_frame()
     }
 
-    /// Used for automated testing, to determine how many frames have passed.
-    #[cfg(any(test, feature = "debugger"))]
+    /// Used for automated testing and screenshots, to determine how many frames have passed.
     pub fn ppu_frame_number(&self) -> u64 {
         self.ppu.frame_number()
     }
@@ -232,6 +231,10 @@ pub struct NesCpu {
     /// The debugger information
     #[cfg(feature = "debugger")]
     pub debugger: NesCpuDebuggerPoint,
+    /// The address of the instruction currently being executed, used for the ppu event viewer
+    #[cfg(feature = "debugger")]
+    #[serde(skip)]
+    instruction_pc: u16,
     /// The status of nmi_detection from last cpu cycle
     prev_nmi: bool,
     /// True when an nmi has been detected
@@ -311,6 +314,8 @@ impl NesCpu {
             },
             #[cfg(feature = "debugger")]
             done_fetching: false,
+            #[cfg(feature = "debugger")]
+            instruction_pc: 0,
             prev_nmi: false,
             nmi_detected: false,
             interrupt_shift: [(false, false); 2],
@@ -348,6 +353,12 @@ impl NesCpu {
         self.subcycle == 0
     }
 
+    /// Returns the address of the instruction currently being executed
+    #[cfg(feature = "debugger")]
+    pub fn instruction_pc(&self) -> u16 {
+        self.instruction_pc
+    }
+
     /// Returns true when done fetching all bytes for an instruction.
     #[cfg(feature = "debugger")]
     pub fn breakpoint_option(&self) -> bool {
@@ -534,6 +545,17 @@ impl NesCpu {
             self.dma_count += 1;
         } else {
             let a = bus.memory_cycle_read(address, oe, cpu_peripherals);
+            #[cfg(feature = "debugger")]
+            {
+                if (0x2000..=0x3fff).contains(&address) {
+                    cpu_peripherals.ppu.record_event(
+                        crate::ppu::events::PpuEventKind::RegisterRead,
+                        0x2000 | (address & 7),
+                        a,
+                        self.instruction_pc,
+                    );
+                }
+            }
             c(self, a);
             bus.joy_clock_signal(false, true);
             bus.joy_clock_signal(true, true);
@@ -559,6 +581,26 @@ impl NesCpu {
             bus.joy_out_signal(self.outs);
         }
         bus.memory_cycle_write(addr, data, [true; 2], cpu_peripherals);
+        #[cfg(feature = "debugger")]
+        {
+            if !self.dma_running {
+                if (0x2000..=0x3fff).contains(&addr) {
+                    cpu_peripherals.ppu.record_event(
+                        crate::ppu::events::PpuEventKind::RegisterWrite,
+                        0x2000 | (addr & 7),
+                        data,
+                        self.instruction_pc,
+                    );
+                } else if addr == 0x4014 {
+                    cpu_peripherals.ppu.record_event(
+                        crate::ppu::events::PpuEventKind::OamDma,
+                        addr,
+                        data,
+                        self.instruction_pc,
+                    );
+                }
+            }
+        }
     }
 
     /// Returns true when a breakpoint is active
@@ -614,6 +656,9 @@ impl NesCpu {
         #[cfg(feature = "debugger")]
         {
             s.done_fetching = false;
+            if s.subcycle == 0 {
+                s.instruction_pc = s.pc;
+            }
         }
 
         s.irq.check_level();
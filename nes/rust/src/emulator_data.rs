@@ -4,10 +4,15 @@ use std::{io::Write, path::PathBuf};
 
 use crate::{
     apu::NesApu,
-    cartridge::NesCartridge,
+    cartridge::{ExpansionAudio, NesCartridge},
     cpu::{NesCpu, NesCpuPeripherals},
     motherboard::NesMotherboard,
-    ppu::NesPpu,
+    ppu::{
+        hdpack::HdPack,
+        palette::{NesPalette, PaletteSource},
+        NesPpu,
+    },
+    region::Region,
 };
 
 use common_emulator::audio::AudioProducerWithRate;
@@ -41,6 +46,22 @@ pub struct EmulatorConfiguration {
     pub controller_config: [crate::controller::ControllerConfig; 4],
     /// The scaler to use for the emulator
     pub scaler: Option<common_emulator::video::ScalingAlgorithm>,
+    /// The path to the bios for the famicom disk system
+    fds_bios: Option<String>,
+    /// The region to use for all roms, None to use the region of the rom
+    pub region: Option<Region>,
+    /// Where the palette of the ppu comes from
+    pub palette: PaletteSource,
+    /// Render every sprite on a scanline, instead of only the first 8
+    pub unlimited_sprites: bool,
+    /// The amount of the picture to remove from each edge
+    pub overscan: common_emulator::video::Overscan,
+    /// The folder containing hd packs, each pack is in a folder named after the rom it is for, or after the sha256 of the rom
+    hd_pack_path: Option<String>,
+    /// Screenshots are saved after the scaling algorithm is applied, instead of the raw image of the ppu
+    pub scaled_screenshots: bool,
+    /// The rom database file used to correct the headers of roms
+    rom_database: Option<String>,
 }
 
 impl Default for EmulatorConfiguration {
@@ -76,6 +97,14 @@ impl Default for EmulatorConfiguration {
             ],
             controller_config: controller,
             scaler: None,
+            fds_bios: None,
+            region: None,
+            palette: PaletteSource::Builtin,
+            unlimited_sprites: false,
+            overscan: common_emulator::video::Overscan::default(),
+            hd_pack_path: None,
+            scaled_screenshots: false,
+            rom_database: None,
         }
     }
 }
@@ -103,6 +132,17 @@ impl EmulatorConfiguration {
         self.save();
     }
 
+    /// Retrieve the path to the famicom disk system bios
+    pub fn fds_bios(&self) -> Option<PathBuf> {
+        self.fds_bios.as_ref().map(PathBuf::from)
+    }
+
+    /// Set the path to the famicom disk system bios
+    pub fn set_fds_bios(&mut self, pb: PathBuf) {
+        self.fds_bios = Some(pb.into_os_string().into_string().unwrap());
+        self.save();
+    }
+
     ///Load a configuration file
     pub fn load(name: std::path::PathBuf) -> Self {
         let mut result = EmulatorConfiguration {
@@ -152,6 +192,40 @@ impl EmulatorConfiguration {
         let _e = f.write_all(data.as_bytes());
     }
 
+    /// Retrieve the folder containing hd packs
+    pub fn hd_pack_path(&self) -> Option<PathBuf> {
+        self.hd_pack_path.as_ref().map(PathBuf::from)
+    }
+
+    /// Set the folder containing hd packs, None to disable hd packs
+    pub fn set_hd_pack_path(&mut self, pb: Option<PathBuf>) {
+        self.hd_pack_path = pb.map(|pb| pb.into_os_string().into_string().unwrap());
+        self.save();
+    }
+
+    /// Retrieve the path to the rom database file
+    pub fn rom_database(&self) -> Option<PathBuf> {
+        self.rom_database.as_ref().map(PathBuf::from)
+    }
+
+    /// Set the path to the rom database file, None to stop correcting the headers of roms
+    pub fn set_rom_database(&mut self, pb: Option<PathBuf>) {
+        self.rom_database = pb.map(|pb| pb.into_os_string().into_string().unwrap());
+        self.save();
+    }
+
+    /// Load the rom database file selected in the configuration, used when roms are loaded
+    pub fn update_rom_database(&self) {
+        if let Some(path) = self.rom_database() {
+            match crate::cartridge::load_rom_database(&path) {
+                Ok(count) => println!("Loaded {} roms from {}", count, path.display()),
+                Err(e) => println!("Failed to load the rom database {}: {}", path.display(), e),
+            }
+        } else {
+            crate::cartridge::unload_rom_database();
+        }
+    }
+
     ///Retrieve the start rom
     pub fn start_rom(&self) -> Option<String> {
         self.start_rom.to_owned()
@@ -164,6 +238,10 @@ pub struct LocalEmulatorData {
     pub gilrs: gilrs::Gilrs,
     /// The network object for interacting with other emulators
     pub network: Option<common_emulator::network::Network>,
+    /// The hd pack for the current rom
+    pub hd_pack: Option<HdPack>,
+    /// The ntsc filter, kept until the scaler selects a different preset
+    pub ntsc: Option<common_emulator::video::ntsc::NtscFilter>,
 }
 
 impl Default for LocalEmulatorData {
@@ -171,6 +249,8 @@ impl Default for LocalEmulatorData {
         Self {
             gilrs: gilrs::GilrsBuilder::new().build().unwrap(),
             network: None,
+            hd_pack: None,
+            ntsc: None,
         }
     }
 }
@@ -195,6 +275,10 @@ pub struct LocalEmulatorDataClone {
     pub image: common_emulator::video::PixelImage<egui::Color32>,
     /// The number of samples per second of the audio output.
     sound_rate: u32,
+    /// The palette of the ppu, built from the configuration
+    pub palette: NesPalette,
+    /// The error from the last attempt to save the changes made to a famicom disk system disk
+    pub disk_error: Option<String>,
 }
 
 impl LocalEmulatorDataClone {
@@ -260,18 +344,29 @@ impl LocalEmulatorDataClone {
         pb
     }
 
+    /// Returns the path of where to save screenshots to
+    pub fn screenshot_path(&self) -> std::path::PathBuf {
+        let mut pb = self.dirs.data_dir().to_path_buf();
+        pb.push("screenshots");
+        if !pb.exists() {
+            let _ = std::fs::create_dir_all(&pb);
+        }
+        pb
+    }
+
     /// Finds roms for the system
     pub fn find_roms(&mut self, dir: &str) {
         self.parser
-            .find_roms(dir, self.save_path(), self.get_save_other(), |n, p| {
-                NesCartridge::load_cartridge(n, p)
+            .find_roms(dir, self.save_path(), self.get_save_other(), |n, d, p| {
+                NesCartridge::load_cartridge_contents(n, d, p)
             })
     }
 
     /// Process the list of roms
     pub fn process_roms(&mut self) {
-        self.parser
-            .process_roms(self.save_path(), |n, p| NesCartridge::load_cartridge(n, p))
+        self.parser.process_roms(self.save_path(), |n, d, p| {
+            NesCartridge::load_cartridge_contents(n, d, p)
+        })
     }
 }
 
@@ -305,6 +400,11 @@ impl LocalEmulatorDataClone {
         let user_config = EmulatorConfiguration::load(user_path);
 
         let config = user_config;
+        config.update_rom_database();
+        let palette = NesPalette::from_source(&config.palette).unwrap_or_else(|e| {
+            println!("Failed to load the palette: {}", e);
+            NesPalette::default()
+        });
         Self {
             configuration: config,
             parser: common_emulator::romlist::RomListParser::new(Self::get_other_path(&dirs)),
@@ -317,6 +417,8 @@ impl LocalEmulatorDataClone {
             proxy,
             image: common_emulator::video::PixelImage::<egui::Color32>::default(),
             sound_rate: 0,
+            palette,
+            disk_error: None,
         }
     }
 }
@@ -350,10 +452,16 @@ pub struct NesEmulatorData {
     nmi: [bool; 5],
     /// Used for triggering the cpu irq line
     prev_irq: bool,
+    /// The irq line of the cartridge from the previous cpu cycle, used for recording mapper irqs as ppu events
+    #[cfg(feature = "debugger")]
+    #[serde(skip)]
+    prev_cart_irq: bool,
     /// A large counter used to indicate how many clock cycles have passed. This wraps eventually.
     big_counter: u64,
     /// Indicates vblank was just set
     vblank_just_set: u8,
+    /// The region of the console, determines the clock dividers
+    region: Region,
     #[serde(skip)]
     /// Local emulator data that does not get stored into save states
     pub local: LocalEmulatorDataClone,
@@ -387,7 +495,10 @@ impl NesEmulatorData {
         >,
     ) -> Self {
         let mb: NesMotherboard = NesMotherboard::new();
-        let ppu = NesPpu::new();
+        let local = LocalEmulatorDataClone::new(proxy);
+        let mut ppu = NesPpu::new();
+        ppu.set_palette(local.palette.clone());
+        ppu.set_unlimited_sprites(local.configuration.unlimited_sprites);
         let apu = NesApu::new();
 
         Self {
@@ -408,21 +519,167 @@ impl NesEmulatorData {
                 .as_millis(),
             nmi: [false; 5],
             prev_irq: false,
+            #[cfg(feature = "debugger")]
+            prev_cart_irq: false,
             big_counter: 0,
             vblank_just_set: 0,
-            local: LocalEmulatorDataClone::new(proxy),
+            region: Region::Ntsc,
+            local,
             olocal: Some(LocalEmulatorData::default()),
         }
     }
 
     /// Return the framerate of the ppu
     pub fn ppu_frame_rate(&self) -> f32 {
-        60.0
+        self.region.frame_rate()
     }
 
     /// Return the cpu frequency.
     pub fn cpu_frequency(&self) -> f32 {
-        21.47727e6 / 12.0
+        self.region.cpu_frequency()
+    }
+
+    /// Set the region of the console, changing the timing of the cpu, ppu, apu, and cartridge
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.cpu_peripherals.ppu.set_region(region);
+        self.cpu_peripherals.apu.set_region(region);
+        if let Some(cart) = self.mb.cartridge_mut() {
+            cart.set_region(region);
+        }
+    }
+
+    /// Returns the last frame of the ppu, cropped and then scaled or filtered as the configuration specifies
+    pub fn scaled_frame(&mut self) -> common_emulator::video::PixelImage<egui::Color32> {
+        let scaler = self.local.configuration.scaler;
+        let overscan = &self.local.configuration.overscan;
+        let ppu = &self.cpu_peripherals.ppu;
+        if let (Some(pack), Some(tiles)) = (self.hd_pack(), ppu.pixel_tiles()) {
+            let frame = ppu.get_frame().to_pixels_egui().crop(overscan);
+            let (tiles, _width, _height) = overscan.crop(tiles, 256, 240);
+            pack.compose(&frame, &tiles)
+        } else if let Some(preset) = scaler.and_then(|s| s.ntsc_preset()) {
+            let (colors, width, height) = overscan.crop(ppu.frame_colors(), 256, 240);
+            let phase = common_emulator::video::ntsc::phase_at(
+                ppu.frame_phase(),
+                overscan.left as u16,
+                (overscan.top as u16).min(239),
+            );
+            match self.olocal.as_mut() {
+                Some(olocal) => {
+                    let filter = match olocal.ntsc.take() {
+                        Some(f) if f.preset() == preset => f,
+                        _ => common_emulator::video::ntsc::NtscFilter::new(preset),
+                    };
+                    olocal
+                        .ntsc
+                        .insert(filter)
+                        .filter(&colors, width, height, phase)
+                }
+                None => common_emulator::video::ntsc::NtscFilter::new(preset)
+                    .filter(&colors, width, height, phase),
+            }
+        } else {
+            ppu.get_frame()
+                .to_pixels_egui()
+                .crop(overscan)
+                .resize(scaler)
+        }
+    }
+
+    /// The details of the current rom and frame, stored in screenshots
+    pub fn screenshot_info(&self) -> common_emulator::screenshot::ScreenshotInfo {
+        let cart = self.mb.cartridge();
+        common_emulator::screenshot::ScreenshotInfo {
+            rom_name: cart.map(|c| c.rom_name()).unwrap_or_default(),
+            rom_hash: cart.map(|c| c.hash()).unwrap_or_default(),
+            frame_number: self.cpu_peripherals.ppu_frame_number(),
+        }
+    }
+
+    /// Save the last frame of the ppu as a png. The frame is either the raw image of the ppu, or the image after scaling.
+    pub fn save_screenshot(&mut self, path: &std::path::Path, scaled: bool) -> std::io::Result<()> {
+        let info = self.screenshot_info();
+        if scaled {
+            common_emulator::screenshot::save_png(path, &self.scaled_frame().to_rgb_image(), &info)
+        } else {
+            common_emulator::screenshot::save_png(path, self.cpu_peripherals.ppu.get_frame(), &info)
+        }
+    }
+
+    /// The horizontal and vertical scale of the image from scaled_frame, compared to the image of the ppu
+    pub fn frame_scale(&self) -> egui::Vec2 {
+        if let Some(pack) = self.hd_pack() {
+            egui::vec2(pack.scale() as f32, pack.scale() as f32)
+        } else {
+            self.local
+                .configuration
+                .scaler
+                .map(|s| s.scale_factor())
+                .unwrap_or(egui::vec2(1.0, 1.0))
+        }
+    }
+
+    /// Returns the hd pack in use
+    fn hd_pack(&self) -> Option<&HdPack> {
+        self.olocal.as_ref().and_then(|o| o.hd_pack.as_ref())
+    }
+
+    /// Load the hd pack for the current rom, from the hd pack folder of the configuration
+    pub fn update_hd_pack(&mut self) {
+        let mut folders = Vec::new();
+        if let (Some(p), Some(cart)) =
+            (self.local.configuration.hd_pack_path(), self.mb.cartridge())
+        {
+            //archive members are named after the member, not the archive
+            let rom_name = cart.rom_name();
+            let name = match common_emulator::archive::split_name(&rom_name) {
+                Some((_, member)) => member,
+                None => rom_name,
+            };
+            if let Some(stem) = std::path::Path::new(&name).file_stem() {
+                folders.push(p.join(stem));
+            }
+            folders.push(p.join(cart.hash()));
+        }
+        let folder = folders
+            .into_iter()
+            .find(|folder| folder.join("hires.txt").exists());
+        let pack = folder.and_then(|folder| match HdPack::load(&folder) {
+            Ok(pack) => Some(pack),
+            Err(e) => {
+                println!("Failed to load the hd pack {}: {}", folder.display(), e);
+                None
+            }
+        });
+        self.cpu_peripherals.ppu.set_record_tiles(pack.is_some());
+        if let Some(olocal) = &mut self.olocal {
+            olocal.hd_pack = pack;
+        }
+    }
+
+    /// Build the palette from the configuration and give it to the ppu
+    pub fn update_palette(&mut self) {
+        match NesPalette::from_source(&self.local.configuration.palette) {
+            Ok(p) => {
+                self.local.palette = p;
+                self.cpu_peripherals
+                    .ppu
+                    .set_palette(self.local.palette.clone());
+            }
+            Err(e) => println!("Failed to load the palette: {}", e),
+        }
+    }
+
+    /// Select the region from the configuration, or from the header of the rom when the configuration does not specify one
+    pub fn update_region(&mut self) {
+        let region = self.local.configuration.region.unwrap_or_else(|| {
+            self.mb
+                .cartridge()
+                .map(|c| Region::from(c.header().timing))
+                .unwrap_or_default()
+        });
+        self.set_region(region);
     }
 
     /// Finds roms for the system
@@ -465,6 +722,14 @@ impl NesEmulatorData {
                 self.local = lcl;
                 self.olocal = olcl;
                 self.cpu_peripherals.ppu.set_frame(screen);
+                self.cpu_peripherals
+                    .ppu
+                    .set_palette(self.local.palette.clone());
+                self.cpu_peripherals
+                    .ppu
+                    .set_unlimited_sprites(self.local.configuration.unlimited_sprites);
+                let hd = self.hd_pack().is_some();
+                self.cpu_peripherals.ppu.set_record_tiles(hd);
                 Ok(())
             }
             Err(e) => Err(e),
@@ -486,7 +751,9 @@ impl NesEmulatorData {
         let controller3 = self.mb.get_controller(2);
         let controller4 = self.mb.get_controller(3);
         let mb: NesMotherboard = NesMotherboard::new();
-        let ppu = NesPpu::new();
+        let mut ppu = NesPpu::new();
+        ppu.set_palette(self.local.palette.clone());
+        ppu.set_unlimited_sprites(self.local.configuration.unlimited_sprites);
         let apu = NesApu::new();
 
         #[cfg(feature = "debugger")]
@@ -516,26 +783,91 @@ impl NesEmulatorData {
             .as_millis();
         self.nmi = [false; 5];
         self.prev_irq = false;
+        self.set_region(self.region);
         if let Some(cart) = cart {
             let name = cart.rom_name();
-            let cart = NesCartridge::load_cartridge(name, &self.local.save_path());
+            let cart = NesCartridge::load_cartridge_with_patches(
+                name,
+                &self.local.save_path(),
+                cart.patches(),
+            );
             if let Ok(cart) = cart {
                 self.insert_cartridge(cart);
             }
         }
     }
 
-    /// Remove a cartridge from the motherboard, returning it to the caller.
+    /// Remove a cartridge from the motherboard, returning it to the caller. Changes made to a disk are saved first.
     pub fn remove_cartridge(&mut self) -> Option<NesCartridge> {
+        self.flush_disk();
         self.mb.remove_cartridge()
     }
 
+    /// Save the changes made to the disk of a famicom disk system image, a failure is kept in disk_error
+    pub fn flush_disk(&mut self) {
+        if let Some(cart) = self.mb.cartridge_mut() {
+            let result = cart.flush_disk();
+            self.set_disk_result(result);
+        }
+    }
+
+    /// Insert the next disk side into the drive, saving the changes made to the disk
+    pub fn flip_disk(&mut self) {
+        if let Some(cart) = self.mb.cartridge_mut() {
+            let result = cart.flip_disk();
+            self.set_disk_result(result);
+        }
+    }
+
+    /// Remove the disk from the drive, saving the changes made to the disk
+    pub fn eject_disk(&mut self) {
+        if let Some(cart) = self.mb.cartridge_mut() {
+            let result = cart.eject_disk();
+            self.set_disk_result(result);
+        }
+    }
+
+    /// Keep the result of saving the changes to a disk, so that a failure can be shown
+    fn set_disk_result(&mut self, result: std::io::Result<()>) {
+        self.local.disk_error = result
+            .err()
+            .map(|e| format!("Failed to save the changes to the disk: {}", e));
+    }
+
     /// Insert a cartridge into the motherboard.
-    pub fn insert_cartridge(&mut self, cart: NesCartridge) {
+    pub fn insert_cartridge(&mut self, mut cart: NesCartridge) {
+        self.flush_disk();
         self.local
             .configuration
             .set_startup(cart.rom_name().to_owned());
+        if cart.is_disk_system() {
+            if let Some(bios) = self.local.configuration.fds_bios() {
+                if let Err(e) = cart.load_fds_bios(&bios) {
+                    println!("Failed to load the fds bios {}: {:?}", bios.display(), e);
+                }
+            } else {
+                println!("The fds bios must be set in the configuration to run disk images");
+            }
+        }
         self.mb.insert_cartridge(cart);
+        self.update_region();
+        self.update_hd_pack();
+    }
+
+    /// Start playing a song of an nsf file, by selecting the song and resetting the system
+    pub fn play_song(&mut self, song: u8) {
+        if let Some(cart) = self.mb.cartridge_mut() {
+            cart.select_song(song);
+        }
+        self.reset();
+    }
+
+    /// Returns the song of an nsf file being played, and how long it has been playing
+    pub fn song_elapsed(&self) -> Option<(u8, std::time::Duration)> {
+        let (song, cycles) = self.mb.cartridge()?.current_song()?;
+        let elapsed =
+            std::time::Duration::from_secs_f64(cycles as f64 / self.cpu_frequency() as f64);
+        Some((song, elapsed))
     }
 
     /// Run a single cycle of the cpu and ppu system, dividing the input as necessary
@@ -553,7 +885,7 @@ impl NesEmulatorData {
 
         self.ppu_clock_counter += 1;
 
-        if self.ppu_clock_counter >= 4 {
+        if self.ppu_clock_counter >= self.region.ppu_divider() {
             self.ppu_clock_counter = 0;
             self.cpu_peripherals.ppu_cycle(&mut self.mb);
             if self.cpu_peripherals.ppu.vblank_just_set {
@@ -567,14 +899,36 @@ impl NesEmulatorData {
         }
 
         self.cpu_clock_counter += 1;
-        if self.cpu_clock_counter >= 12 {
+        if self.cpu_clock_counter >= self.region.cpu_divider() {
             self.cpu_clock_counter = 0;
             let nmi = self.nmi[2];
 
-            self.cpu_peripherals.apu.clock_slow(sound, streams, filter);
+            let cart_audio = if let Some(cart) = self.mb.cartridge_mut() {
+                cart.cpu_cycle();
+                cart.clock_audio();
+                cart.audio_sample()
+            } else {
+                ExpansionAudio::default()
+            };
+            self.cpu_peripherals
+                .apu
+                .clock_slow(sound, streams, filter, &cart_audio);
             let irq = self.cpu_peripherals.apu.irq();
             self.cpu.set_dma_input(self.cpu_peripherals.apu.dma());
             let cart_irq = self.mb.cartridge().map(|cart| cart.irq()).unwrap_or(false);
+            #[cfg(feature = "debugger")]
+            {
+                if cart_irq && !self.prev_cart_irq {
+                    let pc = self.cpu.instruction_pc();
+                    self.cpu_peripherals.ppu.record_event(
+                        crate::ppu::events::PpuEventKind::MapperIrq,
+                        0,
+                        0,
+                        pc,
+                    );
+                }
+                self.prev_cart_irq = cart_irq;
+            }
             self.cpu.cycle(
                 &mut self.mb,
                 &mut self.cpu_peripherals,
@@ -21,6 +21,7 @@ mod emulator_data;
 mod genie;
 mod motherboard;
 mod ppu;
+mod region;
 
 use emulator_data::NesEmulatorData;
 
@@ -409,7 +410,7 @@ fn main() {
         nes_data.local.configuration.get_rom_path(),
         nes_data.local.save_path(),
         nes_data.local.get_save_other(),
-        |n, p| NesCartridge::load_cartridge(n, p),
+        |n, d, p| NesCartridge::load_cartridge_contents(n, d, p),
     );
     let mut multi_window = MultiWindow::new();
 
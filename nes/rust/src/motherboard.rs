@@ -228,6 +228,11 @@ impl NesMotherboard {
         self.cart.as_ref()
     }
 
+    /// Returns true when the cartridge needs the ppu to perform the exact memory fetches of the real ppu
+    pub fn exact_ppu_fetches(&self) -> bool {
+        self.cart.as_ref().is_some_and(|c| c.exact_ppu_fetches())
+    }
+
     /// Return a mutable reference to the cartridge if it exists
     pub fn cartridge_mut(&mut self) -> Option<&mut NesCartridge> {
         self.cart.as_mut()
@@ -363,7 +368,7 @@ impl NesMotherboard {
                 }
                 if let Some(cart) = &mut self.cart {
                     cart.memory_nop();
-                    cart.other_memory_read(addr);
+                    cart.other_memory_read(0x2000 | addr);
                 }
             }
             0x4000..=0x4017 => {
@@ -444,7 +449,7 @@ impl NesMotherboard {
                 per.ppu_write(addr, data, &mut self.ppu_palette_ram);
                 if let Some(cart) = &mut self.cart {
                     cart.memory_nop();
-                    cart.other_memory_write(addr, data);
+                    cart.other_memory_write(0x2000 | addr, data);
                 }
             }
             0x4000..=0x4017 => {
@@ -482,7 +487,7 @@ impl NesMotherboard {
         if let Some(cart) = &self.cart {
             let (a10, vram_enable, data) = cart.ppu_peek_1(addr);
             let vram_address = if !vram_enable {
-                if (0x2000..=0x3fff).contains(&addr) {
+                if (0x2000..=0x3fff).contains(&addr) || cart.ppu_pattern_vram(addr) {
                     Some(addr | ((a10 as u16) << 10))
                 } else {
                     None
@@ -533,9 +538,10 @@ impl NesMotherboard {
         self.last_ppu_cycle = 1;
         if let Some(cart) = &mut self.cart {
             let (a10, vram_enable) = cart.ppu_cycle_1(addr);
+            let pattern_vram = cart.ppu_pattern_vram(addr);
             let addr = addr & !0x400;
             self.vram_address = if !vram_enable {
-                if (0x2000..=0x3fff).contains(&addr) {
+                if (0x2000..=0x3fff).contains(&addr) || pattern_vram {
                     Some(addr | ((a10 as u16) << 10))
                 } else {
                     None
@@ -620,9 +626,35 @@ impl NesMotherboard {
         }
     }
 
+    /// Finish a ppu memory cycle where the ppu does not actually read or write, the address is still on the bus
+    pub fn ppu_cycle_2_idle(&mut self, ppu: &NesPpu) {
+        if self.last_ppu_cycle != 1 {
+            println!(
+                "ERROR PPU CYCLING d @ {},{} from {:?}",
+                ppu.column(),
+                ppu.row(),
+                self.last_ppu_coordinates
+            );
+        }
+        self.last_ppu_coordinates = (ppu.column(), ppu.row());
+        self.last_ppu_cycle = 2;
+    }
+
     /// Read a palette address
     pub fn ppu_palette_read(&self, addr: u16) -> u8 {
         let addr2: usize = (addr as usize) & 0x1f;
         self.ppu_palette_ram[addr2]
     }
+
+    /// Write a palette address, used for modifying the palette from the debugger
+    pub fn ppu_palette_write(&mut self, addr: u16, data: u8) {
+        let addr2 = match addr & 0x1f {
+            0x10 => 0,
+            0x14 => 4,
+            0x18 => 8,
+            0x1c => 0xc,
+            a => a,
+        };
+        self.ppu_palette_ram[addr2 as usize] = data;
+    }
 }
@@ -1,10 +1,18 @@
 //! The ppu module for the emulator. Responsible for emulating the chip that generates all of the graphics for the nes.
 
 use crate::motherboard::NesMotherboard;
+use crate::region::Region;
 use common_emulator::video::RgbImage;
 use egui_multiwin::egui::Vec2;
 use serde_with::Bytes;
 
+#[cfg(feature = "debugger")]
+pub mod events;
+pub mod hdpack;
+pub mod palette;
+use hdpack::{PixelTile, TileLayer};
+use palette::NesPalette;
+
 #[cfg(feature = "eframe")]
 use eframe::egui;
 
@@ -74,6 +82,11 @@ impl PpuSprite {
         self.y
     }
 
+    /// Returns the tile number of the sprite
+    pub fn tile(&self) -> u8 {
+        self.tile
+    }
+
     /// Returns the attribute of the sprite
     pub fn attribute(&self) -> u8 {
         self.attribute
@@ -167,8 +180,7 @@ pub struct NesPpu {
     vram_address: u16,
     /// The temporary vram address used in the scrolling algorithm
     temporary_vram_address: u16,
-    /// The frame number of the ppu, used for testing and debugging purposes.
-    #[cfg(any(test, feature = "debugger"))]
+    /// The frame number of the ppu, used for testing, debugging, and screenshots.
     frame_number: u64,
     /// For read operations by the cpu
     ppudata_buffer: u8,
@@ -183,6 +195,12 @@ pub struct NesPpu {
     secondary_oam: [u8; 32],
     /// The sprites for the current scanline being rendered
     sprites: [PpuSprite; 8],
+    /// Render every sprite in range on a scanline instead of only the first 8
+    #[serde(skip)]
+    unlimited_sprites: bool,
+    /// The sprites in range for the current scanline after the first 8, used when unlimited_sprites is set
+    #[serde(skip)]
+    extra_sprites: Vec<PpuSprite>,
     /// Was sprite 0 in range on the sprite evaluation line?
     sprite0_eval: bool,
     /// Is sprite 0 in range for the current line?
@@ -204,6 +222,28 @@ pub struct NesPpu {
     pub bg_debug: Option<(u8, u8)>,
     /// Indicates the mode the ppu access is in
     mode: Option<PpuMode>,
+    /// The region of the ppu, determines the number of scanlines and the emphasis bits
+    region: Region,
+    /// The palette for converting colors into rgb
+    #[serde(skip)]
+    palette: NesPalette,
+    /// The raw colors of the frame, used by the ntsc filter
+    #[serde(skip, default = "NesPpu::blank_colors")]
+    frame_colors: Vec<u16>,
+    /// The phase of the ntsc color subcarrier, in twelfths of a cycle
+    ntsc_phase: u8,
+    /// The phase of the ntsc color subcarrier at the start of the frame
+    frame_phase: u8,
+    /// The tile that generated each pixel of the frame, recorded only when a hd pack is in use
+    #[serde(skip)]
+    pixel_tiles: Option<Vec<Option<PixelTile>>>,
+    /// The pattern table data and row for the background tiles being rendered, like patterntable_shift
+    #[serde(skip)]
+    tile_shift: [([u8; 16], u8); 2],
+    /// The log of register accesses and mapper irqs, for the event viewer
+    #[cfg(feature = "debugger")]
+    #[serde(skip)]
+    pub events: events::PpuEventLog,
 }
 
 /// The flags that set the nametable base
@@ -236,85 +276,12 @@ const PPU_REGISTER1_EMPHASIZE_GREEN: u8 = 0x40;
 /// The flag for emphasizing the blue channel
 const PPU_REGISTER1_EMPHASIZE_BLUE: u8 = 0x80;
 
+/// The change in the phase of the ntsc color subcarrier for each scanline, 341 pixels of 8 samples each, 12 samples per cycle
+const PPU_NTSC_SCANLINE_PHASE: u8 = ((341 * 8) % 12) as u8;
+
 /// The number of cycles where the ppu is in a special state on startup.
 const PPU_STARTUP_CYCLE_COUNT: u16 = 29658;
 
-/// The palette for the ppu
-const PPU_PALETTE: [[u8; 3]; 64] = palette_generator(); //TODO put in correct colors into the palette
-
-/// Build a palette for the ppu.
-const fn palette_generator() -> [[u8; 3]; 64] {
-    let mut palette: [[u8; 3]; 64] = [[0; 3]; 64];
-    palette[0] = [84, 84, 84];
-    palette[1] = [0, 30, 116];
-    palette[2] = [8, 16, 144];
-    palette[3] = [48, 0, 136];
-    palette[4] = [68, 0, 100];
-    palette[5] = [92, 0, 48];
-    palette[6] = [84, 4, 0];
-    palette[7] = [60, 24, 0];
-    palette[8] = [32, 42, 0];
-    palette[9] = [8, 58, 0];
-    palette[10] = [0, 64, 0];
-    palette[11] = [0, 60, 0];
-    palette[12] = [0, 50, 60];
-    palette[13] = [0, 0, 0];
-    palette[14] = [0, 0, 0];
-    palette[15] = [0, 0, 0];
-
-    palette[16] = [152, 150, 152];
-    palette[17] = [8, 76, 196];
-    palette[18] = [48, 50, 236];
-    palette[19] = [92, 30, 228];
-    palette[20] = [136, 20, 176];
-    palette[21] = [160, 20, 100];
-    palette[22] = [152, 34, 32];
-    palette[23] = [120, 60, 0];
-    palette[24] = [84, 90, 0];
-    palette[25] = [40, 114, 0];
-    palette[26] = [8, 124, 0];
-    palette[27] = [0, 118, 40];
-    palette[28] = [0, 102, 120];
-    palette[29] = [0, 0, 0];
-    palette[30] = [0, 0, 0];
-    palette[31] = [0, 0, 0];
-
-    palette[32] = [236, 238, 236];
-    palette[33] = [76, 154, 236];
-    palette[34] = [120, 124, 236];
-    palette[35] = [176, 98, 236];
-    palette[36] = [228, 84, 236];
-    palette[37] = [236, 88, 180];
-    palette[38] = [236, 106, 100];
-    palette[39] = [212, 136, 32];
-    palette[40] = [160, 170, 0];
-    palette[41] = [116, 196, 0];
-    palette[42] = [76, 208, 32];
-    palette[43] = [56, 204, 108];
-    palette[44] = [56, 180, 204];
-    palette[45] = [60, 60, 60];
-    palette[46] = [0, 0, 0];
-    palette[47] = [0, 0, 0];
-
-    palette[48] = [236, 238, 236];
-    palette[49] = [168, 204, 236];
-    palette[50] = [188, 188, 236];
-    palette[51] = [212, 178, 236];
-    palette[52] = [236, 174, 236];
-    palette[53] = [236, 174, 212];
-    palette[54] = [236, 180, 176];
-    palette[55] = [228, 196, 144];
-    palette[56] = [204, 210, 120];
-    palette[57] = [180, 222, 120];
-    palette[58] = [168, 226, 144];
-    palette[59] = [152, 226, 180];
-    palette[60] = [160, 214, 228];
-    palette[61] = [160, 162, 160];
-    palette[62] = [0, 0, 0];
-    palette[63] = [0, 0, 0];
-    palette
-}
-
 impl NesPpu {
     /// Return a new ppu.
     pub fn new() -> Self {
@@ -351,7 +318,6 @@ impl NesPpu {
             pend_vram_read: None,
             vram_address: 0,
             temporary_vram_address: 0,
-            #[cfg(any(test, feature = "debugger"))]
             frame_number: 0,
             ppudata_buffer: 0,
             last_cpu_data: 0,
@@ -359,6 +325,8 @@ impl NesPpu {
             oam,
             secondary_oam: oam2,
             sprites: [PpuSprite::new(); 8],
+            unlimited_sprites: false,
+            extra_sprites: Vec::new(),
             sprite0_current: false,
             sprite0_eval: false,
             secondaryoamaddress: 0,
@@ -370,9 +338,213 @@ impl NesPpu {
             #[cfg(feature = "debugger")]
             bg_debug: None,
             mode: None,
+            region: Region::Ntsc,
+            palette: NesPalette::default(),
+            frame_colors: Self::blank_colors(),
+            ntsc_phase: 0,
+            frame_phase: 0,
+            pixel_tiles: None,
+            tile_shift: [([0; 16], 0); 2],
+            #[cfg(feature = "debugger")]
+            events: events::PpuEventLog::default(),
+        }
+    }
+
+    /// Set the region of the ppu
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+    }
+
+    /// The number of scanlines in a frame, including the pre-render scanline
+    #[cfg(feature = "debugger")]
+    pub fn scanlines(&self) -> u16 {
+        self.region.scanlines()
+    }
+
+    /// Record an event for the event viewer, at the current scanline and dot
+    #[cfg(feature = "debugger")]
+    pub fn record_event(&mut self, kind: events::PpuEventKind, address: u16, value: u8, pc: u16) {
+        let event = events::PpuEvent {
+            kind,
+            address,
+            value,
+            pc,
+            scanline: self.scanline_number,
+            dot: self.scanline_cycle,
+            scroll: events::PpuScroll {
+                vram_address: self.vram_address,
+                temporary_vram_address: self.temporary_vram_address,
+                fine_x: self.scrollx,
+                write_toggle: self.address_bit,
+            },
+        };
+        self.events.record(event);
+    }
+
+    /// Set the palette used to convert colors into rgb
+    pub fn set_palette(&mut self, palette: NesPalette) {
+        self.palette = palette;
+    }
+
+    /// Set whether every sprite in range of a scanline is rendered, removing the flicker caused by the limit of 8 sprites per scanline.
+    /// The sprite overflow flag is still set the same way as the real ppu.
+    pub fn set_unlimited_sprites(&mut self, unlimited: bool) {
+        self.unlimited_sprites = unlimited;
+        if !unlimited {
+            self.extra_sprites.clear();
+        }
+    }
+
+    /// Set whether the tile that generates each pixel is recorded, for use with hd packs
+    pub fn set_record_tiles(&mut self, record: bool) {
+        self.pixel_tiles = if record {
+            Some(vec![None; 256 * 240])
+        } else {
+            None
+        };
+    }
+
+    /// Returns the tile that generated each pixel of the frame, if the tiles are being recorded
+    pub fn pixel_tiles(&self) -> Option<&[Option<PixelTile>]> {
+        self.pixel_tiles.as_deref()
+    }
+
+    /// Read the 16 bytes of pattern table data for a tile, without affecting the bus
+    fn peek_tile(&self, bus: &NesMotherboard, address: u16) -> [u8; 16] {
+        core::array::from_fn(|i| bus.ppu_peek(address + i as u16))
+    }
+
+    /// Read the 4 colors of a palette, the first being the background color
+    fn peek_palette(&self, bus: &NesMotherboard, base: u16) -> [u8; 4] {
+        [
+            bus.ppu_palette_read(0x3f00) & 63,
+            bus.ppu_palette_read(base + 1) & 63,
+            bus.ppu_palette_read(base + 2) & 63,
+            bus.ppu_palette_read(base + 3) & 63,
+        ]
+    }
+
+    /// Build the identity of the tile for a pixel of a sprite on the current scanline
+    fn sprite_pixel_tile(&self, bus: &NesMotherboard, index: usize, cycle: u8) -> PixelTile {
+        let e = self.sprite(index);
+        //sprites were fetched on the previous scanline
+        let row = (self.scanline_number - 1) as u8;
+        let address = self.sprite_patterntable_base(e) + e.tile_num(row, self.sprite_height());
+        let flip_h = (e.attribute & 0x40) != 0;
+        PixelTile {
+            chr: self.peek_tile(bus, address),
+            palette: self.peek_palette(bus, 0x3f10 | e.pallete()),
+            x: if flip_h {
+                7 - (cycle - e.x)
+            } else {
+                cycle - e.x
+            },
+            y: e.line_number(row),
+            flip_h,
+            flip_v: (e.attribute & 0x80) != 0,
+            layer: TileLayer::Sprite,
+        }
+    }
+
+    /// Returns the sprite for the current scanline with the given index, including the sprites beyond the first 8
+    fn sprite(&self, index: usize) -> &PpuSprite {
+        if index < 8 {
+            &self.sprites[index]
+        } else {
+            &self.extra_sprites[index - 8]
+        }
+    }
+
+    /// Find the sprites in range of the next scanline beyond the first 8, fetching their pattern data without affecting the bus.
+    fn fetch_extra_sprites(&mut self, bus: &NesMotherboard) {
+        self.extra_sprites.clear();
+        if self.scanline_number >= 240 {
+            return;
+        }
+        let row = self.scanline_number as u8;
+        let height = self.sprite_height() as u16;
+        let mut found = 0;
+        for i in 0..64 {
+            let y = self.oam[i * 4];
+            //same range check as sprite evaluation
+            if (row as u16 + 1) > y as u16 && (row as u16 + 1) <= (y as u16 + height) {
+                found += 1;
+                if found > 8 {
+                    let mut sprite = PpuSprite {
+                        y,
+                        tile: self.oam[i * 4 + 1],
+                        attribute: self.oam[i * 4 + 2],
+                        x: self.oam[i * 4 + 3],
+                        patterntable_data: 0,
+                    };
+                    let calc = self.sprite_patterntable_base(&sprite)
+                        + sprite.tile_num(row, self.sprite_height())
+                        + sprite.line_number(row) as u16;
+                    sprite.patterntable_data =
+                        u16::from_le_bytes([bus.ppu_peek(calc), bus.ppu_peek(calc + 8)]);
+                    self.extra_sprites.push(sprite);
+                }
+            }
         }
     }
 
+    /// The scanline before rendering starts, the last scanline of the frame
+    fn prerender_line(&self) -> u16 {
+        self.region.scanlines() - 1
+    }
+
+    /// Returns the emphasis bits of the mask register as red, green, blue in bits 5, 6, 7.
+    /// The pal ppu has the red and green bits swapped.
+    fn emphasis(&self) -> u8 {
+        let e = self.registers[1]
+            & (PPU_REGISTER1_EMPHASIZE_BLUE
+                | PPU_REGISTER1_EMPHASIZE_GREEN
+                | PPU_REGISTER1_EMPHASIZE_RED);
+        if self.region.swaps_emphasis() {
+            (e & PPU_REGISTER1_EMPHASIZE_BLUE)
+                | ((e & PPU_REGISTER1_EMPHASIZE_RED) << 1)
+                | ((e & PPU_REGISTER1_EMPHASIZE_GREEN) >> 1)
+        } else {
+            e
+        }
+    }
+
+    /// The rgb color of an entry of the system palette, without the grayscale and emphasis bits applied
+    pub fn system_color(&self, entry: u8) -> [u8; 3] {
+        self.palette.color(entry & 0x3f, 0)
+    }
+
+    /// Convert a color from palette ram into the color that is displayed, applying the grayscale and emphasis bits
+    fn pixel_color(&self, entry: u8) -> [u8; 3] {
+        let raw = self.raw_color(entry);
+        self.palette.color((raw & 0x3f) as u8, (raw >> 6) as u8)
+    }
+
+    /// Apply the grayscale bit to a color from palette ram, returning the color in bits 0-5 and the emphasis bits in bits 6-8
+    fn raw_color(&self, entry: u8) -> u16 {
+        let entry = if (self.registers[1] & PPU_REGISTER1_GREYSCALE) != 0 {
+            entry & 0x30
+        } else {
+            entry & 0x3f
+        };
+        entry as u16 | ((self.emphasis() >> 5) as u16) << 6
+    }
+
+    /// Returns the raw colors of the last frame, the color in bits 0-5 and the emphasis bits in bits 6-8
+    pub fn frame_colors(&self) -> &[u16] {
+        &self.frame_colors
+    }
+
+    /// Returns the phase of the ntsc color subcarrier at the start of the last frame, in twelfths of a cycle
+    pub fn frame_phase(&self) -> u8 {
+        self.frame_phase
+    }
+
+    /// A blank frame of raw colors
+    fn blank_colors() -> Vec<u16> {
+        vec![0; 256 * 240]
+    }
+
     /// Return the operating row
     pub fn row(&self) -> u16 {
         self.scanline_number
@@ -384,7 +556,6 @@ impl NesPpu {
     }
 
     /// Return the frame number of the ppu, mostly used for testing and debugging the ppu
-    #[cfg(any(test, feature = "debugger"))]
     pub fn frame_number(&self) -> u64 {
         self.frame_number
     }
@@ -393,10 +564,7 @@ impl NesPpu {
     pub fn reset(&mut self) {
         self.registers[0] = 0;
         self.registers[1] = 0;
-        #[cfg(any(test, feature = "debugger"))]
-        {
-            self.frame_number = 0;
-        }
+        self.frame_number = 0;
     }
 
     /// Returns the vram address of the ppu
@@ -422,6 +590,12 @@ impl NesPpu {
         s
     }
 
+    /// Modify a byte of the sprite memory, used for editing sprites from the debugger
+    #[cfg(any(test, feature = "debugger"))]
+    pub fn set_oam(&mut self, addr: u8, data: u8) {
+        self.oam[addr as usize] = data;
+    }
+
     /// Performs a dump of the ppu without side effects.
     pub fn dump(&self, addr: u16) -> Option<u8> {
         match addr {
@@ -633,7 +807,7 @@ impl NesPpu {
     /// This increments the scanline cycle machine, sweeping across every scanline, and down every row sequentially.
     fn increment_scanline_cycle(&mut self) {
         if self.should_render_background_cycle(8) || self.should_render_sprites(8) {
-            if self.scanline_number < 240 || self.scanline_number == 261 {
+            if self.scanline_number < 240 || self.scanline_number == self.prerender_line() {
                 if (self.scanline_cycle >= 328 || self.scanline_cycle <= 256)
                     && self.scanline_cycle != 0
                     && (self.scanline_cycle & 7) == 0
@@ -650,7 +824,9 @@ impl NesPpu {
                     _ => {}
                 }
             }
-            if self.scanline_number == 261 && (280..=304).contains(&self.scanline_cycle) {
+            if self.scanline_number == self.prerender_line()
+                && (280..=304).contains(&self.scanline_cycle)
+            {
                 self.transfer_vertical_position();
             }
         }
@@ -659,16 +835,25 @@ impl NesPpu {
         if self.scanline_cycle == 341 {
             self.scanline_cycle = 0;
             self.scanline_number += 1;
-            if self.scanline_number == 262 {
+            self.ntsc_phase = (self.ntsc_phase + PPU_NTSC_SCANLINE_PHASE) % 12;
+            if self.scanline_number >= self.region.scanlines() {
                 self.frame_odd = !self.frame_odd;
                 self.scanline_number = 0;
+                self.frame_phase = self.ntsc_phase;
+                #[cfg(feature = "debugger")]
+                {
+                    self.events.end_frame(&self.frame_data);
+                }
             }
         } else if self.scanline_cycle == 339
-            && self.scanline_number == 261
+            && self.scanline_number == self.prerender_line()
             && self.frame_odd
+            && self.region.skips_odd_cycle()
             && ((self.registers[1] & (PPU_REGISTER1_DRAW_BACKGROUND)) != 0)
         {
             self.scanline_cycle += 1;
+            //skipping a pixel skips 8 of the 12 samples of a color cycle
+            self.ntsc_phase = (self.ntsc_phase + 4) % 12;
         }
     }
 
@@ -816,6 +1001,13 @@ impl NesPpu {
                     self.attributetable_shift[1] = self.attributetable_data;
                     self.patterntable_shift[0] = self.patterntable_shift[1];
                     self.patterntable_shift[1] = self.patterntable_tile;
+                    if self.pixel_tiles.is_some() {
+                        let base = self.background_patterntable_base();
+                        let offset = (self.nametable_data as u16) << 4;
+                        let row = (((7 + self.vram_address) >> 12) & 7) as u8;
+                        self.tile_shift[0] = self.tile_shift[1];
+                        self.tile_shift[1] = (self.peek_tile(bus, base + offset), row);
+                    }
                 }
             }
             _ => {}
@@ -856,7 +1048,11 @@ impl NesPpu {
                 self.pend_vram_read = None;
             } else {
                 self.mode = None;
-                bus.ppu_cycle_2_read(self);
+                if bus.exact_ppu_fetches() {
+                    bus.ppu_cycle_2_idle(self);
+                } else {
+                    bus.ppu_cycle_2_read(self);
+                }
                 self.cycle1_done = false;
             }
         }
@@ -1040,6 +1236,7 @@ impl NesPpu {
             } else if self.scanline_cycle <= 256 {
                 //each cycle here renders a single pixel
                 let cycle = (self.scanline_cycle - 1) as u8;
+                let mut bg_tile = None;
                 let bg_pixel = if self.should_render_background_cycle(cycle as u16) {
                     let prev_tile = ((self.scrollx & 7) + (cycle & 7)) > 7;
                     let index = 7 - ((cycle.wrapping_add(self.scrollx)) % 8);
@@ -1064,6 +1261,23 @@ impl NesPpu {
                         self.attributetable_shift[1]
                     };
                     let extra_palette_bits = (attribute >> (2 * combined)) & 3;
+                    if self.pixel_tiles.is_some() {
+                        let (chr, row) = if !prev_tile {
+                            self.tile_shift[0]
+                        } else {
+                            self.tile_shift[1]
+                        };
+                        bg_tile = Some(PixelTile {
+                            chr,
+                            palette: self
+                                .peek_palette(bus, 0x3f00 + ((extra_palette_bits as u16) << 2)),
+                            x: 7 - index,
+                            y: row,
+                            flip_h: false,
+                            flip_v: false,
+                            layer: TileLayer::Background,
+                        });
+                    }
                     #[cfg(feature = "debugger")]
                     {
                         if let Some((x, y)) = self.bg_debug {
@@ -1077,14 +1291,11 @@ impl NesPpu {
                     }
                     let lower_bits = (upper_bit << 1) | lower_bit;
 
-                    let mut palette_entry = if lower_bits == 0 {
+                    let palette_entry = if lower_bits == 0 {
                         0
                     } else {
                         ((extra_palette_bits << 2) | lower_bits) as u16
                     };
-                    if (self.registers[1] & PPU_REGISTER1_GREYSCALE) != 0 {
-                        palette_entry &= 0x30;
-                    }
 
                     let pixel_entry = bus.ppu_palette_read(0x3f00 + palette_entry) & 63;
                     if lower_bits == 0 {
@@ -1102,8 +1313,12 @@ impl NesPpu {
                 }
                 let spr_pixel: Option<(usize, u8)> =
                     if self.should_render_sprites(cycle) && self.scanline_number > 0 {
-                        let mut sprite_pixels =
-                            self.sprites.iter().enumerate().filter_map(|(index, e)| {
+                        let mut sprite_pixels = self
+                            .sprites
+                            .iter()
+                            .chain(self.extra_sprites.iter())
+                            .enumerate()
+                            .filter_map(|(index, e)| {
                                 if cycle >= e.x
                                     && ((cycle as u16) < ((e.x as u16).wrapping_add(8)))
                                     && e.y < 240
@@ -1120,11 +1335,8 @@ impl NesPpu {
                                     if upper_bit == 0 && lower_bit == 0 {
                                         None
                                     } else {
-                                        let mut palette_entry =
+                                        let palette_entry =
                                             e.pallete() | ((upper_bit << 1) | lower_bit) as u16;
-                                        if (self.registers[1] & PPU_REGISTER1_GREYSCALE) != 0 {
-                                            palette_entry &= 0x30;
-                                        }
                                         let pixel_entry =
                                             bus.ppu_palette_read(0x3f10 | palette_entry) & 63;
                                         Some((index, pixel_entry))
@@ -1139,7 +1351,7 @@ impl NesPpu {
                     };
 
                 let priority = spr_pixel.map_or(true, |(index, _spr)| {
-                    (self.sprites[index].attribute & 0x20) == 0
+                    (self.sprite(index).attribute & 0x20) == 0
                 });
                 if let Some((index, _spr)) = spr_pixel {
                     if bg_pixel.is_some() && index == 0 && self.sprite0_current && cycle < 255 {
@@ -1181,17 +1393,22 @@ impl NesPpu {
                     bus.ppu_palette_read(addr) & 63
                 };
 
-                if (self.registers[1]
-                    & (PPU_REGISTER1_EMPHASIZE_BLUE
-                        | PPU_REGISTER1_EMPHASIZE_GREEN
-                        | PPU_REGISTER1_EMPHASIZE_RED))
-                    != 0
-                {
-                    //TODO implement color emphasis
-                    //println!("TODO: implement color emphasis");
+                if self.pixel_tiles.is_some() {
+                    let tile = if let Some((index, _spr)) =
+                        spr_pixel.filter(|_| priority || bg_pixel.is_none())
+                    {
+                        Some(self.sprite_pixel_tile(bus, index, cycle))
+                    } else {
+                        bg_tile
+                    };
+                    if let Some(tiles) = &mut self.pixel_tiles {
+                        tiles[self.scanline_number as usize * 256 + cycle as usize] = tile;
+                    }
                 }
 
-                let pixel = PPU_PALETTE[pixel_entry as usize];
+                let pixel = self.pixel_color(pixel_entry);
+                self.frame_colors[self.scanline_number as usize * 256 + cycle as usize] =
+                    self.raw_color(pixel_entry);
                 self.frame_data.data
                     [((self.scanline_number * 256 + cycle as u16) as u32 * 3) as usize] = pixel[0];
                 self.frame_data.data
@@ -1288,8 +1505,14 @@ impl NesPpu {
                         }
                         _ => {}
                     }
+                    if self.unlimited_sprites && self.scanline_cycle == 320 {
+                        self.fetch_extra_sprites(bus);
+                    }
                 } else {
                     self.idle_operation(bus, self.scanline_cycle - 1);
+                    if self.scanline_cycle == 320 {
+                        self.extra_sprites.clear();
+                    }
                 }
                 self.increment_scanline_cycle();
             } else if self.scanline_cycle <= 336 {
@@ -1302,10 +1525,14 @@ impl NesPpu {
                 }
                 self.increment_scanline_cycle();
             } else {
-                //do nothing
                 let cycle = self.scanline_cycle - 337;
                 if self.should_fetch_background() || Some(PpuMode::Background) == self.mode {
-                    self.background_fetch(bus, cycle);
+                    if bus.exact_ppu_fetches() {
+                        //two unused nametable fetches
+                        self.background_fetch(bus, cycle & 1);
+                    } else {
+                        self.background_fetch(bus, cycle);
+                    }
                 } else {
                     self.idle_operation(bus, cycle);
                 }
@@ -1316,23 +1543,20 @@ impl NesPpu {
                 self.idle_operation(bus, self.scanline_cycle - 1);
             }
             self.increment_scanline_cycle();
-        } else if self.scanline_number <= 260 {
+        } else if self.scanline_number < self.prerender_line() {
             //vblank lines
-            if self.scanline_cycle == 1 && self.scanline_number == 241 {
+            if self.scanline_cycle == 1 && self.scanline_number == self.region.vblank_line() {
                 self.registers[2] |= 0x80;
                 self.vblank_just_set = true;
                 self.frame_end = true;
-                #[cfg(any(test, feature = "debugger"))]
-                {
-                    self.frame_number = self.frame_number.wrapping_add(1);
-                }
+                self.frame_number = self.frame_number.wrapping_add(1);
             }
             if self.scanline_cycle > 0 {
                 self.idle_operation(bus, self.scanline_cycle - 1);
             }
             self.increment_scanline_cycle();
         } else {
-            if self.scanline_number == 261 && self.scanline_cycle == 1 {
+            if self.scanline_number == self.prerender_line() && self.scanline_cycle == 1 {
                 self.suppress_nmi = false;
                 self.registers[2] &= !0xE0; //vblank, sprite 0, sprite overflow
             }
@@ -1446,7 +1670,12 @@ impl NesPpu {
             } else {
                 let cycle = self.scanline_cycle - 337;
                 if self.should_fetch_background() || Some(PpuMode::Background) == self.mode {
-                    self.background_fetch(bus, cycle);
+                    if bus.exact_ppu_fetches() {
+                        //two unused nametable fetches
+                        self.background_fetch(bus, cycle & 1);
+                    } else {
+                        self.background_fetch(bus, cycle);
+                    }
                 } else {
                     self.idle_operation(bus, cycle);
                 }
@@ -1517,12 +1746,9 @@ impl NesPpu {
             let lower_bit = (pattern_low >> index2) & 1;
 
             if upper_bit != 0 || lower_bit != 0 {
-                let mut palette_entry = sprite.pallete() | ((upper_bit << 1) | lower_bit) as u16;
-                if (self.registers[1] & PPU_REGISTER1_GREYSCALE) != 0 {
-                    palette_entry &= 0x30;
-                }
+                let palette_entry = sprite.pallete() | ((upper_bit << 1) | lower_bit) as u16;
                 let pixel_entry = bus.ppu_palette_read(0x3f10 | palette_entry) & 63;
-                let p = PPU_PALETTE[pixel_entry as usize];
+                let p = self.pixel_color(pixel_entry);
                 pixel[0] = p[0];
                 pixel[1] = p[1];
                 pixel[2] = p[2];
@@ -1572,14 +1798,11 @@ impl NesPpu {
         let extra_palette_bits = (attribute >> (2 * combined)) & 3;
         let lower_bits = (upper_bit << 1) | lower_bit;
 
-        let mut palette_entry = if lower_bits == 0 {
+        let palette_entry = if lower_bits == 0 {
             0
         } else {
             ((extra_palette_bits << 2) | lower_bits) as u16
         };
-        if (self.registers[1] & PPU_REGISTER1_GREYSCALE) != 0 {
-            palette_entry &= 0x30;
-        }
         0x3f00 + palette_entry
     }
 
@@ -1620,7 +1843,7 @@ impl NesPpu {
     pub fn render_palette(&self, buf: &mut Box<RgbImage>, bus: &NesMotherboard) {
         for (i, pixel) in buf.data.chunks_exact_mut(3).enumerate() {
             let pixel_entry = bus.ppu_palette_read(0x3f00 + i as u16) & 63;
-            let p = PPU_PALETTE[pixel_entry as usize];
+            let p = self.pixel_color(pixel_entry);
             pixel[0] = p[0];
             pixel[1] = p[1];
             pixel[2] = p[2];
@@ -1645,7 +1868,7 @@ impl NesPpu {
             let address =
                 self.render_attribute_table_pixel_address(quadrant, col as u8, row as u8, bus);
 
-            let p = PPU_PALETTE[address as usize];
+            let p = self.pixel_color(address as u8);
             pixel[0] = p[0];
             pixel[1] = p[1];
             pixel[2] = p[2];
@@ -1670,15 +1893,16 @@ impl NesPpu {
             let address = self.render_nametable_pixel_address(quadrant, col as u8, row as u8, bus);
             let pixel_entry = bus.ppu_palette_read(address) & 63;
 
-            let p = PPU_PALETTE[pixel_entry as usize];
+            let p = self.pixel_color(pixel_entry);
             pixel[0] = p[0];
             pixel[1] = p[1];
             pixel[2] = p[2];
         }
     }
 
-    /// Renders the entire pattern table into the given buffer
-    pub fn render_pattern_table(&self, buf: &mut Box<RgbImage>, bus: &NesMotherboard) {
+    /// Renders the entire pattern table into the given buffer, using one of the 8 palettes.
+    /// Palettes 0-3 are the background palettes and palettes 4-7 are the sprite palettes.
+    pub fn render_pattern_table(&self, buf: &mut Box<RgbImage>, bus: &NesMotherboard, palette: u8) {
         for (i, pixel) in buf.data.chunks_exact_mut(3).enumerate() {
             let column = (i & 0x78) >> 3;
             let row = (i & 0xF800) >> 11;
@@ -1696,12 +1920,14 @@ impl NesPpu {
             let upper_bit = (data_high >> index) & 1;
             let lower_bit = (data_low >> index) & 1;
 
-            let mut palette_entry = ((upper_bit << 1) | lower_bit) as u16;
-            if (self.registers[1] & PPU_REGISTER1_GREYSCALE) != 0 {
-                palette_entry &= 0x30;
-            }
+            let lower_bits = ((upper_bit << 1) | lower_bit) as u16;
+            let palette_entry = if lower_bits == 0 {
+                0
+            } else {
+                ((palette as u16 & 7) << 2) | lower_bits
+            };
             let pixel_entry = bus.ppu_palette_read(0x3f00 + palette_entry) & 63;
-            let p = PPU_PALETTE[pixel_entry as usize];
+            let p = self.pixel_color(pixel_entry);
             pixel[0] = p[0];
             pixel[1] = p[1];
             pixel[2] = p[2];
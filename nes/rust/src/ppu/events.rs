@@ -0,0 +1,199 @@
+//! Records the accesses to the ppu registers and the mapper irqs during a frame, along with the scanline and dot they happened on.
+//! This is used for finding out when raster effects, like split screens and mid-frame scrolling, happen.
+
+use common_emulator::video::RgbImage;
+
+/// The types of events that are recorded
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PpuEventKind {
+    /// The cpu wrote to a ppu register
+    RegisterWrite,
+    /// The cpu read a ppu register
+    RegisterRead,
+    /// The cpu started an oam dma by writing to $4014
+    OamDma,
+    /// The mapper of the cartridge asserted its irq
+    MapperIrq,
+}
+
+/// The scrolling registers of the ppu
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PpuScroll {
+    /// The vram address, v
+    pub vram_address: u16,
+    /// The temporary vram address, t
+    pub temporary_vram_address: u16,
+    /// The fine horizontal scroll, x
+    pub fine_x: u8,
+    /// The write toggle shared by $2005 and $2006, w
+    pub write_toggle: bool,
+}
+
+impl PpuScroll {
+    /// The horizontal scroll in pixels that the temporary vram address and fine x specify, including the nametable select
+    pub fn x(&self) -> u16 {
+        let t = self.temporary_vram_address;
+        let nametable = if (t & 0x400) != 0 { 256 } else { 0 };
+        nametable + ((t & 0x1f) << 3 | self.fine_x as u16)
+    }
+
+    /// The vertical scroll in pixels that the temporary vram address specifies, including the nametable select
+    pub fn y(&self) -> u16 {
+        let t = self.temporary_vram_address;
+        let nametable = if (t & 0x800) != 0 { 240 } else { 0 };
+        nametable + ((((t >> 5) & 0x1f) << 3) | ((t >> 12) & 7))
+    }
+}
+
+/// A single recorded event
+#[derive(Copy, Clone, Debug)]
+pub struct PpuEvent {
+    /// The type of event
+    pub kind: PpuEventKind,
+    /// The address accessed by the cpu, 0 for mapper irqs
+    pub address: u16,
+    /// The value written or read, 0 for mapper irqs
+    pub value: u8,
+    /// The address of the cpu instruction that caused the event
+    pub pc: u16,
+    /// The scanline the event happened on
+    pub scanline: u16,
+    /// The dot of the scanline the event happened on
+    pub dot: u16,
+    /// The scroll state of the ppu after the event
+    pub scroll: PpuScroll,
+}
+
+/// The events of the current frame and the previous frame
+#[derive(Default)]
+pub struct PpuEventLog {
+    /// Events are only recorded when this is set
+    pub enabled: bool,
+    /// The events of the frame in progress
+    current: Vec<PpuEvent>,
+    /// The events of the last complete frame
+    last: Vec<PpuEvent>,
+    /// The image of the last complete frame
+    last_frame: Option<RgbImage>,
+}
+
+impl PpuEventLog {
+    /// Record an event, if recording is enabled
+    pub fn record(&mut self, event: PpuEvent) {
+        if self.enabled {
+            self.current.push(event);
+        }
+    }
+
+    /// Finish the current frame, keeping its events and image for viewing
+    pub fn end_frame(&mut self, frame: &RgbImage) {
+        if self.enabled {
+            self.last = std::mem::take(&mut self.current);
+            self.last_frame = Some(frame.clone());
+        } else {
+            self.current.clear();
+        }
+    }
+
+    /// The events of the last complete frame
+    pub fn last_events(&self) -> &[PpuEvent] {
+        &self.last
+    }
+
+    /// The image of the last complete frame
+    pub fn last_frame(&self) -> Option<&RgbImage> {
+        self.last_frame.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a register write event with the given scroll state
+    fn event(address: u16, scroll: PpuScroll) -> PpuEvent {
+        PpuEvent {
+            kind: PpuEventKind::RegisterWrite,
+            address,
+            value: 0,
+            pc: 0x8000,
+            scanline: 0,
+            dot: 0,
+            scroll,
+        }
+    }
+
+    /// Build a scroll state from the temporary vram address and fine x
+    fn scroll(t: u16, fine_x: u8) -> PpuScroll {
+        PpuScroll {
+            vram_address: 0,
+            temporary_vram_address: t,
+            fine_x,
+            write_toggle: false,
+        }
+    }
+
+    #[test]
+    fn scroll_position() {
+        assert_eq!(scroll(0, 0).x(), 0);
+        assert_eq!(scroll(0, 0).y(), 0);
+        //coarse x 5, fine x 3, second horizontal nametable
+        assert_eq!(scroll(0x405, 3).x(), 256 + 5 * 8 + 3);
+        //coarse y 10, fine y 6, second vertical nametable
+        let s = scroll(0x6000 | 0x800 | 10 << 5, 0);
+        assert_eq!(s.y(), 240 + 10 * 8 + 6);
+        assert_eq!(s.x(), 0);
+        //the largest values of every field
+        let s = scroll(0x7fff, 7);
+        assert_eq!(s.x(), 511);
+        assert_eq!(s.y(), 240 + 255);
+    }
+
+    #[test]
+    fn events_only_recorded_when_enabled() {
+        let frame = RgbImage::new(4, 4);
+        let mut log = PpuEventLog::default();
+        log.record(event(0x2000, scroll(0, 0)));
+        log.end_frame(&frame);
+        assert!(log.last_events().is_empty());
+        assert!(log.last_frame().is_none());
+
+        log.enabled = true;
+        log.record(event(0x2005, scroll(1, 0)));
+        log.record(event(0x2006, scroll(2, 0)));
+        //events of the frame in progress are not shown until the frame ends
+        assert!(log.last_events().is_empty());
+        log.end_frame(&frame);
+        let addresses: Vec<u16> = log.last_events().iter().map(|e| e.address).collect();
+        assert_eq!(addresses, [0x2005, 0x2006]);
+        assert_eq!(log.last_frame().map(|f| f.width), Some(4));
+    }
+
+    #[test]
+    fn end_frame_rotates_events() {
+        let mut log = PpuEventLog {
+            enabled: true,
+            ..Default::default()
+        };
+        log.record(event(0x2001, scroll(0, 0)));
+        log.end_frame(&RgbImage::new(1, 1));
+        log.record(event(0x2002, scroll(0, 0)));
+        log.end_frame(&RgbImage::new(2, 2));
+        assert_eq!(log.last_events().len(), 1);
+        assert_eq!(log.last_events()[0].address, 0x2002);
+        assert_eq!(log.last_frame().map(|f| f.width), Some(2));
+        //a frame with no events replaces the previous events
+        log.end_frame(&RgbImage::new(3, 3));
+        assert!(log.last_events().is_empty());
+
+        //events recorded while disabled are dropped, the last frame is kept
+        log.record(event(0x2003, scroll(0, 0)));
+        log.enabled = false;
+        log.end_frame(&RgbImage::new(4, 4));
+        assert!(log.last_events().is_empty());
+        assert_eq!(log.last_frame().map(|f| f.width), Some(3));
+        log.enabled = true;
+        log.end_frame(&RgbImage::new(5, 5));
+        assert!(log.last_events().is_empty());
+    }
+}
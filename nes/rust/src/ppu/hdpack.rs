@@ -0,0 +1,240 @@
+//! Hd packs replace the 8x8 tiles drawn by the ppu with higher resolution images.
+//! A pack is a folder containing a hires.txt file and the png images it refers to.
+//! Tiles are matched by the 16 bytes of pattern table data and the 4 colors of the palette used to draw them.
+
+use std::collections::HashMap;
+
+#[cfg(feature = "eframe")]
+use eframe::egui;
+
+#[cfg(feature = "egui-multiwin")]
+use egui_multiwin::egui;
+
+use common_emulator::video::PixelImage;
+
+/// The layer of the ppu that a tile was drawn on
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TileLayer {
+    /// The tile is part of the background
+    Background,
+    /// The tile is part of a sprite
+    Sprite,
+}
+
+/// The identity of the tile that generated a single pixel of the ppu
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PixelTile {
+    /// The 16 bytes of pattern table data for the tile
+    pub chr: [u8; 16],
+    /// The colors of the palette for the tile, the first one is the background color
+    pub palette: [u8; 4],
+    /// The column of the pixel in the pattern table data, 0-7
+    pub x: u8,
+    /// The row of the pixel in the pattern table data, 0-7
+    pub y: u8,
+    /// The tile is flipped horizontally
+    pub flip_h: bool,
+    /// The tile is flipped vertically
+    pub flip_v: bool,
+    /// The layer the tile was drawn on
+    pub layer: TileLayer,
+}
+
+/// A png image from a hd pack, converted to rgba
+#[derive(Clone)]
+struct HdImage {
+    /// The pixels of the image, row by row
+    pixels: Vec<[u8; 4]>,
+    /// The width of the image in pixels
+    width: u32,
+    /// The height of the image in pixels
+    height: u32,
+}
+
+impl HdImage {
+    /// Load a png image
+    fn load(path: &std::path::Path) -> std::io::Result<Self> {
+        let to_error = |e: png::DecodingError| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string())
+        };
+        let mut decoder = png::Decoder::new(std::fs::File::open(path)?);
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info().map_err(to_error)?;
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf).map_err(to_error)?;
+        let data = &buf[..info.buffer_size()];
+        let pixels: Vec<[u8; 4]> = match info.color_type {
+            png::ColorType::Rgba => data
+                .chunks_exact(4)
+                .map(|p| [p[0], p[1], p[2], p[3]])
+                .collect(),
+            png::ColorType::Rgb => data
+                .chunks_exact(3)
+                .map(|p| [p[0], p[1], p[2], 255])
+                .collect(),
+            png::ColorType::GrayscaleAlpha => data
+                .chunks_exact(2)
+                .map(|p| [p[0], p[0], p[0], p[1]])
+                .collect(),
+            png::ColorType::Grayscale => data.iter().map(|p| [*p, *p, *p, 255]).collect(),
+            png::ColorType::Indexed => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "Indexed png images were not expanded",
+                ));
+            }
+        };
+        Ok(Self {
+            pixels,
+            width: info.width,
+            height: info.height,
+        })
+    }
+
+    /// Get a pixel of the image, transparent when outside of the image
+    fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        if x < self.width && y < self.height {
+            self.pixels[(y * self.width + x) as usize]
+        } else {
+            [0; 4]
+        }
+    }
+}
+
+/// The replacement for a single tile
+#[derive(Clone)]
+struct HdTile {
+    /// The index of the image containing the replacement
+    image: usize,
+    /// The left edge of the replacement in the image
+    x: u32,
+    /// The top edge of the replacement in the image
+    y: u32,
+    /// The brightness to apply to the replacement
+    brightness: f32,
+}
+
+/// A loaded hd pack
+#[derive(Clone)]
+pub struct HdPack {
+    /// The amount the tiles of the pack are scaled up by
+    scale: u32,
+    /// The images of the pack
+    images: Vec<HdImage>,
+    /// The tile replacements of the pack, indexed by pattern table data and palette
+    tiles: HashMap<([u8; 16], [u8; 4]), HdTile>,
+}
+
+/// Parse a string of hex digits into bytes
+fn parse_hex<const N: usize>(s: &str) -> Option<[u8; N]> {
+    let s = s.trim();
+    if s.len() != N * 2 {
+        return None;
+    }
+    let mut out = [0; N];
+    for (i, b) in out.iter_mut().enumerate() {
+        *b = u8::from_str_radix(s.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+    Some(out)
+}
+
+impl HdPack {
+    /// Load a hd pack from the folder containing the hires.txt file.
+    /// Only tiles identified by their pattern table data are supported, tiles identified by a tile number are ignored.
+    pub fn load(folder: &std::path::Path) -> std::io::Result<Self> {
+        let text = std::fs::read_to_string(folder.join("hires.txt"))?;
+        let mut pack = Self {
+            scale: 1,
+            images: Vec::new(),
+            tiles: HashMap::new(),
+        };
+        for line in text.lines() {
+            let line = line.trim();
+            if let Some(scale) = line.strip_prefix("<scale>") {
+                pack.scale = scale.trim().parse().unwrap_or(1).max(1);
+            } else if let Some(img) = line.strip_prefix("<img>") {
+                pack.images.push(HdImage::load(&folder.join(img.trim()))?);
+            } else if let Some(tile) = line.strip_prefix("<tile>") {
+                let fields: Vec<&str> = tile.split(',').collect();
+                if fields.len() < 5 {
+                    continue;
+                }
+                let chr = parse_hex::<16>(fields[1]);
+                let palette = parse_hex::<4>(fields[2]);
+                let image = fields[0].trim().parse::<usize>().ok();
+                let x = fields[3].trim().parse::<u32>().ok();
+                let y = fields[4].trim().parse::<u32>().ok();
+                let brightness = fields
+                    .get(5)
+                    .and_then(|b| b.trim().parse::<f32>().ok())
+                    .unwrap_or(1.0);
+                if let (Some(chr), Some(palette), Some(image), Some(x), Some(y)) =
+                    (chr, palette, image, x, y)
+                {
+                    if image < pack.images.len() {
+                        pack.tiles.insert(
+                            (chr, palette),
+                            HdTile {
+                                image,
+                                x,
+                                y,
+                                brightness,
+                            },
+                        );
+                    }
+                }
+            }
+        }
+        Ok(pack)
+    }
+
+    /// The amount the pack scales up the image of the ppu
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+
+    /// Build an upscaled frame from a frame of the ppu and the tile that generated each pixel of the frame.
+    /// Pixels from tiles that the pack does not replace are scaled up without modification.
+    pub fn compose(
+        &self,
+        frame: &PixelImage<egui::Color32>,
+        tiles: &[Option<PixelTile>],
+    ) -> PixelImage<egui::Color32> {
+        let scale = self.scale as usize;
+        let width = frame.width as usize * scale;
+        let height = frame.height as usize * scale;
+        let mut pixels = vec![egui::Color32::BLACK; width * height];
+        for y in 0..frame.height as usize {
+            for x in 0..frame.width as usize {
+                let original = frame.get_pixel(egui::vec2(x as f32, y as f32));
+                let tile = tiles
+                    .get(y * frame.width as usize + x)
+                    .copied()
+                    .flatten()
+                    .and_then(|t| self.tiles.get(&(t.chr, t.palette)).map(|hd| (t, hd)));
+                for sy in 0..scale {
+                    for sx in 0..scale {
+                        let color = if let Some((t, hd)) = tile {
+                            let ix = if t.flip_h { scale - 1 - sx } else { sx };
+                            let iy = if t.flip_v { scale - 1 - sy } else { sy };
+                            let p = self.images[hd.image].pixel(
+                                hd.x + (t.x as usize * scale + ix) as u32,
+                                hd.y + (t.y as usize * scale + iy) as u32,
+                            );
+                            if p[3] == 0 {
+                                original
+                            } else {
+                                let b = |c: u8| (c as f32 * hd.brightness).min(255.0) as u8;
+                                egui::Color32::from_rgb(b(p[0]), b(p[1]), b(p[2]))
+                            }
+                        } else {
+                            original
+                        };
+                        pixels[(y * scale + sy) * width + x * scale + sx] = color;
+                    }
+                }
+            }
+        }
+        PixelImage::new(pixels, width as u16, height as u16)
+    }
+}
@@ -0,0 +1,223 @@
+//! The palette of the ppu, which converts the colors in palette ram into rgb colors.
+//! A palette can be the built in palette, loaded from a .pal file, or generated from the levels of the ntsc signal.
+
+use std::io::Write;
+
+use common_emulator::video::ntsc;
+
+/// The number of colors the ppu can generate, without emphasis
+pub const PALETTE_COLORS: usize = 64;
+/// The number of combinations of the emphasis bits
+const EMPHASIS_COMBINATIONS: usize = 8;
+
+/// The built in palette for the ppu
+const BUILTIN_PALETTE: [[u8; 3]; PALETTE_COLORS] = palette_generator();
+
+/// The brightness of a color channel that is not emphasized, for each emphasis bit that is set
+const PPU_EMPHASIS_ATTENUATION: f32 = 0.816;
+
+/// Apply color emphasis to a color. The emphasis bits are red, green, blue in bits 0, 1, 2.
+/// Each emphasis bit darkens the channels that it does not emphasize.
+fn emphasize(color: [u8; 3], emphasis: u8) -> [u8; 3] {
+    let mut out = color;
+    for (i, c) in out.iter_mut().enumerate() {
+        let darken = (0..3)
+            .filter(|b| *b != i && (emphasis & (1 << b)) != 0)
+            .count();
+        *c = (*c as f32 * PPU_EMPHASIS_ATTENUATION.powi(darken as i32)) as u8;
+    }
+    out
+}
+
+/// Build a palette for the ppu.
+const fn palette_generator() -> [[u8; 3]; 64] {
+    let mut palette: [[u8; 3]; 64] = [[0; 3]; 64];
+    palette[0] = [84, 84, 84];
+    palette[1] = [0, 30, 116];
+    palette[2] = [8, 16, 144];
+    palette[3] = [48, 0, 136];
+    palette[4] = [68, 0, 100];
+    palette[5] = [92, 0, 48];
+    palette[6] = [84, 4, 0];
+    palette[7] = [60, 24, 0];
+    palette[8] = [32, 42, 0];
+    palette[9] = [8, 58, 0];
+    palette[10] = [0, 64, 0];
+    palette[11] = [0, 60, 0];
+    palette[12] = [0, 50, 60];
+    palette[13] = [0, 0, 0];
+    palette[14] = [0, 0, 0];
+    palette[15] = [0, 0, 0];
+
+    palette[16] = [152, 150, 152];
+    palette[17] = [8, 76, 196];
+    palette[18] = [48, 50, 236];
+    palette[19] = [92, 30, 228];
+    palette[20] = [136, 20, 176];
+    palette[21] = [160, 20, 100];
+    palette[22] = [152, 34, 32];
+    palette[23] = [120, 60, 0];
+    palette[24] = [84, 90, 0];
+    palette[25] = [40, 114, 0];
+    palette[26] = [8, 124, 0];
+    palette[27] = [0, 118, 40];
+    palette[28] = [0, 102, 120];
+    palette[29] = [0, 0, 0];
+    palette[30] = [0, 0, 0];
+    palette[31] = [0, 0, 0];
+
+    palette[32] = [236, 238, 236];
+    palette[33] = [76, 154, 236];
+    palette[34] = [120, 124, 236];
+    palette[35] = [176, 98, 236];
+    palette[36] = [228, 84, 236];
+    palette[37] = [236, 88, 180];
+    palette[38] = [236, 106, 100];
+    palette[39] = [212, 136, 32];
+    palette[40] = [160, 170, 0];
+    palette[41] = [116, 196, 0];
+    palette[42] = [76, 208, 32];
+    palette[43] = [56, 204, 108];
+    palette[44] = [56, 180, 204];
+    palette[45] = [60, 60, 60];
+    palette[46] = [0, 0, 0];
+    palette[47] = [0, 0, 0];
+
+    palette[48] = [236, 238, 236];
+    palette[49] = [168, 204, 236];
+    palette[50] = [188, 188, 236];
+    palette[51] = [212, 178, 236];
+    palette[52] = [236, 174, 236];
+    palette[53] = [236, 174, 212];
+    palette[54] = [236, 180, 176];
+    palette[55] = [228, 196, 144];
+    palette[56] = [204, 210, 120];
+    palette[57] = [180, 222, 120];
+    palette[58] = [168, 226, 144];
+    palette[59] = [152, 226, 180];
+    palette[60] = [160, 214, 228];
+    palette[61] = [160, 162, 160];
+    palette[62] = [0, 0, 0];
+    palette[63] = [0, 0, 0];
+    palette
+}
+
+/// The settings for generating a palette from the ntsc signal of the ppu
+#[derive(serde::Serialize, serde::Deserialize, Copy, Clone, Debug, PartialEq)]
+pub struct NtscPaletteSettings {
+    /// The adjustment of the hue, in degrees
+    pub hue: f32,
+    /// The saturation, 1.0 is normal
+    pub saturation: f32,
+    /// The contrast, 1.0 is normal
+    pub contrast: f32,
+    /// The brightness, 0.0 is normal
+    pub brightness: f32,
+}
+
+impl Default for NtscPaletteSettings {
+    fn default() -> Self {
+        Self {
+            hue: 0.0,
+            saturation: 1.0,
+            contrast: 1.0,
+            brightness: 0.0,
+        }
+    }
+}
+
+/// Where the palette of the ppu comes from
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Default)]
+pub enum PaletteSource {
+    /// The palette built into the emulator
+    #[default]
+    Builtin,
+    /// A .pal file with 64 or 512 colors
+    File(String),
+    /// A palette generated from the ntsc signal
+    Ntsc(NtscPaletteSettings),
+}
+
+/// A complete palette, with a color for every combination of the emphasis bits
+#[derive(Clone)]
+pub struct NesPalette {
+    /// The colors, 64 colors for each of the 8 combinations of emphasis bits
+    colors: Vec<[u8; 3]>,
+}
+
+impl Default for NesPalette {
+    fn default() -> Self {
+        Self::from_colors(&BUILTIN_PALETTE)
+    }
+}
+
+impl NesPalette {
+    /// Build a palette from 64 colors, calculating the emphasized colors
+    fn from_colors(colors: &[[u8; 3]]) -> Self {
+        let mut all = Vec::with_capacity(PALETTE_COLORS * EMPHASIS_COMBINATIONS);
+        for emphasis in 0..EMPHASIS_COMBINATIONS {
+            for c in colors {
+                all.push(emphasize(*c, emphasis as u8));
+            }
+        }
+        Self { colors: all }
+    }
+
+    /// Build the palette described by the source
+    pub fn from_source(source: &PaletteSource) -> std::io::Result<Self> {
+        match source {
+            PaletteSource::Builtin => Ok(Self::default()),
+            PaletteSource::File(f) => Self::load(std::path::Path::new(f)),
+            PaletteSource::Ntsc(settings) => Ok(Self::generate_ntsc(settings)),
+        }
+    }
+
+    /// Load a .pal file, containing 64 colors or 512 colors with all of the emphasis combinations
+    pub fn load(path: &std::path::Path) -> std::io::Result<Self> {
+        let data = std::fs::read(path)?;
+        let colors: Vec<[u8; 3]> = data.chunks_exact(3).map(|c| [c[0], c[1], c[2]]).collect();
+        if data.len() == PALETTE_COLORS * 3 {
+            Ok(Self::from_colors(&colors))
+        } else if data.len() == PALETTE_COLORS * EMPHASIS_COMBINATIONS * 3 {
+            Ok(Self { colors })
+        } else {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "A palette file must contain 64 or 512 colors",
+            ))
+        }
+    }
+
+    /// Save the palette as a .pal file with 512 colors
+    pub fn save(&self, path: &std::path::Path) -> std::io::Result<()> {
+        let mut f = std::fs::File::create(path)?;
+        let data: Vec<u8> = self.colors.iter().flatten().copied().collect();
+        f.write_all(&data)
+    }
+
+    /// Generate a palette by decoding the ntsc signal that the ppu generates for each color
+    pub fn generate_ntsc(settings: &NtscPaletteSettings) -> Self {
+        let mut colors = Vec::with_capacity(PALETTE_COLORS * EMPHASIS_COMBINATIONS);
+        for emphasis in 0..EMPHASIS_COMBINATIONS as u8 {
+            for entry in 0..PALETTE_COLORS as u8 {
+                colors.push(Self::ntsc_color(entry, emphasis, settings));
+            }
+        }
+        Self { colors }
+    }
+
+    /// Decode the ntsc signal of a single color into rgb, applying the adjustments of the settings
+    fn ntsc_color(entry: u8, emphasis: u8, settings: &NtscPaletteSettings) -> [u8; 3] {
+        let (y, i, q) = ntsc::decode_yiq(entry, emphasis, settings.hue.to_radians());
+        ntsc::yiq_to_rgb(
+            y * settings.contrast + settings.brightness,
+            i * settings.saturation,
+            q * settings.saturation,
+        )
+    }
+
+    /// Get the color for an entry of palette ram, with the emphasis bits red, green, blue in bits 0, 1, 2.
+    pub fn color(&self, entry: u8, emphasis: u8) -> [u8; 3] {
+        self.colors[(emphasis as usize & 7) * PALETTE_COLORS + (entry as usize & 0x3f)]
+    }
+}
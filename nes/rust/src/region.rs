@@ -0,0 +1,108 @@
+//! The region of the console determines the clock speeds of the cpu and ppu, and the number of scanlines in a frame.
+
+use crate::cartridge::CpuTiming;
+
+/// The regions that the nes and its clones were made for
+#[derive(
+    serde::Serialize,
+    serde::Deserialize,
+    Copy,
+    Clone,
+    Debug,
+    PartialEq,
+    Default,
+    strum::EnumIter,
+    strum::Display,
+)]
+pub enum Region {
+    /// The ntsc nes and famicom, RP2A03 cpu and RP2C02 ppu
+    #[default]
+    Ntsc,
+    /// The pal nes, RP2A07 cpu and RP2C07 ppu
+    Pal,
+    /// The dendy famiclone. It has pal style frames, but the cpu runs close to the speed of the ntsc cpu.
+    Dendy,
+}
+
+impl From<CpuTiming> for Region {
+    fn from(value: CpuTiming) -> Self {
+        match value {
+            CpuTiming::Ntsc | CpuTiming::MultipleRegion => Region::Ntsc,
+            CpuTiming::Pal => Region::Pal,
+            CpuTiming::Dendy => Region::Dendy,
+        }
+    }
+}
+
+impl Region {
+    /// The frequency of the master clock in hertz
+    pub fn master_clock(&self) -> f32 {
+        match self {
+            Region::Ntsc => 21.477272e6,
+            Region::Pal | Region::Dendy => 26.601712e6,
+        }
+    }
+
+    /// The number of master clock cycles for each cpu cycle
+    pub fn cpu_divider(&self) -> u8 {
+        match self {
+            Region::Ntsc => 12,
+            Region::Pal => 16,
+            Region::Dendy => 15,
+        }
+    }
+
+    /// The number of master clock cycles for each ppu cycle
+    pub fn ppu_divider(&self) -> u8 {
+        match self {
+            Region::Ntsc => 4,
+            Region::Pal | Region::Dendy => 5,
+        }
+    }
+
+    /// The frequency of the cpu in hertz
+    pub fn cpu_frequency(&self) -> f32 {
+        self.master_clock() / self.cpu_divider() as f32
+    }
+
+    /// The number of scanlines in a frame, including the pre-render scanline
+    pub fn scanlines(&self) -> u16 {
+        match self {
+            Region::Ntsc => 262,
+            Region::Pal | Region::Dendy => 312,
+        }
+    }
+
+    /// The scanline where the vblank flag is set
+    pub fn vblank_line(&self) -> u16 {
+        match self {
+            Region::Ntsc | Region::Pal => 241,
+            Region::Dendy => 291,
+        }
+    }
+
+    /// Returns true when the ppu skips a cycle on odd frames
+    pub fn skips_odd_cycle(&self) -> bool {
+        *self == Region::Ntsc
+    }
+
+    /// Returns true when the ppu swaps the red and green emphasis bits
+    pub fn swaps_emphasis(&self) -> bool {
+        *self != Region::Ntsc
+    }
+
+    /// Returns true when the apu uses the rate tables of the pal console
+    pub fn pal_apu(&self) -> bool {
+        *self == Region::Pal
+    }
+
+    /// The number of frames per second
+    pub fn frame_rate(&self) -> f32 {
+        let mut cycles = 341.0 * self.scanlines() as f32;
+        if self.skips_odd_cycle() {
+            //one cycle is skipped every other frame
+            cycles -= 0.5;
+        }
+        self.master_clock() / self.ppu_divider() as f32 / cycles
+    }
+}
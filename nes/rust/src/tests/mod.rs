@@ -2399,3 +2399,1217 @@ fn controller4() {
     }
     assert!(nes_data.mb.check_vram(129, "Passed".to_string().as_bytes()));
 }
+
+/// A folder for the files written by the tests, unique to the test process so that concurrent test runs do not collide
+fn test_temp_dir() -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("nes_tests_{}", std::process::id()));
+    std::fs::create_dir_all(&path).unwrap();
+    path
+}
+
+/// Write a file to the test folder, returning the path to it
+fn write_test_file(name: &str, contents: &[u8]) -> std::path::PathBuf {
+    let path = test_temp_dir().join(name);
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+/// Build a rom with the given header, followed by prg and chr rom filled with data based on the seed, so that each test rom has a unique hash.
+/// Every 4kb bank of the data is different.
+fn build_test_rom(header: [u8; 16], prg_size: usize, chr_size: usize, seed: u8) -> Vec<u8> {
+    let mut rom = header.to_vec();
+    rom.extend(
+        (0..prg_size + chr_size).map(|i| (i as u8).wrapping_mul(seed) ^ seed ^ (i >> 12) as u8),
+    );
+    rom
+}
+
+/// Write a test rom to the test folder and load it, with the save files going to the test folder
+fn load_test_rom(name: &str, rom: &[u8]) -> NesCartridge {
+    let path = write_test_file(name, rom);
+    NesCartridge::load_cartridge(path.display().to_string(), &test_temp_dir()).unwrap()
+}
+
+/// Run a ppu address cycle and read cycle on a cartridge, returning the byte read
+fn ppu_fetch(cart: &mut NesCartridge, addr: u16) -> u8 {
+    let _ = cart.ppu_cycle_1(addr);
+    cart.ppu_cycle_read()
+}
+
+#[test]
+fn mmc2_mmc4_chr_latches() {
+    //ines 1.0 headers, mapper 9 and 10, 128kb prg rom, 128kb chr rom
+    for (mapper, prg_bank_size) in [(9u8, 8192), (10, 16384)] {
+        let header = [
+            b'N',
+            b'E',
+            b'S',
+            0x1a,
+            8,
+            16,
+            mapper << 4,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+        ];
+        let rom = build_test_rom(header, 131072, 131072, mapper);
+        let chr = |bank: usize, offset: usize| rom[16 + 131072 + bank * 4096 + offset];
+        let mut cart = load_test_rom(&format!("mmc_latch_test{}.nes", mapper), &rom);
+
+        cart.memory_write(0xa000, 1);
+        assert_eq!(cart.memory_dump(0x8000), Some(rom[16 + prg_bank_size]));
+        cart.memory_write(0xb000, 1);
+        cart.memory_write(0xc000, 2);
+        cart.memory_write(0xd000, 3);
+        cart.memory_write(0xe000, 4);
+
+        //both latches start on the $FE banks
+        assert_eq!(ppu_fetch(&mut cart, 0x0010), chr(2, 0x10));
+        assert_eq!(ppu_fetch(&mut cart, 0x1010), chr(4, 0x10));
+
+        //the latch switches after the fetch of the triggering tile has completed
+        assert_eq!(ppu_fetch(&mut cart, 0x0fd8), chr(2, 0xfd8));
+        assert_eq!(ppu_fetch(&mut cart, 0x0010), chr(1, 0x10));
+        assert_eq!(ppu_fetch(&mut cart, 0x1fd8), chr(4, 0xfd8));
+        assert_eq!(ppu_fetch(&mut cart, 0x1010), chr(3, 0x10));
+        let _ = ppu_fetch(&mut cart, 0x1fe8);
+        assert_eq!(ppu_fetch(&mut cart, 0x1010), chr(4, 0x10));
+
+        //the mmc2 only triggers the low latch on the first byte of the tile
+        let _ = ppu_fetch(&mut cart, 0x0fe9);
+        let expected = if mapper == 9 {
+            chr(1, 0x10)
+        } else {
+            chr(2, 0x10)
+        };
+        assert_eq!(ppu_fetch(&mut cart, 0x0010), expected);
+    }
+}
+
+#[test]
+fn mmc4_prg_ram() {
+    //ines 1.0 header, mapper 10, 128kb prg rom, 128kb chr rom
+    let header = [
+        b'N', b'E', b'S', 0x1a, 8, 16, 0xa0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    ];
+    let rom = build_test_rom(header, 131072, 131072, 10);
+    let mut cart = load_test_rom("mmc4_ram_test.nes", &rom);
+    cart.memory_write(0x6123, 0x5a);
+    assert_eq!(cart.memory_dump(0x6123), Some(0x5a));
+    //the last 16kb bank is fixed at 0xc000
+    assert_eq!(cart.memory_dump(0xc000), Some(rom[16 + 131072 - 16384]));
+}
+
+#[test]
+fn vrc6_banking_and_irq() {
+    //ines 1.0 headers, mapper 24 and 26, 128kb prg rom, 128kb chr rom
+    for mapper in [24u8, 26] {
+        let header = [
+            b'N',
+            b'E',
+            b'S',
+            0x1a,
+            8,
+            16,
+            mapper << 4,
+            0x10,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+        ];
+        let rom = build_test_rom(header, 131072, 131072, mapper);
+        let mut cart = load_test_rom(&format!("vrc6_test{}.nes", mapper), &rom);
+        //mapper 26 swaps the a0 and a1 lines
+        let reg = |addr: u16| {
+            if mapper == 26 {
+                (addr & !3) | ((addr & 1) << 1) | ((addr & 2) >> 1)
+            } else {
+                addr
+            }
+        };
+
+        cart.memory_write(reg(0x8000), 2);
+        cart.memory_write(reg(0xc000), 3);
+        assert_eq!(cart.memory_dump(0x8000), Some(rom[16 + 2 * 16384]));
+        assert_eq!(cart.memory_dump(0xc000), Some(rom[16 + 3 * 8192]));
+        assert_eq!(cart.memory_dump(0xe000), Some(rom[16 + 131072 - 8192]));
+
+        //1kb chr banks
+        cart.memory_write(reg(0xd001), 5);
+        cart.memory_write(reg(0xe002), 9);
+        assert_eq!(
+            cart.ppu_peek_1(0x0410).2,
+            Some(rom[16 + 131072 + 5 * 1024 + 0x10])
+        );
+        assert_eq!(
+            cart.ppu_peek_1(0x1810).2,
+            Some(rom[16 + 131072 + 9 * 1024 + 0x10])
+        );
+
+        //prg ram is only accessible when enabled in the control register
+        cart.memory_write(0x6000, 0x12);
+        assert_eq!(cart.memory_dump(0x6000), None);
+        cart.memory_write(reg(0xb003), 0x80);
+        cart.memory_write(0x6000, 0x12);
+        assert_eq!(cart.memory_dump(0x6000), Some(0x12));
+
+        //the irq counter in cycle mode overflows after counting up from the latch
+        cart.memory_write(reg(0xf000), 0xfe);
+        cart.memory_write(reg(0xf001), 6);
+        cart.cpu_cycle();
+        assert!(!cart.irq());
+        cart.cpu_cycle();
+        assert!(cart.irq());
+        cart.memory_write(reg(0xf002), 0);
+        assert!(!cart.irq());
+    }
+}
+
+#[test]
+fn vrc6_expansion_audio() {
+    let mut header = [0; 16];
+    header[0..4].copy_from_slice(b"NES\x1a");
+    header[4] = 8;
+    header[5] = 16;
+    header[6] = 0x80;
+    header[7] = 0x10;
+    let rom = build_test_rom(header, 131072, 131072, 24);
+    let mut cart = load_test_rom("vrc6_audio_test.nes", &rom);
+
+    //the first pulse channel at full volume, ignoring the duty cycle
+    let sample = |c: &NesCartridge| {
+        c.audio_sample()
+            .get(crate::cartridge::ExpansionAudioChip::Vrc6)
+    };
+    cart.memory_write(0x9000, 0x8f);
+    cart.clock_audio();
+    assert_eq!(sample(&cart), 0.0);
+    cart.memory_write(0x9002, 0x80);
+    cart.clock_audio();
+    assert!((sample(&cart) - 15.0 / 61.0).abs() < 0.0001);
+}
+
+/// The ines 1.0 header of a synthetic mmc5 rom with 128kb of prg rom and 128kb of chr rom
+const MMC5_TEST_HEADER: [u8; 16] = [
+    b'N', b'E', b'S', 0x1a, 8, 16, 0x50, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+];
+
+#[test]
+fn mmc5_audio() {
+    let mut cart = load_test_rom(
+        "mmc5_audio_test.nes",
+        &build_test_rom(MMC5_TEST_HEADER, 131072, 131072, 5),
+    );
+    let sample = |c: &NesCartridge| {
+        c.audio_sample()
+            .get(crate::cartridge::ExpansionAudioChip::Mmc5)
+    };
+    //the pcm channel in write mode, mixed at half level
+    cart.memory_write(0x5011, 0x80);
+    assert!((sample(&cart) - 64.0 / 157.5).abs() < 0.0001);
+    //writes of zero are ignored
+    cart.memory_write(0x5011, 0);
+    assert!((sample(&cart) - 64.0 / 157.5).abs() < 0.0001);
+
+    //the pcm channel in read mode takes reads of 0x8000-0xbfff, a zero triggers the irq
+    cart.memory_write(0x5114, 0x80);
+    cart.rom_byte_hack(0, 0);
+    cart.rom_byte_hack(1, 0x40);
+    cart.memory_write(0x5010, 0x81);
+    cart.memory_read(0x8001);
+    assert!((sample(&cart) - 32.0 / 157.5).abs() < 0.0001);
+    assert!(!cart.irq());
+    cart.memory_read(0x8000);
+    assert!(cart.irq());
+    assert_eq!(cart.memory_read(0x5010), Some(0x81));
+    assert!(!cart.irq());
+    assert_eq!(cart.memory_dump(0x5010), Some(0x01));
+
+    //the length counters of the pulse channels
+    cart.memory_write(0x5015, 3);
+    cart.memory_write(0x5003, 0x08);
+    cart.memory_write(0x5007, 0x08);
+    assert_eq!(cart.memory_dump(0x5015), Some(3));
+    cart.memory_write(0x5015, 2);
+    assert_eq!(cart.memory_dump(0x5015), Some(2));
+}
+
+#[test]
+fn mmc5_exram_and_nametables() {
+    let mut cart = load_test_rom(
+        "mmc5_exram_test.nes",
+        &build_test_rom(MMC5_TEST_HEADER, 131072, 131072, 5),
+    );
+    //exram mode 2 is general purpose ram
+    cart.memory_write(0x5104, 2);
+    cart.memory_write(0x5c10, 0x33);
+    assert_eq!(cart.memory_dump(0x5c10), Some(0x33));
+    //exram mode 3 is read only
+    cart.memory_write(0x5104, 3);
+    cart.memory_write(0x5c10, 0x44);
+    assert_eq!(cart.memory_dump(0x5c10), Some(0x33));
+    //exram modes 0 and 1 can not be read by the cpu, and only take writes while rendering
+    cart.memory_write(0x5104, 0);
+    assert_eq!(cart.memory_dump(0x5c10), None);
+    cart.memory_write(0x5c10, 0x44);
+    cart.memory_write(0x5104, 2);
+    assert_eq!(cart.memory_dump(0x5c10), Some(0));
+    cart.memory_write(0x5104, 0);
+
+    //ciram page a, ciram page b, exram, and fill mode
+    cart.memory_write(0x5105, 0xe4);
+    cart.memory_write(0x5106, 0x42);
+    cart.memory_write(0x5107, 2);
+    assert_eq!(cart.ppu_peek_1(0x2010), (false, false, None));
+    assert_eq!(cart.ppu_peek_1(0x2410), (true, false, None));
+    let _ = cart.ppu_cycle_1(0x2810);
+    cart.ppu_cycle_write(0x77);
+    assert_eq!(cart.ppu_peek_1(0x2810), (false, true, Some(0x77)));
+    assert_eq!(cart.ppu_peek_1(0x2c10), (false, true, Some(0x42)));
+    assert_eq!(cart.ppu_peek_1(0x2fc0), (false, true, Some(0xaa)));
+}
+
+#[test]
+fn mmc5_vertical_split() {
+    let rom = build_test_rom(MMC5_TEST_HEADER, 131072, 131072, 5);
+    let mut cart = load_test_rom("mmc5_split_test.nes", &rom);
+    cart.memory_write(0x5104, 0);
+    cart.other_memory_write(0x2001, 0x18);
+    //three reads of the same nametable address start the frame
+    for _ in 0..3 {
+        let _ = ppu_fetch(&mut cart, 0x2000);
+    }
+    //the split covers the left 4 tiles, using chr bank 3
+    cart.memory_write(0x5200, 0x84);
+    cart.memory_write(0x5201, 0);
+    cart.memory_write(0x5202, 3);
+    cart.memory_write(0x5c02, 0x99);
+    //the nametable and pattern fetches for tile 2 come from the split
+    assert_eq!(ppu_fetch(&mut cart, 0x2002), 0x99);
+    assert_eq!(
+        ppu_fetch(&mut cart, 0x0990),
+        rom[16 + 131072 + 3 * 4096 + 0x990]
+    );
+}
+
+#[test]
+fn namco163_chr_vram() {
+    //ines 1.0 header, mapper 19, 32kb prg rom, 8kb chr rom
+    let header = [
+        b'N', b'E', b'S', 0x1a, 2, 1, 0x30, 0x10, 0, 0, 0, 0, 0, 0, 0, 0,
+    ];
+    let rom = build_test_rom(header, 32768, 8192, 19);
+    let mut cart = load_test_rom("namco163_test.nes", &rom);
+    //chr banks 0xe0 and above select the internal vram
+    cart.memory_write(0x8000, 0xe1);
+    cart.memory_write(0xa000, 0xe0);
+    assert!(cart.ppu_pattern_vram(0x0010));
+    assert_eq!(cart.ppu_peek_1(0x0010), (true, false, None));
+    assert!(cart.ppu_pattern_vram(0x1010));
+    assert_eq!(cart.ppu_peek_1(0x1010), (false, false, None));
+    assert!(!cart.ppu_pattern_vram(0x0410));
+    assert!(cart.ppu_peek_1(0x0410).2.is_some());
+    //bits 6 and 7 of 0xe800 select chr rom for the low and high pattern tables
+    cart.memory_write(0xe800, 0x40);
+    assert!(!cart.ppu_pattern_vram(0x0010));
+    assert!(cart.ppu_peek_1(0x0010).2.is_some());
+    assert!(cart.ppu_pattern_vram(0x1010));
+    cart.memory_write(0xe800, 0xc0);
+    assert!(!cart.ppu_pattern_vram(0x1010));
+    assert!(cart.ppu_peek_1(0x1010).2.is_some());
+}
+
+#[test]
+fn fme7_registers_irq_and_audio() {
+    //ines 1.0 header, mapper 69, 128kb prg rom, 128kb chr rom
+    let header = [
+        b'N', b'E', b'S', 0x1a, 8, 16, 0x50, 0x40, 0, 0, 0, 0, 0, 0, 0, 0,
+    ];
+    let rom = build_test_rom(header, 131072, 131072, 69);
+    let mut cart = load_test_rom("fme7_test.nes", &rom);
+    let command = |c: &mut NesCartridge, cmd: u8, data: u8| {
+        c.memory_write(0x8000, cmd);
+        c.memory_write(0xa000, data);
+    };
+
+    command(&mut cart, 9, 3);
+    assert_eq!(cart.memory_dump(0x8000), Some(rom[16 + 3 * 8192]));
+    assert_eq!(cart.memory_dump(0xe000), Some(rom[16 + 131072 - 8192]));
+    command(&mut cart, 2, 7);
+    assert_eq!(
+        cart.ppu_peek_1(0x0810).2,
+        Some(rom[16 + 131072 + 7 * 1024 + 0x10])
+    );
+
+    //0x6000 holds either prg rom or prg ram
+    command(&mut cart, 8, 4);
+    assert_eq!(cart.memory_dump(0x6000), Some(rom[16 + 4 * 8192]));
+    command(&mut cart, 8, 0x40);
+    assert_eq!(cart.memory_dump(0x6000), None);
+    command(&mut cart, 8, 0xc0);
+    cart.memory_write(0x6000, 0x21);
+    assert_eq!(cart.memory_dump(0x6000), Some(0x21));
+
+    command(&mut cart, 0xc, 1);
+    assert!(cart.ppu_peek_1(0x2800).0);
+    command(&mut cart, 0xc, 3);
+    assert!(cart.ppu_peek_1(0x2000).0);
+
+    //the irq counter triggers when it wraps from 0 to 0xffff
+    command(&mut cart, 0xe, 1);
+    command(&mut cart, 0xf, 0);
+    command(&mut cart, 0xd, 0x81);
+    cart.cpu_cycle();
+    assert!(!cart.irq());
+    cart.cpu_cycle();
+    assert!(cart.irq());
+    command(&mut cart, 0xd, 0);
+    assert!(!cart.irq());
+
+    //channel a at full volume, with the tone and noise disabled in the mixer
+    let sample = |c: &NesCartridge| {
+        c.audio_sample()
+            .get(crate::cartridge::ExpansionAudioChip::Sunsoft5b)
+    };
+    let audio = |c: &mut NesCartridge, reg: u8, data: u8| {
+        c.memory_write(0xc000, reg);
+        c.memory_write(0xe000, data);
+    };
+    audio(&mut cart, 7, 0x3f);
+    audio(&mut cart, 8, 0x0f);
+    assert!((sample(&cart) - 1.0 / 3.0).abs() < 0.0001);
+    //an attack envelope that stops at the end, clocked every 16 cpu cycles
+    audio(&mut cart, 8, 0x10);
+    audio(&mut cart, 0xb, 1);
+    audio(&mut cart, 0xc, 0);
+    audio(&mut cart, 0xd, 4);
+    assert_eq!(sample(&cart), 0.0);
+    for _ in 0..16 * 31 {
+        cart.clock_audio();
+    }
+    assert!((sample(&cart) - 1.0 / 3.0).abs() < 0.0001);
+    for _ in 0..16 {
+        cart.clock_audio();
+    }
+    assert_eq!(sample(&cart), 0.0);
+}
+
+#[test]
+fn vrc2_vrc4_detection() {
+    //ines 1.0 header, mapper 23, 128kb prg rom, 128kb chr rom, no submapper
+    let header = [
+        b'N', b'E', b'S', 0x1a, 8, 16, 0x70, 0x10, 0, 0, 0, 0, 0, 0, 0, 0,
+    ];
+    let load = || {
+        load_test_rom(
+            "vrc_detect_test.nes",
+            &build_test_rom(header, 131072, 131072, 23),
+        )
+    };
+    //the vrc2 is assumed until a vrc4 register is written
+    let mut cart = load();
+    cart.memory_write(0x9000, 1);
+    assert!(cart.cartridge_registers().contains_key("Microwire"));
+    cart.memory_write(0xf002, 2);
+    let registers = cart.cartridge_registers();
+    assert!(!registers.contains_key("Microwire"));
+    assert_eq!(registers.get("IRQ Control"), Some(&2));
+
+    let mut cart = load();
+    cart.memory_write(0x9002, 2);
+    assert_eq!(cart.cartridge_registers().get("Control"), Some(&2));
+}
+
+#[test]
+fn axrom_single_screen_and_bus_conflicts() {
+    //ines 1.0 header, mapper 7, 128kb prg rom, chr ram
+    let header = [
+        b'N', b'E', b'S', 0x1a, 8, 0, 0x70, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    ];
+    let rom = build_test_rom(header, 131072, 0, 7);
+    let mut cart = load_test_rom("axrom_test.nes", &rom);
+    assert!(!cart.ppu_peek_1(0x2400).0);
+    cart.memory_write(0x8000, 0x12);
+    assert_eq!(cart.memory_dump(0x8000), Some(rom[16 + 2 * 32768]));
+    assert!(cart.ppu_peek_1(0x2000).0);
+    assert!(cart.ppu_peek_1(0x2c00).0);
+
+    //nes 2.0 header, mapper 7 submapper 2 has bus conflicts
+    let header = [
+        b'N', b'E', b'S', 0x1a, 8, 0, 0x70, 0x08, 0x20, 0, 0x07, 0x07, 0, 0, 0, 0,
+    ];
+    let mut cart = load_test_rom("axrom_test.nes", &build_test_rom(header, 131072, 0, 7));
+    cart.rom_byte_hack(0, 0x01);
+    cart.memory_write(0x8000, 0x13);
+    let registers = cart.cartridge_registers();
+    assert_eq!(registers.get("PRG bank"), Some(&1));
+    assert_eq!(registers.get("Nametable"), Some(&0));
+}
+
+#[test]
+fn gxrom_and_color_dreams() {
+    //ines 1.0 header, mapper 66, 128kb prg rom, 32kb chr rom
+    let header = [
+        b'N', b'E', b'S', 0x1a, 8, 4, 0x20, 0x40, 0, 0, 0, 0, 0, 0, 0, 0,
+    ];
+    let rom = build_test_rom(header, 131072, 32768, 66);
+    let mut cart = load_test_rom("gxrom_test.nes", &rom);
+    cart.rom_byte_hack(0, 0xff);
+    cart.memory_write(0x8000, 0x21);
+    assert_eq!(cart.memory_dump(0x8000), Some(rom[16 + 2 * 32768]));
+    assert_eq!(
+        cart.ppu_peek_1(0x0010).2,
+        Some(rom[16 + 131072 + 8192 + 0x10])
+    );
+
+    //ines 1.0 header, mapper 11, 128kb prg rom, 32kb chr rom
+    let header = [
+        b'N', b'E', b'S', 0x1a, 8, 4, 0xb0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    ];
+    let rom = build_test_rom(header, 131072, 32768, 66);
+    let mut cart = load_test_rom("gxrom_test.nes", &rom);
+    cart.rom_byte_hack(0, 0xff);
+    cart.memory_write(0x8000, 0x21);
+    assert_eq!(cart.memory_dump(0x8000), Some(rom[16 + 32768]));
+    assert_eq!(
+        cart.ppu_peek_1(0x0010).2,
+        Some(rom[16 + 131072 + 2 * 8192 + 0x10])
+    );
+    //the value written is combined with the rom contents at the address
+    cart.rom_byte_hack(32768, 0x1f);
+    cart.memory_write(0x8000, 0x33);
+    let registers = cart.cartridge_registers();
+    assert_eq!(registers.get("PRG bank"), Some(&3));
+    assert_eq!(registers.get("CHR bank"), Some(&1));
+}
+
+#[test]
+fn mapper34_boards() {
+    //ines 1.0 header, mapper 34 with 8kb of chr rom is the bnrom
+    let header = [
+        b'N', b'E', b'S', 0x1a, 8, 1, 0x20, 0x20, 0, 0, 0, 0, 0, 0, 0, 0,
+    ];
+    let rom = build_test_rom(header, 131072, 8192, 34);
+    let mut cart = load_test_rom("mapper34_test.nes", &rom);
+    assert_eq!(cart.cartridge_registers().get("NINA-001"), Some(&0));
+    cart.rom_byte_hack(0, 0xff);
+    cart.memory_write(0x8000, 2);
+    assert_eq!(cart.memory_dump(0x8000), Some(rom[16 + 2 * 32768]));
+
+    //ines 1.0 header, mapper 34 with more chr rom is the nina-001
+    let header = [
+        b'N', b'E', b'S', 0x1a, 4, 8, 0x20, 0x20, 0, 0, 0, 0, 0, 0, 0, 0,
+    ];
+    let rom = build_test_rom(header, 65536, 65536, 34);
+    let mut cart = load_test_rom("mapper34_test.nes", &rom);
+    assert_eq!(cart.cartridge_registers().get("NINA-001"), Some(&1));
+    cart.memory_write(0x7ffd, 1);
+    cart.memory_write(0x7ffe, 2);
+    cart.memory_write(0x7fff, 3);
+    assert_eq!(cart.memory_dump(0x7ffd), Some(1));
+    assert_eq!(cart.memory_dump(0x8000), Some(rom[16 + 32768]));
+    assert_eq!(
+        cart.ppu_peek_1(0x0010).2,
+        Some(rom[16 + 65536 + 2 * 4096 + 0x10])
+    );
+    assert_eq!(
+        cart.ppu_peek_1(0x1010).2,
+        Some(rom[16 + 65536 + 3 * 4096 + 0x10])
+    );
+
+    //nes 2.0 header, submapper 2 selects the bnrom regardless of the chr rom size
+    let header = [
+        b'N', b'E', b'S', 0x1a, 4, 8, 0x20, 0x28, 0x20, 0, 0x07, 0, 0, 0, 0, 0,
+    ];
+    let cart = load_test_rom(
+        "mapper34_test.nes",
+        &build_test_rom(header, 65536, 65536, 34),
+    );
+    assert_eq!(cart.cartridge_registers().get("NINA-001"), Some(&0));
+}
+
+#[test]
+fn camerica_fire_hawk_mirroring() {
+    //ines 1.0 header, mapper 71, vertical mirroring, 128kb prg rom, chr ram
+    let header = [
+        b'N', b'E', b'S', 0x1a, 8, 0, 0x71, 0x40, 0, 0, 0, 0, 0, 0, 0, 0,
+    ];
+    let rom = build_test_rom(header, 131072, 0, 71);
+    let mut cart = load_test_rom("camerica_test.nes", &rom);
+    cart.memory_write(0xc000, 3);
+    assert_eq!(cart.memory_dump(0x8000), Some(rom[16 + 3 * 16384]));
+    assert!(cart.ppu_peek_1(0x2400).0);
+    assert!(!cart.ppu_peek_1(0x2800).0);
+    //fire hawk is detected by the first write to the mirroring register
+    cart.memory_write(0x9000, 0x10);
+    assert!(cart.ppu_peek_1(0x2000).0);
+    assert!(cart.ppu_peek_1(0x2800).0);
+    cart.memory_write(0x9000, 0);
+    assert!(!cart.ppu_peek_1(0x2400).0);
+
+    //nes 2.0 header, submapper 1 has the mirroring register at 0x8000-0x9fff
+    let header = [
+        b'N', b'E', b'S', 0x1a, 8, 0, 0x71, 0x48, 0x10, 0, 0, 0x07, 0, 0, 0, 0,
+    ];
+    let mut cart = load_test_rom("camerica_test.nes", &build_test_rom(header, 131072, 0, 71));
+    assert!(!cart.ppu_peek_1(0x2400).0);
+    cart.memory_write(0x8000, 0x10);
+    assert!(cart.ppu_peek_1(0x2400).0);
+    assert_eq!(cart.cartridge_registers().get("Nametable"), Some(&1));
+}
+
+/// Build a famicom disk system image with a single side holding one 4 byte file.
+/// The qd format follows each block with two crc bytes and has no header.
+fn build_test_disk(qd: bool) -> Vec<u8> {
+    let mut info = vec![0; 56];
+    info[0] = 1;
+    info[1..15].copy_from_slice(b"*NINTENDO-HVC*");
+    let mut file_header = vec![0; 16];
+    file_header[0] = 3;
+    file_header[3..11].copy_from_slice(b"TESTFILE");
+    file_header[12] = 0x60;
+    file_header[13] = 4;
+    let blocks = [
+        info,
+        vec![2, 1],
+        file_header,
+        vec![4, 0x11, 0x22, 0x33, 0x44],
+    ];
+    let mut side = Vec::new();
+    for b in blocks {
+        side.extend_from_slice(&b);
+        if qd {
+            side.extend_from_slice(&[0, 0]);
+        }
+    }
+    if qd {
+        side.resize(65536, 0);
+        side
+    } else {
+        side.resize(65500, 0);
+        let mut image = vec![b'F', b'D', b'S', 0x1a, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        image.extend_from_slice(&side);
+        image
+    }
+}
+
+/// Run the disk drive until it transfers a byte, returning the byte it read from the disk
+fn fds_next_byte(cart: &mut NesCartridge) -> u8 {
+    for _ in 0..1000000 {
+        cart.cpu_cycle();
+        if (cart.memory_dump(0x4030).unwrap() & 2) != 0 {
+            return cart.memory_read(0x4031).unwrap();
+        }
+    }
+    panic!("The disk drive did not transfer a byte");
+}
+
+#[test]
+fn fds_disk_parsing() {
+    let fds = load_test_rom("fds_parse_test.fds", &build_test_disk(false));
+    let qd = load_test_rom("fds_parse_test.qd", &build_test_disk(true));
+    assert!(fds.is_disk_system());
+    assert_eq!(fds.disk_sides(), 1);
+    assert_eq!(fds.inserted_disk(), Some(0));
+    //the qd image has the same blocks once the crc bytes are removed
+    assert_eq!(
+        fds.cartridge().nonvolatile.disk_sides,
+        qd.cartridge().nonvolatile.disk_sides
+    );
+    assert_eq!(fds.cartridge().nonvolatile.disk_sides[0].len(), 65500);
+    //the drive sees a gap before each block, ended by 0x80
+    let raw = &fds.cartridge().volatile.disk[0];
+    let first = raw.iter().position(|b| *b != 0).unwrap();
+    assert!(first > 3000);
+    assert_eq!(raw[first], 0x80);
+    assert_eq!(&raw[first + 1..first + 15], b"\x01*NINTENDO-HVC");
+    let path = write_test_file("fds_short_test.fds", b"FDS\x1a");
+    assert!(NesCartridge::load_cartridge(path.display().to_string(), &test_temp_dir()).is_err());
+}
+
+#[test]
+fn fds_drive_write_and_flush() {
+    let name = "fds_drive_test.fds".to_string();
+    let patch = test_temp_dir().join(format!("{}.ips", name));
+    let _ = std::fs::remove_file(&patch);
+    let mut cart = load_test_rom(&name, &build_test_disk(false));
+    cart.memory_write(0x4023, 1);
+    //motor on, read mode, ready
+    cart.memory_write(0x4025, 0x45);
+    //the gap end marker sets the transfer flag without an irq
+    assert_eq!(fds_next_byte(&mut cart), 0x80);
+    assert_eq!(fds_next_byte(&mut cart), 1);
+    for b in b"*NINTENDO-HVC*" {
+        assert_eq!(fds_next_byte(&mut cart), *b);
+    }
+    //read up to the start of the file data, then write over the file
+    let mut previous = 0;
+    loop {
+        let b = fds_next_byte(&mut cart);
+        if previous == 0x80 && b == 4 {
+            break;
+        }
+        previous = b;
+    }
+    cart.memory_write(0x4024, 0xaa);
+    cart.memory_write(0x4025, 0x41);
+    for d in [0xbb, 0xcc, 0xdd] {
+        fds_next_byte(&mut cart);
+        cart.memory_write(0x4024, d);
+    }
+    fds_next_byte(&mut cart);
+    cart.memory_write(0x4025, 0x45);
+
+    //the changes are only saved when asked for
+    assert!(cart.disk_modified());
+    for _ in 0..1000 {
+        cart.cpu_cycle();
+    }
+    assert!(!patch.exists());
+    cart.eject_disk().unwrap();
+    assert!(!cart.disk_modified());
+    assert_eq!(cart.inserted_disk(), None);
+    assert!(patch.exists());
+
+    //the saved changes are applied when the disk is loaded again
+    let cart = load_test_rom(&name, &build_test_disk(false));
+    let raw = &cart.cartridge().volatile.disk[0];
+    let file = raw.windows(2).position(|w| w == [0x80, 4]).unwrap() + 2;
+    assert_eq!(raw[file..file + 4], [0xaa, 0xbb, 0xcc, 0xdd]);
+    assert_eq!(
+        cart.cartridge().nonvolatile.disk_sides,
+        load_test_rom("fds_parse_test.fds", &build_test_disk(false))
+            .cartridge()
+            .nonvolatile
+            .disk_sides
+    );
+    let _ = std::fs::remove_file(patch);
+}
+
+#[test]
+fn fds_audio() {
+    let mut cart = load_test_rom("fds_audio_test.fds", &build_test_disk(false));
+    cart.memory_write(0x4023, 2);
+    //a square wave in the wavetable, which can only be written while bit 7 of 0x4089 is set
+    cart.memory_write(0x4089, 0x80);
+    for i in 0..64 {
+        cart.memory_write(0x4040 + i, if i < 32 { 63 } else { 0 });
+    }
+    cart.memory_write(0x4089, 0);
+    cart.memory_write(0x4040, 0);
+    assert_eq!(cart.memory_dump(0x4040), Some(63 | 0x40));
+    //full volume in direct mode
+    cart.memory_write(0x4080, 0x80 | 32);
+    assert_eq!(cart.memory_dump(0x4090), Some(32 | 0x40));
+    //a frequency of 0x800 plays the 64 entries of the wave in 2048 cycles
+    cart.memory_write(0x4082, 0);
+    cart.memory_write(0x4083, 8);
+    let sample = |c: &NesCartridge| {
+        c.audio_sample()
+            .get(crate::cartridge::ExpansionAudioChip::Fds)
+    };
+    let mut levels = Vec::new();
+    for _ in 0..4096 {
+        cart.clock_audio();
+        levels.push(sample(&cart));
+    }
+    assert_eq!(levels.iter().cloned().fold(f32::MAX, f32::min), 0.0);
+    assert_eq!(levels.iter().cloned().fold(f32::MIN, f32::max), 1.0);
+    //the output changes twice each cycle of the wave
+    let changes = levels.windows(2).filter(|w| w[0] != w[1]).count();
+    assert!((3..=5).contains(&changes));
+    //the master volume scales the output
+    cart.memory_write(0x4089, 1);
+    cart.memory_write(0x4083, 0x80);
+    cart.clock_audio();
+    assert!((sample(&cart) - 2.0 / 3.0).abs() < 0.0001);
+}
+
+#[test]
+fn nes2_header_decoding() {
+    //vs system, pal, 8kb of battery backed prg ram, one miscellaneous rom, and an expansion device
+    let header = [
+        b'N', b'E', b'S', 0x1a, 2, 1, 0x02, 0x09, 0, 0, 0x70, 0, 1, 0x21, 1, 0x41,
+    ];
+    let mut rom = build_test_rom(header, 32768, 8192, 11);
+    rom.extend_from_slice(&[1, 2, 3, 4]);
+    let cart = load_test_rom("nes2_header_test.nes", &rom);
+    let h = cart.header();
+    assert_eq!(h.prg_ram_size, 0);
+    assert_eq!(h.prg_nvram_size, 8192);
+    assert_eq!(h.chr_ram_size, 0);
+    assert_eq!(h.chr_nvram_size, 0);
+    assert_eq!(h.timing, crate::cartridge::CpuTiming::Pal);
+    assert_eq!(
+        h.console,
+        crate::cartridge::ConsoleType::VsSystem {
+            ppu: 1,
+            hardware: 2
+        }
+    );
+    assert_eq!(h.misc_roms, 1);
+    assert_eq!(h.expansion_device, 1);
+    assert_eq!(cart.cartridge().nonvolatile.misc_rom, vec![1, 2, 3, 4]);
+
+    //extended console type, dendy, chr ram only, and a submapper
+    let header = [
+        b'N', b'E', b'S', 0x1a, 2, 0, 0x20, 0x0b, 0x20, 0, 0x07, 0x97, 3, 0x04, 0, 0,
+    ];
+    let rom = build_test_rom(header, 32768, 0, 11);
+    let cart = load_test_rom("nes2_header_test.nes", &rom);
+    assert_eq!(cart.submapper(), 2);
+    let h = cart.header();
+    assert_eq!(h.prg_ram_size, 8192);
+    assert_eq!(h.prg_nvram_size, 0);
+    assert_eq!(h.chr_ram_size, 8192);
+    assert_eq!(h.chr_nvram_size, 32768);
+    assert_eq!(h.timing, crate::cartridge::CpuTiming::Dendy);
+    assert_eq!(h.console, crate::cartridge::ConsoleType::Extended(4));
+    assert_eq!(h.misc_roms, 0);
+    assert_eq!(cart.cartridge().volatile.chr_ram.len(), 8192 + 32768);
+
+    //the exponent form of the prg rom size, 2^14 * 1
+    let header = [
+        b'N', b'E', b'S', 0x1a, 0x38, 1, 0, 0x08, 0, 0x0f, 0, 0, 2, 0, 0, 0,
+    ];
+    let rom = build_test_rom(header, 16384, 8192, 11);
+    let cart = load_test_rom("nes2_header_test.nes", &rom);
+    assert_eq!(cart.cartridge().nonvolatile.prg_rom.len(), 16384);
+    assert_eq!(
+        cart.header().timing,
+        crate::cartridge::CpuTiming::MultipleRegion
+    );
+    assert_eq!(cart.header().console, crate::cartridge::ConsoleType::Nes);
+}
+
+#[test]
+fn rom_database_correction() {
+    //ines 1.0 header, mapper 0, horizontal mirroring, no prg ram size given
+    let header = [b'N', b'E', b'S', 0x1a, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    let rom = build_test_rom(header, 16384, 8192, 37);
+    let data = &rom[16..];
+    let sha1 = data_encoding::HEXUPPER
+        .encode(ring::digest::digest(&ring::digest::SHA1_FOR_LEGACY_USE_ONLY, data).as_ref());
+    let sha256 =
+        data_encoding::HEXLOWER.encode(ring::digest::digest(&ring::digest::SHA256, data).as_ref());
+    let save_path = test_temp_dir();
+    let load = |db: &crate::cartridge::RomDatabase| {
+        NesCartridge::load_cartridge_contents_with_database(
+            "database_test.nes".to_string(),
+            rom.clone(),
+            &save_path,
+            Some(db),
+        )
+        .unwrap()
+    };
+
+    //the nes 2.0 xml database lists every ram on the board
+    let xml = format!(
+        "<nes20db>\n<game>\n\t<!-- Test\\database test.nes -->\n\t<prgrom size=\"16384\"/>\n\t<rom size=\"24576\" sha1=\"{}\"/>\n\t<pcb mapper=\"0\" submapper=\"0\" mirroring=\"V\" battery=\"0\"/>\n\t<console type=\"0\" region=\"1\"/>\n</game>\n</nes20db>\n",
+        sha1
+    );
+    let db_path = write_test_file("database_test.xml", xml.as_bytes());
+    let db = crate::cartridge::RomDatabase::load(&db_path).unwrap();
+    assert_eq!(db.len(), 1);
+    let cart = load(&db);
+    let correction = cart.header_correction().unwrap();
+    assert!(correction.starts_with("database test.nes"));
+    assert!(correction.contains("mirroring"));
+    assert_eq!(cart.header().prg_ram_size, 0);
+    assert_eq!(cart.header().timing, crate::cartridge::CpuTiming::Pal);
+
+    //ram sizes that are left out of a toml database keep the value from the header
+    let toml = format!(
+        "[[game]]\nname = \"database test\"\nhash = \"{}\"\nmapper = 0\nmirroring = \"V\"\n",
+        sha256
+    );
+    let db_path2 = write_test_file("database_test.toml", toml.as_bytes());
+    let db = crate::cartridge::RomDatabase::load(&db_path2).unwrap();
+    assert_eq!(db.len(), 1);
+    let cart = load(&db);
+    assert!(cart.header_correction().unwrap().contains("mirroring"));
+    assert_eq!(cart.header().prg_ram_size, 8192);
+
+    //without a database the header is kept
+    let cart = NesCartridge::load_cartridge_contents_with_database(
+        "database_test.nes".to_string(),
+        rom.clone(),
+        &save_path,
+        None,
+    )
+    .unwrap();
+    assert!(cart.header_correction().is_none());
+    assert_eq!(cart.header().timing, crate::cartridge::CpuTiming::Ntsc);
+
+    let _ = std::fs::remove_file(db_path);
+    let _ = std::fs::remove_file(db_path2);
+}
+
+#[test]
+fn rom_huge_exponent_size() {
+    //nes 2.0 header using the exponent form for prg rom with the largest exponent and multiplier
+    let header = [
+        b'N', b'E', b'S', 0x1a, 0xff, 1, 0, 8, 0, 0x0f, 0, 0, 0, 0, 0, 0,
+    ];
+    let rom = build_test_rom(header, 16384, 8192, 41);
+    let rom_path = write_test_file("huge_size_test.nes", &rom);
+    let cart = NesCartridge::load_cartridge(rom_path.display().to_string(), &test_temp_dir());
+    assert!(cart.is_err());
+    let _ = std::fs::remove_file(rom_path);
+}
+
+/// Build a unif rom for a board with 32kb of prg rom and 8kb of chr rom, with an optional MIRR chunk
+fn build_test_unif(board: &str, mirr: Option<u8>) -> Vec<u8> {
+    let mut rom = b"UNIF".to_vec();
+    rom.extend_from_slice(&7u32.to_le_bytes());
+    rom.resize(32, 0);
+    let mut chunk = |id: &[u8], data: &[u8]| {
+        rom.extend_from_slice(id);
+        rom.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        rom.extend_from_slice(data);
+    };
+    let mut name = board.as_bytes().to_vec();
+    name.push(0);
+    chunk(b"MAPR", &name);
+    if let Some(m) = mirr {
+        chunk(b"MIRR", &[m]);
+    }
+    chunk(b"PRG0", &[0xea; 32768]);
+    chunk(b"CHR0", &[0; 8192]);
+    rom
+}
+
+#[test]
+fn unif_board_ram_and_nametables() {
+    let load = |board: &str, mirr: Option<u8>| {
+        load_test_rom("unif_test.unf", &build_test_unif(board, mirr))
+    };
+    //the prg ram depends on the board
+    assert_eq!(load("NES-NROM-256", None).header().prg_ram_size, 0);
+    assert_eq!(load("NES-SKROM", None).header().prg_ram_size, 8192);
+    assert_eq!(load("NES-SXROM", None).header().prg_ram_size, 32768);
+
+    //single screen nametables use one page of the console vram
+    let mut cart = load("NES-NROM-256", Some(2));
+    assert_eq!(cart.ppu_cycle_1(0x2c00), (false, false));
+    let mut cart2 = load("NES-NROM-256", Some(3));
+    assert_eq!(cart2.ppu_cycle_1(0x2000), (true, false));
+    assert_eq!(cart.ppu_cycle_1(0x0000), (false, false));
+
+    //four screen nametables put the second two nametables on the cartridge
+    let mut cart = load("NES-NROM-256", Some(4));
+    assert_eq!(cart.ppu_cycle_1(0x2400), (true, false));
+    assert_eq!(cart.ppu_cycle_1(0x2812), (false, true));
+    cart.ppu_cycle_write(0x55);
+    assert_eq!(cart.ppu_cycle_1(0x2c12), (false, true));
+    cart.ppu_cycle_write(0xaa);
+    let _ = cart.ppu_cycle_1(0x2812);
+    assert_eq!(cart.ppu_cycle_read(), 0x55);
+    assert_eq!(cart.ppu_peek_1(0x2c12).2, Some(0xaa));
+}
+
+#[test]
+fn ppu_emphasis_and_grayscale() {
+    let mut nes_data = NesEmulatorData::new(None);
+    let nc = NesCartridge::load_cartridge(
+        "../test_roms/vbl_nmi_timing/1.frame_basics.nes".to_string(),
+        &nes_data.local.save_path(),
+    )
+    .unwrap();
+    nes_data.insert_cartridge(nc);
+
+    //the ppu ignores register writes until it has warmed up
+    loop {
+        nes_data.cycle_step(&mut Vec::new(), &mut Vec::new(), &mut None);
+        if nes_data.cpu_peripherals.ppu_frame_end()
+            && nes_data.cpu_peripherals.ppu_frame_number() == 2
+        {
+            break;
+        }
+    }
+    //write the palette through the ppu registers, with rendering disabled
+    nes_data
+        .mb
+        .memory_cycle_write(0x2001, 0, [false; 2], &mut nes_data.cpu_peripherals);
+    for (i, color) in [0x16, 0x2a, 0x10, 0x20].iter().enumerate() {
+        for (addr, data) in [(0x2006, 0x3f), (0x2006, i as u8), (0x2007, *color)] {
+            nes_data
+                .mb
+                .memory_cycle_write(addr, data, [false; 2], &mut nes_data.cpu_peripherals);
+        }
+    }
+
+    let mut scratch = [0; 32];
+    let mut render = |nes_data: &mut NesEmulatorData, mask: u8| {
+        nes_data.cpu_peripherals.ppu.write(1, mask, &mut scratch);
+        let mut buf = Box::new(common_emulator::video::RgbImage::new(16, 2));
+        nes_data
+            .cpu_peripherals
+            .ppu
+            .render_palette(&mut buf, &nes_data.mb);
+        let mut colors = [[0; 3]; 4];
+        for (i, c) in colors.iter_mut().enumerate() {
+            c.copy_from_slice(&buf.data[i * 3..i * 3 + 3]);
+        }
+        colors
+    };
+
+    let plain = render(&mut nes_data, 0);
+    assert_ne!(plain[0], plain[2]);
+
+    //grayscale keeps only the brightness of the color
+    let gray = render(&mut nes_data, 0x01);
+    assert_eq!(gray[0], plain[2]);
+    assert_eq!(gray[1], plain[3]);
+
+    //emphasizing red darkens green and blue
+    let red = render(&mut nes_data, 0x20);
+    assert_eq!(red[0][0], plain[0][0]);
+    assert!(red[0][1] < plain[0][1]);
+    assert!(red[0][2] < plain[0][2]);
+
+    //emphasizing all three darkens every channel
+    let all = render(&mut nes_data, 0xe0);
+    for c in 0..3 {
+        assert!(all[0][c] < plain[0][c]);
+    }
+
+    //the pal ppu swaps the red and green emphasis bits
+    nes_data
+        .cpu_peripherals
+        .ppu
+        .set_region(crate::region::Region::Pal);
+    let green = render(&mut nes_data, 0x20);
+    assert!(green[1][0] < plain[1][0]);
+    assert_eq!(green[1][1], plain[1][1]);
+    assert!(green[1][2] < plain[1][2]);
+}
+
+#[test]
+fn palette_files() {
+    use crate::ppu::palette::NesPalette;
+    //the built in palette calculates the emphasized colors
+    let builtin = NesPalette::default();
+    assert_eq!(builtin.color(0x16, 0), [152, 34, 32]);
+    assert_eq!(builtin.color(0x16, 1), [152, 27, 26]);
+
+    //an exported palette has all 512 colors and loads back the same
+    let path = test_temp_dir().join("palette_test.pal");
+    builtin.save(&path).unwrap();
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), 512 * 3);
+    let loaded = NesPalette::load(&path).unwrap();
+    for emphasis in 0..8 {
+        for entry in 0..64 {
+            assert_eq!(
+                loaded.color(entry, emphasis),
+                builtin.color(entry, emphasis)
+            );
+        }
+    }
+
+    //a palette with 64 colors gets the emphasized colors calculated
+    let colors: Vec<u8> = (0..64u8).flat_map(|i| [i * 4, 200, 100]).collect();
+    std::fs::write(&path, colors).unwrap();
+    let loaded = NesPalette::load(&path).unwrap();
+    assert_eq!(loaded.color(0x10, 0), [64, 200, 100]);
+    assert_eq!(loaded.color(0x10, 2), [52, 200, 81]);
+
+    //other sizes are rejected
+    std::fs::write(&path, [0; 100]).unwrap();
+    assert_eq!(
+        NesPalette::load(&path).err().map(|e| e.kind()),
+        Some(std::io::ErrorKind::InvalidData)
+    );
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn palette_ntsc_generation() {
+    use crate::ppu::palette::{NesPalette, NtscPaletteSettings};
+    let close = |a: [u8; 3], b: [u8; 3]| (0..3).all(|i| a[i].abs_diff(b[i]) <= 1);
+    let gray = |c: [u8; 3]| close(c, [c[0]; 3]);
+
+    let settings = NtscPaletteSettings::default();
+    let palette = NesPalette::generate_ntsc(&settings);
+    assert_eq!(palette.color(0x0f, 0), [0, 0, 0]);
+    assert_eq!(palette.color(0x0d, 0), [0, 0, 0]);
+    assert!(palette.color(0x20, 0).iter().all(|c| *c >= 250));
+    assert!(gray(palette.color(0x00, 0)));
+    //the hues of the reds, greens, and blues
+    let red = palette.color(0x16, 0);
+    assert!(red[0] > red[1] && red[0] > red[2]);
+    let green = palette.color(0x1a, 0);
+    assert!(green[1] > green[0] && green[1] > green[2]);
+    let blue = palette.color(0x12, 0);
+    assert!(blue[2] > blue[0] && blue[2] > blue[1]);
+    //emphasizing all three colors darkens white to a gray
+    let dark = palette.color(0x20, 7);
+    assert!(gray(dark) && dark[0] < 200);
+
+    //without saturation every color is a gray
+    let settings = NtscPaletteSettings {
+        saturation: 0.0,
+        ..Default::default()
+    };
+    let palette = NesPalette::generate_ntsc(&settings);
+    assert!(gray(palette.color(0x16, 0)));
+
+    let settings = NtscPaletteSettings {
+        brightness: 0.1,
+        ..Default::default()
+    };
+    let brighter = NesPalette::generate_ntsc(&settings);
+    assert!(
+        brighter.color(0x00, 0)[0]
+            > NesPalette::generate_ntsc(&Default::default()).color(0x00, 0)[0]
+    );
+}
+
+#[test]
+fn ppu_unlimited_sprites_overflow() {
+    //the sprite overflow flag behaves the same when the sprite limit is removed
+    for (rom, frames) in [
+        ("1.Basics.nes", 30),
+        ("2.Details.nes", 30),
+        ("3.Timing.nes", 150),
+        ("4.Obscure.nes", 30),
+        ("5.Emulator.nes", 30),
+    ] {
+        let mut nes_data = NesEmulatorData::new(None);
+        nes_data.cpu_peripherals.ppu.set_unlimited_sprites(true);
+        let nc = NesCartridge::load_cartridge(
+            format!("../test_roms/sprite_overflow_tests/{}", rom),
+            &nes_data.local.save_path(),
+        )
+        .unwrap();
+        nes_data.insert_cartridge(nc);
+
+        loop {
+            nes_data.cycle_step(&mut Vec::new(), &mut Vec::new(), &mut None);
+            if nes_data.cpu_peripherals.ppu_frame_end()
+                && nes_data.cpu_peripherals.ppu_frame_number() == frames
+            {
+                break;
+            }
+        }
+        println!("Testing {}", rom);
+        assert!(nes_data.mb.check_vram(194, "PASSED".to_string().as_bytes()));
+    }
+}
+
+#[test]
+fn overscan_cropping() {
+    let image: Vec<u16> = (0..12).collect();
+    let overscan = common_emulator::video::Overscan {
+        top: 1,
+        bottom: 0,
+        left: 1,
+        right: 1,
+    };
+    assert_eq!(overscan.crop(&image, 4, 3), (vec![5, 6, 9, 10], 2, 2));
+    //at least one pixel is always kept
+    let overscan = common_emulator::video::Overscan {
+        top: 200,
+        bottom: 200,
+        left: 200,
+        right: 200,
+    };
+    assert_eq!(overscan.crop(&image, 4, 3), (vec![11], 1, 1));
+
+    let mut nes_data = NesEmulatorData::new(None);
+    let nc = NesCartridge::load_cartridge(
+        "../test_roms/vbl_nmi_timing/1.frame_basics.nes".to_string(),
+        &nes_data.local.save_path(),
+    )
+    .unwrap();
+    nes_data.insert_cartridge(nc);
+    loop {
+        nes_data.cycle_step(&mut Vec::new(), &mut Vec::new(), &mut None);
+        if nes_data.cpu_peripherals.ppu_frame_end()
+            && nes_data.cpu_peripherals.ppu_frame_number() == 2
+        {
+            break;
+        }
+    }
+    nes_data.local.configuration.scaler = None;
+    nes_data.local.configuration.overscan = common_emulator::video::Overscan {
+        top: 8,
+        bottom: 8,
+        left: 4,
+        right: 0,
+    };
+    let frame = nes_data.scaled_frame();
+    assert_eq!((frame.width, frame.height), (252, 224));
+}
+
+#[test]
+fn screenshot() {
+    let mut nes_data = NesEmulatorData::new(None);
+    let nc = NesCartridge::load_cartridge(
+        "../test_roms/vbl_nmi_timing/1.frame_basics.nes".to_string(),
+        &nes_data.local.save_path(),
+    )
+    .unwrap();
+    let hash = nc.hash();
+    nes_data.insert_cartridge(nc);
+
+    loop {
+        nes_data.cycle_step(&mut Vec::new(), &mut Vec::new(), &mut None);
+        if nes_data.cpu_peripherals.ppu_frame_end()
+            && nes_data.cpu_peripherals.ppu_frame_number() == 10
+        {
+            break;
+        }
+    }
+    let path = test_temp_dir().join("screenshot_test.png");
+    nes_data.save_screenshot(&path, false).unwrap();
+
+    let decoder = png::Decoder::new(std::fs::File::open(&path).unwrap());
+    let reader = decoder.read_info().unwrap();
+    let info = reader.info();
+    assert_eq!((info.width, info.height), (256, 240));
+    let text: Vec<(String, String)> = info
+        .uncompressed_latin1_text
+        .iter()
+        .map(|t| (t.keyword.clone(), t.text.clone()))
+        .collect();
+    assert!(text.contains(&("Rom hash".to_string(), hash)));
+    assert!(text.contains(&("Frame".to_string(), "10".to_string())));
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn ppu_editing() {
+    let mut nes_data = NesEmulatorData::new(None);
+    let nc = NesCartridge::load_cartridge(
+        "../test_roms/vbl_nmi_timing/1.frame_basics.nes".to_string(),
+        &nes_data.local.save_path(),
+    )
+    .unwrap();
+    nes_data.insert_cartridge(nc);
+
+    nes_data.mb.ppu_palette_write(0x3f05, 0x2a);
+    assert_eq!(nes_data.mb.ppu_palette_read(0x3f05), 0x2a);
+    //the background color of the sprite palettes is shared with the background palettes
+    nes_data.mb.ppu_palette_write(0x3f14, 0x16);
+    assert_eq!(nes_data.mb.ppu_palette_read(0x3f04), 0x16);
+
+    for (i, v) in [0x40, 0x12, 0xc3, 0x80].iter().enumerate() {
+        nes_data.cpu_peripherals.ppu.set_oam(20 + i as u8, *v);
+    }
+    let sprite = nes_data.cpu_peripherals.ppu.get_64_sprites()[5];
+    assert_eq!(sprite.y(), 0x40);
+    assert_eq!(sprite.attribute(), 0xc3);
+    assert_eq!(sprite.x(), 0x80);
+}
@@ -1,5 +1,6 @@
 //! This modules contains the window for editing controller properties
 
+use crate::ppu::palette::{NtscPaletteSettings, PaletteSource};
 use crate::NesEmulatorData;
 use strum::IntoEnumIterator;
 
@@ -19,6 +20,14 @@ use crate::egui_multiwin_dynamic::{
 enum Message {
     ///A path has been selected for roms
     NewRomPath(std::path::PathBuf),
+    ///A file has been selected for the fds bios
+    NewFdsBios(std::path::PathBuf),
+    ///A file has been selected for the palette
+    NewPalette(std::path::PathBuf),
+    ///A path has been selected for hd packs
+    NewHdPackPath(std::path::PathBuf),
+    ///A file has been selected for the rom database
+    NewRomDatabase(std::path::PathBuf),
 }
 
 /// The window for dumping ppu nametable data
@@ -83,6 +92,23 @@ impl TrackedWindow for Window {
                     Message::NewRomPath(pb) => {
                         c.local.configuration.set_rom_path(pb);
                     }
+                    Message::NewFdsBios(pb) => {
+                        c.local.configuration.set_fds_bios(pb);
+                    }
+                    Message::NewPalette(pb) => {
+                        c.local.configuration.palette =
+                            PaletteSource::File(pb.display().to_string());
+                        c.update_palette();
+                        save_config = true;
+                    }
+                    Message::NewHdPackPath(pb) => {
+                        c.local.configuration.set_hd_pack_path(Some(pb));
+                        c.update_hd_pack();
+                    }
+                    Message::NewRomDatabase(pb) => {
+                        c.local.configuration.set_rom_database(Some(pb));
+                        c.local.configuration.update_rom_database();
+                    }
                 }
             }
 
@@ -122,6 +148,139 @@ impl TrackedWindow for Window {
                 ));
             }
 
+            ui.label("Overscan:");
+            let mut overscan = c.local.configuration.overscan;
+            if !c.local.resolution_locked {
+                ui.add(egui::Slider::new(&mut overscan.top, 0..=64).text("Top"));
+                ui.add(egui::Slider::new(&mut overscan.bottom, 0..=64).text("Bottom"));
+                ui.add(egui::Slider::new(&mut overscan.left, 0..=64).text("Left"));
+                ui.add(egui::Slider::new(&mut overscan.right, 0..=64).text("Right"));
+                if overscan != c.local.configuration.overscan {
+                    c.local.configuration.overscan = overscan;
+                    save_config = true;
+                }
+            } else {
+                ui.label(format!(
+                    "Top {}, Bottom {}, Left {}, Right {}",
+                    overscan.top, overscan.bottom, overscan.left, overscan.right
+                ));
+            }
+
+            if ui
+                .checkbox(
+                    &mut c.local.configuration.unlimited_sprites,
+                    "Remove sprite limit",
+                )
+                .changed()
+            {
+                c.cpu_peripherals
+                    .ppu
+                    .set_unlimited_sprites(c.local.configuration.unlimited_sprites);
+                save_config = true;
+            }
+
+            if ui
+                .checkbox(
+                    &mut c.local.configuration.scaled_screenshots,
+                    "Scale screenshots",
+                )
+                .changed()
+            {
+                save_config = true;
+            }
+
+            let mut region = c.local.configuration.region;
+            egui::ComboBox::from_label("Region")
+                .selected_text(
+                    region
+                        .map(|r| r.to_string())
+                        .unwrap_or("Automatic".to_string()),
+                )
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut region, None, "Automatic");
+                    for opt in crate::region::Region::iter() {
+                        ui.selectable_value(&mut region, Some(opt), opt.to_string());
+                    }
+                });
+            if region != c.local.configuration.region {
+                c.local.configuration.region = region;
+                c.update_region();
+                save_config = true;
+            }
+
+            ui.label("Palette:");
+            let mut palette = c.local.configuration.palette.clone();
+            ui.horizontal(|ui| {
+                if ui
+                    .radio(palette == PaletteSource::Builtin, "Built in")
+                    .clicked()
+                {
+                    palette = PaletteSource::Builtin;
+                }
+                if ui
+                    .radio(matches!(palette, PaletteSource::Ntsc(_)), "Generated")
+                    .clicked()
+                    && !matches!(palette, PaletteSource::Ntsc(_))
+                {
+                    palette = PaletteSource::Ntsc(NtscPaletteSettings::default());
+                }
+                if ui
+                    .radio(matches!(palette, PaletteSource::File(_)), "File")
+                    .clicked()
+                {
+                    let f = rfd::AsyncFileDialog::new()
+                        .set_title("Select palette")
+                        .add_filter("Palette", &["pal"])
+                        .set_directory(c.local.default_rom_path())
+                        .pick_file();
+                    let message_sender = self.message_channel.0.clone();
+                    crate::execute(async move {
+                        let file = f.await;
+                        if let Some(file) = file {
+                            let fname = file.path().to_path_buf();
+                            message_sender.send(Message::NewPalette(fname)).ok();
+                        }
+                    });
+                }
+            });
+            match &mut palette {
+                PaletteSource::Builtin => {}
+                PaletteSource::File(f) => {
+                    ui.label(f.as_str());
+                }
+                PaletteSource::Ntsc(settings) => {
+                    ui.add(egui::Slider::new(&mut settings.hue, -180.0..=180.0).text("Hue"));
+                    ui.add(
+                        egui::Slider::new(&mut settings.saturation, 0.0..=2.0).text("Saturation"),
+                    );
+                    ui.add(egui::Slider::new(&mut settings.contrast, 0.0..=2.0).text("Contrast"));
+                    ui.add(
+                        egui::Slider::new(&mut settings.brightness, -1.0..=1.0).text("Brightness"),
+                    );
+                }
+            }
+            if palette != c.local.configuration.palette {
+                c.local.configuration.palette = palette;
+                c.update_palette();
+                save_config = true;
+            }
+            if ui.button("Export palette").clicked() {
+                let f = rfd::AsyncFileDialog::new()
+                    .set_title("Export palette")
+                    .add_filter("Palette", &["pal"])
+                    .set_directory(c.local.default_rom_path())
+                    .save_file();
+                let palette = c.local.palette.clone();
+                crate::execute(async move {
+                    let file = f.await;
+                    if let Some(file) = file {
+                        if let Err(e) = palette.save(file.path()) {
+                            println!("Failed to export the palette: {}", e);
+                        }
+                    }
+                });
+            }
+
             ui.label("Folder for roms:");
             if ui
                 .add(
@@ -144,6 +303,94 @@ impl TrackedWindow for Window {
                 });
             }
 
+            ui.label("FDS bios:");
+            let bios = c
+                .local
+                .configuration
+                .fds_bios()
+                .map(|p| p.display().to_string())
+                .unwrap_or("None".to_string());
+            if ui
+                .add(egui::Label::new(bios).sense(egui::Sense::click()))
+                .clicked()
+            {
+                let f = rfd::AsyncFileDialog::new()
+                    .set_title("Select fds bios")
+                    .set_directory(c.local.default_rom_path())
+                    .pick_file();
+                let message_sender = self.message_channel.0.clone();
+                crate::execute(async move {
+                    let file = f.await;
+                    if let Some(file) = file {
+                        let fname = file.path().to_path_buf();
+                        message_sender.send(Message::NewFdsBios(fname)).ok();
+                    }
+                });
+            }
+
+            ui.label("HD packs folder:");
+            let hd_packs = c
+                .local
+                .configuration
+                .hd_pack_path()
+                .map(|p| p.display().to_string())
+                .unwrap_or("None".to_string());
+            if ui
+                .add(egui::Label::new(hd_packs).sense(egui::Sense::click()))
+                .clicked()
+            {
+                let f = rfd::AsyncFileDialog::new()
+                    .set_title("Select hd packs folder")
+                    .set_directory(c.local.default_rom_path())
+                    .pick_folder();
+                let message_sender = self.message_channel.0.clone();
+                crate::execute(async move {
+                    let file = f.await;
+                    if let Some(file) = file {
+                        let fname = file.path().to_path_buf();
+                        message_sender.send(Message::NewHdPackPath(fname)).ok();
+                    }
+                });
+            }
+            if c.local.configuration.hd_pack_path().is_some()
+                && ui.button("Disable hd packs").clicked()
+            {
+                c.local.configuration.set_hd_pack_path(None);
+                c.update_hd_pack();
+            }
+
+            ui.label("Rom database (nes20db.xml), used to correct rom headers:");
+            let database = c
+                .local
+                .configuration
+                .rom_database()
+                .map(|p| p.display().to_string())
+                .unwrap_or("None".to_string());
+            if ui
+                .add(egui::Label::new(database).sense(egui::Sense::click()))
+                .clicked()
+            {
+                let f = rfd::AsyncFileDialog::new()
+                    .set_title("Select rom database")
+                    .set_directory(c.local.default_rom_path())
+                    .add_filter("Rom database", &["xml", "toml"])
+                    .pick_file();
+                let message_sender = self.message_channel.0.clone();
+                crate::execute(async move {
+                    let file = f.await;
+                    if let Some(file) = file {
+                        let fname = file.path().to_path_buf();
+                        message_sender.send(Message::NewRomDatabase(fname)).ok();
+                    }
+                });
+            }
+            if c.local.configuration.rom_database().is_some()
+                && ui.button("Stop using the rom database").clicked()
+            {
+                c.local.configuration.set_rom_database(None);
+                c.local.configuration.update_rom_database();
+            }
+
             if save_config {
                 c.local.configuration.save();
             }
@@ -52,6 +52,8 @@ pub struct MainNesWindow {
     pub texture: Option<egui::TextureHandle>,
     /// The filter used for audio playback, filtering out high frequency noise, increasing the quality of audio playback.
     filter: Option<biquad::DirectForm1<f32>>,
+    /// The cpu frequency that the audio filter was calculated for, the filter is rebuilt when the region changes it
+    filter_frequency: f32,
     /// The stream used for audio playback during emulation
     #[cfg(any(feature = "eframe", feature = "egui-multiwin"))]
     sound_stream: Option<cpal::Stream>,
@@ -91,6 +93,7 @@ impl MainNesWindow {
             sound: producer,
             texture: None,
             filter: None,
+            filter_frequency: 0.0,
             sound_stream: stream,
             paused: false,
         }
@@ -126,6 +129,7 @@ impl MainNesWindow {
                 sound: producer,
                 texture: None,
                 filter: None,
+                filter_frequency: 0.0,
                 sound_stream: stream,
                 paused: false,
                 mouse: false,
@@ -162,10 +166,14 @@ impl eframe::App for MainNesWindow {
             puffin_egui::profiler_window(ctx);
         }
 
+        if self.filter_frequency != self.c.cpu_frequency() {
+            self.filter = None;
+        }
         if self.filter.is_none() && self.sound_stream.is_some() {
             println!("Initializing with sample rate {}", self.sound_rate);
             let rf = self.sound_rate as f32;
-            let sampling_frequency = c.cpu_frequency();
+            let sampling_frequency = self.c.cpu_frequency();
+            self.filter_frequency = sampling_frequency;
             let filter_coeff = biquad::Coefficients::<f32>::from_params(
                 biquad::Type::LowPass,
                 biquad::Hertz::<f32>::from_hz(sampling_frequency).unwrap(),
@@ -223,6 +231,8 @@ impl eframe::App for MainNesWindow {
             }
         }
 
+        let mut take_screenshot = false;
+
         egui::TopBottomPanel::top("menu_bar").show(&ctx, |ui| {
             egui::menu::bar(ui, |ui| {
                 ui.menu_button("File", |ui| {
@@ -230,6 +240,11 @@ impl eframe::App for MainNesWindow {
                     if ui.add_enabled(true, button).clicked() {
                         ui.close_menu();
                     }
+                    let button = egui::Button::new("Take screenshot");
+                    if ui.add_enabled(true, button).clicked() {
+                        take_screenshot = true;
+                        ui.close_menu();
+                    }
                 });
             });
         });
@@ -245,6 +260,22 @@ impl eframe::App for MainNesWindow {
             load_state = true;
         }
 
+        if ctx.input(|i| i.key_pressed(egui::Key::F8)) {
+            take_screenshot = true;
+        }
+
+        if take_screenshot {
+            let path = self
+                .c
+                .local
+                .screenshot_path()
+                .join(common_emulator::screenshot::screenshot_name());
+            let scaled = self.c.local.configuration.scaled_screenshots;
+            if let Err(e) = self.c.save_screenshot(&path, scaled) {
+                println!("Failed to save the screenshot {}: {}", path.display(), e);
+            }
+        }
+
         let name = if let Some(cart) = self.c.mb.cartridge() {
             cart.save_name()
         } else {
@@ -252,6 +283,7 @@ impl eframe::App for MainNesWindow {
         };
         let name = format!("./saves/{}", name);
         if save_state {
+            self.c.flush_disk();
             let mut path = std::path::PathBuf::from(&name);
             path.pop();
             let _ = std::fs::create_dir_all(path);
@@ -291,7 +323,8 @@ impl eframe::App for MainNesWindow {
             puffin::profile_scope!("sleep time");
             let time_now = std::time::SystemTime::now();
             let frame_time = time_now.duration_since(self.last_frame_time).unwrap();
-            let desired_frame_length = std::time::Duration::from_nanos(1_000_000_000u64 / 60);
+            let desired_frame_length =
+                std::time::Duration::from_secs_f32(1.0 / self.c.ppu_frame_rate());
             if frame_time < desired_frame_length {
                 let st = (desired_frame_length - frame_time);
                 spin_sleep::sleep(st);
@@ -316,7 +349,11 @@ impl TrackedWindow for MainNesWindow {
         true
     }
 
-    fn can_quit(&mut self, _c: &mut NesEmulatorData) -> bool {
+    fn can_quit(&mut self, c: &mut NesEmulatorData) -> bool {
+        c.flush_disk();
+        if let Some(e) = &c.local.disk_error {
+            println!("{}", e);
+        }
         self.sound_stream.take();
         loop {
             if self.recording.stop().is_ok() {
@@ -392,10 +429,15 @@ impl TrackedWindow for MainNesWindow {
         #[cfg(feature = "puffin")]
         puffin::profile_scope!("frame rendering");
 
+        //the region changes the cpu frequency, which the filter and the audio interval depend on
+        if self.filter_frequency != c.cpu_frequency() {
+            self.filter = None;
+        }
         if self.filter.is_none() && self.sound_stream.is_some() {
             println!("Initializing with sample rate {}", c.local.get_sound_rate());
             let rf = c.local.get_sound_rate() as f32;
             let sampling_frequency = c.cpu_frequency();
+            self.filter_frequency = sampling_frequency;
             let filter_coeff = biquad::Coefficients::<f32>::from_params(
                 biquad::Type::LowPass,
                 biquad::Hertz::<f32>::from_hz(sampling_frequency).unwrap(),
@@ -564,12 +606,7 @@ impl TrackedWindow for MainNesWindow {
                             c.wait_for_frame_end = false;
                         }
                         if !self.paused {
-                            let image = c
-                                .cpu_peripherals
-                                .ppu_get_frame()
-                                .to_pixels_egui()
-                                .resize(c.local.configuration.scaler);
-                            c.local.image = image;
+                            c.local.image = c.scaled_frame();
                         }
                         self.recording.send_frame(&c.local.image);
                         if let Some(olocal) = &mut c.olocal {
@@ -597,12 +634,7 @@ impl TrackedWindow for MainNesWindow {
                     }
                     if c.cpu_peripherals.ppu_frame_end() {
                         if !self.paused {
-                            let image = c
-                                .cpu_peripherals
-                                .ppu_get_frame()
-                                .to_pixels_egui()
-                                .resize(c.local.configuration.scaler);
-                            c.local.image = image;
+                            c.local.image = c.scaled_frame();
                         }
                         self.recording.send_frame(&c.local.image);
                         if let Some(olocal) = &mut c.olocal {
@@ -631,12 +663,7 @@ impl TrackedWindow for MainNesWindow {
         }
 
         if self.paused {
-            let image = c
-                .cpu_peripherals
-                .ppu_get_frame()
-                .to_pixels_egui()
-                .resize(c.local.configuration.scaler);
-            c.local.image = image;
+            c.local.image = c.scaled_frame();
         }
         let image = c.local.image.clone().to_egui();
 
@@ -661,6 +688,7 @@ impl TrackedWindow for MainNesWindow {
         let mut save_state = false;
         let mut load_state = false;
         let mut rewind_state = false;
+        let mut take_screenshot = false;
         //Some(true) means start recording, Some(false) means stop recording
         let mut start_stop_recording: Option<bool> = None;
 
@@ -716,6 +744,12 @@ impl TrackedWindow for MainNesWindow {
                         }
                     }
 
+                    let button = egui_multiwin::egui::Button::new("Take screenshot");
+                    if ui.add_enabled(true, button).clicked() {
+                        take_screenshot = true;
+                        ui.close_menu();
+                    }
+
                     let button = egui_multiwin::egui::Button::new("Open data path");
                     if ui.add_enabled(true, button).clicked()
                     {
@@ -753,6 +787,22 @@ impl TrackedWindow for MainNesWindow {
                         ui.close_menu();
                         c.power_cycle();
                     }
+                    if c.mb.cartridge().is_some_and(|cart| cart.disk_sides() > 0) {
+                        if ui.button("Flip disk side").clicked() {
+                            ui.close_menu();
+                            c.flip_disk();
+                        }
+                        if ui.button("Eject disk").clicked() {
+                            ui.close_menu();
+                            c.eject_disk();
+                        }
+                    }
+                    if c.mb.cartridge().is_some_and(|cart| cart.nsf().is_some())
+                        && ui.button("Music player").clicked()
+                    {
+                        ui.close_menu();
+                        windows_to_create.push(super::nsf_player::Window::new_request());
+                    }
                 });
                 #[cfg(feature = "debugger")]
                 {
@@ -793,8 +843,23 @@ impl TrackedWindow for MainNesWindow {
                             ui.close_menu();
                             windows_to_create.push(super::sprite_dump_window::DumpWindow::new_request());
                         }
+                        if ui.button("PPU event viewer").clicked() {
+                            ui.close_menu();
+                            windows_to_create.push(super::ppu_event_viewer::Window::new_request());
+                        }
+                        if ui.button("Edit ppu palette").clicked() {
+                            ui.close_menu();
+                            windows_to_create.push(super::palette_editor::Window::new_request());
+                        }
+                        if ui.button("Edit ppu sprites").clicked() {
+                            ui.close_menu();
+                            windows_to_create.push(super::oam_editor::Window::new_request());
+                        }
                     });
                 }
+                if let Some(e) = &c.local.disk_error {
+                    ui.colored_label(egui_multiwin::egui::Color32::RED, e);
+                }
             });
         });
 
@@ -819,6 +884,13 @@ impl TrackedWindow for MainNesWindow {
             rewind_state = true;
         }
 
+        if egui
+            .egui_ctx
+            .input(|i| i.key_pressed(egui_multiwin::egui::Key::F8))
+        {
+            take_screenshot = true;
+        }
+
         if egui
             .egui_ctx
             .input(|i| i.key_pressed(egui_multiwin::egui::Key::F11))
@@ -844,6 +916,16 @@ impl TrackedWindow for MainNesWindow {
             }
         }
 
+        if take_screenshot {
+            let path = c
+                .local
+                .screenshot_path()
+                .join(common_emulator::screenshot::screenshot_name());
+            if let Err(e) = c.save_screenshot(&path, c.local.configuration.scaled_screenshots) {
+                println!("Failed to save the screenshot {}: {}", path.display(), e);
+            }
+        }
+
         let record_path = c.local.record_path();
         if let Some(rec) = start_stop_recording {
             if rec {
@@ -878,6 +960,7 @@ impl TrackedWindow for MainNesWindow {
         let mut save_path = c.local.save_path();
         save_path.push(ppp.file_name().unwrap());
         if save_state {
+            c.flush_disk();
             let mut path = save_path.clone();
             path.pop();
             let _ = std::fs::create_dir_all(path);
@@ -975,14 +1058,10 @@ impl TrackedWindow for MainNesWindow {
                                     c.cpu_peripherals.ppu.bg_debug =
                                         Some(((coord.x / zoom) as u8, (coord.y / zoom) as u8));
                                 }
-                                let scale_factor = c
-                                    .local
-                                    .configuration
-                                    .scaler
-                                    .map(|s| s.scale_factor())
-                                    .or(Some(1.0))
-                                    .unwrap();
-                                let zcoord = coord / (zoom * scale_factor);
+                                let scale_factor = c.frame_scale();
+                                let overscan = c.local.configuration.overscan;
+                                let zcoord = coord / (zoom * scale_factor)
+                                    + egui::vec2(overscan.left as f32, overscan.top as f32);
                                 c.mb.set_zapper_coords(zcoord.x as u16, zcoord.y as u16);
 
                                 let pixel = c.local.image.get_pixel(coord / zoom);
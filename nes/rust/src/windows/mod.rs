@@ -15,7 +15,11 @@ pub mod genie;
 pub mod main;
 pub mod name_table_dump_window;
 pub mod network;
+pub mod nsf_player;
+pub mod oam_editor;
+pub mod palette_editor;
 pub mod pattern_table_dump_window;
+pub mod ppu_event_viewer;
 pub mod ppu_memory_dump_window;
 pub mod rom_finder;
 pub mod sprite_dump_window;
@@ -36,7 +40,11 @@ pub enum Windows {
     Genie(crate::windows::genie::Window),
     NametableDump(crate::windows::name_table_dump_window::DumpWindow),
     Network(crate::windows::network::Window),
+    NsfPlayer(crate::windows::nsf_player::Window),
+    OamEditor(crate::windows::oam_editor::Window),
+    PaletteEditor(crate::windows::palette_editor::Window),
     PatternTableDump(crate::windows::pattern_table_dump_window::DumpWindow),
+    PpuEvents(crate::windows::ppu_event_viewer::Window),
     PpuMemoryDump(crate::windows::ppu_memory_dump_window::PpuMemoryDumpWindow),
     RomChecker(crate::windows::rom_checker::Window),
     RomFinder(crate::windows::rom_finder::RomFinder),
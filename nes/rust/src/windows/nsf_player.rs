@@ -0,0 +1,143 @@
+//! This module contains the window for playing the music of nsf files
+
+use crate::apu::ApuChannel;
+use crate::NesEmulatorData;
+
+use strum::IntoEnumIterator;
+
+#[cfg(feature = "eframe")]
+use eframe::egui;
+
+#[cfg(feature = "egui-multiwin")]
+use egui_multiwin::{arboard, egui, egui_glow::EguiGlow};
+
+#[cfg(feature = "egui-multiwin")]
+use crate::egui_multiwin_dynamic::{
+    multi_window::NewWindowRequest,
+    tracked_window::{RedrawResponse, TrackedWindow},
+};
+
+/// The window for playing nsf music
+pub struct Window {}
+
+impl Window {
+    /// Create a request to create a new window of self.
+    #[cfg(feature = "egui-multiwin")]
+    pub fn new_request() -> NewWindowRequest {
+        NewWindowRequest {
+            window_state: super::Windows::NsfPlayer(Window {}),
+            builder: egui_multiwin::winit::window::WindowBuilder::new()
+                .with_resizable(true)
+                .with_inner_size(egui_multiwin::winit::dpi::LogicalSize {
+                    width: 400.0,
+                    height: 480.0,
+                })
+                .with_title("UglyOldBob NES Music Player"),
+            options: egui_multiwin::tracked_window::TrackedWindowOptions {
+                vsync: false,
+                shader: None,
+            },
+            id: egui_multiwin::multi_window::new_id(),
+        }
+    }
+}
+
+/// Format a duration as minutes and seconds
+fn format_time(d: std::time::Duration) -> String {
+    let secs = d.as_secs();
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
+
+#[cfg(feature = "egui-multiwin")]
+impl TrackedWindow for Window {
+    fn is_root(&self) -> bool {
+        false
+    }
+
+    fn set_root(&mut self, _root: bool) {}
+
+    fn redraw(
+        &mut self,
+        c: &mut NesEmulatorData,
+        egui: &mut EguiGlow,
+        _window: &egui_multiwin::winit::window::Window,
+        _clipboard: &mut arboard::Clipboard,
+    ) -> RedrawResponse {
+        egui.egui_ctx.request_repaint();
+        let quit = false;
+        let windows_to_create = vec![];
+
+        egui_multiwin::egui::CentralPanel::default().show(&egui.egui_ctx, |ui| {
+            let info = c.mb.cartridge().and_then(|cart| cart.nsf().cloned());
+            if let Some(info) = info {
+                ui.heading(&info.title);
+                ui.label(&info.artist);
+                ui.label(&info.copyright);
+                ui.separator();
+
+                let current = c.song_elapsed();
+                if let Some((song, elapsed)) = current {
+                    let length = info
+                        .tracks
+                        .iter()
+                        .find(|t| t.song == song)
+                        .and_then(|t| t.duration);
+                    match length {
+                        Some(length) => ui.label(format!(
+                            "Song {}: {} / {}",
+                            song + 1,
+                            format_time(elapsed),
+                            format_time(length)
+                        )),
+                        None => ui.label(format!("Song {}: {}", song + 1, format_time(elapsed))),
+                    };
+                }
+
+                let mut play = None;
+                egui::ScrollArea::vertical()
+                    .max_height(ui.available_height() * 0.6)
+                    .show(ui, |ui| {
+                        for track in &info.tracks {
+                            let mut text = format!("{}", track.song + 1);
+                            if let Some(name) = &track.name {
+                                text.push_str(&format!(" {}", name));
+                            }
+                            if let Some(d) = track.duration {
+                                text.push_str(&format!(" ({})", format_time(d)));
+                            }
+                            let selected = current.map(|(s, _)| s) == Some(track.song);
+                            if ui.add(egui::SelectableLabel::new(selected, text)).clicked() {
+                                play = Some(track.song);
+                            }
+                        }
+                    });
+                if let Some(song) = play {
+                    c.play_song(song);
+                }
+                ui.separator();
+
+                ui.label("Channels");
+                ui.horizontal_wrapped(|ui| {
+                    for channel in ApuChannel::iter() {
+                        let mut enabled = !c.cpu_peripherals.apu.channel_muted(channel);
+                        if ui.checkbox(&mut enabled, channel.to_string()).changed() {
+                            c.cpu_peripherals.apu.set_channel_muted(channel, !enabled);
+                        }
+                    }
+                    for chip in &info.chips {
+                        let mut enabled = !c.cpu_peripherals.apu.expansion_muted(*chip);
+                        if ui.checkbox(&mut enabled, chip.to_string()).changed() {
+                            c.cpu_peripherals.apu.set_expansion_muted(*chip, !enabled);
+                        }
+                    }
+                });
+            } else {
+                ui.label("The current rom is not an nsf file");
+            }
+        });
+        RedrawResponse {
+            quit,
+            new_windows: windows_to_create,
+        }
+    }
+}
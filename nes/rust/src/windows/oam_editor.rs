@@ -0,0 +1,119 @@
+//! This module is for the window that views and modifies the sprite memory (oam) of the ppu.
+use crate::NesEmulatorData;
+
+#[cfg(feature = "eframe")]
+use eframe::egui;
+
+#[cfg(feature = "egui-multiwin")]
+use egui_multiwin::{arboard, egui, egui_glow::EguiGlow};
+
+use crate::egui_multiwin_dynamic::{
+    multi_window::NewWindowRequest,
+    tracked_window::{RedrawResponse, TrackedWindow},
+};
+
+/// The window for editing the sprites of the ppu
+pub struct Window {}
+
+impl Window {
+    /// Create a request to create a new window of self.
+    #[cfg(feature = "egui-multiwin")]
+    pub fn new_request() -> NewWindowRequest {
+        NewWindowRequest {
+            window_state: super::Windows::OamEditor(Window {}),
+            builder: egui_multiwin::winit::window::WindowBuilder::new()
+                .with_resizable(true)
+                .with_inner_size(egui_multiwin::winit::dpi::LogicalSize {
+                    width: 640.0,
+                    height: 768.0,
+                })
+                .with_title("UglyOldBob NES PPU OAM Editor"),
+            options: egui_multiwin::tracked_window::TrackedWindowOptions {
+                vsync: false,
+                shader: None,
+            },
+            id: egui_multiwin::multi_window::new_id(),
+        }
+    }
+}
+
+#[cfg(feature = "egui-multiwin")]
+impl TrackedWindow for Window {
+    fn is_root(&self) -> bool {
+        false
+    }
+
+    fn set_root(&mut self, _root: bool) {}
+
+    fn redraw(
+        &mut self,
+        c: &mut NesEmulatorData,
+        egui: &mut EguiGlow,
+        _window: &egui_multiwin::winit::window::Window,
+        _clipboard: &mut arboard::Clipboard,
+    ) -> RedrawResponse {
+        egui.egui_ctx.request_repaint();
+        let quit = false;
+        let windows_to_create = vec![];
+
+        egui_multiwin::egui::CentralPanel::default().show(&egui.egui_ctx, |ui| {
+            ui.label("PPU OAM Editor Window");
+            #[cfg(not(feature = "debugger"))]
+            {
+                let _ = c;
+                ui.label("Sprites can only be modified when the debugger is enabled");
+            }
+            #[cfg(feature = "debugger")]
+            {
+                egui_multiwin::egui::ScrollArea::vertical().show(ui, |ui| {
+                    let ppu = &mut c.cpu_peripherals.ppu;
+                    let sprites = ppu.get_64_sprites();
+                    egui::Grid::new("oam_editor").striped(true).show(ui, |ui| {
+                        for heading in [
+                            "Sprite", "X", "Y", "Tile", "Palette", "Behind", "Flip H", "Flip V",
+                        ] {
+                            ui.label(heading);
+                        }
+                        ui.end_row();
+                        for (i, sprite) in sprites.iter().enumerate() {
+                            let mut x = sprite.x();
+                            let mut y = sprite.y();
+                            let mut tile = sprite.tile();
+                            let attribute = sprite.attribute();
+                            let mut palette = attribute & 3;
+                            let mut behind = (attribute & 0x20) != 0;
+                            let mut flip_h = (attribute & 0x40) != 0;
+                            let mut flip_v = (attribute & 0x80) != 0;
+                            ui.label(format!("{:02X}", i));
+                            ui.add(egui::DragValue::new(&mut x).hexadecimal(2, false, true));
+                            ui.add(egui::DragValue::new(&mut y).hexadecimal(2, false, true));
+                            ui.add(egui::DragValue::new(&mut tile).hexadecimal(2, false, true));
+                            ui.add(egui::DragValue::new(&mut palette).clamp_range(0..=3));
+                            ui.checkbox(&mut behind, "");
+                            ui.checkbox(&mut flip_h, "");
+                            ui.checkbox(&mut flip_v, "");
+                            ui.end_row();
+                            //the unused bits of the attribute are kept as they are
+                            let attribute = (attribute & 0x1c)
+                                | (palette & 3)
+                                | if behind { 0x20 } else { 0 }
+                                | if flip_h { 0x40 } else { 0 }
+                                | if flip_v { 0x80 } else { 0 };
+                            let data = [y, tile, attribute, x];
+                            let old = [sprite.y(), sprite.tile(), sprite.attribute(), sprite.x()];
+                            if data != old {
+                                for (j, d) in data.iter().enumerate() {
+                                    ppu.set_oam((i * 4 + j) as u8, *d);
+                                }
+                            }
+                        }
+                    });
+                });
+            }
+        });
+        RedrawResponse {
+            quit,
+            new_windows: windows_to_create,
+        }
+    }
+}
@@ -0,0 +1,156 @@
+//! This module is for the window that views and modifies the palette ram of the ppu.
+use crate::NesEmulatorData;
+
+#[cfg(feature = "eframe")]
+use eframe::egui;
+
+#[cfg(feature = "egui-multiwin")]
+use egui_multiwin::{arboard, egui, egui_glow::EguiGlow};
+
+use crate::egui_multiwin_dynamic::{
+    multi_window::NewWindowRequest,
+    tracked_window::{RedrawResponse, TrackedWindow},
+};
+
+/// The size of each color shown in the window
+const COLOR_SIZE: f32 = 32.0;
+
+/// The window for editing the palette ram
+pub struct Window {
+    /// The entry of palette ram selected for modification
+    selected: u8,
+}
+
+impl Window {
+    /// Create a request to create a new window of self.
+    #[cfg(feature = "egui-multiwin")]
+    pub fn new_request() -> NewWindowRequest {
+        NewWindowRequest {
+            window_state: super::Windows::PaletteEditor(Window { selected: 0 }),
+            builder: egui_multiwin::winit::window::WindowBuilder::new()
+                .with_resizable(true)
+                .with_inner_size(egui_multiwin::winit::dpi::LogicalSize {
+                    width: 640.0,
+                    height: 480.0,
+                })
+                .with_title("UglyOldBob NES PPU Palette Editor"),
+            options: egui_multiwin::tracked_window::TrackedWindowOptions {
+                vsync: false,
+                shader: None,
+            },
+            id: egui_multiwin::multi_window::new_id(),
+        }
+    }
+}
+
+/// The address to read for an entry of palette ram.
+/// The first color of each sprite palette is a mirror of the first color of the background palette.
+fn entry_address(entry: u8) -> u16 {
+    let entry = if (entry & 3) == 0 { entry & 0xf } else { entry };
+    0x3f00 + entry as u16
+}
+
+/// Show a button filled with a color, outlined when it is selected
+fn color_button(ui: &mut egui::Ui, rgb: [u8; 3], text: String, selected: bool) -> egui::Response {
+    let fill = egui::Color32::from_rgb(rgb[0], rgb[1], rgb[2]);
+    //pick a text color that can be seen on top of the fill
+    let luma = rgb[0] as u16 * 3 + rgb[1] as u16 * 6 + rgb[2] as u16;
+    let text_color = if luma > 1280 {
+        egui::Color32::BLACK
+    } else {
+        egui::Color32::WHITE
+    };
+    let stroke = if selected {
+        egui::Stroke::new(3.0, egui::Color32::RED)
+    } else {
+        egui::Stroke::NONE
+    };
+    ui.add(
+        egui::Button::new(egui::RichText::new(text).color(text_color).monospace())
+            .fill(fill)
+            .stroke(stroke)
+            .min_size(egui::vec2(COLOR_SIZE, COLOR_SIZE)),
+    )
+}
+
+#[cfg(feature = "egui-multiwin")]
+impl TrackedWindow for Window {
+    fn is_root(&self) -> bool {
+        false
+    }
+
+    fn set_root(&mut self, _root: bool) {}
+
+    fn redraw(
+        &mut self,
+        c: &mut NesEmulatorData,
+        egui: &mut EguiGlow,
+        _window: &egui_multiwin::winit::window::Window,
+        _clipboard: &mut arboard::Clipboard,
+    ) -> RedrawResponse {
+        egui.egui_ctx.request_repaint();
+        let quit = false;
+        let windows_to_create = vec![];
+
+        egui_multiwin::egui::CentralPanel::default().show(&egui.egui_ctx, |ui| {
+            ui.label("PPU Palette Editor Window");
+            egui_multiwin::egui::ScrollArea::vertical().show(ui, |ui| {
+                ui.label("Palette ram, select an entry to modify it");
+                for (row, name) in ["Background", "Sprite"].iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.add_sized(egui::vec2(80.0, COLOR_SIZE), egui::Label::new(*name));
+                        for i in 0..16u8 {
+                            let entry = row as u8 * 16 + i;
+                            let value = c.mb.ppu_palette_read(entry_address(entry)) & 0x3f;
+                            let rgb = c.cpu_peripherals.ppu.system_color(value);
+                            if color_button(
+                                ui,
+                                rgb,
+                                format!("{:02X}", value),
+                                self.selected == entry,
+                            )
+                            .on_hover_text(format!("${:04X}", 0x3f00 + entry as u16))
+                            .clicked()
+                            {
+                                self.selected = entry;
+                            }
+                        }
+                    });
+                }
+                ui.separator();
+                let address = 0x3f00 + self.selected as u16;
+                let mut value = c.mb.ppu_palette_read(entry_address(self.selected)) & 0x3f;
+                let before = value;
+                ui.horizontal(|ui| {
+                    ui.label(format!("Entry ${:04X}", address));
+                    ui.add(
+                        egui::DragValue::new(&mut value)
+                            .clamp_range(0..=0x3f)
+                            .hexadecimal(2, false, true),
+                    );
+                });
+                ui.label("System palette, select a color to put in the entry");
+                for row in 0..4u8 {
+                    ui.horizontal(|ui| {
+                        for col in 0..16u8 {
+                            let color = row * 16 + col;
+                            let rgb = c.cpu_peripherals.ppu.system_color(color);
+                            if color_button(ui, rgb, format!("{:02X}", color), color == value)
+                                .clicked()
+                            {
+                                value = color;
+                            }
+                        }
+                    });
+                }
+                if value != before {
+                    c.mb.ppu_palette_write(address, value);
+                }
+            });
+        });
+        RedrawResponse {
+            quit,
+            new_windows: windows_to_create,
+        }
+    }
+}
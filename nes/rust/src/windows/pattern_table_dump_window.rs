@@ -23,6 +23,8 @@ pub struct DumpWindow {
     /// The texture used for rendering the image.
     #[cfg(any(feature = "eframe", feature = "egui-multiwin"))]
     texture: Option<egui_multiwin::egui::TextureHandle>,
+    /// The palette used to draw the pattern tables, 0-3 are background palettes and 4-7 are sprite palettes
+    palette: u8,
 }
 
 #[cfg(feature = "egui-multiwin")]
@@ -33,6 +35,7 @@ impl DumpWindow {
             window_state: super::Windows::PatternTableDump(DumpWindow {
                 buf: Box::new(RgbImage::new(256, 128)),
                 texture: None,
+                palette: 0,
             }),
             builder: egui_multiwin::winit::window::WindowBuilder::new()
                 .with_resizable(true)
@@ -71,10 +74,19 @@ impl TrackedWindow for DumpWindow {
 
         egui_multiwin::egui::CentralPanel::default().show(&egui.egui_ctx, |ui| {
             ui.label("PPU Pattern Table Dump Window");
+            ui.horizontal(|ui| {
+                ui.label("Palette");
+                for i in 0..4 {
+                    ui.selectable_value(&mut self.palette, i, format!("Background {}", i));
+                }
+                for i in 4..8 {
+                    ui.selectable_value(&mut self.palette, i, format!("Sprite {}", i - 4));
+                }
+            });
             egui_multiwin::egui::ScrollArea::vertical().show(ui, |ui| {
                 c.cpu_peripherals
                     .ppu
-                    .render_pattern_table(&mut self.buf, &c.mb);
+                    .render_pattern_table(&mut self.buf, &c.mb, self.palette);
                 let image = self.buf.to_pixels_egui().to_egui();
                 if self.texture.is_none() {
                     self.texture = Some(egui.egui_ctx.load_texture(
@@ -0,0 +1,219 @@
+//! This module is for the window that shows when the cpu accessed the ppu registers during a frame.
+use crate::NesEmulatorData;
+
+#[cfg(feature = "eframe")]
+use eframe::egui;
+
+#[cfg(feature = "egui-multiwin")]
+use egui_multiwin::{arboard, egui, egui_glow::EguiGlow};
+
+use crate::egui_multiwin_dynamic::{
+    multi_window::NewWindowRequest,
+    tracked_window::{RedrawResponse, TrackedWindow},
+};
+
+/// The number of dots in a scanline
+#[cfg(feature = "debugger")]
+const DOTS: u16 = 341;
+
+/// The color used to draw an event
+#[cfg(feature = "debugger")]
+fn event_color(event: &crate::ppu::events::PpuEvent) -> egui::Color32 {
+    use crate::ppu::events::PpuEventKind;
+    match event.kind {
+        PpuEventKind::RegisterWrite => match event.address & 7 {
+            0 => egui::Color32::from_rgb(255, 64, 64),
+            1 => egui::Color32::from_rgb(255, 160, 0),
+            3 | 4 => egui::Color32::from_rgb(255, 255, 0),
+            5 => egui::Color32::from_rgb(0, 255, 0),
+            6 => egui::Color32::from_rgb(0, 255, 255),
+            _ => egui::Color32::from_rgb(64, 128, 255),
+        },
+        PpuEventKind::RegisterRead => egui::Color32::from_rgb(255, 128, 255),
+        PpuEventKind::OamDma => egui::Color32::from_rgb(160, 96, 255),
+        PpuEventKind::MapperIrq => egui::Color32::WHITE,
+    }
+}
+
+/// The name of the ppu register for an address
+#[cfg(feature = "debugger")]
+fn register_name(address: u16) -> &'static str {
+    match address {
+        0x4014 => "OAMDMA",
+        a => match a & 7 {
+            0 => "PPUCTRL",
+            1 => "PPUMASK",
+            2 => "PPUSTATUS",
+            3 => "OAMADDR",
+            4 => "OAMDATA",
+            5 => "PPUSCROLL",
+            6 => "PPUADDR",
+            _ => "PPUDATA",
+        },
+    }
+}
+
+/// The window for viewing the ppu events of a frame
+pub struct Window {
+    /// The texture used for rendering the frame behind the events.
+    #[cfg(any(feature = "eframe", feature = "egui-multiwin"))]
+    texture: Option<egui::TextureHandle>,
+    /// The size of each dot of the grid
+    zoom: f32,
+}
+
+impl Window {
+    /// Create a request to create a new window of self.
+    #[cfg(feature = "egui-multiwin")]
+    pub fn new_request() -> NewWindowRequest {
+        NewWindowRequest {
+            window_state: super::Windows::PpuEvents(Window {
+                texture: None,
+                zoom: 3.0,
+            }),
+            builder: egui_multiwin::winit::window::WindowBuilder::new()
+                .with_resizable(true)
+                .with_inner_size(egui_multiwin::winit::dpi::LogicalSize {
+                    width: 1024.0,
+                    height: 768.0,
+                })
+                .with_title("UglyOldBob NES PPU Event Viewer"),
+            options: egui_multiwin::tracked_window::TrackedWindowOptions {
+                vsync: false,
+                shader: None,
+            },
+            id: egui_multiwin::multi_window::new_id(),
+        }
+    }
+}
+
+#[cfg(feature = "egui-multiwin")]
+impl TrackedWindow for Window {
+    fn is_root(&self) -> bool {
+        false
+    }
+
+    fn set_root(&mut self, _root: bool) {}
+
+    fn redraw(
+        &mut self,
+        c: &mut NesEmulatorData,
+        egui: &mut EguiGlow,
+        _window: &egui_multiwin::winit::window::Window,
+        _clipboard: &mut arboard::Clipboard,
+    ) -> RedrawResponse {
+        egui.egui_ctx.request_repaint();
+        let quit = false;
+        let windows_to_create = vec![];
+
+        egui_multiwin::egui::CentralPanel::default().show(&egui.egui_ctx, |ui| {
+            ui.label("PPU Event Viewer Window");
+            #[cfg(not(feature = "debugger"))]
+            {
+                let _ = (c, &self.texture, self.zoom);
+                ui.label("Events are only recorded when the debugger is enabled");
+            }
+            #[cfg(feature = "debugger")]
+            {
+                let ppu = &mut c.cpu_peripherals.ppu;
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut ppu.events.enabled, "Record events");
+                    ui.add(egui::Slider::new(&mut self.zoom, 1.0..=6.0).text("Zoom"));
+                });
+                if let Some(frame) = ppu.events.last_frame() {
+                    let image = frame.to_pixels_egui().to_egui();
+                    if self.texture.is_none() {
+                        self.texture = Some(egui.egui_ctx.load_texture(
+                            "NES_PPU_EVENTS",
+                            image,
+                            egui_multiwin::egui::TextureOptions::NEAREST,
+                        ));
+                    } else if let Some(t) = &mut self.texture {
+                        t.set_partial([0, 0], image, egui_multiwin::egui::TextureOptions::NEAREST);
+                    }
+                }
+                let scanlines = ppu.scanlines();
+                let events = ppu.events.last_events();
+                ui.label(format!("{} events in the last frame", events.len()));
+                egui_multiwin::egui::ScrollArea::both().show(ui, |ui| {
+                    let zoom = self.zoom;
+                    let (response, painter) = ui.allocate_painter(
+                        egui::vec2(DOTS as f32 * zoom, scanlines as f32 * zoom),
+                        egui::Sense::hover(),
+                    );
+                    let origin = response.rect.left_top();
+                    let dot_pos = |dot: u16, scanline: u16| {
+                        origin + egui::vec2(dot as f32 * zoom, scanline as f32 * zoom)
+                    };
+                    painter.rect_filled(response.rect, 0.0, egui::Color32::from_gray(32));
+                    //the visible pixels are output on dots 1-256 of scanlines 0-239
+                    if let (Some(t), Some(frame)) = (&self.texture, ppu.events.last_frame()) {
+                        let rect = egui::Rect::from_min_size(
+                            dot_pos(1, 0),
+                            egui::vec2(frame.width as f32 * zoom, frame.height as f32 * zoom),
+                        );
+                        painter.image(
+                            t.id(),
+                            rect,
+                            egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0)),
+                            egui::Color32::WHITE,
+                        );
+                    }
+                    for e in events {
+                        let rect = egui::Rect::from_min_size(
+                            dot_pos(e.dot, e.scanline),
+                            egui::vec2(zoom, zoom),
+                        );
+                        painter.rect_filled(rect, 0.0, event_color(e));
+                    }
+                    if let Some(cursor) = response.hover_pos() {
+                        let pos = cursor - origin;
+                        let dot = (pos.x / zoom).floor() as i32;
+                        let scanline = (pos.y / zoom).floor() as i32;
+                        //events within a few dots of the cursor are shown, individual dots are hard to point at
+                        let near: Vec<&crate::ppu::events::PpuEvent> = events
+                            .iter()
+                            .filter(|e| {
+                                e.scanline as i32 == scanline && (e.dot as i32 - dot).abs() <= 2
+                            })
+                            .collect();
+                        response.on_hover_ui_at_pointer(|ui| {
+                            ui.label(format!("Scanline {}, dot {}", scanline, dot));
+                            for e in near {
+                                ui.separator();
+                                ui.colored_label(
+                                    event_color(e),
+                                    format!(
+                                        "{:?} at scanline {}, dot {}",
+                                        e.kind, e.scanline, e.dot
+                                    ),
+                                );
+                                if e.kind != crate::ppu::events::PpuEventKind::MapperIrq {
+                                    ui.label(format!(
+                                        "{} ${:04X} = ${:02X}",
+                                        register_name(e.address),
+                                        e.address,
+                                        e.value
+                                    ));
+                                }
+                                ui.label(format!("PC ${:04X}", e.pc));
+                                ui.label(format!(
+                                    "v ${:04X}, t ${:04X}, x {}, w {}",
+                                    e.scroll.vram_address,
+                                    e.scroll.temporary_vram_address,
+                                    e.scroll.fine_x,
+                                    e.scroll.write_toggle as u8
+                                ));
+                                ui.label(format!("Scroll {},{}", e.scroll.x(), e.scroll.y()));
+                            }
+                        });
+                    }
+                });
+            }
+        });
+        RedrawResponse {
+            quit,
+            new_windows: windows_to_create,
+        }
+    }
+}
@@ -60,7 +60,7 @@ impl TrackedWindow for RomFinder {
         _clipboard: &mut arboard::Clipboard,
     ) -> RedrawResponse {
         let mut quit = false;
-        let windows_to_create = vec![];
+        let mut windows_to_create = vec![];
 
         //scan for roms if needed
         let rp = c.local.configuration.get_rom_path().to_owned();
@@ -99,6 +99,9 @@ impl TrackedWindow for RomFinder {
                                         ))
                                         .sense(Sense::click()),
                                     );
+                                    if let Some(correction) = &r.correction {
+                                        ui.label(format!("(header corrected, {})", correction));
+                                    }
                                     if let Some(cart) = c.mb.cartridge() {
                                         if p.display().to_string() == cart.rom_name()
                                             && !self.scrolled
@@ -135,6 +138,9 @@ impl TrackedWindow for RomFinder {
                     }
                 }
                 if let Some(nc) = new_rom {
+                    if nc.nsf().is_some() {
+                        windows_to_create.push(super::nsf_player::Window::new_request());
+                    }
                     c.remove_cartridge();
                     c.insert_cartridge(nc);
                     c.power_cycle();
@@ -158,10 +158,10 @@ pub fn romlist_bench(c: &mut Criterion) {
                 "../test_roms",
                 std::path::PathBuf::new(),
                 std::path::PathBuf::new(),
-                |n, p| SnesCartridge::load_cartridge(n, p),
+                |n, d, p| SnesCartridge::load_cartridge_contents(n, d, p),
             );
-            list.process_roms(std::path::PathBuf::new(), |n, p| {
-                SnesCartridge::load_cartridge(n, p)
+            list.process_roms(std::path::PathBuf::new(), |n, d, p| {
+                SnesCartridge::load_cartridge_contents(n, d, p)
             });
         });
     });
@@ -173,10 +173,10 @@ pub fn romlist_bench(c: &mut Criterion) {
                 "../test_roms",
                 std::path::PathBuf::new(),
                 std::path::PathBuf::new(),
-                |n, p| SnesCartridge::load_cartridge(n, p),
+                |n, d, p| SnesCartridge::load_cartridge_contents(n, d, p),
             );
-            list.process_roms(std::path::PathBuf::new(), |n, p| {
-                SnesCartridge::load_cartridge(n, p)
+            list.process_roms(std::path::PathBuf::new(), |n, d, p| {
+                SnesCartridge::load_cartridge_contents(n, d, p)
             });
         });
     });
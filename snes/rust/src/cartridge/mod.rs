@@ -454,6 +454,9 @@ pub struct SnesCartridge {
     #[serde(skip)]
     /// The convenience name of the rom
     rom_name: String,
+    #[serde(skip)]
+    /// The patches applied to the rom when it was loaded
+    patches: Vec<PathBuf>,
 }
 
 /// The data from a cartridge that needs to be saved when loading a save state
@@ -462,6 +465,8 @@ pub struct SnesCartridgeBackup {
     data: NonvolatileCartridgeData,
     /// The convenience name of the rom
     rom_name: String,
+    /// The patches applied to the rom when it was loaded
+    patches: Vec<PathBuf>,
 }
 
 /// Calculate the sha256 of a chunk of data, and return it in a hex encoded string.
@@ -564,6 +569,7 @@ impl SnesCartridge {
         SnesCartridgeBackup {
             data: self.data.nonvolatile.clone(),
             rom_name: self.rom_name.clone(),
+            patches: self.patches.clone(),
         }
     }
 
@@ -573,6 +579,12 @@ impl SnesCartridge {
         pb.push(format!("{}.prgram", self.save));
         self.data.volatile.prg_ram.upgrade_to_persistent(pb);
         self.rom_name = old_data.rom_name;
+        self.patches = old_data.patches;
+    }
+
+    /// Retrieve the list of patches applied to the rom
+    pub fn patches(&self) -> &[PathBuf] {
+        &self.patches
     }
 
     /// Retrieve the hash of the rom contents
@@ -666,6 +678,7 @@ impl SnesCartridge {
                 .unwrap()
                 .to_string(),
             rom_name: name.to_owned(),
+            patches: Vec::new(),
         })
     }
 
@@ -732,22 +745,76 @@ impl SnesCartridge {
         Err(CartridgeError::HeaderNotFound)
     }
 
-    /// Load a cartridge, returning an error or the new cartridge
+    /// Load a cartridge, returning an error or the new cartridge. Any patches next to the rom are applied.
     pub fn load_cartridge(name: String, sp: &Path) -> Result<Self, CartridgeError> {
-        let rom_contents = std::fs::read(name.clone());
-        if let Err(e) = rom_contents {
-            return Err(CartridgeError::FsError(e.kind().to_string()));
+        let patches = common_emulator::patch::find_patches(Path::new(&name));
+        Self::load_cartridge_with_patches(name, sp, &patches)
+    }
+
+    /// Load a cartridge from the contents of a rom that has already been read, returning an error or the new cartridge.
+    /// Any patches next to the rom are applied.
+    pub fn load_cartridge_contents(
+        name: String,
+        rom_contents: Vec<u8>,
+        sp: &Path,
+    ) -> Result<Self, CartridgeError> {
+        let patches = common_emulator::patch::find_patches(Path::new(&name));
+        Self::load_contents_with_patches(name, rom_contents, sp, &patches)
+    }
+
+    /// Load a cartridge, applying the given list of patches in order before parsing the rom.
+    pub fn load_cartridge_with_patches(
+        name: String,
+        sp: &Path,
+        patches: &[PathBuf],
+    ) -> Result<Self, CartridgeError> {
+        let rom_contents = common_emulator::archive::read_rom(&name)
+            .map_err(|e| CartridgeError::FsError(e.kind().to_string()))?;
+        Self::load_contents_with_patches(name, rom_contents, sp, patches)
+    }
+
+    /// Load a cartridge from the contents of a rom, applying the given list of patches in order before parsing the rom.
+    fn load_contents_with_patches(
+        name: String,
+        mut rom_contents: Vec<u8>,
+        sp: &Path,
+        patches: &[PathBuf],
+    ) -> Result<Self, CartridgeError> {
+        for p in patches {
+            let patch =
+                std::fs::read(p).map_err(|e| CartridgeError::FsError(e.kind().to_string()))?;
+            match common_emulator::patch::apply_patch(&mut rom_contents, &patch) {
+                Ok(()) => {}
+                //ups and bps patches are usually made for roms without the copier header
+                Err(common_emulator::patch::PatchError::SourceMismatch)
+                    if rom_contents.len() % 1024 == 512 =>
+                {
+                    let mut stripped = rom_contents[512..].to_vec();
+                    common_emulator::patch::apply_patch(&mut stripped, &patch)?;
+                    rom_contents = stripped;
+                }
+                Err(e) => return Err(e.into()),
+            }
         }
-        let rom_contents = rom_contents.unwrap();
 
         let preheader = rom_contents.len() % 1024 == 512;
         if rom_contents.len() % 512 != 0 {
             return Err(CartridgeError::BadLength);
         }
 
+        let save = common_emulator::archive::rom_file_name(&name);
         let mut cart = Self::find_rom_header(name, preheader, &rom_contents);
 
         if let Ok(c) = &mut cart {
+            c.save = save;
+            //patched roms get their own save files
+            for p in patches {
+                if let Some(n) = p.file_name() {
+                    c.save.push('+');
+                    c.save.push_str(&n.to_string_lossy());
+                }
+            }
+            c.patches = patches.to_vec();
             let mut pb: PathBuf = sp.to_path_buf();
             pb.push(format!("{}.prgram", c.save));
             if c.data.volatile.battery_backup {
@@ -41,6 +41,8 @@ pub struct EmulatorConfiguration {
     pub controller_config: [crate::controller::ControllerConfig; 4],
     /// The scaler to use for the emulator
     pub scaler: Option<common_emulator::video::ScalingAlgorithm>,
+    /// Screenshots are saved after the scaling algorithm is applied, instead of the raw image of the ppu
+    pub scaled_screenshots: bool,
 }
 
 impl Default for EmulatorConfiguration {
@@ -76,6 +78,7 @@ impl Default for EmulatorConfiguration {
             ],
             controller_config: controller,
             scaler: None,
+            scaled_screenshots: false,
         }
     }
 }
@@ -115,6 +118,8 @@ impl EmulatorConfiguration {
                     Ok(p) => {
                         result = p;
                         result.path = name.into_os_string().into_string().unwrap();
+                        //the ntsc filters only work on the colors of the nes ppu
+                        result.scaler = result.scaler.filter(|s| s.ntsc_preset().is_none());
                     }
                     Err(e) => {
                         println!("Failed to load config file: {}", e);
@@ -260,18 +265,28 @@ impl LocalEmulatorDataClone {
         pb
     }
 
+    /// Returns the path of where to save screenshots to
+    pub fn screenshot_path(&self) -> std::path::PathBuf {
+        let mut pb = self.dirs.data_dir().to_path_buf();
+        pb.push("screenshots");
+        if !pb.exists() {
+            let _ = std::fs::create_dir_all(&pb);
+        }
+        pb
+    }
+
     /// Finds roms for the system
     pub fn find_roms(&mut self, dir: &str) {
         self.parser
-            .find_roms(dir, self.save_path(), self.get_save_other(), |n, p| {
-                SnesCartridge::load_cartridge(n, p)
+            .find_roms(dir, self.save_path(), self.get_save_other(), |n, d, p| {
+                SnesCartridge::load_cartridge_contents(n, d, p)
             })
     }
 
     /// Process the list of roms
     pub fn process_roms(&mut self) {
-        self.parser.process_roms(self.get_save_other(), |n, p| {
-            SnesCartridge::load_cartridge(n, p)
+        self.parser.process_roms(self.get_save_other(), |n, d, p| {
+            SnesCartridge::load_cartridge_contents(n, d, p)
         })
     }
 }
@@ -424,6 +439,31 @@ impl SnesEmulatorData {
         1.89e9 / 88.0
     }
 
+    /// The details of the current rom and frame, stored in screenshots
+    pub fn screenshot_info(&self) -> common_emulator::screenshot::ScreenshotInfo {
+        let cart = self.mb.cartridge();
+        common_emulator::screenshot::ScreenshotInfo {
+            rom_name: cart.map(|c| c.rom_name()).unwrap_or_default(),
+            rom_hash: cart.map(|c| c.hash()).unwrap_or_default(),
+            frame_number: self.cpu_peripherals.ppu_frame_number(),
+        }
+    }
+
+    /// Save the last frame of the ppu as a png. The frame is either the raw image of the ppu, or the image after scaling.
+    pub fn save_screenshot(&self, path: &std::path::Path, scaled: bool) -> std::io::Result<()> {
+        let info = self.screenshot_info();
+        let frame = self.cpu_peripherals.ppu.backup_frame();
+        if scaled {
+            let image = frame
+                .to_pixels_egui()
+                .resize(self.local.configuration.scaler)
+                .to_rgb_image();
+            common_emulator::screenshot::save_png(path, &image, &info)
+        } else {
+            common_emulator::screenshot::save_png(path, &frame, &info)
+        }
+    }
+
     /// Finds roms for the system
     pub fn find_roms(&mut self, dir: &str) {
         self.local.find_roms(dir)
@@ -506,7 +546,11 @@ impl SnesEmulatorData {
         self.prev_irq = false;
         if let Some(cart) = cart {
             let name = cart.rom_name();
-            let cart = SnesCartridge::load_cartridge(name, &self.local.save_path());
+            let cart = SnesCartridge::load_cartridge_with_patches(
+                name,
+                &self.local.save_path(),
+                cart.patches(),
+            );
             if let Ok(cart) = cart {
                 self.insert_cartridge(cart);
             }
@@ -409,7 +409,7 @@ fn main() {
         nes_data.local.configuration.get_rom_path(),
         nes_data.local.save_path(),
         nes_data.local.get_save_other(),
-        |n, p| SnesCartridge::load_cartridge(n, p),
+        |n, d, p| SnesCartridge::load_cartridge_contents(n, d, p),
     );
     let mut multi_window = MultiWindow::new();
 
@@ -18,8 +18,7 @@ use egui_multiwin::egui;
 pub struct SnesPpu {
     /// The frame data stored in the ppu for being displayed onto the screen later.
     frame_data: Box<RgbImage>,
-    /// The frame number of the ppu, used for testing and debugging purposes.
-    #[cfg(any(test, feature = "debugger"))]
+    /// The frame number of the ppu, used for testing, debugging, and screenshots.
     frame_number: u64,
     #[cfg(feature = "debugger")]
     /// For debugging pixel generation of the background
@@ -42,7 +41,6 @@ impl SnesPpu {
     pub fn new() -> Self {
         Self {
             frame_data: Box::new(RgbImage::new(256, 224)),
-            #[cfg(any(test, feature = "debugger"))]
             frame_number: 0,
             #[cfg(any(test, feature = "debugger"))]
             bg_debug: None,
@@ -61,7 +59,6 @@ impl SnesPpu {
     }
 
     /// Return the frame number of the ppu, mostly used for testing and debugging the ppu
-    #[cfg(any(test, feature = "debugger"))]
     pub fn frame_number(&self) -> u64 {
         self.frame_number
     }
@@ -98,6 +95,7 @@ impl SnesPpu {
     /// Run a single clock cycle of the ppu
     pub fn cycle(&mut self, bus: &mut SnesMotherboard) {
         self.frame_end = true;
+        self.frame_number = self.frame_number.wrapping_add(1);
     }
 }
 
@@ -94,6 +94,16 @@ impl TrackedWindow for Window {
                 c.local.configuration.set_startup("".to_string());
             }
 
+            if ui
+                .checkbox(
+                    &mut c.local.configuration.scaled_screenshots,
+                    "Scale screenshots",
+                )
+                .changed()
+            {
+                save_config = true;
+            }
+
             let mut scaler = c.local.configuration.scaler;
             if !c.local.resolution_locked {
                 egui::ComboBox::from_label("Scaling algorithm")
@@ -105,7 +115,10 @@ impl TrackedWindow for Window {
                     )
                     .show_ui(ui, |ui| {
                         ui.selectable_value(&mut scaler, None, "None");
-                        for opt in common_emulator::video::ScalingAlgorithm::iter() {
+                        //the ntsc filters only work on the colors of the nes ppu, they would only stretch the image
+                        for opt in common_emulator::video::ScalingAlgorithm::iter()
+                            .filter(|s| s.ntsc_preset().is_none())
+                        {
                             ui.selectable_value(&mut scaler, Some(opt), opt.to_string());
                         }
                     });
@@ -611,6 +611,7 @@ impl TrackedWindow for MainSnesWindow {
         let mut save_state = false;
         let mut load_state = false;
         let mut rewind_state = false;
+        let mut take_screenshot = false;
         //Some(true) means start recording, Some(false) means stop recording
         let mut start_stop_recording: Option<bool> = None;
 
@@ -666,6 +667,12 @@ impl TrackedWindow for MainSnesWindow {
                         }
                     }
 
+                    let button = egui_multiwin::egui::Button::new("Take screenshot");
+                    if ui.add_enabled(true, button).clicked() {
+                        take_screenshot = true;
+                        ui.close_menu();
+                    }
+
                     let button = egui_multiwin::egui::Button::new("Open data path");
                     if ui.add_enabled(true, button).clicked()
                     {
@@ -751,6 +758,13 @@ impl TrackedWindow for MainSnesWindow {
             rewind_state = true;
         }
 
+        if egui
+            .egui_ctx
+            .input(|i| i.key_pressed(egui_multiwin::egui::Key::F8))
+        {
+            take_screenshot = true;
+        }
+
         if egui
             .egui_ctx
             .input(|i| i.key_pressed(egui_multiwin::egui::Key::F11))
@@ -776,6 +790,16 @@ impl TrackedWindow for MainSnesWindow {
             }
         }
 
+        if take_screenshot {
+            let path = c
+                .local
+                .screenshot_path()
+                .join(common_emulator::screenshot::screenshot_name());
+            if let Err(e) = c.save_screenshot(&path, c.local.configuration.scaled_screenshots) {
+                println!("Failed to save the screenshot {}: {}", path.display(), e);
+            }
+        }
+
         let record_path = c.local.record_path();
         if let Some(rec) = start_stop_recording {
             if rec {